@@ -1,5 +1,17 @@
-use std::vec;
-use super::super::features::image_tools::ImageToolsState;
+use std::{ops::Range, vec};
+use crossterm::event::KeyEvent;
+
+use super::super::features::{self, Feature, FeatureRegistry};
+use super::command::{self, CommandRegistry};
+use super::completion::CompletionPopup;
+use super::config::{self, Config, ConfigError, ConfigWatcher, NavigationConfig};
+use super::history::{History, HistorySearch};
+use super::keymap::{self, Keymap};
+use super::line_editor::LineEditor;
+use super::navigation::{Navigation, NavigationItem};
+use super::vi::ViState;
+use super::paths;
+use super::theme::{self, Theme};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FocusArea {
@@ -10,85 +22,135 @@ pub enum FocusArea {
     Footer,
 }
 
-#[derive(Debug, Clone)]
-pub enum NavigationState {
-    Main,
-    Submenu { parent_index: usize },
-}
-
-#[derive(Debug, Clone)]
-pub struct NavigationItem {
-    pub name: String,
-    pub submenu: Option<Vec<NavigationItem>>,
-}
-
-impl NavigationItem {
-    pub fn new(name: &str) -> Self {
-        Self {
-            name: name.to_string(),
-            submenu: None,
-        }
-    }
-
-    pub fn with_submenu(name: &str, submenu: Vec<NavigationItem>) -> Self {
-        Self {
-            name: name.to_string(),
-            submenu: Some(submenu),
-        }
-    }
-}
-
 pub struct AppState {
     pub focus: FocusArea,
-    pub input_buffer: String,
-    pub cursor_position: usize,
+    pub input: LineEditor,
     pub output_log: Vec<String>,
     pub show_quit_modal: bool,
-    pub selected_navigation_item: usize,
     pub preview_content: String,
     pub is_command_mode: bool,
-    pub navigation_state: NavigationState,
-    pub navigation_items: Vec<NavigationItem>,
-    pub image_tools_state: ImageToolsState,
+    pub navigation: Navigation,
+    pub features: FeatureRegistry,
+    pub commands: CommandRegistry,
+    // Bytes of the input the last command error points at
+    pub input_error: Option<Range<usize>>,
+    // Tab completion candidates on offer under the input
+    pub completion: Option<CompletionPopup>,
+    pub history: History,
+    // Set while Ctrl-R is searching the history
+    pub history_search: Option<HistorySearch>,
+    // Enter inserts a newline and the input box grows with its text
+    pub multiline: bool,
+    pub input_max_height: u16,
+    // Columns inside the input box at the last draw, for moving by rows
+    pub input_width: u16,
+    // Opt-in vi-style modal editing of the input
+    pub vi_mode: bool,
+    pub vi: ViState,
+    pub config: Config,
+    // Mistakes in the configuration file, shown in a modal until dismissed
+    pub config_errors: Vec<ConfigError>,
+    config_watcher: ConfigWatcher,
+    // What each key does, with the user's keys.toml over the defaults
+    pub keymap: Keymap,
+    // Mistakes in keys.toml, shown in the same modal
+    pub key_errors: Vec<ConfigError>,
+    keys_watcher: ConfigWatcher,
+    // Styles everything is drawn with
+    pub theme: Theme,
+    // NO_COLOR is set, so frames are drawn without colours
+    pub no_color: bool,
 }
 
 impl AppState {
+    /// The app as the user runs it: every feature, with the configuration,
+    /// key bindings, theme and history read from the user's files
     pub fn new() -> Self {
-        let navigation_items = vec![
-            NavigationItem::new("Home"),
-            NavigationItem::with_submenu("Image Tools", vec![
-                NavigationItem::new("Home"),
-                NavigationItem::new("Open"),
-                NavigationItem::new("Close"),
-            ]),
-            NavigationItem::new("Settings"),
-            NavigationItem::new("Local LLMs"),
-            NavigationItem::new("Help"),
-            NavigationItem::new("About"),
-        ];
+        let mut features = FeatureRegistry::default();
+        features::register_features(&mut features, features::plugins::plugins_dir().as_deref());
+
+        let config_watcher = ConfigWatcher::new(config::config_path());
+        let (config, config_errors) = match config_watcher.path() {
+            Some(path) => Config::load(path),
+            None => (Config::default(), Vec::new()),
+        };
+
+        let mut app_state = Self::with(features, config);
+        app_state.config_errors = config_errors;
+        app_state.config_watcher = config_watcher;
+        app_state.keys_watcher = ConfigWatcher::new(keymap::keys_path());
+        app_state.history = History::new(paths::data_dir().map(|dir| dir.join("history")));
+        app_state.no_color = theme::no_color();
+        app_state.load_keymap();
+        let name = app_state.config.general.theme.clone();
+        if let Err(error) = app_state.set_theme(&name) {
+            app_state.log_output(error);
+        }
+        app_state
+    }
+
+    /// An app with `features` and `config` that reads none of the user's
+    /// files: the default keys and dark theme, and a history kept in memory
+    pub fn with(mut features: FeatureRegistry, config: Config) -> Self {
+        for feature in features.iter_mut() {
+            feature.config_changed(&config);
+        }
+        let navigation = Navigation::new(navigation_items(&features, &config.navigation));
+
+        let mut commands = CommandRegistry::default();
+        command::register_builtin_commands(&mut commands);
+        for feature in features.iter_mut() {
+            feature.register_commands(&mut commands);
+        }
 
         let mut app_state = Self {
             focus: FocusArea::Navigation,
-            input_buffer: String::new(),
-            cursor_position: 0,
+            input: LineEditor::default(),
             output_log: Vec::new(),
             show_quit_modal: false,
-            selected_navigation_item: 0,
             preview_content: String::from("Welcome to Pipemind Console!"),
             is_command_mode: false,
-            navigation_state: NavigationState::Main,
-            navigation_items,
-            image_tools_state: ImageToolsState::new(),
+            navigation,
+            features,
+            commands,
+            input_error: None,
+            completion: None,
+            history: History::default(),
+            history_search: None,
+            multiline: false,
+            input_max_height: config.layout.input_max_height,
+            input_width: 80,
+            vi_mode: config.general.vi_mode,
+            vi: ViState::default(),
+            theme: Theme::default().with_colors(&config.colors),
+            config,
+            config_errors: Vec::new(),
+            config_watcher: ConfigWatcher::new(None),
+            keymap: Keymap::default(),
+            key_errors: Vec::new(),
+            keys_watcher: ConfigWatcher::new(None),
+            no_color: false,
         };
-
         app_state.update_preview_based_on_navigation();
         app_state
     }
 
+    /// The built-in features and default settings, as tests start from
+    #[cfg(test)]
+    pub fn for_tests() -> Self {
+        let mut features = FeatureRegistry::default();
+        features::register_features(&mut features, None);
+        Self::with(features, Config::default())
+    }
+
     pub fn reset_input(&mut self) {
-        self.input_buffer.clear();
-        self.cursor_position = 0;
+        self.input.reset();
         self.is_command_mode = false;
+        self.input_error = None;
+        self.completion = None;
+        self.history_search = None;
+        self.multiline = false;
+        self.vi.reset();
     }
 
     pub fn log_output(&mut self, message: String) {
@@ -107,109 +169,223 @@ impl AppState {
         self.preview_content = content;
     }
 
-    pub fn update_preview_based_on_navigation(&mut self) {
-        match &self.navigation_state {
-            NavigationState::Main => {
-                match self.selected_navigation_item {
-                    1 => { // Image Tools index
-                        self.preview_content = self.image_tools_state.welcome_message.clone();
-                    }
-                    0 => { // Home
-                        self.preview_content = String::from("Welcome to Pipemind Console!");
-                    }
-                    _ => {
-                        self.preview_content = String::from("Select an option from the menu.");
-                    }
-                }
-            }
-            NavigationState::Submenu { parent_index } => {
-                if *parent_index == 1 { // Image Tools
-                    match self.selected_navigation_item {
-                        0 => { // Home
-                            self.preview_content = self.image_tools_state.welcome_message.clone();
-                        }
-                        1 => { // Open
-                            self.preview_content = String::from("Select an image file to analyze...");
-                        }
-                        2 => { // Close
-                            self.preview_content = String::from("Close the current image...");
-                        }
-                        _ => {
-                            self.preview_content = String::from("Select an option from the submenu.");
-                        }
-                    }
-                }
-            }
+    /// The feature the content area belongs to: the one whose navigation
+    /// entry is highlighted or has its submenu open
+    pub fn active_feature(&self) -> Option<&dyn Feature> {
+        let id = self.active_feature_id()?;
+        self.features.find(&id)
+    }
+
+    pub fn active_feature_mut(&mut self) -> Option<&mut (dyn Feature + 'static)> {
+        let id = self.active_feature_id()?;
+        self.features.find_mut(&id)
+    }
+
+    fn active_feature_id(&self) -> Option<String> {
+        self.navigation
+            .trail()
+            .into_iter()
+            .rev()
+            .find(|item| self.features.find(&item.id).is_some())
+            .map(|item| item.id.clone())
+    }
+
+    /// Brings a feature on screen unless it already is, e.g. after a command
+    pub fn show_feature(&mut self, id: &str) {
+        if !self.navigation.is_within(id) {
+            self.navigation.reveal(id);
+            self.update_preview_based_on_navigation();
         }
     }
 
-    pub fn get_current_navigation_items(&self) -> Vec<String> {
-        match &self.navigation_state {
-            NavigationState::Main => {
-                self.navigation_items.iter()
-                    .map(|item| item.name.clone())
-                    .collect()
+    /// Lets every feature do its background work, logging what it reports.
+    /// Features that finished starting in the background join the menu.
+    pub fn poll_features(&mut self) {
+        let (started, errors) = self.features.poll_started();
+        for id in &started {
+            if let Some(feature) = self.features.find_mut(id) {
+                feature.config_changed(&self.config);
+                feature.register_commands(&mut self.commands);
             }
-            NavigationState::Submenu { parent_index } => {
-                if let Some(submenu) = &self.navigation_items[*parent_index].submenu {
-                    submenu.iter()
-                        .map(|item| item.name.clone())
-                        .collect()
-                } else {
-                    vec![]
-                }
+        }
+        if !started.is_empty() {
+            self.rebuild_navigation();
+        }
+        for error in errors {
+            self.log_output(error);
+        }
+
+        let messages: Vec<String> = self.features.iter_mut().flat_map(|feature| feature.poll()).collect();
+        for message in messages {
+            self.log_output(message);
+        }
+    }
+
+    /// Gives a key to the first feature with an overlay open. Returns false
+    /// if none has one.
+    pub fn handle_modal_key(&mut self, key: &KeyEvent) -> bool {
+        let Some(feature) = self.features.iter_mut().find(|feature| feature.is_modal()) else {
+            return false;
+        };
+        if let Some(message) = feature.handle_modal_key(key) {
+            self.log_output(message);
+        }
+        true
+    }
+
+    /// Re-reads the configuration file if it was saved since the last look
+    pub fn poll_config(&mut self) {
+        if self.keys_watcher.changed() {
+            let count = self.load_keymap();
+            if let Some(path) = self.keys_watcher.path() {
+                let message = match count {
+                    0 => format!("Reloaded {}", paths::display_path(path)),
+                    1 => format!("Reloaded {} with 1 mistake", paths::display_path(path)),
+                    count => format!("Reloaded {} with {} mistakes", paths::display_path(path), count),
+                };
+                self.log_output(message);
             }
         }
+        if !self.config_watcher.changed() {
+            return;
+        }
+        let Some(path) = self.config_watcher.path() else {
+            return;
+        };
+        let (config, errors) = Config::load(path);
+        let message = match errors.len() {
+            0 => format!("Reloaded {}", paths::display_path(path)),
+            1 => format!("Reloaded {} with 1 mistake", paths::display_path(path)),
+            count => format!("Reloaded {} with {} mistakes", paths::display_path(path), count),
+        };
+        self.apply_config(config);
+        self.config_errors = errors;
+        self.log_output(message);
     }
 
-    pub fn get_current_navigation_count(&self) -> usize {
-        match &self.navigation_state {
-            NavigationState::Main => self.navigation_items.len(),
-            NavigationState::Submenu { parent_index } => {
-                if let Some(submenu) = &self.navigation_items[*parent_index].submenu {
-                    submenu.len()
-                } else {
-                    0
-                }
+    /// Reads the key bindings, keeping their mistakes for the modal.
+    /// Returns how many there were.
+    fn load_keymap(&mut self) -> usize {
+        let (keymap, errors) = match self.keys_watcher.path() {
+            Some(path) => Keymap::load(path),
+            None => (Keymap::default(), Vec::new()),
+        };
+        self.keymap = keymap;
+        self.key_errors = errors;
+        self.key_errors.len()
+    }
+
+    /// Whether mistakes in config.toml or keys.toml are waiting to be seen
+    pub fn has_config_errors(&self) -> bool {
+        !self.config_errors.is_empty() || !self.key_errors.is_empty()
+    }
+
+    /// Switches to a new configuration. Settings that can also be changed
+    /// at runtime, like vi mode, only follow the file when it changes them.
+    pub fn apply_config(&mut self, config: Config) {
+        for feature in self.features.iter_mut() {
+            feature.config_changed(&config);
+        }
+        let old = std::mem::replace(&mut self.config, config);
+        let new = &self.config;
+        if new.general.vi_mode != old.general.vi_mode {
+            self.vi_mode = new.general.vi_mode;
+            self.vi.reset();
+        }
+        if new.layout.input_max_height != old.layout.input_max_height {
+            self.input_max_height = new.layout.input_max_height;
+        }
+        if new.general.theme != old.general.theme || new.colors != old.colors {
+            let name = new.general.theme.clone();
+            if let Err(error) = self.set_theme(&name) {
+                self.log_output(error);
             }
         }
+        if self.config.navigation != old.navigation {
+            self.rebuild_navigation();
+        }
+    }
+
+    /// Builds the menu afresh, staying on the same entry if it is still there
+    fn rebuild_navigation(&mut self) {
+        let selected = self.navigation.selected().map(|item| item.id.clone());
+        self.navigation = Navigation::new(navigation_items(&self.features, &self.config.navigation));
+        if let Some(id) = &selected
+            && self.navigation.reveal(id)
+        {
+            return;
+        }
+        self.update_preview_based_on_navigation();
+    }
+
+    /// Switches to a built-in or custom theme, with the config file's colours
+    /// over it. Keeps the current theme if it can't be loaded.
+    pub fn set_theme(&mut self, name: &str) -> Result<(), String> {
+        let theme = theme::load(name).map_err(|error| format!("Could not load the {} theme: {}", name, error))?;
+        self.theme = theme.with_colors(&self.config.colors);
+        Ok(())
+    }
+
+    pub fn update_preview_based_on_navigation(&mut self) {
+        if let Some(content) = self.navigation.selected().map(|item| item.preview_content(self)) {
+            self.preview_content = content;
+        }
+    }
+
+    pub fn get_current_navigation_count(&self) -> usize {
+        self.navigation.items().len()
     }
 
     pub fn get_current_selection_index(&self) -> usize {
-        self.selected_navigation_item
+        self.navigation.selected_index()
     }
 
     pub fn is_in_submenu(&self) -> bool {
-        matches!(self.navigation_state, NavigationState::Submenu { .. })
+        self.navigation.depth() > 0
     }
 
     pub fn enter_submenu(&mut self) {
-        if let Some(submenu) = &self.navigation_items[self.selected_navigation_item].submenu {
-            if !submenu.is_empty() {
-                self.navigation_state = NavigationState::Submenu {
-                    parent_index: self.selected_navigation_item,
-                };
-                self.selected_navigation_item = 0;
-                self.update_preview_based_on_navigation();
-            }
+        if self.navigation.enter() {
+            self.update_preview_based_on_navigation();
         }
     }
 
     pub fn exit_submenu(&mut self) {
-        if let NavigationState::Submenu { parent_index } = self.navigation_state {
-            self.navigation_state = NavigationState::Main;
-            self.selected_navigation_item = parent_index;
+        if self.navigation.back() {
             self.update_preview_based_on_navigation();
         }
     }
 
+    /// Acts on Enter over the highlighted item: opens its menu or runs its
+    /// action. Returns a message for the output log, if any.
+    pub fn activate_navigation_item(&mut self) -> Option<String> {
+        let item = self.navigation.selected()?;
+        if item.has_children() {
+            self.enter_submenu();
+            return None;
+        }
+        let action = item.action?;
+        action(self)
+    }
+
     pub fn select_navigation_item(&mut self, index: usize) {
-        let max_index = self.get_current_navigation_count().saturating_sub(1);
-        self.selected_navigation_item = index.min(max_index);
+        self.navigation.select(index);
         self.update_preview_based_on_navigation();
     }
 }
 
+/// The top-level menu: Home, then the features, reordered and filtered as
+/// configured
+fn navigation_items(features: &FeatureRegistry, config: &NavigationConfig) -> Vec<NavigationItem> {
+    let mut items = vec![NavigationItem::new("home", "Home").preview_text("Welcome to Pipemind Console!")];
+    items.extend(features.iter().map(|feature| feature.navigation_item()));
+
+    items.retain(|item| !config.hidden.contains(&item.id));
+    // A stable sort keeps the entries that aren't listed in their usual order
+    items.sort_by_key(|item| config.order.iter().position(|id| *id == item.id).unwrap_or(config.order.len()));
+    items
+}
+
 impl Default for AppState {
     fn default() -> Self {
         Self::new()
@@ -222,33 +398,56 @@ mod tests {
 
     #[test]
     fn test_app_state_initialization() {
-        let app_state = AppState::new();
+        let app_state = AppState::for_tests();
         assert_eq!(app_state.focus, FocusArea::Navigation);
-        assert!(app_state.input_buffer.is_empty());
-        assert_eq!(app_state.cursor_position, 0);
+        assert!(app_state.input.is_empty());
+        assert_eq!(app_state.input.cursor(), 0);
         assert!(app_state.output_log.is_empty());
         assert!(!app_state.show_quit_modal);
-        assert_eq!(app_state.selected_navigation_item, 0);
+        assert_eq!(app_state.get_current_selection_index(), 0);
         assert!(!app_state.is_command_mode);
-        assert!(matches!(app_state.navigation_state, NavigationState::Main));
+        assert!(!app_state.is_in_submenu());
     }
 
     #[test]
     fn test_navigation_state_transitions() {
-        let mut app_state = AppState::new();
+        let mut app_state = AppState::for_tests();
 
         // Select Image Tools (index 1)
         app_state.select_navigation_item(1);
-        assert_eq!(app_state.selected_navigation_item, 1);
+        assert_eq!(app_state.get_current_selection_index(), 1);
+        assert_eq!(app_state.active_feature().map(|feature| feature.id()), Some("image_tools"));
 
         // Enter submenu
         app_state.enter_submenu();
-        assert!(matches!(app_state.navigation_state, NavigationState::Submenu { parent_index: 1 }));
-        assert_eq!(app_state.selected_navigation_item, 0);
+        assert!(app_state.is_in_submenu());
+        assert_eq!(app_state.get_current_selection_index(), 0);
+        assert_eq!(app_state.navigation.breadcrumb(), "Navigation › Image Tools");
 
         // Exit submenu
         app_state.exit_submenu();
-        assert!(matches!(app_state.navigation_state, NavigationState::Main));
-        assert_eq!(app_state.selected_navigation_item, 1);
+        assert!(!app_state.is_in_submenu());
+        assert_eq!(app_state.get_current_selection_index(), 1);
+    }
+
+    #[test]
+    fn test_apply_config_reorders_navigation() {
+        let mut app_state = AppState::for_tests();
+        app_state.select_navigation_item(1);
+
+        let mut config = Config::default();
+        config.navigation.order = vec![String::from("settings"), String::from("image_tools")];
+        config.navigation.hidden = vec![String::from("home")];
+        config.general.vi_mode = true;
+        config.general.theme = String::from("solarized");
+        app_state.apply_config(config);
+
+        let ids: Vec<&str> = app_state.navigation.top_level().iter().map(|item| item.id.as_str()).collect();
+        assert_eq!(ids, ["settings", "image_tools"]);
+        assert!(!ids.contains(&"home"));
+        // The selection follows Image Tools to its new place
+        assert_eq!(app_state.get_current_selection_index(), 1);
+        assert!(app_state.vi_mode);
+        assert_eq!(app_state.theme.name, "solarized");
     }
 }
\ No newline at end of file
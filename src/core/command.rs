@@ -0,0 +1,652 @@
+// This file holds the registry of slash commands typed into the input line.
+//
+// Every command declares its name, aliases, arguments and help text next to
+// the handler that runs it. The core registers the built-in commands below,
+// each feature registers its own at startup, and `/help` is generated from
+// whatever ended up in the registry.
+//
+// Command lines are split like a shell would: quotes group words, a
+// backslash escapes the next character, and `--name value` or
+// `--name=value` set options. Every error keeps the byte span of the text
+// that caused it, so the input box can point at it.
+
+use std::{fmt, ops::Range};
+
+use super::{app_state::AppState, paths, theme};
+
+/// Runs a command. The returned text is shown in the preview.
+pub type CommandHandler = fn(&mut AppState, &CommandArgs) -> String;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// The next word on the line
+    Positional,
+    /// All remaining words, e.g. a search query
+    Rest,
+    /// `--name value` or `--name=value`
+    Option,
+    /// `--name` on its own
+    Switch,
+}
+
+/// What Tab offers for an argument's value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgCompletion {
+    None,
+    /// A fixed set of values
+    Values(&'static [&'static str]),
+    /// Any file or folder
+    Path,
+    /// Folders only
+    Folder,
+    /// The name of a registered command
+    Command,
+    /// A built-in or custom theme
+    Theme,
+}
+
+/// One argument in a command's schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub required: bool,
+    pub kind: ArgKind,
+    pub completion: ArgCompletion,
+}
+
+impl ArgSpec {
+    const fn new(name: &'static str, help: &'static str, required: bool, kind: ArgKind) -> Self {
+        Self { name, help, required, kind, completion: ArgCompletion::None }
+    }
+
+    pub const fn required(name: &'static str, help: &'static str) -> Self {
+        Self::new(name, help, true, ArgKind::Positional)
+    }
+
+    pub const fn optional(name: &'static str, help: &'static str) -> Self {
+        Self::new(name, help, false, ArgKind::Positional)
+    }
+
+    /// An optional argument that swallows the rest of the line
+    pub const fn rest(name: &'static str, help: &'static str) -> Self {
+        Self::new(name, help, false, ArgKind::Rest)
+    }
+
+    /// An optional `--name value` argument
+    pub const fn option(name: &'static str, help: &'static str) -> Self {
+        Self::new(name, help, false, ArgKind::Option)
+    }
+
+    /// An optional `--name` flag without a value
+    pub const fn switch(name: &'static str, help: &'static str) -> Self {
+        Self::new(name, help, false, ArgKind::Switch)
+    }
+
+    /// Completes the value from a fixed list
+    pub const fn values(mut self, values: &'static [&'static str]) -> Self {
+        self.completion = ArgCompletion::Values(values);
+        self
+    }
+
+    /// Completes the value as a file or folder path
+    pub const fn path(mut self) -> Self {
+        self.completion = ArgCompletion::Path;
+        self
+    }
+
+    /// Completes the value as a folder path
+    pub const fn folder(mut self) -> Self {
+        self.completion = ArgCompletion::Folder;
+        self
+    }
+
+    /// Completes the value as a command name
+    pub const fn command(mut self) -> Self {
+        self.completion = ArgCompletion::Command;
+        self
+    }
+
+    /// Completes theme names
+    pub const fn theme(mut self) -> Self {
+        self.completion = ArgCompletion::Theme;
+        self
+    }
+
+    pub fn is_flag(&self) -> bool {
+        matches!(self.kind, ArgKind::Option | ArgKind::Switch)
+    }
+
+    /// How the argument appears in usage lines: `<path>`, `[name]`,
+    /// `[query...]`, `[--size <size>]` or `[--force]`
+    pub fn usage(&self) -> String {
+        match self.kind {
+            ArgKind::Positional if self.required => format!("<{}>", self.name),
+            ArgKind::Positional => format!("[{}]", self.name),
+            ArgKind::Rest if self.required => format!("<{}...>", self.name),
+            ArgKind::Rest => format!("[{}...]", self.name),
+            ArgKind::Option => format!("[--{} <{}>]", self.name, self.name),
+            ArgKind::Switch => format!("[--{}]", self.name),
+        }
+    }
+}
+
+/// A word of a command line after quotes and escapes are resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    /// Bytes of the original line the word came from, quotes included
+    pub span: Range<usize>,
+    /// True if any part was quoted or escaped, so `"--x"` stays a plain word
+    pub quoted: bool,
+}
+
+/// Splits a command line into words, resolving quotes and escapes.
+///
+/// Single quotes keep everything literally. Inside double quotes a
+/// backslash escapes only `"` and `\`; outside quotes it escapes anything.
+pub fn tokenize(line: &str) -> Result<Vec<Token>, CommandError> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut text = String::new();
+        let mut quoted = false;
+        let mut end = start;
+        while let Some(&(i, c)) = chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            chars.next();
+            end = i + c.len_utf8();
+            match c {
+                '\\' => {
+                    let Some((j, escaped)) = chars.next() else {
+                        return Err(CommandError::new(CommandErrorKind::TrailingEscape, i..end));
+                    };
+                    text.push(escaped);
+                    end = j + escaped.len_utf8();
+                    quoted = true;
+                }
+                '\'' | '"' => {
+                    quoted = true;
+                    let mut closed = false;
+                    while let Some((j, inner)) = chars.next() {
+                        end = j + inner.len_utf8();
+                        if inner == c {
+                            closed = true;
+                            break;
+                        }
+                        if c == '"'
+                            && inner == '\\'
+                            && let Some(&(k, next @ ('"' | '\\'))) = chars.peek()
+                        {
+                            chars.next();
+                            end = k + 1;
+                            text.push(next);
+                            continue;
+                        }
+                        text.push(inner);
+                    }
+                    if !closed {
+                        return Err(CommandError::new(CommandErrorKind::UnterminatedQuote(c), i..line.len()));
+                    }
+                }
+                _ => text.push(c),
+            }
+        }
+        tokens.push(Token { text, span: start..end, quoted });
+    }
+    Ok(tokens)
+}
+
+/// Why a command line could not be run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandErrorKind {
+    UnterminatedQuote(char),
+    TrailingEscape,
+    UnknownCommand(String),
+    UnknownOption(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+    MissingArgument(String),
+    TooManyArguments,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandError {
+    pub kind: CommandErrorKind,
+    /// Bytes of the command line to highlight
+    pub span: Range<usize>,
+    /// Usage line of the command, once one was found
+    pub usage: Option<String>,
+}
+
+impl CommandError {
+    pub fn new(kind: CommandErrorKind, span: Range<usize>) -> Self {
+        Self { kind, span, usage: None }
+    }
+
+    fn with_usage(mut self, usage: String) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            CommandErrorKind::UnterminatedQuote(quote) => write!(f, "Missing closing {}", quote)?,
+            CommandErrorKind::TrailingEscape => write!(f, "Nothing to escape after \\")?,
+            CommandErrorKind::UnknownCommand(name) => {
+                write!(f, "Unknown command: /{}. Type /help for a list.", name)?
+            }
+            CommandErrorKind::UnknownOption(name) => write!(f, "Unknown option --{}", name)?,
+            CommandErrorKind::MissingValue(name) => write!(f, "Option --{} needs a value", name)?,
+            CommandErrorKind::UnexpectedValue(name) => write!(f, "Option --{} takes no value", name)?,
+            CommandErrorKind::MissingArgument(usage) => write!(f, "Missing argument {}", usage)?,
+            CommandErrorKind::TooManyArguments => write!(f, "Too many arguments")?,
+        }
+        if let Some(usage) = &self.usage {
+            write!(f, ". Usage: {}", usage)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub args: Vec<ArgSpec>,
+    pub help: &'static str,
+    pub handler: CommandHandler,
+}
+
+impl Command {
+    pub fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.name);
+        for arg in &self.args {
+            usage.push(' ');
+            usage.push_str(&arg.usage());
+        }
+        usage
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+
+    /// The argument the word after `words` would fill, if any. A word after
+    /// `--name` fills that option.
+    pub fn arg_at(&self, words: &[Token]) -> Option<&ArgSpec> {
+        let mut pending = None;
+        let mut position = 0;
+        let mut options_done = false;
+        for word in words {
+            if pending.take().is_some() {
+                continue;
+            }
+            match word.text.strip_prefix("--").filter(|_| !word.quoted && !options_done) {
+                Some("") => options_done = true,
+                Some(flag) => {
+                    pending = self.args.iter().find(|arg| arg.kind == ArgKind::Option && arg.name == flag);
+                }
+                None => position += 1,
+            }
+        }
+        pending.or_else(|| {
+            self.args
+                .iter()
+                .filter(|arg| !arg.is_flag())
+                .enumerate()
+                .find(|(index, arg)| *index == position || (arg.kind == ArgKind::Rest && *index < position))
+                .map(|(_, arg)| arg)
+        })
+    }
+
+    /// Pairs the words after the command name with the argument schema.
+    /// `name_span` is where errors about missing arguments point.
+    pub fn bind(&self, tokens: &[Token], name_span: Range<usize>) -> Result<CommandArgs, CommandError> {
+        self.bind_tokens(tokens, name_span).map_err(|error| error.with_usage(self.usage()))
+    }
+
+    fn bind_tokens(&self, tokens: &[Token], name_span: Range<usize>) -> Result<CommandArgs, CommandError> {
+        let mut values = Vec::new();
+        let mut positional = Vec::new();
+        let mut options_done = false;
+        let mut tokens = tokens.iter();
+
+        while let Some(token) = tokens.next() {
+            let flag = token.text.strip_prefix("--").filter(|_| !token.quoted && !options_done);
+            let Some(flag) = flag else {
+                positional.push(token);
+                continue;
+            };
+            // A bare `--` makes everything after it positional
+            if flag.is_empty() {
+                options_done = true;
+                continue;
+            }
+            let (name, inline) = match flag.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (flag, None),
+            };
+            let spec = self
+                .args
+                .iter()
+                .find(|arg| arg.is_flag() && arg.name == name)
+                .ok_or_else(|| CommandError::new(CommandErrorKind::UnknownOption(name.to_string()), token.span.clone()))?;
+            let value = match (spec.kind, inline) {
+                (ArgKind::Switch, Some(_)) => {
+                    return Err(CommandError::new(
+                        CommandErrorKind::UnexpectedValue(name.to_string()),
+                        token.span.clone(),
+                    ));
+                }
+                (ArgKind::Switch, None) => String::new(),
+                (_, Some(value)) => value.to_string(),
+                (_, None) => tokens
+                    .next()
+                    .map(|value| value.text.clone())
+                    .ok_or_else(|| CommandError::new(CommandErrorKind::MissingValue(name.to_string()), token.span.clone()))?,
+            };
+            values.retain(|(arg, _)| *arg != spec.name);
+            values.push((spec.name, value));
+        }
+
+        let mut remaining = positional.as_slice();
+        for arg in self.args.iter().filter(|arg| !arg.is_flag()) {
+            if remaining.is_empty() {
+                if arg.required {
+                    return Err(CommandError::new(CommandErrorKind::MissingArgument(arg.usage()), name_span));
+                }
+                break;
+            }
+            if arg.kind == ArgKind::Rest {
+                let words: Vec<&str> = remaining.iter().map(|token| token.text.as_str()).collect();
+                values.push((arg.name, words.join(" ")));
+                remaining = &[];
+            } else {
+                values.push((arg.name, remaining[0].text.clone()));
+                remaining = &remaining[1..];
+            }
+        }
+        if let (Some(first), Some(last)) = (remaining.first(), remaining.last()) {
+            return Err(CommandError::new(CommandErrorKind::TooManyArguments, first.span.start..last.span.end));
+        }
+        Ok(CommandArgs { command: self.name, values })
+    }
+}
+
+/// The arguments a command was called with, by name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandArgs {
+    command: &'static str,
+    values: Vec<(&'static str, String)>,
+}
+
+impl CommandArgs {
+    /// Name of the command being run, for handlers shared by several commands
+    pub fn command(&self) -> &'static str {
+        self.command
+    }
+
+    /// Every argument that was given, as name and value
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        self.values.iter().map(|(name, value)| (*name, value.as_str()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(arg, _)| *arg == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// True if the option or switch was given
+    pub fn has(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CommandRegistry {
+    commands: Vec<Command>,
+}
+
+impl CommandRegistry {
+    /// Adds a command. Names and aliases must be unique across the registry.
+    pub fn register(&mut self, command: Command) {
+        debug_assert!(
+            std::iter::once(command.name)
+                .chain(command.aliases.iter().copied())
+                .all(|name| self.find(name).is_none()),
+            "command /{} clashes with an existing command",
+            command.name
+        );
+        self.commands.push(command);
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Command> {
+        self.commands.iter().find(|command| command.matches(name))
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// One line per command: usage, help text and aliases
+    pub fn help_text(&self) -> String {
+        let width = self.commands.iter().map(|command| command.usage().len()).max().unwrap_or(0);
+        let mut text = String::from("Available commands:\n");
+        for command in &self.commands {
+            text.push_str(&format!("\n  {:<width$}  {}", command.usage(), command.help, width = width));
+            if !command.aliases.is_empty() {
+                let aliases: Vec<String> = command.aliases.iter().map(|alias| format!("/{}", alias)).collect();
+                text.push_str(&format!(" (also {})", aliases.join(", ")));
+            }
+        }
+        text.push_str("\n\nType /help <command> for details.");
+        text
+    }
+
+    /// Usage, help text and argument descriptions of one command
+    pub fn command_help(&self, name: &str) -> Option<String> {
+        let command = self.find(name.trim_start_matches('/'))?;
+        let mut text = format!("{}\n\n{}", command.usage(), command.help);
+        for arg in &command.args {
+            text.push_str(&format!("\n  {}  {}", arg.usage(), arg.help));
+        }
+        if !command.aliases.is_empty() {
+            let aliases: Vec<String> = command.aliases.iter().map(|alias| format!("/{}", alias)).collect();
+            text.push_str(&format!("\n\nAliases: {}", aliases.join(", ")));
+        }
+        Some(text)
+    }
+}
+
+/// Runs a command line such as `/open "my goblin.png"`, returning the text to
+/// preview. Errors carry the span of the line they are about.
+pub fn execute(app_state: &mut AppState, line: &str) -> Result<String, CommandError> {
+    let tokens = tokenize(line)?;
+    let Some((first, words)) = tokens.split_first() else {
+        return Ok(app_state.commands.help_text());
+    };
+    let name = first.text.strip_prefix('/').unwrap_or(&first.text);
+    if name.is_empty() && words.is_empty() {
+        return Ok(app_state.commands.help_text());
+    }
+    // Handlers need the whole state, registry included, so run a copy of the entry
+    let Some(command) = app_state.commands.find(name).cloned() else {
+        return Err(CommandError::new(CommandErrorKind::UnknownCommand(name.to_string()), first.span.clone()));
+    };
+    let args = command.bind(words, first.span.clone())?;
+    Ok((command.handler)(app_state, &args))
+}
+
+pub fn register_builtin_commands(registry: &mut CommandRegistry) {
+    registry.register(Command {
+        name: "help",
+        aliases: &["?"],
+        args: vec![ArgSpec::optional("command", "Command to describe").command()],
+        help: "List the commands, or describe one",
+        handler: |app_state, args| match args.get("command") {
+            Some(name) => app_state
+                .commands
+                .command_help(name)
+                .unwrap_or_else(|| format!("Unknown command: /{}", name.trim_start_matches('/'))),
+            None => app_state.commands.help_text(),
+        },
+    });
+    registry.register(Command {
+        name: "clear",
+        aliases: &["cls"],
+        args: vec![],
+        help: "Clear the preview and the output log",
+        handler: |app_state, _| {
+            app_state.output_log.clear();
+            String::new()
+        },
+    });
+    registry.register(Command {
+        name: "vi",
+        aliases: &[],
+        args: vec![ArgSpec::optional("state", "on or off; without it, vi mode is toggled").values(&["on", "off"])],
+        help: "Switch vi-style editing of the input on or off",
+        handler: |app_state, args| {
+            app_state.vi_mode = match args.get("state") {
+                Some("on") => true,
+                Some("off") => false,
+                Some(other) => return format!("Expected on or off, not {}", other),
+                None => !app_state.vi_mode,
+            };
+            app_state.vi.reset();
+            String::from(if app_state.vi_mode { "Vi mode on" } else { "Vi mode off" })
+        },
+    });
+    registry.register(Command {
+        name: "theme",
+        aliases: &[],
+        args: vec![ArgSpec::optional("name", "Theme to switch to; without it, the themes are listed").theme()],
+        help: "Switch the colour theme until the next restart, or list the themes",
+        handler: |app_state, args| {
+            let Some(name) = args.get("name") else {
+                let mut text = String::from("Themes:\n");
+                for name in theme::available() {
+                    let marker = if name == app_state.theme.name { "*" } else { " " };
+                    text.push_str(&format!("\n{} {}", marker, name));
+                }
+                if let Some(dir) = theme::themes_dir() {
+                    text.push_str(&format!("\n\nCustom themes are read from {}", paths::display_path(&dir)));
+                }
+                return text;
+            };
+            match app_state.set_theme(name) {
+                Ok(()) if app_state.no_color => format!("Switched to the {} theme, but NO_COLOR is set", name),
+                Ok(()) => format!("Switched to the {} theme", name),
+                Err(error) => error,
+            }
+        },
+    });
+    registry.register(Command {
+        name: "keys",
+        aliases: &["bindings"],
+        args: vec![],
+        help: "List the key bindings",
+        handler: |app_state, _| app_state.keymap.help_text(),
+    });
+    registry.register(Command {
+        name: "quit",
+        aliases: &["q", "exit"],
+        args: vec![],
+        help: "Ask to quit Pipemind",
+        handler: |app_state, _| {
+            app_state.show_quit_modal = true;
+            String::new()
+        },
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        tokenize(line).unwrap().into_iter().map(|token| token.text).collect()
+    }
+
+    #[test]
+    fn test_tokenize_quotes_and_escapes() {
+        assert_eq!(words(r#"/open "my goblin.png""#), ["/open", "my goblin.png"]);
+        assert_eq!(words(r#"a\ b 'c \d' "e\"f\g""#), ["a b", r"c \d", r#"e"f\g"#]);
+        assert_eq!(words(r#"--size="512 x 512" ''"#), ["--size=512 x 512", ""]);
+
+        let tokens = tokenize(r#"/open "a b" c"#).unwrap();
+        assert_eq!(tokens[1].span, 6..11);
+        assert!(tokens[1].quoted && !tokens[2].quoted);
+
+        let error = tokenize(r#"/open "goblin.png"#).unwrap_err();
+        assert_eq!((error.kind, error.span), (CommandErrorKind::UnterminatedQuote('"'), 6..17));
+        assert_eq!(tokenize(r"/open x\").unwrap_err().span, 7..8);
+    }
+
+    #[test]
+    fn test_binds_arguments_to_schema() {
+        let command = Command {
+            name: "search",
+            aliases: &["find"],
+            args: vec![
+                ArgSpec::required("folder", "Where"),
+                ArgSpec::rest("query", "What"),
+                ArgSpec::option("limit", "How many"),
+                ArgSpec::switch("fresh", "Rescan first"),
+            ],
+            help: "Search",
+            handler: |_, _| String::new(),
+        };
+        assert_eq!(command.usage(), "/search <folder> [query...] [--limit <limit>] [--fresh]");
+        assert!(command.matches("find"));
+
+        let bind = |line: &str| command.bind(&tokenize(line).unwrap(), 0..7);
+        let args = bind("out --limit 5 a goblin --fresh").unwrap();
+        assert_eq!(args.get("folder"), Some("out"));
+        assert_eq!(args.get("query"), Some("a goblin"));
+        assert_eq!(args.get("limit"), Some("5"));
+        assert!(args.has("fresh"));
+        assert_eq!(bind("out --limit=7").unwrap().get("limit"), Some("7"));
+        assert_eq!(bind("out -- --fresh").unwrap().get("query"), Some("--fresh"));
+        assert_eq!(bind(r#"out "--fresh""#).unwrap().get("query"), Some("--fresh"));
+        assert!(bind("out").unwrap().get("query").is_none());
+
+        let error = bind("").unwrap_err();
+        assert_eq!(error.span, 0..7);
+        assert!(error.to_string().starts_with("Missing argument <folder>. Usage: /search"));
+        let error = bind("out --size 5").unwrap_err();
+        assert_eq!((error.kind, error.span), (CommandErrorKind::UnknownOption("size".into()), 4..10));
+        assert_eq!(bind("out --limit").unwrap_err().kind, CommandErrorKind::MissingValue("limit".into()));
+        assert_eq!(bind("out --fresh=1").unwrap_err().kind, CommandErrorKind::UnexpectedValue("fresh".into()));
+    }
+
+    #[test]
+    fn test_help_is_generated_from_registry() {
+        let mut app_state = AppState::for_tests();
+        let help = execute(&mut app_state, "/help").unwrap();
+        for command in app_state.commands.commands() {
+            assert!(help.contains(&command.usage()), "{} missing from help", command.name);
+        }
+        assert!(execute(&mut app_state, "/? clear").unwrap().contains("Aliases: /cls"));
+        let error = execute(&mut app_state, "/nope").unwrap_err();
+        assert!(error.to_string().starts_with("Unknown command: /nope"));
+        let error = execute(&mut app_state, "/clear now please").unwrap_err();
+        assert_eq!((error.kind, error.span), (CommandErrorKind::TooManyArguments, 7..17));
+
+        execute(&mut app_state, "/quit").unwrap();
+        assert!(app_state.show_quit_modal);
+    }
+}
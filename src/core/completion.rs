@@ -0,0 +1,274 @@
+// This file works out what Tab can complete in the input line.
+//
+// Only command lines complete. The word under the cursor is either the
+// command name, an `--option` name, or the value of an argument, and the
+// argument's schema says whether that value is one of a fixed set, a path,
+// another command's name or a theme's.
+
+use std::{fs, ops::Range, path::PathBuf};
+
+use super::{
+    command::{tokenize, ArgCompletion, ArgKind, ArgSpec, CommandRegistry},
+    paths::expand_tilde,
+    theme,
+};
+
+/// Candidates for the word under the cursor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completions {
+    /// Bytes of the input the word occupies, up to the cursor
+    pub range: Range<usize>,
+    /// Unescaped words the user could mean, sorted
+    pub candidates: Vec<String>,
+    // The quote the word was opened with, kept when it is replaced
+    quote: Option<char>,
+}
+
+impl Completions {
+    /// The longest start shared by every candidate
+    pub fn common_prefix(&self) -> String {
+        let Some((first, others)) = self.candidates.split_first() else {
+            return String::new();
+        };
+        let mut prefix = first.as_str();
+        for candidate in others {
+            let shared = prefix
+                .char_indices()
+                .zip(candidate.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(prefix.len().min(candidate.len()), |((i, _), _)| i);
+            prefix = &prefix[..shared];
+        }
+        prefix.to_string()
+    }
+
+    /// The input text for `word`, quoted or escaped like the word being
+    /// replaced. A `finished` word is closed off so typing can go on to
+    /// the next argument; folders stay open to complete inside them.
+    pub fn replacement(&self, word: &str, finished: bool) -> String {
+        let mut text = String::new();
+        match self.quote {
+            Some(quote) => {
+                text.push(quote);
+                for c in word.chars() {
+                    if quote == '"' && matches!(c, '"' | '\\') {
+                        text.push('\\');
+                    }
+                    text.push(c);
+                }
+            }
+            None => {
+                for c in word.chars() {
+                    if c.is_whitespace() || matches!(c, '"' | '\'' | '\\') {
+                        text.push('\\');
+                    }
+                    text.push(c);
+                }
+            }
+        }
+        if finished && !word.ends_with('/') {
+            if let Some(quote) = self.quote {
+                text.push(quote);
+            }
+            text.push(' ');
+        }
+        text
+    }
+}
+
+/// Tab completion state while several candidates are on offer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionPopup {
+    pub completions: Completions,
+    /// None until Tab is pressed again to pick a candidate
+    pub selected: Option<usize>,
+}
+
+impl CompletionPopup {
+    pub fn new(completions: Completions) -> Self {
+        Self { completions, selected: None }
+    }
+
+    /// Moves to the next (or previous) candidate and returns it
+    pub fn cycle(&mut self, forward: bool) -> &str {
+        let count = self.completions.candidates.len();
+        let selected = match (self.selected, forward) {
+            (None, true) => 0,
+            (None, false) => count - 1,
+            (Some(index), true) => (index + 1) % count,
+            (Some(index), false) => (index + count - 1) % count,
+        };
+        self.selected = Some(selected);
+        &self.completions.candidates[selected]
+    }
+}
+
+/// Works out the candidates for the word that ends at `cursor` in a command line
+pub fn complete(registry: &CommandRegistry, line: &str, cursor: usize) -> Option<Completions> {
+    if !line.starts_with('/') {
+        return None;
+    }
+    let (start, quote) = current_word(&line[..cursor]);
+    let word = &line[start..cursor];
+    // Close an open quote so the partial word reads as one
+    let closed = match quote {
+        Some(quote) => format!("{}{}", word, quote),
+        None => word.to_string(),
+    };
+    let prefix = tokenize(&closed).ok()?.pop().map(|token| token.text).unwrap_or_default();
+    let earlier = tokenize(&line[..start]).ok()?;
+
+    let mut candidates: Vec<String> = match earlier.split_first() {
+        None => registry
+            .commands()
+            .iter()
+            .map(|command| format!("/{}", command.name))
+            .filter(|name| name.starts_with(&prefix))
+            .collect(),
+        Some((first, words)) => {
+            let command = registry.find(first.text.strip_prefix('/')?)?;
+            let flag = prefix.strip_prefix("--").filter(|_| quote.is_none());
+            match flag.map(|flag| flag.split_once('=')) {
+                // `--name=val` completes the option's value in place
+                Some(Some((name, value))) => {
+                    let spec = command.args.iter().find(|arg| arg.kind == ArgKind::Option && arg.name == name)?;
+                    value_candidates(registry, spec, value)
+                        .into_iter()
+                        .map(|value| format!("--{}={}", name, value))
+                        .collect()
+                }
+                Some(None) => command
+                    .args
+                    .iter()
+                    .filter(|arg| arg.is_flag())
+                    .map(|arg| format!("--{}", arg.name))
+                    .filter(|flag| flag.starts_with(&prefix))
+                    .collect(),
+                None => value_candidates(registry, command.arg_at(words)?, &prefix),
+            }
+        }
+    };
+    candidates.sort();
+    candidates.dedup();
+    Some(Completions { range: start..cursor, candidates, quote })
+}
+
+/// Where the word before the end of `text` starts, and the quote it is
+/// still inside, if any
+fn current_word(text: &str) -> (usize, Option<char>) {
+    let mut start = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some('"'), '\\') | (None, '\\') => escaped = true,
+            (Some(open), _) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, _) if c.is_whitespace() => start = i + c.len_utf8(),
+            (None, _) => {}
+        }
+    }
+    // The quote only matters if it opened the word
+    let quote = quote.filter(|open| text[start..].starts_with(*open));
+    (start, quote)
+}
+
+fn value_candidates(registry: &CommandRegistry, spec: &ArgSpec, prefix: &str) -> Vec<String> {
+    match spec.completion {
+        ArgCompletion::None => Vec::new(),
+        ArgCompletion::Values(values) => values
+            .iter()
+            .filter(|value| value.starts_with(prefix))
+            .map(|value| value.to_string())
+            .collect(),
+        ArgCompletion::Command => registry
+            .commands()
+            .iter()
+            .map(|command| command.name.to_string())
+            .filter(|name| name.starts_with(prefix))
+            .collect(),
+        ArgCompletion::Theme => theme::available().into_iter().filter(|name| name.starts_with(prefix)).collect(),
+        ArgCompletion::Path => path_candidates(prefix, false),
+        ArgCompletion::Folder => path_candidates(prefix, true),
+    }
+}
+
+/// Entries of the folder `prefix` points into whose names start with the
+/// rest of it. Folders end in `/`; hidden entries need a leading `.`.
+fn path_candidates(prefix: &str, folders_only: bool) -> Vec<String> {
+    if prefix == "~" {
+        return vec![String::from("~/")];
+    }
+    let (folder, name) = match prefix.rfind('/') {
+        Some(i) => prefix.split_at(i + 1),
+        None => ("", prefix),
+    };
+    let dir = if folder.is_empty() { PathBuf::from(".") } else { expand_tilde(folder) };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if !file_name.starts_with(name) || (file_name.starts_with('.') && !name.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.path().is_dir();
+            if folders_only && !is_dir {
+                return None;
+            }
+            Some(format!("{}{}{}", folder, file_name, if is_dir { "/" } else { "" }))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::app_state::AppState;
+
+    fn candidates(line: &str) -> Vec<String> {
+        let registry = AppState::for_tests().commands;
+        complete(&registry, line, line.len()).map(|c| c.candidates).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_completes_commands_and_values() {
+        assert_eq!(candidates("/cl"), ["/clear", "/close"]);
+        assert_eq!(candidates("/convert c"), ["comfyui"]);
+        assert_eq!(candidates("/help sc"), ["scan"]);
+        assert!(candidates("/convert a1111 c").is_empty());
+        assert!(candidates("not a command").is_empty());
+
+        let completions = Completions { range: 0..0, candidates: vec!["/clear".into(), "/close".into()], quote: None };
+        assert_eq!(completions.common_prefix(), "/cl");
+        assert_eq!(completions.replacement("my file", true), r"my\ file ");
+        let quoted = Completions { quote: Some('"'), ..completions };
+        assert_eq!(quoted.replacement("my \"file\"", true), r#""my \"file\"" "#);
+        assert_eq!(quoted.replacement("out/", true), "\"out/");
+    }
+
+    #[test]
+    fn test_completes_paths() {
+        let dir = std::env::temp_dir().join(format!("pipemind-completion-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("night shots")).unwrap();
+        fs::create_dir_all(dir.join(".cache")).unwrap();
+        fs::write(dir.join("goblin.png"), b"").unwrap();
+        let base = dir.display().to_string();
+
+        assert_eq!(
+            candidates(&format!("/open {}/", base)),
+            [format!("{}/goblin.png", base), format!("{}/night shots/", base)]
+        );
+        assert_eq!(candidates(&format!("/scan {}/", base)), [format!("{}/night shots/", base)]);
+        assert_eq!(candidates(&format!("/open \"{}/n", base)), [format!("{}/night shots/", base)]);
+        assert_eq!(candidates(&format!("/open {}/.", base)), [format!("{}/.cache/", base)]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
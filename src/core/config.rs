@@ -0,0 +1,640 @@
+// This file holds the user's configuration, read from
+// `$XDG_CONFIG_HOME/pipemind/config.toml` (`~/.config/pipemind` by default).
+//
+// Every option is described once in `OPTIONS`: its section and key, the
+// kind of value it takes, and how it is read from and written to `Config`.
+// Parsing walks the file against that schema, so whatever is missing keeps
+// its default and every mistake is reported with its line number instead of
+// spoiling the rest of the file.
+//
+// The file is checked for changes on every tick and re-applied when it is
+// saved, so edits take effect without a restart. Changes made in the app
+// are written back with `save_changes`, which only touches the lines of the
+// options that changed.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use ratatui::style::Color;
+use toml_edit::{Document, DocumentMut, Item, Value};
+
+use super::paths;
+
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Where the configuration is read from
+pub fn config_path() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join(CONFIG_FILE))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub general: GeneralConfig,
+    pub layout: LayoutConfig,
+    pub navigation: NavigationConfig,
+    pub colors: ColorConfig,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneralConfig {
+    /// How long the main loop waits for a key before redrawing
+    pub poll_interval_ms: u64,
+    pub vi_mode: bool,
+    /// CLIP's merges file; empty for the one in the data directory
+    pub clip_vocabulary: String,
+    /// A built-in theme or one from the themes folder
+    pub theme: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutConfig {
+    pub header_height: u16,
+    pub footer_height: u16,
+    pub navigation_width: u16,
+    pub navigation_side: NavigationSide,
+    pub preview_min_height: u16,
+    /// Most rows the input box grows to in multi-line mode, borders included
+    pub input_max_height: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NavigationSide {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NavigationConfig {
+    /// Ids of top-level entries to show first, in this order
+    pub order: Vec<String>,
+    /// Ids of top-level entries to leave out
+    pub hidden: Vec<String>,
+}
+
+/// Colours that go over the theme's; `None` keeps the theme's own
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ColorConfig {
+    pub border: Option<Color>,
+    pub border_focused: Option<Color>,
+    /// The selected row of lists and menus
+    pub highlight: Option<Color>,
+    /// Prompts and key hints
+    pub accent: Option<Color>,
+    pub error: Option<Color>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            general: GeneralConfig {
+                poll_interval_ms: 100,
+                vi_mode: false,
+                clip_vocabulary: String::new(),
+                theme: String::from("dark"),
+            },
+            layout: LayoutConfig {
+                header_height: 3,
+                footer_height: 3,
+                navigation_width: 20,
+                navigation_side: NavigationSide::Left,
+                preview_min_height: 10,
+                input_max_height: 10,
+            },
+            navigation: NavigationConfig::default(),
+            colors: ColorConfig::default(),
+        }
+    }
+}
+
+/// The kind of value an option takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionKind {
+    Bool,
+    Number { min: i64, max: i64 },
+    /// One of a fixed set of words
+    Enum(&'static [&'static str]),
+    Path,
+    /// Any word, such as a theme's name
+    Text,
+    /// A colour name such as "dark gray", an index or `#rrggbb`; empty for
+    /// the theme's
+    Color,
+    /// A list of words
+    List,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigValue {
+    Bool(bool),
+    Number(i64),
+    /// Enums, paths, words and colours
+    Text(String),
+    List(Vec<String>),
+}
+
+impl ConfigValue {
+    fn as_bool(&self) -> bool {
+        matches!(self, ConfigValue::Bool(true))
+    }
+
+    fn as_number(&self) -> i64 {
+        match self {
+            ConfigValue::Number(number) => *number,
+            _ => 0,
+        }
+    }
+
+    fn as_text(&self) -> &str {
+        match self {
+            ConfigValue::Text(text) => text,
+            _ => "",
+        }
+    }
+
+    fn as_color(&self) -> Option<Color> {
+        Color::from_str(self.as_text()).ok()
+    }
+
+    fn as_list(&self) -> Vec<String> {
+        match self {
+            ConfigValue::List(list) => list.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigValue::Bool(value) => write!(f, "{}", value),
+            ConfigValue::Number(number) => write!(f, "{}", number),
+            ConfigValue::Text(text) => write!(f, "{}", text),
+            ConfigValue::List(list) => write!(f, "{}", list.join(", ")),
+        }
+    }
+}
+
+/// One setting in the file: `key` under `[section]`
+pub struct ConfigOption {
+    pub section: &'static str,
+    pub key: &'static str,
+    pub kind: OptionKind,
+    pub help: &'static str,
+    pub get: fn(&Config) -> ConfigValue,
+    /// Only ever given a value that `OptionKind` accepted
+    pub set: fn(&mut Config, ConfigValue),
+}
+
+impl ConfigOption {
+    /// Checks a value from the file against the option's kind
+    pub fn read(&self, item: &Item) -> Result<ConfigValue, String> {
+        match self.kind {
+            OptionKind::Bool => item.as_bool().map(ConfigValue::Bool).ok_or_else(|| String::from("expected true or false")),
+            OptionKind::Number { min, max } => match item.as_integer() {
+                Some(number) if (min..=max).contains(&number) => Ok(ConfigValue::Number(number)),
+                _ => Err(format!("expected a whole number from {} to {}", min, max)),
+            },
+            OptionKind::Enum(values) => match item.as_str() {
+                Some(value) if values.contains(&value) => Ok(ConfigValue::Text(value.to_string())),
+                _ => Err(format!("expected one of {}", values.join(", "))),
+            },
+            OptionKind::Path => item
+                .as_str()
+                .map(|path| ConfigValue::Text(path.to_string()))
+                .ok_or_else(|| String::from("expected a path in quotes")),
+            OptionKind::Text => match item.as_str() {
+                Some(text) if !text.trim().is_empty() => Ok(ConfigValue::Text(text.to_string())),
+                _ => Err(String::from("expected a word in quotes")),
+            },
+            OptionKind::Color => match item.as_str() {
+                Some(name) if name.is_empty() || Color::from_str(name).is_ok() => Ok(ConfigValue::Text(name.to_string())),
+                _ => Err(String::from("expected a colour name, a number from 0 to 255, \"#rrggbb\" or \"\"")),
+            },
+            OptionKind::List => item
+                .as_array()
+                .and_then(|array| array.iter().map(|value| value.as_str().map(str::to_string)).collect::<Option<Vec<_>>>())
+                .map(ConfigValue::List)
+                .ok_or_else(|| String::from("expected a list of words in quotes")),
+        }
+    }
+
+    /// Checks a value typed without TOML's quotes and brackets: a list is
+    /// words separated by commas
+    pub fn parse_text(&self, text: &str) -> Result<ConfigValue, String> {
+        let text = text.trim();
+        let value = match self.kind {
+            OptionKind::Bool => match text {
+                "true" => Value::from(true),
+                "false" => Value::from(false),
+                _ => return Err(String::from("expected true or false")),
+            },
+            OptionKind::Number { min, max } => match text.parse::<i64>() {
+                Ok(number) => Value::from(number),
+                Err(_) => return Err(format!("expected a whole number from {} to {}", min, max)),
+            },
+            OptionKind::List => text.split(',').map(str::trim).filter(|word| !word.is_empty()).collect(),
+            OptionKind::Enum(_) | OptionKind::Path | OptionKind::Text | OptionKind::Color => Value::from(text),
+        };
+        self.read(&Item::Value(value))
+    }
+}
+
+impl From<&ConfigValue> for Value {
+    fn from(value: &ConfigValue) -> Self {
+        match value {
+            ConfigValue::Bool(value) => Value::from(*value),
+            ConfigValue::Number(number) => Value::from(*number),
+            ConfigValue::Text(text) => Value::from(text.as_str()),
+            ConfigValue::List(list) => list.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+const SIDES: &[&str] = &["left", "right"];
+
+// An unset colour is written as ""
+fn color_value(color: Option<Color>) -> ConfigValue {
+    ConfigValue::Text(color.map(|color| color.to_string()).unwrap_or_default())
+}
+
+pub static OPTIONS: &[ConfigOption] = &[
+    ConfigOption {
+        section: "general",
+        key: "poll_interval_ms",
+        kind: OptionKind::Number { min: 10, max: 1000 },
+        help: "Milliseconds to wait for a key before redrawing",
+        get: |config| ConfigValue::Number(config.general.poll_interval_ms as i64),
+        set: |config, value| config.general.poll_interval_ms = value.as_number() as u64,
+    },
+    ConfigOption {
+        section: "general",
+        key: "vi_mode",
+        kind: OptionKind::Bool,
+        help: "Start the input line in vi mode",
+        get: |config| ConfigValue::Bool(config.general.vi_mode),
+        set: |config, value| config.general.vi_mode = value.as_bool(),
+    },
+    ConfigOption {
+        section: "general",
+        key: "clip_vocabulary",
+        kind: OptionKind::Path,
+        help: "CLIP's bpe_simple_vocab_16e6.txt.gz; empty for the one in the data folder",
+        get: |config| ConfigValue::Text(config.general.clip_vocabulary.clone()),
+        set: |config, value| config.general.clip_vocabulary = value.as_text().to_string(),
+    },
+    ConfigOption {
+        section: "general",
+        key: "theme",
+        kind: OptionKind::Text,
+        help: "dark, light, high-contrast, solarized, or the name of a file in the themes folder",
+        get: |config| ConfigValue::Text(config.general.theme.clone()),
+        set: |config, value| config.general.theme = value.as_text().to_string(),
+    },
+    ConfigOption {
+        section: "layout",
+        key: "header_height",
+        kind: OptionKind::Number { min: 3, max: 10 },
+        help: "Rows of the header, borders included",
+        get: |config| ConfigValue::Number(config.layout.header_height.into()),
+        set: |config, value| config.layout.header_height = value.as_number() as u16,
+    },
+    ConfigOption {
+        section: "layout",
+        key: "footer_height",
+        kind: OptionKind::Number { min: 3, max: 10 },
+        help: "Rows of the footer, borders included",
+        get: |config| ConfigValue::Number(config.layout.footer_height.into()),
+        set: |config, value| config.layout.footer_height = value.as_number() as u16,
+    },
+    ConfigOption {
+        section: "layout",
+        key: "navigation_width",
+        kind: OptionKind::Number { min: 10, max: 80 },
+        help: "Columns of the navigation menu",
+        get: |config| ConfigValue::Number(config.layout.navigation_width.into()),
+        set: |config, value| config.layout.navigation_width = value.as_number() as u16,
+    },
+    ConfigOption {
+        section: "layout",
+        key: "navigation_side",
+        kind: OptionKind::Enum(SIDES),
+        help: "Which side the navigation menu is on",
+        get: |config| {
+            let side = match config.layout.navigation_side {
+                NavigationSide::Left => "left",
+                NavigationSide::Right => "right",
+            };
+            ConfigValue::Text(side.to_string())
+        },
+        set: |config, value| {
+            config.layout.navigation_side = match value.as_text() {
+                "right" => NavigationSide::Right,
+                _ => NavigationSide::Left,
+            }
+        },
+    },
+    ConfigOption {
+        section: "layout",
+        key: "preview_min_height",
+        kind: OptionKind::Number { min: 3, max: 100 },
+        help: "Fewest rows the preview keeps while the input grows",
+        get: |config| ConfigValue::Number(config.layout.preview_min_height.into()),
+        set: |config, value| config.layout.preview_min_height = value.as_number() as u16,
+    },
+    ConfigOption {
+        section: "layout",
+        key: "input_max_height",
+        kind: OptionKind::Number { min: 3, max: 50 },
+        help: "Most rows the input grows to in multi-line mode",
+        get: |config| ConfigValue::Number(config.layout.input_max_height.into()),
+        set: |config, value| config.layout.input_max_height = value.as_number() as u16,
+    },
+    ConfigOption {
+        section: "navigation",
+        key: "order",
+        kind: OptionKind::List,
+        help: "Ids of menu entries to show first, e.g. [\"image_tools\", \"home\"]",
+        get: |config| ConfigValue::List(config.navigation.order.clone()),
+        set: |config, value| config.navigation.order = value.as_list(),
+    },
+    ConfigOption {
+        section: "navigation",
+        key: "hidden",
+        kind: OptionKind::List,
+        help: "Ids of menu entries to leave out",
+        get: |config| ConfigValue::List(config.navigation.hidden.clone()),
+        set: |config, value| config.navigation.hidden = value.as_list(),
+    },
+    ConfigOption {
+        section: "colors",
+        key: "border",
+        kind: OptionKind::Color,
+        help: "Borders of the areas without focus",
+        get: |config| color_value(config.colors.border),
+        set: |config, value| config.colors.border = value.as_color(),
+    },
+    ConfigOption {
+        section: "colors",
+        key: "border_focused",
+        kind: OptionKind::Color,
+        help: "Border of the area with focus",
+        get: |config| color_value(config.colors.border_focused),
+        set: |config, value| config.colors.border_focused = value.as_color(),
+    },
+    ConfigOption {
+        section: "colors",
+        key: "highlight",
+        kind: OptionKind::Color,
+        help: "Background of the selected menu entry",
+        get: |config| color_value(config.colors.highlight),
+        set: |config, value| config.colors.highlight = value.as_color(),
+    },
+    ConfigOption {
+        section: "colors",
+        key: "accent",
+        kind: OptionKind::Color,
+        help: "Input prompts and key hints",
+        get: |config| color_value(config.colors.accent),
+        set: |config, value| config.colors.accent = value.as_color(),
+    },
+    ConfigOption {
+        section: "colors",
+        key: "error",
+        kind: OptionKind::Color,
+        help: "Errors and the quit dialog",
+        get: |config| color_value(config.colors.error),
+        set: |config, value| config.colors.error = value.as_color(),
+    },
+];
+
+/// The option `key` under `[section]`
+pub fn find_option(section: &str, key: &str) -> Option<&'static ConfigOption> {
+    OPTIONS.iter().find(|option| option.section == section && option.key == key)
+}
+
+/// A mistake in the file, by line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Config {
+    /// Reads the file's text over the defaults. Options with mistakes keep
+    /// their default; a file that isn't TOML at all gives only defaults.
+    pub fn parse(text: &str) -> (Self, Vec<ConfigError>) {
+        let mut config = Self::default();
+        let document = match Document::parse(text) {
+            Ok(document) => document,
+            Err(error) => {
+                let line = error.span().map_or(1, |span| line_at(text, span.start));
+                return (config, vec![ConfigError { line, message: error.message().trim().to_string() }]);
+            }
+        };
+
+        let mut errors = Vec::new();
+        let mut report = |span: Option<std::ops::Range<usize>>, message: String| {
+            errors.push(ConfigError { line: span.map_or(1, |span| line_at(text, span.start)), message });
+        };
+        let root = document.as_table();
+        for (section, item) in root.iter() {
+            let key_span = root.key(section).and_then(|key| key.span());
+            let Some(table) = item.as_table_like() else {
+                report(key_span, format!("{} should be a [section]", section));
+                continue;
+            };
+            if !OPTIONS.iter().any(|option| option.section == section) {
+                report(key_span, format!("unknown section [{}]", section));
+                continue;
+            }
+            for (key, value) in table.iter() {
+                let span = table.key(key).and_then(|key| key.span());
+                let Some(option) = find_option(section, key) else {
+                    report(span, format!("unknown option {}.{}", section, key));
+                    continue;
+                };
+                match option.read(value) {
+                    Ok(value) => (option.set)(&mut config, value),
+                    Err(message) => report(value.span().or(span), format!("{}.{}: {}", section, key, message)),
+                }
+            }
+        }
+        (config, errors)
+    }
+
+    /// Reads `path`; a missing file is the defaults without complaint
+    pub fn load(path: &Path) -> (Self, Vec<ConfigError>) {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(_) => (Self::default(), Vec::new()),
+        }
+    }
+}
+
+/// Writes new values for some options into the file at `path`, creating it
+/// if needed. Everything else in the file is kept as it was: comments, the
+/// order of sections and keys, and the comment after a changed value.
+pub fn save_changes(path: &Path, changes: &[(&ConfigOption, ConfigValue)]) -> io::Result<()> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error),
+    };
+    let mut document: DocumentMut = text.parse().map_err(|error: toml_edit::TomlError| {
+        let line = error.span().map_or(1, |span| line_at(&text, span.start));
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("fix line {} by hand first: {}", line, error.message().trim()),
+        )
+    })?;
+
+    for (option, value) in changes {
+        // New sections go at the end as `[section]`, not inline
+        let section = document.entry(option.section).or_insert(toml_edit::table());
+        let Some(table) = section.as_table_like_mut() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("fix [{}] by hand first: it should be a section", option.section),
+            ));
+        };
+        let item = table.entry(option.key).or_insert(Item::None);
+        let mut value = Value::from(value);
+        if let Some(old) = item.as_value() {
+            *value.decor_mut() = old.decor().clone();
+        }
+        *item = Item::Value(value);
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Write then rename, so a crash mid-save leaves the old file intact
+    let temp = path.with_extension("tmp");
+    let result = fs::write(&temp, document.to_string()).and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// The line number of a byte offset, for reporting mistakes
+pub fn line_at(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+/// Notices when the configuration file is saved
+#[derive(Debug, Clone, Default)]
+pub struct ConfigWatcher {
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let modified = path.as_deref().and_then(modified_time);
+        Self { path, modified }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// True once per change to the file, including it appearing or going away
+    pub fn changed(&mut self) -> bool {
+        let modified = self.path.as_deref().and_then(modified_time);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_merges_over_defaults() {
+        let (config, errors) = Config::parse(
+            "# Mine\n[general]\npoll_interval_ms = 50\n\n[layout]\nnavigation_side = \"right\"\n\n[navigation]\nhidden = [\"about\"]\n\n[colors]\nborder = \"dark grey\"\nerror = \"#ff8800\"\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(config.general.poll_interval_ms, 50);
+        assert_eq!(config.layout.navigation_side, NavigationSide::Right);
+        assert_eq!(config.layout.navigation_width, 20);
+        assert_eq!(config.navigation.hidden, ["about"]);
+        assert_eq!(config.colors.border, Some(Color::DarkGray));
+        assert_eq!(config.colors.error, Some(Color::Rgb(0xff, 0x88, 0x00)));
+        assert_eq!((find_option("colors", "error").unwrap().get)(&config).to_string(), "#FF8800");
+    }
+
+    #[test]
+    fn test_errors_have_line_numbers() {
+        let (config, errors) = Config::parse(
+            "[general]\npoll_interval_ms = 5\nvi_mode = true\nspeed = 3\n\n[layout]\nnavigation_side = \"up\"\n\n[colours]\n",
+        );
+        let lines: Vec<usize> = errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, [2, 4, 7, 9]);
+        assert_eq!(errors[0].to_string(), "line 2: general.poll_interval_ms: expected a whole number from 10 to 1000");
+        // The options without mistakes still apply
+        assert!(config.general.vi_mode);
+        assert_eq!(config.general.poll_interval_ms, 100);
+
+        let (config, errors) = Config::parse("[general]\n\nvi_mode = \n");
+        assert_eq!(config, Config::default());
+        assert_eq!(errors.first().map(|error| error.line), Some(3));
+    }
+
+    #[test]
+    fn test_save_changes_keeps_comments_and_order() {
+        let path = std::env::temp_dir().join(format!("pipemind_config_{}.toml", std::process::id()));
+        let original = "# My settings\n[layout]\nnavigation_width = 30 # wide\nheader_height = 4\n\n[general]\n# Snappy\npoll_interval_ms = 50\n";
+        fs::write(&path, original).unwrap();
+
+        let width = find_option("layout", "navigation_width").unwrap();
+        let hidden = find_option("navigation", "hidden").unwrap();
+        let error = find_option("colors", "error").unwrap();
+        let changes = [
+            (width, width.parse_text("24").unwrap()),
+            (hidden, hidden.parse_text("about, help").unwrap()),
+            (error, error.parse_text("#ff8800").unwrap()),
+        ];
+        save_changes(&path, &changes).unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("[navigation]\nhidden = [\"about\", \"help\"]\n"));
+        assert!(saved.starts_with(
+            "# My settings\n[layout]\nnavigation_width = 24 # wide\nheader_height = 4\n\n[general]\n# Snappy\npoll_interval_ms = 50\n"
+        ));
+        let (config, errors) = Config::parse(&saved);
+        assert!(errors.is_empty());
+        assert_eq!(config.layout.navigation_width, 24);
+        assert_eq!(config.navigation.hidden, ["about", "help"]);
+        assert_eq!(config.colors.error, Some(Color::Rgb(0xff, 0x88, 0x00)));
+        assert!(width.parse_text("500").is_err());
+
+        // A section that isn't one is left for the user to fix
+        fs::write(&path, "general = 1\n").unwrap();
+        let vi_mode = find_option("general", "vi_mode").unwrap();
+        let error = save_changes(&path, &[(vi_mode, ConfigValue::Bool(true))]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("fix [general] by hand first"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "general = 1\n");
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,260 @@
+// This file decides what a key press does.
+//
+// The keymap turns keys into actions, looking in the contexts that apply
+// to what is on screen: the modal context while the quit dialog or the
+// configuration errors are up, the input context while the input line has
+// focus and the navigation context otherwise, each after the global one.
+// While the content area has focus, the view's own context and the shared
+// view context come before the navigation one, and the view on screen
+// gets the action first.
+// A feature's overlay takes the keys themselves, so only the global
+// actions reach past it, and a key that merely starts a global chord goes
+// to the overlay rather than being held. Input actions are carried out in
+// `input`.
+
+use crossterm::event::KeyEvent;
+
+use super::{
+    app_state::{AppState, FocusArea},
+    input,
+    keymap::{Action, Context, Step},
+};
+
+/// Handles a key press. Returns true when the app should quit.
+pub fn handle_key(app_state: &mut AppState, key: &KeyEvent) -> bool {
+    let contexts: Vec<Context> = if dialog_open(app_state) {
+        vec![Context::Modal, Context::Global]
+    } else if feature_modal_open(app_state) {
+        vec![Context::Global]
+    } else if app_state.has_focus(FocusArea::Input) {
+        vec![Context::Global, Context::Input]
+    } else if app_state.has_focus(FocusArea::Preview) {
+        let view = app_state.active_feature().and_then(|feature| feature.key_context());
+        [Context::Global].into_iter().chain(view).chain([Context::View, Context::Navigation]).collect()
+    } else {
+        vec![Context::Global, Context::Navigation]
+    };
+    let steps = if !dialog_open(app_state) && feature_modal_open(app_state) {
+        app_state.keymap.feed_through(&contexts, key)
+    } else {
+        app_state.keymap.feed(&contexts, key)
+    };
+    steps.into_iter().any(|step| run_step(app_state, step))
+}
+
+fn dialog_open(app_state: &AppState) -> bool {
+    app_state.show_quit_modal || app_state.has_config_errors()
+}
+
+fn feature_modal_open(app_state: &AppState) -> bool {
+    app_state.features.iter().any(|feature| feature.is_modal())
+}
+
+fn run_step(app_state: &mut AppState, step: Step) -> bool {
+    let (action, key) = match &step {
+        Step::Action(action, key) => (Some(*action), *key),
+        Step::Key(key) => (None, Some(*key)),
+    };
+
+    if dialog_open(app_state) {
+        match action {
+            Some(Action::Quit) => app_state.show_quit_modal = true,
+            Some(Action::Confirm) if app_state.show_quit_modal => return true,
+            Some(Action::Cancel) if app_state.show_quit_modal => app_state.show_quit_modal = false,
+            // The configuration errors stay up until dismissed
+            Some(Action::Confirm | Action::Cancel) => {
+                app_state.config_errors.clear();
+                app_state.key_errors.clear();
+            }
+            _ => {}
+        }
+        return false;
+    }
+
+    // Overlays such as the file browser take every other key until they close
+    if feature_modal_open(app_state) {
+        match (action, key) {
+            (Some(Action::Quit), _) => app_state.show_quit_modal = true,
+            (_, Some(key)) => {
+                app_state.handle_modal_key(&key);
+            }
+            _ => {}
+        }
+        return false;
+    }
+
+    match action {
+        Some(Action::Quit) => app_state.show_quit_modal = true,
+        Some(Action::FocusHeader) => app_state.set_focus(FocusArea::Header),
+        Some(Action::FocusNavigation) => app_state.set_focus(FocusArea::Navigation),
+        Some(Action::FocusPreview) => app_state.set_focus(FocusArea::Preview),
+        Some(Action::FocusInput) => app_state.set_focus(FocusArea::Input),
+        Some(Action::FocusFooter) => app_state.set_focus(FocusArea::Footer),
+        _ if app_state.has_focus(FocusArea::Input) => input::handle_step(app_state, step),
+        _ => navigate(app_state, action),
+    }
+    false
+}
+
+fn navigate(app_state: &mut AppState, action: Option<Action>) {
+    // Let the view on screen take its actions (e.g. moving in the node tree) first
+    if app_state.has_focus(FocusArea::Preview)
+        && let Some(action) = action
+        && app_state.active_feature_mut().is_some_and(|feature| feature.handle_action(action))
+    {
+        return;
+    }
+
+    let on_navigation = app_state.has_focus(FocusArea::Navigation);
+    match action {
+        Some(Action::FocusNext) => app_state.set_focus(next_area(app_state.focus, true)),
+        Some(Action::FocusPrevious) => app_state.set_focus(next_area(app_state.focus, false)),
+        Some(Action::NavDown) if on_navigation => {
+            let count = app_state.get_current_navigation_count();
+            let index = app_state.get_current_selection_index();
+            if index + 1 < count {
+                app_state.select_navigation_item(index + 1);
+            }
+        }
+        Some(Action::NavUp) if on_navigation => {
+            let index = app_state.get_current_selection_index();
+            if index > 0 {
+                app_state.select_navigation_item(index - 1);
+            }
+        }
+        // Outside the menu, down and up move the focus along
+        Some(Action::NavDown) => app_state.set_focus(next_area(app_state.focus, true)),
+        Some(Action::NavUp) => app_state.set_focus(next_area(app_state.focus, false)),
+        Some(Action::Activate) if on_navigation => {
+            if let Some(message) = app_state.activate_navigation_item() {
+                app_state.log_output(message);
+            }
+        }
+        Some(Action::Back) if on_navigation => app_state.exit_submenu(),
+        _ => {}
+    }
+}
+
+/// The area after (or before) `area` when cycling the focus. The input line
+/// is left out; it is reached with its own key.
+fn next_area(area: FocusArea, forward: bool) -> FocusArea {
+    const CYCLE: [FocusArea; 4] = [FocusArea::Header, FocusArea::Navigation, FocusArea::Preview, FocusArea::Footer];
+    let Some(index) = CYCLE.iter().position(|&cycled| cycled == area) else {
+        return area;
+    };
+    let step = if forward { 1 } else { CYCLE.len() - 1 };
+    CYCLE[(index + step) % CYCLE.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    use ratatui::{layout::Rect, Frame};
+
+    use super::*;
+    use crate::{
+        core::{config::Config, keymap::Keymap, navigation::NavigationItem},
+        features::{settings::SettingsState, Feature, FeatureRegistry},
+    };
+
+    // An overlay that is always open and keeps the keys it gets
+    struct Overlay {
+        keys: Vec<KeyEvent>,
+    }
+
+    impl Feature for Overlay {
+        fn id(&self) -> &str {
+            "overlay"
+        }
+
+        fn navigation_item(&self) -> NavigationItem {
+            NavigationItem::new("overlay", "Overlay")
+        }
+
+        fn render(&self, _f: &mut Frame, _area: Rect, _app_state: &AppState) {}
+
+        fn is_modal(&self) -> bool {
+            true
+        }
+
+        fn handle_modal_key(&mut self, key: &KeyEvent) -> Option<String> {
+            self.keys.push(*key);
+            None
+        }
+    }
+
+    fn press(app_state: &mut AppState, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        handle_key(app_state, &KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn test_quit_asks_first() {
+        let mut app_state = AppState::for_tests();
+        assert!(!press(&mut app_state, KeyCode::Char('q'), KeyModifiers::CONTROL));
+        assert!(app_state.show_quit_modal);
+        // j is a modal key now, not a navigation one
+        assert!(!press(&mut app_state, KeyCode::Char('j'), KeyModifiers::NONE));
+        assert_eq!(app_state.get_current_selection_index(), 0);
+        assert!(!press(&mut app_state, KeyCode::Char('n'), KeyModifiers::NONE));
+        assert!(!app_state.show_quit_modal);
+        press(&mut app_state, KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert!(press(&mut app_state, KeyCode::Char('y'), KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn test_remapped_keys_and_chords() {
+        let mut app_state = AppState::for_tests();
+        app_state.keymap = Keymap::parse("[global]\nquit = \"ctrl-x ctrl-c\"\n\n[navigation]\nnav_down = \"n\"\n").0;
+
+        press(&mut app_state, KeyCode::Char('n'), KeyModifiers::NONE);
+        assert_eq!(app_state.get_current_selection_index(), 1);
+        press(&mut app_state, KeyCode::Char('j'), KeyModifiers::NONE);
+        assert_eq!(app_state.get_current_selection_index(), 1);
+        press(&mut app_state, KeyCode::Char('l'), KeyModifiers::NONE);
+        assert_eq!(app_state.focus, FocusArea::Preview);
+
+        press(&mut app_state, KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert!(!app_state.show_quit_modal);
+        press(&mut app_state, KeyCode::Char('x'), KeyModifiers::CONTROL);
+        assert_eq!(app_state.keymap.pending().map(|chord| chord.to_string()).as_deref(), Some("ctrl-x"));
+        press(&mut app_state, KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert!(app_state.show_quit_modal);
+        assert!(app_state.keymap.pending().is_none());
+    }
+
+    #[test]
+    fn test_overlays_get_keys_that_start_global_chords() {
+        let mut features = FeatureRegistry::default();
+        features.register(Overlay { keys: Vec::new() });
+        let mut app_state = AppState::with(features, Config::default());
+        app_state.keymap = Keymap::parse("[global]\nquit = [\"ctrl-q\", \"g q\"]\n").0;
+
+        press(&mut app_state, KeyCode::Char('g'), KeyModifiers::NONE);
+        assert!(app_state.keymap.pending().is_none());
+        press(&mut app_state, KeyCode::Char('q'), KeyModifiers::NONE);
+        let typed: Vec<KeyCode> = app_state.features.get::<Overlay>().unwrap().keys.iter().map(|key| key.code).collect();
+        assert_eq!(typed, [KeyCode::Char('g'), KeyCode::Char('q')]);
+        assert!(!app_state.show_quit_modal);
+
+        press(&mut app_state, KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert!(app_state.show_quit_modal);
+    }
+
+    #[test]
+    fn test_views_take_their_keys_from_the_keymap() {
+        let mut app_state = AppState::for_tests();
+        app_state.keymap = Keymap::parse("[view]\nstep_forward = \"+\"\n").0;
+        assert!(app_state.navigation.reveal("settings"));
+        app_state.set_focus(FocusArea::Preview);
+        let changed = |app_state: &AppState| app_state.features.get::<SettingsState>().unwrap().has_changes();
+
+        press(&mut app_state, KeyCode::Right, KeyModifiers::NONE);
+        assert!(!changed(&app_state));
+        press(&mut app_state, KeyCode::Char('+'), KeyModifiers::NONE);
+        assert!(changed(&app_state));
+        // d, the settings screen's own key, puts it back
+        press(&mut app_state, KeyCode::Char('d'), KeyModifiers::NONE);
+        assert!(!changed(&app_state));
+    }
+}
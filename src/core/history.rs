@@ -0,0 +1,205 @@
+// This file keeps the lines entered in the input box so they can be
+// recalled with Up/Down or found again with Ctrl-R.
+//
+// The history is saved to a plain file, one entry per line, oldest first.
+// Entering a line again moves it to the end instead of storing it twice,
+// and only the newest MAX_HISTORY entries are kept.
+
+use std::{fs, path::PathBuf};
+
+/// How many entries are remembered
+pub const MAX_HISTORY: usize = 1000;
+
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    entries: Vec<String>,
+    // Index of the entry shown while walking with Up/Down
+    position: Option<usize>,
+    // The line that was being typed before walking started
+    draft: String,
+    // Where entries are persisted; None keeps them in memory only
+    file: Option<PathBuf>,
+}
+
+impl History {
+    /// Loads the history saved in `file`, if there is one
+    pub fn new(file: Option<PathBuf>) -> Self {
+        let entries = file
+            .as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .map(|contents| contents.lines().map(unescape).collect())
+            .unwrap_or_default();
+        Self { entries, file, ..Self::default() }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    /// Records an entered line and saves the history
+    pub fn push(&mut self, line: &str) {
+        self.reset_navigation();
+        if line.trim().is_empty() {
+            return;
+        }
+        self.entries.retain(|entry| entry != line);
+        self.entries.push(line.to_string());
+        let excess = self.entries.len().saturating_sub(MAX_HISTORY);
+        self.entries.drain(..excess);
+
+        if let Some(file) = &self.file {
+            let contents: Vec<String> = self.entries.iter().map(|entry| escape(entry)).collect();
+            // Losing the history is not worth interrupting the user over
+            if let Some(parent) = file.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            let _ = fs::write(file, contents.join("\n") + "\n");
+        }
+    }
+
+    /// Steps to the previous entry. `current` is kept as the draft when
+    /// walking starts.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let position = match self.position {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len() - 1
+            }
+            Some(position) => position.saturating_sub(1),
+        };
+        self.position = Some(position);
+        self.get(position)
+    }
+
+    /// Steps to the next entry, back to the draft after the newest
+    pub fn newer(&mut self) -> Option<&str> {
+        let position = self.position? + 1;
+        if position < self.entries.len() {
+            self.position = Some(position);
+            self.get(position)
+        } else {
+            self.position = None;
+            Some(&self.draft)
+        }
+    }
+
+    /// Forgets the Up/Down walk, e.g. after the line was edited
+    pub fn reset_navigation(&mut self) {
+        self.position = None;
+        self.draft.clear();
+    }
+
+    /// The newest entry before `before` that contains `query`
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+}
+
+/// State of a Ctrl-R reverse search
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistorySearch {
+    pub query: String,
+    /// Index of the entry that matches, if any does
+    pub found: Option<usize>,
+    /// The input line before the search, restored if it is cancelled
+    pub original: String,
+}
+
+impl HistorySearch {
+    pub fn new(original: String) -> Self {
+        Self { original, ..Self::default() }
+    }
+
+    /// Finds the newest match for the query, starting at the current one
+    /// so typing more narrows down instead of skipping ahead
+    pub fn update(&mut self, history: &History) {
+        let before = self.found.map_or(history.entries().len(), |found| found + 1);
+        self.found = history.search(&self.query, before);
+    }
+
+    /// Moves to the next older match, staying put if there is none
+    pub fn older(&mut self, history: &History) {
+        let before = self.found.unwrap_or(history.entries().len());
+        if let Some(found) = history.search(&self.query, before) {
+            self.found = Some(found);
+        }
+    }
+}
+
+// Entries are stored one per line, so newlines and backslashes are escaped
+fn escape(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut entry = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                chars.next();
+                entry.push('\n');
+            }
+            ('\\', Some('\\')) => {
+                chars.next();
+                entry.push('\\');
+            }
+            _ => entry.push(c),
+        }
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walk_keeps_draft_and_persists() {
+        let file = std::env::temp_dir().join(format!("pipemind-history-{}", std::process::id()));
+        let _ = fs::remove_file(&file);
+        let mut history = History::new(Some(file.clone()));
+        for line in ["/open a.png", "/pin", "two\nlines \\n", "/open a.png", " "] {
+            history.push(line);
+        }
+        assert_eq!(history.entries(), ["/pin", "two\nlines \\n", "/open a.png"]);
+
+        assert_eq!(history.older("/sc"), Some("/open a.png"));
+        assert_eq!(history.older("ignored"), Some("two\nlines \\n"));
+        assert_eq!(history.older(""), Some("/pin"));
+        assert_eq!(history.older(""), Some("/pin"));
+        assert_eq!(history.newer(), Some("two\nlines \\n"));
+        assert_eq!(history.newer(), Some("/open a.png"));
+        assert_eq!(history.newer(), Some("/sc"));
+        assert_eq!(history.newer(), None);
+
+        assert_eq!(History::new(Some(file.clone())).entries(), history.entries());
+        let _ = fs::remove_file(&file);
+    }
+
+    #[test]
+    fn test_reverse_search() {
+        let mut history = History::default();
+        for line in ["/open goblin.png", "/pin", "/open troll.png"] {
+            history.push(line);
+        }
+        let mut search = HistorySearch::new(String::new());
+        search.query.push_str("open");
+        search.update(&history);
+        assert_eq!(search.found, Some(2));
+        search.older(&history);
+        assert_eq!(search.found, Some(0));
+        search.older(&history);
+        assert_eq!(search.found, Some(0));
+        search.query.push_str(" t");
+        search.update(&history);
+        assert_eq!(search.found, None);
+    }
+}
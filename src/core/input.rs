@@ -1,114 +1,374 @@
 
+// This file runs the input line's actions and turns the keys no action
+// claims into typing.
+//
+// The keymap resolves a key first, but the history search and vi mode get
+// a single key before the action it is bound to, as they read keys their
+// own way.
+
 use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
-use super::app_state::AppState;
-
-pub fn handle_key_event(app_state: &mut AppState, key: &KeyEvent) {
-    match (key.code, key.modifiers) {
-        // Control key combinations
-        (KeyCode::Char('a'), m) if m.contains(KeyModifiers::CONTROL) => {
-            // Move cursor to start of line (like in terminal)
-            app_state.cursor_position = 0;
-        }
-        (KeyCode::Char('e'), m) if m.contains(KeyModifiers::CONTROL) => {
-            // Move cursor to end of line (like in terminal)
-            app_state.cursor_position = app_state.input_buffer.len();
-        }
-        (KeyCode::Char('u'), m) if m.contains(KeyModifiers::CONTROL) => {
-            // Clear from cursor to start (like in terminal)
-            let remaining = app_state.input_buffer[app_state.cursor_position..].to_string();
-            app_state.input_buffer = remaining;
-            app_state.cursor_position = 0;
-            update_preview_and_mode(app_state);
-        }
-        (KeyCode::Char('k'), m) if m.contains(KeyModifiers::CONTROL) => {
-            // Clear from cursor to end (like in terminal)
-            app_state.input_buffer.truncate(app_state.cursor_position);
-            update_preview_and_mode(app_state);
-        }
-        // Regular character input (no modifiers)
-        (KeyCode::Char(c), m) if m.is_empty() => {
-            // If it's empty and we receive '/', mark as command mode
-            if app_state.input_buffer.is_empty() && c == '/' {
-                app_state.is_command_mode = true;
-            }
+use super::{
+    app_state::AppState,
+    command,
+    completion::{self, CompletionPopup},
+    history::HistorySearch,
+    keymap::{Action, Step},
+    text,
+    vi::ViOutcome,
+};
+
+/// Runs an input action, or types an unbound key
+pub fn handle_step(app_state: &mut AppState, step: Step) {
+    let (action, key) = match step {
+        Step::Action(action, key) => (Some(action), key),
+        Step::Key(key) => (None, Some(key)),
+    };
+    // Anything but completing puts the completion popup away
+    if !matches!(action, Some(Action::Complete | Action::CompletePrevious)) {
+        app_state.completion = None;
+    }
 
-            // Insert character at cursor position
-            app_state.input_buffer.insert(app_state.cursor_position, c);
-            app_state.cursor_position += 1;
-            update_preview(app_state);
-        }
-        (KeyCode::Backspace, _) => {
-            // Remove character before cursor
-            if app_state.cursor_position > 0 {
-                app_state.cursor_position -= 1;
-                app_state.input_buffer.remove(app_state.cursor_position);
-                update_preview_and_mode(app_state);
+    if let Some(key) = &key {
+        // A reverse search takes the keys until it is accepted or cancelled
+        if app_state.history_search.is_some() && !handle_search_key(app_state, key) {
+            return;
+        }
+
+        // Vi mode claims the keys it knows and lets the rest through
+        if app_state.vi_mode {
+            let before = app_state.input.text().to_string();
+            if app_state.vi.handle_key(&mut app_state.input, key) == ViOutcome::Handled {
+                if app_state.input.text() != before {
+                    update_preview_and_mode(app_state);
+                }
+                return;
             }
         }
-        (KeyCode::Delete, _) => {
-            // Remove character at cursor
-            if app_state.cursor_position < app_state.input_buffer.len() {
-                app_state.input_buffer.remove(app_state.cursor_position);
-                update_preview_and_mode(app_state);
+    }
+
+    let before = app_state.input.text().to_string();
+    match (action, key) {
+        (Some(action), _) => run_action(app_state, action),
+        // Regular character input (Shift is part of typing capitals and symbols)
+        (None, Some(KeyEvent { code: KeyCode::Char(c), modifiers, .. }))
+            if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+        {
+            app_state.input.insert_char(c);
+        }
+        _ => {}
+    }
+
+    // Edits land here as well as motions; only edits refresh the preview.
+    // A recalled history entry is not an edit, so Up keeps walking from it.
+    let recalled = matches!(action, Some(Action::RowUp | Action::RowDown));
+    if app_state.input.text() != before && !recalled {
+        update_preview_and_mode(app_state);
+    }
+}
+
+fn run_action(app_state: &mut AppState, action: Action) {
+    let input = &mut app_state.input;
+    match action {
+        Action::HistorySearch => app_state.history_search = Some(HistorySearch::new(input.text().to_string())),
+        Action::Submit => submit(app_state),
+        Action::ToggleMultiline => app_state.multiline = !app_state.multiline,
+        Action::Undo => input.undo(),
+        Action::Redo => input.redo(),
+
+        // The line editing shortcuts work on the line the cursor is on
+        Action::LineStart => input.move_line_start(),
+        Action::LineEnd => input.move_line_end(),
+        Action::KillLineStart => input.kill_line_start(),
+        Action::KillLineEnd => input.kill_line_end(),
+        Action::KillWordBack => input.kill_word_back(),
+        Action::KillWordForward => input.kill_word_forward(),
+        Action::Yank => input.yank(),
+        Action::YankPop => input.yank_pop(),
+        Action::Transpose => input.transpose(),
+        Action::WordLeft => input.move_word_left(),
+        Action::WordRight => input.move_word_right(),
+        Action::CharLeft => input.move_left(),
+        Action::CharRight => input.move_right(),
+        Action::DeleteBack => input.backspace(),
+        Action::DeleteForward => input.delete(),
+
+        // Up and Down move between rows of a multi-line input, and reach the
+        // history from its first and last rows
+        Action::RowUp => {
+            if app_state.multiline && move_vertically(app_state, false) {
+                return;
+            }
+            let current = app_state.input.text().to_string();
+            if let Some(entry) = app_state.history.older(&current).map(str::to_string) {
+                set_input(app_state, entry);
             }
         }
-        (KeyCode::Left, _) => {
-            // Move cursor left
-            if app_state.cursor_position > 0 {
-                app_state.cursor_position -= 1;
+        Action::RowDown => {
+            if app_state.multiline && move_vertically(app_state, true) {
+                return;
             }
+            if let Some(entry) = app_state.history.newer().map(str::to_string) {
+                set_input(app_state, entry);
+            }
+        }
+        Action::Complete => complete(app_state, true),
+        Action::CompletePrevious => complete(app_state, false),
+        // A new line switches to multi-line mode
+        Action::Newline => {
+            app_state.multiline = true;
+            input.insert_char('\n');
         }
-        (KeyCode::Right, _) => {
-            // Move cursor right
-            if app_state.cursor_position < app_state.input_buffer.len() {
-                app_state.cursor_position += 1;
+        Action::SubmitOrNewline if app_state.multiline => input.insert_char('\n'),
+        Action::SubmitOrNewline => submit(app_state),
+        _ => {}
+    }
+}
+
+/// Handles command execution or input submission
+fn submit(app_state: &mut AppState) {
+    // Log the input first, so a /clear also clears its own line
+    let line = app_state.input.text().to_string();
+    app_state.log_output(line.clone());
+    app_state.history.push(&line);
+    if app_state.is_command_mode {
+        match command::execute(app_state, &line) {
+            Ok(preview_content) => app_state.update_preview(preview_content),
+            Err(error) => {
+                // Keep the line so the highlighted mistake can be fixed
+                app_state.update_preview(error.to_string());
+                app_state.input_error = Some(error.span);
+                return;
             }
         }
-        (KeyCode::Home, _) => {
-            // Move cursor to start
-            app_state.cursor_position = 0;
+    } else if let Some(feature) = app_state.active_feature_mut() {
+        feature.submit_input(&line);
+    }
+    // Reset input state
+    app_state.reset_input();
+}
+
+/// Moves the cursor one soft-wrapped row up or down, keeping its column.
+/// Returns false if it is already on the first or last row.
+fn move_vertically(app_state: &mut AppState, down: bool) -> bool {
+    let buffer = app_state.input.text();
+    let rows = text::wrap_rows(buffer, app_state.input_width as usize);
+    let (row, column) = text::locate(buffer, &rows, app_state.input.cursor());
+    let target = match down {
+        true if row + 1 < rows.len() => row + 1,
+        false if row > 0 => row - 1,
+        _ => return false,
+    };
+    let offset = text::offset_at_column(buffer, &rows[target], column);
+    app_state.input.set_cursor(offset);
+    true
+}
+
+/// Handles a key during a Ctrl-R search. Returns true if the key should
+/// then be handled as usual, which Enter is, to run the found line.
+fn handle_search_key(app_state: &mut AppState, key: &KeyEvent) -> bool {
+    let Some(search) = &mut app_state.history_search else {
+        return true;
+    };
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let cancel = key.code == KeyCode::Esc || (ctrl && key.code == KeyCode::Char('g'));
+    match key.code {
+        // Cancelling puts back the line from before the search
+        _ if cancel => {
+            let original = std::mem::take(&mut search.original);
+            app_state.history_search = None;
+            set_input(app_state, original);
+            return false;
+        }
+        KeyCode::Char('r') if ctrl => search.older(&app_state.history),
+        KeyCode::Char(c) if !ctrl => {
+            search.query.push(c);
+            search.update(&app_state.history);
         }
-        (KeyCode::End, _) => {
-            // Move cursor to end
-            app_state.cursor_position = app_state.input_buffer.len();
+        KeyCode::Backspace => {
+            search.query.pop();
+            search.found = None;
+            search.update(&app_state.history);
         }
-        (KeyCode::Enter, _) => {
-            // Handle command execution or input submission
-            if app_state.is_command_mode {
-                let preview_content = handle_command(&app_state.input_buffer);
-                app_state.update_preview(preview_content);
-            }
-            // Log the input before clearing
-            app_state.log_output(app_state.input_buffer.clone());
-            // Reset input state
-            app_state.reset_input();
+        // Anything else keeps the found line and leaves the search
+        code => {
+            app_state.history_search = None;
+            return code == KeyCode::Enter;
         }
-        _ => {}
     }
+
+    if let Some(entry) = search.found.and_then(|found| app_state.history.get(found)) {
+        let entry = entry.to_string();
+        set_input(app_state, entry);
+    }
+    false
+}
+
+/// Replaces the whole line, e.g. with a recalled history entry
+fn set_input(app_state: &mut AppState, text: String) {
+    app_state.is_command_mode = text.starts_with('/');
+    // An entry spanning several lines can only be edited in multi-line mode
+    app_state.multiline |= text.contains('\n');
+    app_state.input.set_text(text);
+    update_preview(app_state);
+}
+
+/// Completes the word before the cursor. A unique candidate is inserted
+/// whole; several insert their common prefix and open a popup that further
+/// presses of Tab (or Shift-Tab) walk through.
+fn complete(app_state: &mut AppState, forward: bool) {
+    if let Some(popup) = &mut app_state.completion {
+        let candidate = popup.cycle(forward).to_string();
+        let text = popup.completions.replacement(&candidate, false);
+        let range = popup.completions.range.clone();
+        popup.completions.range.end = range.start + text.len();
+        replace_input(app_state, range, &text);
+        return;
+    }
+
+    let Some(completions) = completion::complete(&app_state.commands, app_state.input.text(), app_state.input.cursor()) else {
+        return;
+    };
+    let range = completions.range.clone();
+    match completions.candidates.as_slice() {
+        [] => {}
+        [candidate] => {
+            let text = completions.replacement(candidate, true);
+            replace_input(app_state, range, &text);
+        }
+        _ => {
+            let text = completions.replacement(&completions.common_prefix(), false);
+            let mut popup = CompletionPopup::new(completions);
+            popup.completions.range.end = range.start + text.len();
+            replace_input(app_state, range, &text);
+            app_state.completion = Some(popup);
+        }
+    }
+}
+
+fn replace_input(app_state: &mut AppState, range: std::ops::Range<usize>, text: &str) {
+    app_state.input.replace_range(range, text);
+    update_preview(app_state);
 }
 
 fn update_preview(app_state: &mut AppState) {
+    // Any edit makes the last error stale
+    app_state.input_error = None;
+    // The feature on screen sees plain input as it is typed
+    if !app_state.is_command_mode {
+        let text = app_state.input.text().to_string();
+        if let Some(feature) = app_state.active_feature_mut() {
+            feature.input_changed(&text);
+        }
+    }
     let content = if app_state.is_command_mode {
-        format!("Command: {}", app_state.input_buffer)
+        format!("Command: {}", app_state.input.text())
     } else {
-        format!("Echo: {}", app_state.input_buffer)
+        format!("Echo: {}", app_state.input.text())
     };
     app_state.update_preview(content);
 }
 
 fn update_preview_and_mode(app_state: &mut AppState) {
-    if app_state.input_buffer.is_empty() {
-        app_state.is_command_mode = false;
-    }
+    // A line starting with '/' is a command
+    app_state.is_command_mode = app_state.input.text().starts_with('/');
+    // Up walks the history afresh from an edited line
+    app_state.history.reset_navigation();
     update_preview(app_state);
 }
 
-fn handle_command(command: &str) -> String {
-    // Strip the leading '/' and handle commands
-    let cmd = command.strip_prefix('/').unwrap_or(command);
-    match cmd {
-        "help" => String::from("Available commands: /help, /clear"),
-        "clear" => String::from(""),
-        _ => format!("Unknown command: {}", cmd),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{app_state::FocusArea, dispatch};
+
+    // The keys go through the dispatcher, as typed keys do
+    fn input_app() -> AppState {
+        let mut app_state = AppState::for_tests();
+        app_state.set_focus(FocusArea::Input);
+        app_state
     }
-}
\ No newline at end of file
+
+    fn type_text(app_state: &mut AppState, text: &str) {
+        for c in text.chars() {
+            dispatch::handle_key(app_state, &KeyEvent::from(KeyCode::Char(c)));
+        }
+    }
+
+    fn press(app_state: &mut AppState, code: KeyCode) {
+        dispatch::handle_key(app_state, &KeyEvent::from(code));
+    }
+
+    #[test]
+    fn test_editing_combining_marks() {
+        let mut app_state = input_app();
+        // "café" with the accent as a combining mark, then a word after it
+        type_text(&mut app_state, "cafe\u{301} noir");
+        for _ in 0..5 {
+            press(&mut app_state, KeyCode::Left);
+        }
+        assert_eq!(app_state.input.cursor(), "cafe\u{301}".len());
+        press(&mut app_state, KeyCode::Backspace);
+        assert_eq!(app_state.input.text(), "caf noir");
+        type_text(&mut app_state, "é");
+        press(&mut app_state, KeyCode::Left);
+        press(&mut app_state, KeyCode::Delete);
+        assert_eq!(app_state.input.text(), "caf noir");
+    }
+
+    #[test]
+    fn test_multiline_editing() {
+        let mut app_state = input_app();
+        app_state.input_width = 10;
+        type_text(&mut app_state, "a goblin");
+        dispatch::handle_key(&mut app_state, &KeyEvent::new(KeyCode::Enter, KeyModifiers::SHIFT));
+        assert!(app_state.multiline);
+        type_text(&mut app_state, "eating a pie");
+        press(&mut app_state, KeyCode::Enter);
+        type_text(&mut app_state, "cave");
+        assert_eq!(app_state.input.text(), "a goblin\neating a pie\ncave");
+
+        // "eating a pie" wraps after "eating a ", so Up visits both rows
+        press(&mut app_state, KeyCode::Up);
+        assert_eq!(app_state.input.cursor(), "a goblin\neating a pie".len());
+        press(&mut app_state, KeyCode::Up);
+        press(&mut app_state, KeyCode::Up);
+        assert_eq!(app_state.input.cursor(), 3);
+        dispatch::handle_key(&mut app_state, &KeyEvent::new(KeyCode::Char('k'), KeyModifiers::CONTROL));
+        assert_eq!(app_state.input.text(), "a g\neating a pie\ncave");
+
+        dispatch::handle_key(&mut app_state, &KeyEvent::new(KeyCode::Enter, KeyModifiers::CONTROL));
+        assert!(app_state.input.text().is_empty() && !app_state.multiline);
+        assert_eq!(app_state.history.entries(), ["a g\neating a pie\ncave"]);
+    }
+
+    #[test]
+    fn test_editing_a_recalled_entry_restarts_the_walk() {
+        let mut app_state = input_app();
+        for line in ["first", "second"] {
+            type_text(&mut app_state, line);
+            press(&mut app_state, KeyCode::Enter);
+        }
+        press(&mut app_state, KeyCode::Up);
+        press(&mut app_state, KeyCode::Up);
+        assert_eq!(app_state.input.text(), "first");
+        type_text(&mut app_state, "!");
+        press(&mut app_state, KeyCode::Up);
+        assert_eq!(app_state.input.text(), "second");
+        press(&mut app_state, KeyCode::Down);
+        assert_eq!(app_state.input.text(), "first!");
+    }
+
+    #[test]
+    fn test_editing_wide_characters() {
+        let mut app_state = input_app();
+        type_text(&mut app_state, "猫の絵🐈");
+        press(&mut app_state, KeyCode::Left);
+        press(&mut app_state, KeyCode::Left);
+        assert_eq!(app_state.input.cursor(), "猫の".len());
+        dispatch::handle_key(&mut app_state, &KeyEvent::new(KeyCode::Char('k'), KeyModifiers::CONTROL));
+        assert_eq!(app_state.input.text(), "猫の");
+        press(&mut app_state, KeyCode::Left);
+        dispatch::handle_key(&mut app_state, &KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL));
+        assert_eq!((app_state.input.text(), app_state.input.cursor()), ("の", 0));
+    }
+}
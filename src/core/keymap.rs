@@ -0,0 +1,702 @@
+// This file maps keys to named actions.
+//
+// Every action belongs to a context: `global` works everywhere but in the
+// dialogs, `navigation` while the input line doesn't have focus, `input`
+// while it does, and `modal` in the quit and configuration error dialogs.
+// While the content area has focus, the view on screen comes before the
+// menu: first its own context, `library` or `settings`, then `view`, the
+// keys the views share.
+// `ACTIONS` lists them all with their default keys, and the dispatcher
+// runs whatever the keymap resolves a key press to.
+//
+// A binding is a key such as "ctrl-q", "shift-enter" or "f2", or a chord of
+// keys pressed one after the other, such as "ctrl-x ctrl-c" or "g g". The
+// user's `keys.toml` in the config folder replaces an action's keys:
+//
+//     [global]
+//     quit = ["ctrl-q", "ctrl-x ctrl-c"]
+//
+//     [navigation]
+//     nav_down = ["j", "down", "ctrl-n"]
+//     back = []                 # unbound
+//
+// Within contexts looked up together, two actions can't share keys and a
+// key can't also start a chord, which could then never be typed; both are
+// reported as mistakes. Global keys are looked up alongside every other
+// context, and one that starts with a plain character is reported for
+// taking that character away from the input line.
+//
+// Vi mode, the history search and the features' overlays, such as the file
+// browser, read their keys directly, as they are small languages of their
+// own.
+
+use std::{fmt, fs, path::{Path, PathBuf}, str::FromStr};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use toml_edit::{Document, Item};
+
+use super::{
+    config::{line_at, ConfigError},
+    paths,
+};
+
+pub const KEYS_FILE: &str = "keys.toml";
+
+/// Where the user's key bindings are read from
+pub fn keys_path() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join(KEYS_FILE))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    Global,
+    Navigation,
+    Input,
+    Modal,
+    View,
+    Library,
+    Settings,
+}
+
+impl Context {
+    pub const ALL: [Context; 7] = [
+        Context::Global,
+        Context::Navigation,
+        Context::Input,
+        Context::Modal,
+        Context::View,
+        Context::Library,
+        Context::Settings,
+    ];
+
+    /// The section of `keys.toml` its bindings go under
+    pub fn name(self) -> &'static str {
+        match self {
+            Context::Global => "global",
+            Context::Navigation => "navigation",
+            Context::Input => "input",
+            Context::Modal => "modal",
+            Context::View => "view",
+            Context::Library => "library",
+            Context::Settings => "settings",
+        }
+    }
+
+    /// Whether a key press can be looked up in both contexts, so their
+    /// bindings can get in each other's way
+    pub fn overlaps(self, other: Context) -> bool {
+        // The menu's keys are looked up after a view's in the content area
+        let shared = |context: Context| matches!(context, Context::View | Context::Navigation);
+        let own = |context: Context| matches!(context, Context::Library | Context::Settings);
+        self == other
+            || self == Context::Global
+            || other == Context::Global
+            || (shared(self) || own(self)) && (shared(other) || own(other)) && !(own(self) && own(other))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    FocusHeader,
+    FocusNavigation,
+    FocusPreview,
+    FocusInput,
+    FocusFooter,
+    FocusNext,
+    FocusPrevious,
+    NavDown,
+    NavUp,
+    Activate,
+    Back,
+    Submit,
+    SubmitOrNewline,
+    Newline,
+    ToggleMultiline,
+    HistorySearch,
+    RowUp,
+    RowDown,
+    Complete,
+    CompletePrevious,
+    Undo,
+    Redo,
+    CharLeft,
+    CharRight,
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+    DeleteBack,
+    DeleteForward,
+    KillLineStart,
+    KillLineEnd,
+    KillWordBack,
+    KillWordForward,
+    Yank,
+    YankPop,
+    Transpose,
+    Confirm,
+    Cancel,
+    StepBack,
+    StepForward,
+    Toggle,
+    Sort,
+    ReverseSort,
+    Rescan,
+    AddFolder,
+    Revert,
+    Reset,
+    Save,
+}
+
+/// An action's name in `keys.toml`, where it applies, and its default keys
+pub struct ActionInfo {
+    pub action: Action,
+    pub name: &'static str,
+    pub context: Context,
+    pub help: &'static str,
+    pub keys: &'static [&'static str],
+}
+
+const fn action(
+    action: Action,
+    name: &'static str,
+    context: Context,
+    help: &'static str,
+    keys: &'static [&'static str],
+) -> ActionInfo {
+    ActionInfo { action, name, context, help, keys }
+}
+
+pub static ACTIONS: &[ActionInfo] = &[
+    action(Action::Quit, "quit", Context::Global, "Ask to quit", &["ctrl-q"]),
+    action(Action::FocusHeader, "focus_header", Context::Global, "Focus the header", &["f1"]),
+    action(Action::FocusNavigation, "focus_navigation", Context::Global, "Focus the navigation menu", &["f2"]),
+    action(Action::FocusPreview, "focus_preview", Context::Global, "Focus the preview", &["f3"]),
+    action(Action::FocusInput, "focus_input", Context::Global, "Focus the input line", &["f4"]),
+    action(Action::FocusFooter, "focus_footer", Context::Global, "Focus the footer", &["f5"]),
+    action(Action::FocusNext, "focus_next", Context::Navigation, "Focus the next area", &["l"]),
+    action(Action::FocusPrevious, "focus_previous", Context::Navigation, "Focus the previous area", &["h"]),
+    action(Action::NavDown, "nav_down", Context::Navigation, "Next menu entry; elsewhere the next area", &["j", "down"]),
+    action(Action::NavUp, "nav_up", Context::Navigation, "Previous menu entry; elsewhere the previous area", &["k", "up"]),
+    action(Action::Activate, "activate", Context::Navigation, "Open the menu entry", &["enter"]),
+    action(Action::Back, "back", Context::Navigation, "Leave the submenu", &["esc"]),
+    action(Action::Submit, "submit", Context::Input, "Send the line, even a multi-line one", &["ctrl-enter", "ctrl-j"]),
+    action(Action::SubmitOrNewline, "submit_or_newline", Context::Input, "Send the line; in multi-line mode, start a new line", &["enter"]),
+    action(Action::Newline, "newline", Context::Input, "Start a new line, switching to multi-line mode", &["shift-enter", "alt-enter"]),
+    action(Action::ToggleMultiline, "toggle_multiline", Context::Input, "Switch multi-line mode on or off", &["alt-m"]),
+    action(Action::HistorySearch, "history_search", Context::Input, "Search the history", &["ctrl-r"]),
+    action(Action::RowUp, "row_up", Context::Input, "The row above, or the previous history entry", &["up"]),
+    action(Action::RowDown, "row_down", Context::Input, "The row below, or the next history entry", &["down"]),
+    action(Action::Complete, "complete", Context::Input, "Complete the word, or the next candidate", &["tab"]),
+    action(Action::CompletePrevious, "complete_previous", Context::Input, "The previous completion candidate", &["backtab"]),
+    action(Action::Undo, "undo", Context::Input, "Undo", &["ctrl-_", "ctrl-7", "ctrl-/"]),
+    action(Action::Redo, "redo", Context::Input, "Redo", &["ctrl-shift-_", "alt-_"]),
+    action(Action::CharLeft, "char_left", Context::Input, "Cursor one character left", &["left"]),
+    action(Action::CharRight, "char_right", Context::Input, "Cursor one character right", &["right"]),
+    action(Action::WordLeft, "word_left", Context::Input, "Cursor one word left", &["alt-b", "ctrl-left"]),
+    action(Action::WordRight, "word_right", Context::Input, "Cursor one word right", &["alt-f", "ctrl-right"]),
+    action(Action::LineStart, "line_start", Context::Input, "Cursor to the start of the line", &["ctrl-a", "home"]),
+    action(Action::LineEnd, "line_end", Context::Input, "Cursor to the end of the line", &["ctrl-e", "end"]),
+    action(Action::DeleteBack, "delete_back", Context::Input, "Delete the character before the cursor", &["backspace"]),
+    action(Action::DeleteForward, "delete_forward", Context::Input, "Delete the character under the cursor", &["delete"]),
+    action(Action::KillLineStart, "kill_line_start", Context::Input, "Cut to the start of the line", &["ctrl-u"]),
+    action(Action::KillLineEnd, "kill_line_end", Context::Input, "Cut to the end of the line", &["ctrl-k"]),
+    action(Action::KillWordBack, "kill_word_back", Context::Input, "Cut the word before the cursor", &["ctrl-w"]),
+    action(Action::KillWordForward, "kill_word_forward", Context::Input, "Cut the word after the cursor", &["alt-d"]),
+    action(Action::Yank, "yank", Context::Input, "Paste the last cut", &["ctrl-y"]),
+    action(Action::YankPop, "yank_pop", Context::Input, "Swap the paste for the cut before it", &["alt-y"]),
+    action(Action::Transpose, "transpose", Context::Input, "Swap the characters around the cursor", &["ctrl-t"]),
+    action(Action::Confirm, "confirm", Context::Modal, "Quit from the quit dialog", &["y"]),
+    action(Action::Cancel, "cancel", Context::Modal, "Close the dialog", &["n", "esc", "enter"]),
+    action(Action::StepBack, "step_back", Context::View, "Fold the node; the setting's previous value", &["left"]),
+    action(Action::StepForward, "step_forward", Context::View, "Unfold the node; the setting's next value", &["right"]),
+    action(Action::Toggle, "toggle", Context::View, "Fold or unfold the node; the setting's next value", &["space"]),
+    action(Action::Sort, "sort", Context::Library, "Sort by the next column", &["s"]),
+    action(Action::ReverseSort, "reverse_sort", Context::Library, "Reverse the sort order", &["S"]),
+    action(Action::Rescan, "rescan", Context::Library, "Scan the folders again", &["r"]),
+    action(Action::AddFolder, "add_folder", Context::Library, "Add a folder to the library", &["o"]),
+    action(Action::Revert, "revert", Context::Settings, "Back to the value in the file", &["r"]),
+    action(Action::Reset, "reset", Context::Settings, "Back to the built-in default", &["d"]),
+    action(Action::Save, "save", Context::Settings, "Save the changes to the file", &["s"]),
+];
+
+impl Action {
+    pub fn info(self) -> &'static ActionInfo {
+        ACTIONS.iter().find(|info| info.action == self).expect("every action is in ACTIONS")
+    }
+
+    pub fn context(self) -> Context {
+        self.info().context
+    }
+}
+
+/// One key with its modifiers. Shift is part of typing a character, so it
+/// only counts for characters alongside Ctrl or Alt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+const MODIFIERS: [(&str, KeyModifiers); 3] =
+    [("ctrl-", KeyModifiers::CONTROL), ("alt-", KeyModifiers::ALT), ("shift-", KeyModifiers::SHIFT)];
+
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("space", KeyCode::Char(' ')),
+];
+
+impl Key {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let plain = !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if code == KeyCode::BackTab || (plain && matches!(code, KeyCode::Char(_))) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code, modifiers }
+    }
+}
+
+impl From<&KeyEvent> for Key {
+    fn from(event: &KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        // What is left after the last modifier is the key, so "ctrl--" is Ctrl and minus
+        while let Some((prefix, modifier)) = MODIFIERS
+            .iter()
+            .find(|(prefix, _)| rest.len() > prefix.len() && rest[..prefix.len()].eq_ignore_ascii_case(prefix))
+        {
+            modifiers |= *modifier;
+            rest = &rest[prefix.len()..];
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => {
+                let plain = !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+                let shift = modifiers.contains(KeyModifiers::SHIFT);
+                // Shift-a is typed as A, and terminals send Ctrl-A as Ctrl-a
+                if plain && shift {
+                    KeyCode::Char(c.to_uppercase().next().unwrap_or(c))
+                } else if modifiers.contains(KeyModifiers::CONTROL) && !shift {
+                    KeyCode::Char(c.to_lowercase().next().unwrap_or(c))
+                } else {
+                    KeyCode::Char(c)
+                }
+            }
+            _ => match NAMED_KEYS.iter().find(|(name, _)| name.eq_ignore_ascii_case(rest)) {
+                Some((_, code)) => *code,
+                None => match rest.strip_prefix(['f', 'F']).and_then(|number| number.parse::<u8>().ok()) {
+                    Some(number) if (1..=24).contains(&number) => KeyCode::F(number),
+                    _ => return Err(format!("{} is not a key", text)),
+                },
+            },
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (prefix, modifier) in MODIFIERS {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}", prefix)?;
+            }
+        }
+        match NAMED_KEYS.iter().find(|(_, code)| *code == self.code) {
+            Some((name, _)) => write!(f, "{}", name),
+            None => match self.code {
+                KeyCode::Char(c) => write!(f, "{}", c),
+                KeyCode::F(number) => write!(f, "f{}", number),
+                code => write!(f, "{}", format!("{:?}", code).to_lowercase()),
+            },
+        }
+    }
+}
+
+/// Keys pressed one after the other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chord(pub Vec<Key>);
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let keys = text.split_whitespace().map(str::parse).collect::<Result<Vec<Key>, String>>()?;
+        if keys.is_empty() {
+            return Err(String::from("a key binding can't be empty"));
+        }
+        Ok(Self(keys))
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keys: Vec<String> = self.0.iter().map(Key::to_string).collect();
+        write!(f, "{}", keys.join(" "))
+    }
+}
+
+/// What a key press came to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// A binding was completed. A binding of one key comes with it, so a
+    /// mode such as vi can still take the key instead.
+    Action(Action, Option<KeyEvent>),
+    /// A key that isn't bound, to be handled as typing
+    Key(KeyEvent),
+}
+
+enum Lookup {
+    Found(Action),
+    // The keys so far start a longer binding
+    Prefix,
+    None,
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    // The keys of each entry of `ACTIONS`
+    bindings: Vec<Vec<Chord>>,
+    // Keys of a chord being typed
+    pending: Vec<KeyEvent>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = ACTIONS
+            .iter()
+            .map(|info| info.keys.iter().map(|keys| keys.parse().expect("default keys parse")).collect())
+            .collect();
+        Self { bindings, pending: Vec::new() }
+    }
+}
+
+impl Keymap {
+    /// The defaults with the file's bindings over them. Mistakes leave the
+    /// action's default keys; conflicting bindings are kept and reported.
+    pub fn parse(text: &str) -> (Self, Vec<ConfigError>) {
+        let mut keymap = Self::default();
+        let document = match Document::parse(text) {
+            Ok(document) => document,
+            Err(error) => {
+                let line = error.span().map_or(1, |span| line_at(text, span.start));
+                return (keymap, vec![ConfigError { line, message: error.message().trim().to_string() }]);
+            }
+        };
+
+        let mut errors = Vec::new();
+        let mut report = |span: Option<std::ops::Range<usize>>, message: String| {
+            errors.push(ConfigError { line: span.map_or(1, |span| line_at(text, span.start)), message });
+        };
+        // Where each action's keys were set, for reporting conflicts
+        let mut spans: Vec<Option<std::ops::Range<usize>>> = vec![None; ACTIONS.len()];
+        let root = document.as_table();
+        for (section, item) in root.iter() {
+            let key_span = root.key(section).and_then(|key| key.span());
+            let Some(context) = Context::ALL.into_iter().find(|context| context.name() == section) else {
+                report(key_span, format!("unknown section [{}]", section));
+                continue;
+            };
+            let Some(table) = item.as_table_like() else {
+                report(key_span, format!("{} should be a [section]", section));
+                continue;
+            };
+            for (name, value) in table.iter() {
+                let span = table.key(name).and_then(|key| key.span());
+                let Some(index) = ACTIONS.iter().position(|info| info.name == name && info.context == context) else {
+                    match ACTIONS.iter().find(|info| info.name == name) {
+                        Some(info) => report(span, format!("{} belongs under [{}]", name, info.context.name())),
+                        None => report(span, format!("unknown action {}.{}", section, name)),
+                    }
+                    continue;
+                };
+                match read_chords(value) {
+                    Ok(chords) => {
+                        keymap.bindings[index] = chords;
+                        spans[index] = value.span().or(span);
+                    }
+                    Err(message) => report(value.span().or(span), format!("{}.{}: {}", section, name, message)),
+                }
+            }
+        }
+        // On the line of the later of the two bindings the file set
+        for (first, second, message) in keymap.conflicts() {
+            let span = [spans[first].clone(), spans[second].clone()].into_iter().flatten().max_by_key(|span| span.start);
+            report(span, message);
+        }
+        errors.sort_by_key(|error| error.line);
+        (keymap, errors)
+    }
+
+    /// Bindings looked up together that get in each other's way: the same
+    /// keys for two actions, or a key that also starts a chord. Global keys that start
+    /// with a plain character count too, as the input line can't have it.
+    /// Returns the two actions' places in `ACTIONS` with what is wrong.
+    fn conflicts(&self) -> Vec<(usize, usize, String)> {
+        let bound: Vec<(usize, &Chord)> = self
+            .bindings
+            .iter()
+            .enumerate()
+            .flat_map(|(index, chords)| chords.iter().map(move |chord| (index, chord)))
+            .collect();
+        let name = |index: usize| format!("{}.{}", ACTIONS[index].context.name(), ACTIONS[index].name);
+        let mut conflicts = Vec::new();
+        for (position, &(first, first_chord)) in bound.iter().enumerate() {
+            if ACTIONS[first].context == Context::Global
+                && let Some(Key { code: KeyCode::Char(c), modifiers: KeyModifiers::NONE }) = first_chord.0.first()
+                && !c.is_control()
+            {
+                let message = format!("{} ({}) takes {} away from typing in the input line", first_chord, name(first), c);
+                conflicts.push((first, first, message));
+            }
+            for &(second, second_chord) in &bound[position + 1..] {
+                if !ACTIONS[first].context.overlaps(ACTIONS[second].context) {
+                    continue;
+                }
+                if first_chord == second_chord {
+                    if first != second {
+                        let message = format!("{} is bound to both {} and {}", first_chord, name(first), name(second));
+                        conflicts.push((first, second, message));
+                    }
+                    continue;
+                }
+                let ((short, short_chord), (long, long_chord)) = if first_chord.0.len() < second_chord.0.len() {
+                    ((first, first_chord), (second, second_chord))
+                } else {
+                    ((second, second_chord), (first, first_chord))
+                };
+                if long_chord.0.starts_with(&short_chord.0) {
+                    let message = format!(
+                        "{} ({}) can't be typed, as {} is bound to {}",
+                        long_chord,
+                        name(long),
+                        short_chord,
+                        name(short)
+                    );
+                    conflicts.push((first, second, message));
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Reads `path`; a missing file is the defaults without complaint
+    pub fn load(path: &Path) -> (Self, Vec<ConfigError>) {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(_) => (Self::default(), Vec::new()),
+        }
+    }
+
+    pub fn keys(&self, action: Action) -> &[Chord] {
+        let index = ACTIONS.iter().position(|info| info.action == action).unwrap_or(0);
+        &self.bindings[index]
+    }
+
+    /// The keys of a chord typed so far
+    pub fn pending(&self) -> Option<Chord> {
+        (!self.pending.is_empty()).then(|| Chord(self.pending.iter().map(Key::from).collect()))
+    }
+
+    /// Takes a key press, looking it up in `contexts` in order. A key that
+    /// starts a chord is held until the chord is complete; one that breaks
+    /// a chord lets the keys before it through as typing.
+    pub fn feed(&mut self, contexts: &[Context], key: &KeyEvent) -> Vec<Step> {
+        self.advance(contexts, key, true)
+    }
+
+    /// Like `feed`, for a view that takes its keys straight away: a key that
+    /// only starts a chord comes back as typing instead of being held.
+    pub fn feed_through(&mut self, contexts: &[Context], key: &KeyEvent) -> Vec<Step> {
+        self.advance(contexts, key, false)
+    }
+
+    fn advance(&mut self, contexts: &[Context], key: &KeyEvent, hold_chords: bool) -> Vec<Step> {
+        self.pending.push(*key);
+        let mut steps = Vec::new();
+        while !self.pending.is_empty() {
+            let keys: Vec<Key> = self.pending.iter().map(Key::from).collect();
+            match self.lookup(contexts, &keys) {
+                Lookup::Found(action) => {
+                    let key = (self.pending.len() == 1).then(|| self.pending[0]);
+                    steps.push(Step::Action(action, key));
+                    self.pending.clear();
+                }
+                Lookup::Prefix if hold_chords => break,
+                Lookup::Prefix | Lookup::None => steps.push(Step::Key(self.pending.remove(0))),
+            }
+        }
+        steps
+    }
+
+    fn lookup(&self, contexts: &[Context], keys: &[Key]) -> Lookup {
+        let bound = |context: Context| {
+            ACTIONS
+                .iter()
+                .zip(&self.bindings)
+                .filter(move |(info, _)| info.context == context)
+                .flat_map(|(info, chords)| chords.iter().map(move |chord| (info.action, chord)))
+        };
+        for &context in contexts {
+            if let Some((action, _)) = bound(context).find(|(_, chord)| chord.0 == keys) {
+                return Lookup::Found(action);
+            }
+        }
+        let starts_chord = contexts
+            .iter()
+            .flat_map(|&context| bound(context))
+            .any(|(_, chord)| chord.0.len() > keys.len() && chord.0.starts_with(keys));
+        if starts_chord { Lookup::Prefix } else { Lookup::None }
+    }
+
+    /// "key label" pairs for a view's footer, with the first key of each
+    /// action of a group; groups whose actions are all unbound are left out
+    pub fn hints(&self, groups: &[(&[Action], &str)]) -> String {
+        let hints: Vec<String> = groups
+            .iter()
+            .filter_map(|(actions, label)| {
+                let keys: Vec<String> =
+                    actions.iter().filter_map(|&action| self.keys(action).first()).map(Chord::to_string).collect();
+                (!keys.is_empty()).then(|| format!("{} {}", keys.join("/"), label))
+            })
+            .collect();
+        hints.join("  ")
+    }
+
+    /// Every action with its keys, by context, for `/keys`
+    pub fn help_text(&self) -> String {
+        let width = ACTIONS.iter().map(|info| info.name.len()).max().unwrap_or(0);
+        let mut text = String::from("Key bindings:");
+        for context in Context::ALL {
+            text.push_str(&format!("\n\n[{}]", context.name()));
+            for (info, chords) in ACTIONS.iter().zip(&self.bindings).filter(|(info, _)| info.context == context) {
+                let keys: Vec<String> = chords.iter().map(Chord::to_string).collect();
+                let keys = if keys.is_empty() { String::from("(none)") } else { keys.join(", ") };
+                text.push_str(&format!("\n  {:<width$}  {:<24}  {}", info.name, keys, info.help, width = width));
+            }
+        }
+        if let Some(path) = keys_path() {
+            text.push_str(&format!("\n\nChange them in {}", paths::display_path(&path)));
+        }
+        text
+    }
+}
+
+/// A key, or a list of them
+fn read_chords(item: &Item) -> Result<Vec<Chord>, String> {
+    let expected = || String::from("expected a key in quotes, such as \"ctrl-q\", or a list of them");
+    if let Some(text) = item.as_str() {
+        return Ok(vec![text.parse()?]);
+    }
+    let array = item.as_array().ok_or_else(expected)?;
+    array.iter().map(|value| value.as_str().ok_or_else(expected)?.parse()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_keys_parse_and_display() {
+        for text in ["ctrl-q", "shift-enter", "ctrl-shift-_", "alt-_", "f2", "ctrl--", "space", "G", "backtab"] {
+            assert_eq!(text.parse::<Key>().unwrap().to_string(), text);
+        }
+        assert_eq!("Ctrl-Q".parse::<Key>().unwrap().to_string(), "ctrl-q");
+        assert_eq!("shift-g".parse::<Key>(), "G".parse::<Key>());
+        assert_eq!(Key::from(&key(KeyCode::Char('G'), KeyModifiers::SHIFT)), "G".parse().unwrap());
+        assert_eq!(Key::from(&key(KeyCode::BackTab, KeyModifiers::SHIFT)), "backtab".parse().unwrap());
+        assert_eq!("ctrl-x  ctrl-c".parse::<Chord>().unwrap().to_string(), "ctrl-x ctrl-c");
+        assert!("ctrl-nope".parse::<Key>().is_err());
+        assert!("".parse::<Chord>().is_err());
+    }
+
+    #[test]
+    fn test_overrides_and_mistakes() {
+        let (keymap, errors) = Keymap::parse(
+            "[global]\nquit = [\"ctrl-x ctrl-c\"]\n\n[navigation]\nback = []\nnav_dwn = \"j\"\nquit = \"q\"\nactivate = \"ctrl-nope\"\n\n[keys]\n",
+        );
+        assert_eq!(keymap.keys(Action::Quit), ["ctrl-x ctrl-c".parse::<Chord>().unwrap()]);
+        assert!(keymap.keys(Action::Back).is_empty());
+        // Mistakes keep the defaults
+        assert_eq!(keymap.keys(Action::Activate), ["enter".parse::<Chord>().unwrap()]);
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                "line 6: unknown action navigation.nav_dwn",
+                "line 7: quit belongs under [global]",
+                "line 8: navigation.activate: ctrl-nope is not a key",
+                "line 10: unknown section [keys]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conflicting_bindings_are_reported() {
+        assert!(Keymap::default().conflicts().is_empty());
+        let (keymap, errors) = Keymap::parse(
+            "[global]\nquit = \"j\"\n\n[navigation]\nnav_down = [\"j\", \"g g\"]\nfocus_next = \"g\"\nnav_up = \"j\"\n",
+        );
+        // The bindings are kept all the same
+        assert_eq!(keymap.keys(Action::FocusNext), ["g".parse::<Chord>().unwrap()]);
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                "line 2: j (global.quit) takes j away from typing in the input line",
+                "line 5: j is bound to both global.quit and navigation.nav_down",
+                "line 6: g g (navigation.nav_down) can't be typed, as g is bound to navigation.focus_next",
+                "line 7: j is bound to both global.quit and navigation.nav_up",
+                "line 7: j is bound to both navigation.nav_down and navigation.nav_up",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chords_wait_for_their_last_key() {
+        let (mut keymap, errors) = Keymap::parse("[global]\nfocus_input = \"ctrl-g i\"\n");
+        assert!(errors.is_empty());
+        let contexts = [Context::Global, Context::Navigation];
+        let g = key(KeyCode::Char('g'), KeyModifiers::CONTROL);
+        let i = key(KeyCode::Char('i'), KeyModifiers::NONE);
+        let j = key(KeyCode::Char('j'), KeyModifiers::NONE);
+
+        assert!(keymap.feed(&contexts, &g).is_empty());
+        assert_eq!(keymap.pending().map(|chord| chord.to_string()).as_deref(), Some("ctrl-g"));
+        assert_eq!(keymap.feed(&contexts, &i), [Step::Action(Action::FocusInput, None)]);
+        assert!(keymap.pending().is_none());
+
+        // A key that breaks the chord lets the ctrl-g through and counts on its own
+        keymap.feed(&contexts, &g);
+        assert_eq!(keymap.feed(&contexts, &j), [Step::Key(g), Step::Action(Action::NavDown, Some(j))]);
+        assert_eq!(keymap.feed(&[Context::Input], &j), [Step::Key(j)]);
+    }
+}
@@ -0,0 +1,441 @@
+// This file implements the editable text behind the input box.
+//
+// LineEditor owns the text and cursor and knows the readline-style edits:
+// grapheme and word motions, kills that collect in a kill ring, yanking
+// back from it, transposing, and undo/redo. It knows nothing about keys or
+// the rest of the app, so any text field can use it.
+
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::text;
+
+/// How many kills the ring remembers
+const KILL_RING_SIZE: usize = 30;
+
+/// How many edits can be undone
+const UNDO_SIZE: usize = 100;
+
+/// How the last edit changed the text, so typing and kills can be merged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum LastEdit {
+    #[default]
+    None,
+    // Typing a word; the run ends at whitespace
+    Typing,
+    Kill,
+    Yank,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Snapshot {
+    text: String,
+    cursor: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LineEditor {
+    text: String,
+    // Byte offset into `text`, always on a grapheme boundary
+    cursor: usize,
+    kill_ring: Vec<String>,
+    // What the last yank inserted and which ring entry it was, for Alt-Y
+    yanked: Option<(Range<usize>, usize)>,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    last_edit: LastEdit,
+}
+
+impl LineEditor {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Empties the editor for a new line. The kill ring is kept.
+    pub fn reset(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+        self.undo.clear();
+        self.redo.clear();
+        self.finish_edit();
+    }
+
+    /// Moves the cursor to `offset`, which must be a grapheme boundary
+    pub fn set_cursor(&mut self, offset: usize) {
+        self.cursor = offset.min(self.text.len());
+        self.finish_edit();
+    }
+
+    /// Replaces the whole text, leaving the cursor at its end
+    pub fn set_text(&mut self, text: String) {
+        self.save_undo();
+        self.cursor = text.len();
+        self.text = text;
+        self.finish_edit();
+    }
+
+    /// Replaces `range` with `text` and puts the cursor after it
+    pub fn replace_range(&mut self, range: Range<usize>, text: &str) {
+        self.save_undo();
+        self.cursor = range.start + text.len();
+        self.text.replace_range(range, text);
+        self.finish_edit();
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        // A run of typing undoes as one step, a word at a time
+        if self.last_edit != LastEdit::Typing {
+            self.save_undo();
+        }
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+        self.finish_edit();
+        if !c.is_whitespace() {
+            self.last_edit = LastEdit::Typing;
+        }
+    }
+
+    /// Removes the grapheme before the cursor, combining marks and all
+    pub fn backspace(&mut self) {
+        let start = text::previous_boundary(&self.text, self.cursor);
+        self.delete_range(start..self.cursor);
+    }
+
+    /// Removes the grapheme at the cursor
+    pub fn delete(&mut self) {
+        let end = text::next_boundary(&self.text, self.cursor);
+        self.delete_range(self.cursor..end);
+    }
+
+    pub fn move_left(&mut self) {
+        self.set_cursor(text::previous_boundary(&self.text, self.cursor));
+    }
+
+    pub fn move_right(&mut self) {
+        self.set_cursor(text::next_boundary(&self.text, self.cursor));
+    }
+
+    pub fn move_line_start(&mut self) {
+        self.set_cursor(text::line_start(&self.text, self.cursor));
+    }
+
+    pub fn move_line_end(&mut self) {
+        self.set_cursor(text::line_end(&self.text, self.cursor));
+    }
+
+    /// Moves to the start of this or the previous word (Alt-B)
+    pub fn move_word_left(&mut self) {
+        self.set_cursor(word_start(&self.text, self.cursor, is_word));
+    }
+
+    /// Moves to the end of this or the next word (Alt-F)
+    pub fn move_word_right(&mut self) {
+        self.set_cursor(word_end(&self.text, self.cursor));
+    }
+
+    /// Kills from the start of the line to the cursor (Ctrl-U)
+    pub fn kill_line_start(&mut self) {
+        let start = text::line_start(&self.text, self.cursor);
+        self.kill(start..self.cursor, false);
+    }
+
+    /// Kills from the cursor to the end of the line (Ctrl-K)
+    pub fn kill_line_end(&mut self) {
+        let end = text::line_end(&self.text, self.cursor);
+        self.kill(self.cursor..end, true);
+    }
+
+    /// Kills back to the previous whitespace, like a shell's Ctrl-W
+    pub fn kill_word_back(&mut self) {
+        let start = word_start(&self.text, self.cursor, |grapheme| !is_space(grapheme));
+        self.kill(start..self.cursor, false);
+    }
+
+    /// Kills to the end of the next word (Alt-D)
+    pub fn kill_word_forward(&mut self) {
+        let end = word_end(&self.text, self.cursor);
+        self.kill(self.cursor..end, true);
+    }
+
+    /// Inserts the latest kill (Ctrl-Y)
+    pub fn yank(&mut self) {
+        let Some(killed) = self.kill_ring.last().cloned() else {
+            return;
+        };
+        self.save_undo();
+        self.text.insert_str(self.cursor, &killed);
+        let range = self.cursor..self.cursor + killed.len();
+        self.cursor = range.end;
+        self.finish_edit();
+        self.yanked = Some((range, self.kill_ring.len() - 1));
+        self.last_edit = LastEdit::Yank;
+    }
+
+    /// Swaps the text just yanked for the kill before it (Alt-Y)
+    pub fn yank_pop(&mut self) {
+        if self.last_edit != LastEdit::Yank {
+            return;
+        }
+        let Some((range, index)) = self.yanked.take() else {
+            return;
+        };
+        let index = index.checked_sub(1).unwrap_or(self.kill_ring.len() - 1);
+        let killed = &self.kill_ring[index];
+        self.text.replace_range(range.clone(), killed);
+        self.cursor = range.start + killed.len();
+        self.yanked = Some((range.start..self.cursor, index));
+    }
+
+    /// Removes `range` into a kill ring entry of its own, e.g. for vi's `d`
+    pub fn cut(&mut self, range: Range<usize>) {
+        self.finish_edit();
+        self.kill(range, true);
+        self.finish_edit();
+    }
+
+    /// Copies `range` into the kill ring without changing the text
+    pub fn copy(&mut self, range: Range<usize>) {
+        if !range.is_empty() {
+            self.push_kill(self.text[range].to_string());
+        }
+        self.finish_edit();
+    }
+
+    /// Inserts the latest kill at `offset`, leaving the cursor on its last
+    /// grapheme like vi's `p` and `P`
+    pub fn paste_at(&mut self, offset: usize) {
+        if self.kill_ring.is_empty() {
+            return;
+        }
+        self.set_cursor(offset);
+        self.yank();
+        self.set_cursor(text::previous_boundary(&self.text, self.cursor));
+    }
+
+    /// Swaps the graphemes around the cursor and steps past them; at the
+    /// end of a line, swaps the last two (Ctrl-T)
+    pub fn transpose(&mut self) {
+        let line_end = text::line_end(&self.text, self.cursor);
+        let middle = if self.cursor == line_end {
+            text::previous_boundary(&self.text, self.cursor)
+        } else {
+            self.cursor
+        };
+        let start = text::previous_boundary(&self.text, middle);
+        let end = text::next_boundary(&self.text, middle);
+        // Needs a grapheme on each side, both on this line
+        if start == middle || end == middle || self.text[start..end].contains('\n') {
+            return;
+        }
+        let swapped = format!("{}{}", &self.text[middle..end], &self.text[start..middle]);
+        self.replace_range(start..end, &swapped);
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(snapshot) = self.undo.pop() {
+            let current = self.restore(snapshot);
+            self.redo.push(current);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(snapshot) = self.redo.pop() {
+            let current = self.restore(snapshot);
+            self.push_undo(current);
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) -> Snapshot {
+        let current = self.snapshot();
+        self.text = snapshot.text;
+        self.cursor = snapshot.cursor;
+        self.finish_edit();
+        current
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot { text: self.text.clone(), cursor: self.cursor }
+    }
+
+    /// Records the text before an edit; a new edit makes redo moot
+    fn save_undo(&mut self) {
+        self.push_undo(self.snapshot());
+        self.redo.clear();
+    }
+
+    fn push_undo(&mut self, snapshot: Snapshot) {
+        self.undo.push(snapshot);
+        if self.undo.len() > UNDO_SIZE {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Ends typing runs, kill sequences and yanks
+    fn finish_edit(&mut self) {
+        self.last_edit = LastEdit::None;
+        self.yanked = None;
+    }
+
+    fn delete_range(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        self.replace_range(range, "");
+    }
+
+    /// Removes `range` into the kill ring. Kills in a row build up one
+    /// entry, so Ctrl-W Ctrl-W yanks back both words.
+    fn kill(&mut self, range: Range<usize>, forward: bool) {
+        if range.is_empty() {
+            return;
+        }
+        let killed = self.text[range.clone()].to_string();
+        match self.kill_ring.last_mut() {
+            Some(last) if self.last_edit == LastEdit::Kill && forward => last.push_str(&killed),
+            Some(last) if self.last_edit == LastEdit::Kill => last.insert_str(0, &killed),
+            _ => self.push_kill(killed),
+        }
+        self.replace_range(range, "");
+        self.last_edit = LastEdit::Kill;
+    }
+
+    fn push_kill(&mut self, killed: String) {
+        self.kill_ring.push(killed);
+        if self.kill_ring.len() > KILL_RING_SIZE {
+            self.kill_ring.remove(0);
+        }
+    }
+}
+
+fn is_space(grapheme: &str) -> bool {
+    grapheme.chars().all(char::is_whitespace)
+}
+
+// Words for Alt-B/F and Alt-D are runs of letters and digits
+fn is_word(grapheme: &str) -> bool {
+    grapheme.chars().any(char::is_alphanumeric)
+}
+
+/// Start of the word before `offset`, skipping what is not part of one
+fn word_start(text: &str, offset: usize, in_word: fn(&str) -> bool) -> usize {
+    let graphemes = text[..offset].grapheme_indices(true).rev().skip_while(|(_, g)| !in_word(g));
+    let mut start = offset;
+    for (index, grapheme) in graphemes {
+        if !in_word(grapheme) {
+            break;
+        }
+        start = index;
+    }
+    // Nothing but separators before the cursor
+    if start == offset { 0 } else { start }
+}
+
+/// End of the word after `offset`, skipping what is not part of one
+fn word_end(text: &str, offset: usize) -> usize {
+    let graphemes = text[offset..].grapheme_indices(true).skip_while(|(_, g)| !is_word(g));
+    let mut end = text.len();
+    for (index, grapheme) in graphemes {
+        if !is_word(grapheme) {
+            end = offset + index;
+            break;
+        }
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor_with(text: &str) -> LineEditor {
+        let mut editor = LineEditor::default();
+        editor.set_text(text.to_string());
+        editor
+    }
+
+    #[test]
+    fn test_word_motions_and_kills() {
+        let mut editor = editor_with("a red-eyed goblin");
+        editor.move_word_left();
+        assert_eq!(editor.cursor(), "a red-eyed ".len());
+        editor.move_word_left();
+        assert_eq!(editor.cursor(), "a red-".len());
+        editor.move_word_right();
+        assert_eq!(editor.cursor(), "a red-eyed".len());
+
+        editor.kill_word_back();
+        editor.kill_word_back();
+        assert_eq!(editor.text(), " goblin");
+        editor.kill_word_forward();
+        assert_eq!(editor.text(), "");
+        // Kills in a row are one entry, forward ones appended
+        editor.yank();
+        assert_eq!(editor.text(), "a red-eyed goblin");
+    }
+
+    #[test]
+    fn test_yank_pop_rotates() {
+        let mut editor = editor_with("pie cave");
+        editor.kill_word_back();
+        editor.move_left();
+        editor.kill_word_back();
+        assert_eq!(editor.text(), " ");
+        editor.yank();
+        assert_eq!(editor.text(), "pie ");
+        editor.yank_pop();
+        assert_eq!(editor.text(), "cave ");
+        editor.yank_pop();
+        assert_eq!(editor.text(), "pie ");
+    }
+
+    #[test]
+    fn test_transpose() {
+        let mut editor = editor_with("gobiln");
+        editor.move_left();
+        editor.move_left();
+        editor.transpose();
+        assert_eq!((editor.text(), editor.cursor()), ("goblin", 5));
+        // At the end it swaps the last two; wide characters stay whole
+        let mut editor = editor_with("漢字");
+        editor.transpose();
+        assert_eq!(editor.text(), "字漢");
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut editor = LineEditor::default();
+        for c in "a goblin".chars() {
+            editor.insert_char(c);
+        }
+        editor.kill_word_back();
+        assert_eq!(editor.text(), "a ");
+        editor.undo();
+        assert_eq!(editor.text(), "a goblin");
+        editor.undo();
+        assert_eq!(editor.text(), "a ");
+        editor.undo();
+        assert_eq!(editor.text(), "");
+        editor.redo();
+        editor.redo();
+        assert_eq!((editor.text(), editor.cursor()), ("a goblin", 8));
+        editor.insert_char('s');
+        editor.redo();
+        assert_eq!(editor.text(), "a goblins");
+
+        // Only the newest edits are kept
+        for _ in 0..UNDO_SIZE + 10 {
+            editor.backspace();
+            editor.insert_char('s');
+        }
+        assert_eq!(editor.undo.len(), UNDO_SIZE);
+    }
+}
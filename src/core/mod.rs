@@ -1,3 +1,15 @@
 pub mod app_state;
 pub mod command;
-pub mod input;
\ No newline at end of file
+pub mod completion;
+pub mod config;
+pub mod dispatch;
+pub mod history;
+pub mod input;
+pub mod keymap;
+pub mod line_editor;
+pub mod navigation;
+pub mod paths;
+pub mod prompt;
+pub mod text;
+pub mod theme;
+pub mod vi;
@@ -0,0 +1,296 @@
+// This file holds the navigation menu as a tree of items.
+//
+// Items are found by their id rather than their position, so adding or
+// moving an entry doesn't change what any other entry does. The selection
+// is a path of ids from the top level down to the highlighted item; every
+// item but the last on the path is a menu that has been opened.
+//
+// Each item brings its own behaviour: the preview text shown while it is
+// highlighted, the view that draws the content area while the user is on
+// it or anywhere below it, and what Enter does on it.
+
+use ratatui::{layout::Rect, Frame};
+
+use super::app_state::AppState;
+
+/// Preview text for an item that doesn't provide its own
+pub const DEFAULT_PREVIEW: &str = "Select an option from the menu.";
+
+/// Builds the preview text while an item is highlighted
+pub type PreviewProvider = fn(&AppState) -> String;
+
+/// Draws the content area for an item and everything below it
+pub type ViewProvider = fn(&mut Frame, Rect, &AppState);
+
+/// Runs when Enter is pressed on an item without children. Returns a
+/// message for the output log when there is something to report.
+pub type NavigationAction = fn(&mut AppState) -> Option<String>;
+
+#[derive(Debug, Clone)]
+pub enum Preview {
+    Text(String),
+    Provider(PreviewProvider),
+}
+
+#[derive(Debug, Clone)]
+pub struct NavigationItem {
+    /// Stable name used to find the item, e.g. "image_tools.open"
+    pub id: String,
+    pub name: String,
+    pub preview: Preview,
+    pub view: Option<ViewProvider>,
+    pub action: Option<NavigationAction>,
+    pub children: Vec<NavigationItem>,
+}
+
+impl NavigationItem {
+    pub fn new(id: &str, name: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            preview: Preview::Text(String::from(DEFAULT_PREVIEW)),
+            view: None,
+            action: None,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn preview_text(mut self, text: &str) -> Self {
+        self.preview = Preview::Text(text.to_string());
+        self
+    }
+
+    pub fn preview(mut self, provider: PreviewProvider) -> Self {
+        self.preview = Preview::Provider(provider);
+        self
+    }
+
+    pub fn view(mut self, view: ViewProvider) -> Self {
+        self.view = Some(view);
+        self
+    }
+
+    pub fn action(mut self, action: NavigationAction) -> Self {
+        self.action = Some(action);
+        self
+    }
+
+    pub fn children(mut self, children: Vec<NavigationItem>) -> Self {
+        self.children = children;
+        self
+    }
+
+    pub fn has_children(&self) -> bool {
+        !self.children.is_empty()
+    }
+
+    pub fn preview_content(&self, app_state: &AppState) -> String {
+        match &self.preview {
+            Preview::Text(text) => text.clone(),
+            Preview::Provider(provider) => provider(app_state),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Navigation {
+    items: Vec<NavigationItem>,
+    // Ids from the top level down to the highlighted item
+    path: Vec<String>,
+}
+
+impl Navigation {
+    pub fn new(items: Vec<NavigationItem>) -> Self {
+        let path = items.first().map(|item| item.id.clone()).into_iter().collect();
+        Self { items, path }
+    }
+
+    pub fn top_level(&self) -> &[NavigationItem] {
+        &self.items
+    }
+
+    /// The items from the top level down to the highlighted one
+    pub fn trail(&self) -> Vec<&NavigationItem> {
+        let mut trail = Vec::with_capacity(self.path.len());
+        let mut level = self.items.as_slice();
+        for id in &self.path {
+            let Some(item) = level.iter().find(|item| &item.id == id) else {
+                break;
+            };
+            trail.push(item);
+            level = &item.children;
+        }
+        trail
+    }
+
+    /// The items of the menu that is open
+    pub fn items(&self) -> &[NavigationItem] {
+        let trail = self.trail();
+        match trail.len().checked_sub(2) {
+            Some(parent) => &trail[parent].children,
+            None => &self.items,
+        }
+    }
+
+    pub fn selected(&self) -> Option<&NavigationItem> {
+        self.trail().last().copied()
+    }
+
+    pub fn selected_index(&self) -> usize {
+        let selected = self.path.last();
+        self.items()
+            .iter()
+            .position(|item| Some(&item.id) == selected)
+            .unwrap_or(0)
+    }
+
+    /// How many menus deep the selection is; 0 on the top level
+    pub fn depth(&self) -> usize {
+        self.path.len().saturating_sub(1)
+    }
+
+    /// Whether `id` is the highlighted item or one of the menus around it
+    pub fn is_within(&self, id: &str) -> bool {
+        self.path.iter().any(|open| open == id)
+    }
+
+    /// Highlights the item at `index` in the open menu, clamped to its length
+    pub fn select(&mut self, index: usize) {
+        let items = self.items();
+        let Some(item) = items.get(index.min(items.len().saturating_sub(1))) else {
+            return;
+        };
+        let id = item.id.clone();
+        match self.path.last_mut() {
+            Some(last) => *last = id,
+            None => self.path.push(id),
+        }
+    }
+
+    /// Opens the highlighted item's menu. Returns false if it has none.
+    pub fn enter(&mut self) -> bool {
+        let Some(first) = self.selected().and_then(|item| item.children.first()) else {
+            return false;
+        };
+        let id = first.id.clone();
+        self.path.push(id);
+        true
+    }
+
+    /// Closes the open menu, highlighting the item it belongs to. Returns
+    /// false on the top level.
+    pub fn back(&mut self) -> bool {
+        if self.path.len() < 2 {
+            return false;
+        }
+        self.path.pop();
+        true
+    }
+
+    /// Highlights the item with `id` wherever it is, opening the menus
+    /// above it. Returns false if there is no such item.
+    pub fn reveal(&mut self, id: &str) -> bool {
+        match find_path(&self.items, id) {
+            Some(path) => {
+                self.path = path;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn find(&self, id: &str) -> Option<&NavigationItem> {
+        let mut found = None;
+        let mut level = self.items.as_slice();
+        for step in find_path(&self.items, id)? {
+            let item = level.iter().find(|item| item.id == step)?;
+            level = &item.children;
+            found = Some(item);
+        }
+        found
+    }
+
+    /// The open menus as a title, like "Navigation › Image Tools › Batch"
+    pub fn breadcrumb(&self) -> String {
+        let trail = self.trail();
+        let menus = &trail[..trail.len().saturating_sub(1)];
+        std::iter::once("Navigation")
+            .chain(menus.iter().map(|item| item.name.as_str()))
+            .collect::<Vec<_>>()
+            .join(" › ")
+    }
+
+    /// The view of the deepest item on the path that provides one
+    pub fn view(&self) -> Option<ViewProvider> {
+        self.trail().iter().rev().find_map(|item| item.view)
+    }
+}
+
+/// Ids from the top level down to the item with `id`
+fn find_path(items: &[NavigationItem], id: &str) -> Option<Vec<String>> {
+    items.iter().find_map(|item| {
+        if item.id == id {
+            return Some(vec![item.id.clone()]);
+        }
+        let mut path = find_path(&item.children, id)?;
+        path.insert(0, item.id.clone());
+        Some(path)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> Navigation {
+        Navigation::new(vec![
+            NavigationItem::new("home", "Home"),
+            NavigationItem::new("tools", "Image Tools").children(vec![
+                NavigationItem::new("tools.open", "Open"),
+                NavigationItem::new("tools.batch", "Batch").children(vec![
+                    NavigationItem::new("tools.batch.run", "Run"),
+                    NavigationItem::new("tools.batch.stop", "Stop"),
+                ]),
+            ]),
+        ])
+    }
+
+    #[test]
+    fn test_walks_any_depth_by_id() {
+        let mut navigation = tree();
+        assert_eq!(navigation.breadcrumb(), "Navigation");
+        assert!(!navigation.enter());
+        navigation.select(5);
+        assert_eq!(navigation.selected().map(|item| item.id.as_str()), Some("tools"));
+
+        assert!(navigation.enter());
+        navigation.select(1);
+        assert!(navigation.enter());
+        navigation.select(1);
+        assert_eq!(navigation.selected().map(|item| item.name.as_str()), Some("Stop"));
+        assert_eq!(navigation.breadcrumb(), "Navigation › Image Tools › Batch");
+        assert_eq!(navigation.depth(), 2);
+        assert!(navigation.is_within("tools"));
+
+        assert!(navigation.back());
+        assert_eq!(navigation.selected_index(), 1);
+        assert!(navigation.back());
+        assert!(!navigation.back());
+        assert_eq!(navigation.selected_index(), 1);
+    }
+
+    #[test]
+    fn test_reveal_survives_reordering() {
+        let mut navigation = tree();
+        assert!(navigation.reveal("tools.batch.run"));
+        assert_eq!(navigation.breadcrumb(), "Navigation › Image Tools › Batch");
+        assert!(!navigation.reveal("missing"));
+        assert_eq!(navigation.find("tools.open").map(|item| item.name.as_str()), Some("Open"));
+
+        // A new entry ahead of the selection doesn't move it
+        navigation.items[1].children.insert(0, NavigationItem::new("tools.home", "Home"));
+        assert_eq!(navigation.selected().map(|item| item.id.as_str()), Some("tools.batch.run"));
+        assert!(navigation.back());
+        assert_eq!(navigation.selected_index(), 2);
+    }
+}
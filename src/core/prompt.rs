@@ -0,0 +1,258 @@
+// This file tokenizes prompts written in the Automatic1111 prompt grammar,
+// which most diffusion UIs accept in some form, so they can be highlighted
+// and checked for unbalanced brackets.
+//
+// The tokens cover the prompt end to end, so joining their spans gives the
+// prompt back. Like the UIs themselves the grammar is forgiving: a bracket
+// without a partner is flagged and everything around it still tokenizes.
+
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptTokenKind {
+    /// Words, commas and anything else without a special meaning
+    Text,
+    /// The parentheses of `(word)` and `(word:1.2)`
+    Emphasis,
+    /// The brackets of `[word]`
+    DeEmphasis,
+    /// The `:1.2` that sets an emphasis weight
+    Weight,
+    /// A `<lora:name:0.8>`, `<hypernet:name>` or other extra network tag
+    ExtraNetwork,
+    /// The `BREAK` keyword that starts a new chunk
+    Break,
+    /// The braces and bars of `{a|b}`, or the brackets and bars of `[a|b]`
+    Alternation,
+    /// The brackets, colons and step of `[from:to:step]`
+    Schedule,
+    /// A bracket that is never closed, or a closing one that was never opened
+    Unbalanced,
+}
+
+/// A run of the prompt with one meaning. Neighbouring runs of the same
+/// kind are merged, so `((` is a single token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptToken {
+    pub kind: PromptTokenKind,
+    pub span: Range<usize>,
+}
+
+// A bracket waiting for its partner
+struct OpenBracket {
+    bracket: char,
+    token: usize,
+    // The `:` and `|` tokens directly inside it
+    separators: Vec<usize>,
+}
+
+/// Splits `prompt` into tokens covering all of it
+pub fn tokenize(prompt: &str) -> Vec<PromptToken> {
+    use PromptTokenKind::*;
+
+    let mut tokens: Vec<PromptToken> = Vec::new();
+    let mut open: Vec<OpenBracket> = Vec::new();
+    let mut chars = prompt.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let mut end = start + c.len_utf8();
+        let kind = match c {
+            // An escaped bracket is just text
+            '\\' => {
+                if let Some((i, escaped)) = chars.next() {
+                    end = i + escaped.len_utf8();
+                }
+                Text
+            }
+            '(' | '[' | '{' => {
+                open.push(OpenBracket { bracket: c, token: tokens.len(), separators: Vec::new() });
+                // Until its partner turns up
+                Unbalanced
+            }
+            ')' | ']' | '}' => {
+                // Brackets opened since its partner are the ones left unclosed
+                match open.iter().rposition(|bracket| bracket.bracket == opening(c)) {
+                    Some(index) => {
+                        let bracket = open.remove(index);
+                        open.truncate(index);
+                        tokens.push(PromptToken { kind: Unbalanced, span: start..end });
+                        close(prompt, &mut tokens, bracket);
+                        continue;
+                    }
+                    None => Unbalanced,
+                }
+            }
+            ':' | '|' => {
+                if let Some(bracket) = open.last_mut() {
+                    bracket.separators.push(tokens.len());
+                }
+                Text
+            }
+            '<' => match extra_network_end(prompt, end) {
+                Some(tag_end) => {
+                    end = tag_end;
+                    ExtraNetwork
+                }
+                None => Text,
+            },
+            'B' if is_break(prompt, start) => {
+                end = start + "BREAK".len();
+                Break
+            }
+            _ => Text,
+        };
+        while chars.next_if(|&(i, _)| i < end).is_some() {}
+        tokens.push(PromptToken { kind, span: start..end });
+    }
+
+    // Merge runs so callers see words rather than characters
+    let mut merged: Vec<PromptToken> = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        match merged.last_mut() {
+            Some(last) if last.kind == token.kind => last.span.end = token.span.end,
+            _ => merged.push(token),
+        }
+    }
+    merged
+}
+
+/// Spans of the brackets in `prompt` that have no partner
+pub fn unbalanced(prompt: &str) -> Vec<Range<usize>> {
+    tokenize(prompt)
+        .into_iter()
+        .filter(|token| token.kind == PromptTokenKind::Unbalanced)
+        .map(|token| token.span)
+        .collect()
+}
+
+fn opening(closing: char) -> char {
+    match closing {
+        ')' => '(',
+        ']' => '[',
+        _ => '{',
+    }
+}
+
+/// Works out what a bracket pair means from the separators directly inside
+/// it. The closing bracket is the last token.
+fn close(prompt: &str, tokens: &mut [PromptToken], bracket: OpenBracket) {
+    use PromptTokenKind::*;
+
+    let closing = tokens.len() - 1;
+    let (colons, bars): (Vec<usize>, Vec<usize>) = bracket
+        .separators
+        .iter()
+        .partition(|&&index| &prompt[tokens[index].span.clone()] == ":");
+    let kind = match bracket.bracket {
+        '{' => Alternation,
+        '[' if !bars.is_empty() => Alternation,
+        '[' if !colons.is_empty() => Schedule,
+        '[' => DeEmphasis,
+        _ => Emphasis,
+    };
+    tokens[bracket.token].kind = kind;
+    tokens[closing].kind = kind;
+    let separators = match kind {
+        Alternation => &bars,
+        Schedule => &colons,
+        _ => &Vec::new(),
+    };
+    for &index in separators {
+        tokens[index].kind = kind;
+    }
+
+    // A number after the last colon is an emphasis weight or a schedule step
+    if let (Emphasis | Schedule, Some(&colon)) = (kind, colons.last()) {
+        let number = &prompt[tokens[colon].span.end..tokens[closing].span.start];
+        if number.trim().parse::<f32>().is_ok() {
+            let kind = if kind == Emphasis { Weight } else { Schedule };
+            for token in &mut tokens[colon..closing] {
+                token.kind = kind;
+            }
+        }
+    }
+}
+
+/// End of the `<type:name...>` tag whose `<` ends at `start`, if it is one
+fn extra_network_end(prompt: &str, start: usize) -> Option<usize> {
+    let rest = &prompt[start..];
+    let close = rest.find(['>', '<', '\n']).filter(|&i| rest[i..].starts_with('>'))?;
+    let (kind, _) = rest[..close].split_once(':')?;
+    let is_name = !kind.is_empty() && kind.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    is_name.then_some(start + close + 1)
+}
+
+/// Whether a standalone `BREAK` starts at `start`
+fn is_break(prompt: &str, start: usize) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    prompt[start..].starts_with("BREAK")
+        && !prompt[..start].chars().next_back().is_some_and(is_word)
+        && !prompt[start + "BREAK".len()..].chars().next().is_some_and(is_word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PromptTokenKind::*;
+
+    fn kinds(prompt: &str) -> Vec<(PromptTokenKind, &str)> {
+        tokenize(prompt)
+            .into_iter()
+            .map(|token| (token.kind, &prompt[token.span]))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenizes_the_grammar() {
+        assert_eq!(
+            kinds("a (goblin:1.2), [pie] <lora:troll_v2:0.8> BREAK {cave|forest}"),
+            [
+                (Text, "a "),
+                (Emphasis, "("),
+                (Text, "goblin"),
+                (Weight, ":1.2"),
+                (Emphasis, ")"),
+                (Text, ", "),
+                (DeEmphasis, "["),
+                (Text, "pie"),
+                (DeEmphasis, "]"),
+                (Text, " "),
+                (ExtraNetwork, "<lora:troll_v2:0.8>"),
+                (Text, " "),
+                (Break, "BREAK"),
+                (Text, " "),
+                (Alternation, "{"),
+                (Text, "cave"),
+                (Alternation, "|"),
+                (Text, "forest"),
+                (Alternation, "}"),
+            ]
+        );
+        assert_eq!(
+            kinds("[day:night:0.5] ((pie)) BREAKFAST \\(x\\)"),
+            [
+                (Schedule, "["),
+                (Text, "day"),
+                (Schedule, ":"),
+                (Text, "night"),
+                (Schedule, ":0.5]"),
+                (Text, " "),
+                (Emphasis, "(("),
+                (Text, "pie"),
+                (Emphasis, "))"),
+                (Text, " BREAKFAST \\(x\\)"),
+            ]
+        );
+        // A colon that isn't followed by a weight is plain text
+        assert_eq!(kinds("(a:b)"), [(Emphasis, "("), (Text, "a:b"), (Emphasis, ")")]);
+    }
+
+    #[test]
+    fn test_flags_unbalanced_brackets() {
+        assert_eq!(unbalanced("(a goblin, [eating) pie]"), [11..12, 23..24]);
+        assert_eq!(unbalanced("a goblin) eating}"), [8..9, 16..17]);
+        assert!(unbalanced("((a), [b:c:3], {d|e}, <lora:x:1>)").is_empty());
+        // A `<` that opens no tag stays text
+        assert_eq!(kinds("a < b"), [(Text, "a < b")]);
+    }
+}
@@ -0,0 +1,165 @@
+// This file holds the Unicode helpers behind text editing.
+//
+// Offsets into edited text stay byte offsets, but they only ever move by
+// whole grapheme clusters, so an accented letter written as a base and a
+// combining mark, or an emoji sequence, is one step for the cursor. Widths
+// are terminal columns: CJK characters and most emoji take two.
+
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Start of the grapheme cluster that ends at `offset`
+pub fn previous_boundary(text: &str, offset: usize) -> usize {
+    text[..offset]
+        .grapheme_indices(true)
+        .next_back()
+        .map_or(0, |(start, _)| start)
+}
+
+/// End of the grapheme cluster that starts at `offset`
+pub fn next_boundary(text: &str, offset: usize) -> usize {
+    text[offset..]
+        .graphemes(true)
+        .next()
+        .map_or(offset, |grapheme| offset + grapheme.len())
+}
+
+/// Columns `text` takes up in the terminal
+pub fn width(text: &str) -> usize {
+    text.width()
+}
+
+/// The first grapheme boundary from which the text up to `offset` fits in
+/// `columns`, for scrolling a line so the cursor stays in view
+pub fn scroll_start(text: &str, offset: usize, columns: usize) -> usize {
+    let mut start = offset;
+    let mut used = 0;
+    for (index, grapheme) in text[..offset].grapheme_indices(true).rev() {
+        used += grapheme.width();
+        if used > columns {
+            break;
+        }
+        start = index;
+    }
+    start
+}
+
+/// Start of the line `offset` is on
+pub fn line_start(text: &str, offset: usize) -> usize {
+    text[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+/// End of the line `offset` is on, before its newline
+pub fn line_end(text: &str, offset: usize) -> usize {
+    text[offset..].find('\n').map_or(text.len(), |i| offset + i)
+}
+
+/// Soft-wraps `text` into rows of at most `columns` columns, breaking
+/// after the last space that fits or, in a long word, between graphemes.
+/// Rows are byte ranges without their newline; there is always one.
+pub fn wrap_rows(text: &str, columns: usize) -> Vec<Range<usize>> {
+    let columns = columns.max(1);
+    let mut rows = Vec::new();
+    let mut line_offset = 0;
+
+    for line in text.split('\n') {
+        let mut row_start = line_offset;
+        let mut row_width = 0;
+        // Where the row may break: just after its last space
+        let mut last_space = None;
+        for (index, grapheme) in line.grapheme_indices(true) {
+            let offset = line_offset + index;
+            let grapheme_width = grapheme.width();
+            if row_width + grapheme_width > columns && offset > row_start {
+                let end = last_space.unwrap_or(offset);
+                rows.push(row_start..end);
+                row_start = end;
+                row_width = width(&text[row_start..offset]);
+                last_space = None;
+            }
+            row_width += grapheme_width;
+            if grapheme.chars().all(char::is_whitespace) {
+                last_space = Some(offset + grapheme.len());
+            }
+        }
+        rows.push(row_start..line_offset + line.len());
+        line_offset += line.len() + 1;
+    }
+    rows
+}
+
+/// The row `offset` falls on and its column within that row. An offset
+/// where a wrapped row ends belongs to the start of the next row.
+pub fn locate(text: &str, rows: &[Range<usize>], offset: usize) -> (usize, usize) {
+    let row = rows
+        .iter()
+        .rposition(|row| row.start <= offset)
+        .unwrap_or(0);
+    (row, width(&text[rows[row].start..offset.min(rows[row].end)]))
+}
+
+/// The boundary in `row` closest to `column` without passing it
+pub fn offset_at_column(text: &str, row: &Range<usize>, column: usize) -> usize {
+    let mut used = 0;
+    for (index, grapheme) in text[row.clone()].grapheme_indices(true) {
+        used += grapheme.width();
+        if used > column {
+            return row.start + index;
+        }
+    }
+    // The end of a wrapped row is the start of the next one, so stop short
+    if row.end < text.len() && !text[row.end..].starts_with('\n') {
+        previous_boundary(text, row.end).max(row.start)
+    } else {
+        row.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boundaries_skip_whole_clusters() {
+        // "é" as e + combining acute, a family emoji, then a CJK character
+        let text = "e\u{301}👨\u{200d}👩\u{200d}👧漢";
+        let mut offsets = vec![0];
+        while *offsets.last().unwrap() < text.len() {
+            offsets.push(next_boundary(text, *offsets.last().unwrap()));
+        }
+        assert_eq!(offsets, [0, 3, 21, 24]);
+        assert_eq!(previous_boundary(text, 24), 21);
+        assert_eq!(previous_boundary(text, 21), 3);
+        assert_eq!(previous_boundary(text, 3), 0);
+        assert_eq!(previous_boundary(text, 0), 0);
+    }
+
+    #[test]
+    fn test_width_and_scroll() {
+        assert_eq!(width("e\u{301}"), 1);
+        assert_eq!(width("漢字"), 4);
+        // Three wide characters in four columns: only the last two fit
+        assert_eq!(scroll_start("漢字漢", 9, 4), 3);
+        assert_eq!(scroll_start("漢字漢", 9, 6), 0);
+    }
+
+    #[test]
+    fn test_wrap_rows_and_locate() {
+        let text = "a red goblin\n漢字漢字";
+        let rows = wrap_rows(text, 7);
+        let texts: Vec<&str> = rows.iter().map(|row| &text[row.clone()]).collect();
+        assert_eq!(texts, ["a red ", "goblin", "漢字漢", "字"]);
+
+        assert_eq!(locate(text, &rows, 6), (1, 0));
+        assert_eq!(locate(text, &rows, 12), (1, 6));
+        assert_eq!(locate(text, &rows, text.len()), (3, 2));
+        // Moving up from the end of "goblin" stays on the row above; a wide
+        // character is never split
+        assert_eq!(offset_at_column(text, &rows[0], 6), 5);
+        assert_eq!(offset_at_column(text, &rows[1], 9), 12);
+        assert_eq!(offset_at_column(text, &rows[2], 3), 16);
+        assert_eq!(wrap_rows("", 5).first(), Some(&(0..0)));
+    }
+}
@@ -0,0 +1,365 @@
+// This file holds the themes: every style the UI draws with, by what it is
+// for rather than by colour, so views ask for `theme.error` instead of red.
+//
+// `dark` is defined here and is the base of every other theme. The rest of
+// the built-in themes, and the user's own in `~/.config/pipemind/themes`,
+// are TOML files that start from a base and restyle some slots:
+//
+//     # goblin.toml
+//     base = "solarized"                    # a built-in theme, dark if left out
+//     accent = "light green"                # a colour sets the foreground
+//     highlight = { bg = "#073642", bold = true }
+//
+// A table can set fg, bg, bold, dim, italic, underline and reversed. A
+// slot that is set replaces the base's style for it entirely. The colours
+// of the config file's `[colors]` section go over whichever theme is used.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use ratatui::style::{Color, Modifier, Style};
+use toml_edit::{Document, Item};
+
+use super::{
+    config::{line_at, ColorConfig},
+    paths,
+    prompt::PromptTokenKind,
+};
+
+/// Names of the themes that come with Pipemind, in the order `/theme` lists them
+pub const BUILTIN: &[&str] = &["dark", "light", "high-contrast", "solarized"];
+
+const BUILTIN_FILES: &[(&str, &str)] = &[
+    ("light", include_str!("themes/light.toml")),
+    ("high-contrast", include_str!("themes/high-contrast.toml")),
+    ("solarized", include_str!("themes/solarized.toml")),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    /// Text being typed or looked at
+    pub text: Style,
+    /// The input line's text while something else has focus
+    pub inactive: Style,
+    /// Hints, ids and other secondary text
+    pub muted: Style,
+    pub border: Style,
+    pub border_focused: Style,
+    /// The input box's border while typing in it
+    pub input_focused: Style,
+    /// Boxes inside a view, and popups such as the file browser
+    pub panel: Style,
+    /// The selected row of lists, menus and tables
+    pub highlight: Style,
+    /// The selected completion
+    pub selected: Style,
+    pub cursor: Style,
+    /// Prompts, labels and key names
+    pub accent: Style,
+    pub heading: Style,
+    /// Names of fields and node inputs
+    pub field: Style,
+    /// Folders and links between nodes
+    pub link: Style,
+    pub success: Style,
+    pub error: Style,
+    /// The quit and configuration error dialogs
+    pub modal: Style,
+    /// What only the pinned image of a comparison has
+    pub removed: Style,
+    /// What only the current image of a comparison has
+    pub added: Style,
+    pub prompt_emphasis: Style,
+    pub prompt_de_emphasis: Style,
+    pub prompt_weight: Style,
+    pub prompt_extra_network: Style,
+    pub prompt_break: Style,
+    pub prompt_alternation: Style,
+    pub prompt_schedule: Style,
+    /// Brackets without a partner
+    pub prompt_unbalanced: Style,
+}
+
+type Slot = fn(&mut Theme) -> &mut Style;
+
+// Every style a theme file can set, by its key
+const SLOTS: &[(&str, Slot)] = &[
+    ("text", |theme| &mut theme.text),
+    ("inactive", |theme| &mut theme.inactive),
+    ("muted", |theme| &mut theme.muted),
+    ("border", |theme| &mut theme.border),
+    ("border_focused", |theme| &mut theme.border_focused),
+    ("input_focused", |theme| &mut theme.input_focused),
+    ("panel", |theme| &mut theme.panel),
+    ("highlight", |theme| &mut theme.highlight),
+    ("selected", |theme| &mut theme.selected),
+    ("cursor", |theme| &mut theme.cursor),
+    ("accent", |theme| &mut theme.accent),
+    ("heading", |theme| &mut theme.heading),
+    ("field", |theme| &mut theme.field),
+    ("link", |theme| &mut theme.link),
+    ("success", |theme| &mut theme.success),
+    ("error", |theme| &mut theme.error),
+    ("modal", |theme| &mut theme.modal),
+    ("removed", |theme| &mut theme.removed),
+    ("added", |theme| &mut theme.added),
+    ("prompt_emphasis", |theme| &mut theme.prompt_emphasis),
+    ("prompt_de_emphasis", |theme| &mut theme.prompt_de_emphasis),
+    ("prompt_weight", |theme| &mut theme.prompt_weight),
+    ("prompt_extra_network", |theme| &mut theme.prompt_extra_network),
+    ("prompt_break", |theme| &mut theme.prompt_break),
+    ("prompt_alternation", |theme| &mut theme.prompt_alternation),
+    ("prompt_schedule", |theme| &mut theme.prompt_schedule),
+    ("prompt_unbalanced", |theme| &mut theme.prompt_unbalanced),
+];
+
+const MODIFIERS: &[(&str, Modifier)] = &[
+    ("bold", Modifier::BOLD),
+    ("dim", Modifier::DIM),
+    ("italic", Modifier::ITALIC),
+    ("underline", Modifier::UNDERLINED),
+    ("reversed", Modifier::REVERSED),
+];
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        let fg = |color| Style::default().fg(color);
+        Self {
+            name: String::from("dark"),
+            text: fg(Color::White),
+            inactive: fg(Color::Gray),
+            muted: fg(Color::DarkGray),
+            border: fg(Color::DarkGray),
+            border_focused: fg(Color::White),
+            input_focused: fg(Color::Green),
+            panel: fg(Color::White),
+            highlight: Style::default().bg(Color::DarkGray),
+            selected: fg(Color::Black).bg(Color::Yellow),
+            cursor: fg(Color::Yellow),
+            accent: fg(Color::Yellow),
+            heading: fg(Color::Green).add_modifier(Modifier::BOLD),
+            field: fg(Color::Green),
+            link: fg(Color::Cyan),
+            success: fg(Color::Green),
+            error: fg(Color::Red),
+            modal: fg(Color::Red),
+            removed: fg(Color::Red),
+            added: fg(Color::Green),
+            prompt_emphasis: fg(Color::Yellow),
+            prompt_de_emphasis: fg(Color::Blue),
+            prompt_weight: fg(Color::LightYellow).add_modifier(Modifier::BOLD),
+            prompt_extra_network: fg(Color::Magenta),
+            prompt_break: fg(Color::LightRed).add_modifier(Modifier::BOLD),
+            prompt_alternation: fg(Color::Cyan),
+            prompt_schedule: fg(Color::Green),
+            prompt_unbalanced: fg(Color::White).bg(Color::Red),
+        }
+    }
+
+    /// Reads a theme file's text
+    pub fn parse(text: &str, name: &str) -> Result<Self, String> {
+        let document = Document::parse(text).map_err(|error| {
+            let line = error.span().map_or(1, |span| line_at(text, span.start));
+            format!("line {}: {}", line, error.message().trim())
+        })?;
+        let root = document.as_table();
+        let mistake = |key: &str, message: String| {
+            let span = root.get(key).and_then(Item::span).or_else(|| root.key(key).and_then(|key| key.span()));
+            format!("line {}: {}", span.map_or(1, |span| line_at(text, span.start)), message)
+        };
+
+        let mut theme = match root.get("base") {
+            None => Self::dark(),
+            Some(base) => match base.as_str() {
+                Some("dark") => Self::dark(),
+                Some(base) => match BUILTIN_FILES.iter().find(|(builtin, _)| *builtin == base) {
+                    Some((builtin, text)) => Self::parse(text, builtin)?,
+                    None => return Err(mistake("base", format!("base should be one of {}", BUILTIN.join(", ")))),
+                },
+                None => return Err(mistake("base", String::from("base should be a theme's name in quotes"))),
+            },
+        };
+        theme.name = name.to_string();
+
+        for (key, item) in root.iter() {
+            if key == "base" {
+                continue;
+            }
+            let Some((_, slot)) = SLOTS.iter().find(|(slot, _)| *slot == key) else {
+                return Err(mistake(key, format!("unknown style {}", key)));
+            };
+            *slot(&mut theme) = parse_style(item).map_err(|message| mistake(key, format!("{}: {}", key, message)))?;
+        }
+        Ok(theme)
+    }
+
+    /// The theme with the config file's colours over it
+    pub fn with_colors(mut self, colors: &ColorConfig) -> Self {
+        if let Some(color) = colors.border {
+            self.border = self.border.fg(color);
+        }
+        if let Some(color) = colors.border_focused {
+            self.border_focused = self.border_focused.fg(color);
+        }
+        if let Some(color) = colors.highlight {
+            self.highlight = self.highlight.bg(color);
+        }
+        if let Some(color) = colors.accent {
+            self.accent = self.accent.fg(color);
+            self.cursor = self.cursor.fg(color);
+            self.selected = self.selected.bg(color);
+        }
+        if let Some(color) = colors.error {
+            self.error = self.error.fg(color);
+            self.modal = self.modal.fg(color);
+        }
+        self
+    }
+
+    /// How each part of a diffusion prompt is highlighted
+    pub fn prompt(&self, kind: PromptTokenKind) -> Style {
+        match kind {
+            PromptTokenKind::Text => Style::default(),
+            PromptTokenKind::Emphasis => self.prompt_emphasis,
+            PromptTokenKind::DeEmphasis => self.prompt_de_emphasis,
+            PromptTokenKind::Weight => self.prompt_weight,
+            PromptTokenKind::ExtraNetwork => self.prompt_extra_network,
+            PromptTokenKind::Break => self.prompt_break,
+            PromptTokenKind::Alternation => self.prompt_alternation,
+            PromptTokenKind::Schedule => self.prompt_schedule,
+            PromptTokenKind::Unbalanced => self.prompt_unbalanced,
+        }
+    }
+}
+
+/// A colour name for the foreground, or a table of colours and modifiers
+fn parse_style(item: &Item) -> Result<Style, String> {
+    let color = |name: &str| {
+        Color::from_str(name).map_err(|_| format!("{} is not a colour name, a number from 0 to 255 or #rrggbb", name))
+    };
+    if let Some(name) = item.as_str() {
+        return Ok(Style::default().fg(color(name)?));
+    }
+    let Some(table) = item.as_table_like() else {
+        return Err(String::from("expected a colour in quotes or a table such as { fg = \"red\", bold = true }"));
+    };
+    let mut style = Style::default();
+    for (key, value) in table.iter() {
+        match key {
+            "fg" | "bg" => {
+                let name = value.as_str().ok_or_else(|| format!("{} should be a colour in quotes", key))?;
+                style = if key == "fg" { style.fg(color(name)?) } else { style.bg(color(name)?) };
+            }
+            _ => {
+                let Some((_, modifier)) = MODIFIERS.iter().find(|(name, _)| *name == key) else {
+                    return Err(format!("unknown key {}", key));
+                };
+                match value.as_bool() {
+                    Some(true) => style = style.add_modifier(*modifier),
+                    Some(false) => {}
+                    None => return Err(format!("{} should be true or false", key)),
+                }
+            }
+        }
+    }
+    Ok(style)
+}
+
+/// The folder custom themes are read from
+pub fn themes_dir() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("themes"))
+}
+
+/// A built-in theme, or `<name>.toml` from the themes folder
+pub fn load(name: &str) -> Result<Theme, String> {
+    if name == "dark" {
+        return Ok(Theme::dark());
+    }
+    if let Some((builtin, text)) = BUILTIN_FILES.iter().find(|(builtin, _)| *builtin == name) {
+        return Theme::parse(text, builtin);
+    }
+    let Some(dir) = themes_dir() else {
+        return Err(format!("there is no theme called {}", name));
+    };
+    load_file(&dir.join(format!("{}.toml", name)), name)
+}
+
+fn load_file(path: &Path, name: &str) -> Result<Theme, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|_| format!("there is no theme called {} (looked for {})", name, paths::display_path(path)))?;
+    Theme::parse(&text, name).map_err(|error| format!("{} {}", paths::display_path(path), error))
+}
+
+/// The built-in themes, then the custom ones by name
+pub fn available() -> Vec<String> {
+    let mut custom: Vec<String> = themes_dir()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+        .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+        .filter(|name| !BUILTIN.contains(&name.as_str()))
+        .collect();
+    custom.sort();
+    BUILTIN.iter().map(|name| name.to_string()).chain(custom).collect()
+}
+
+/// True if the user asked for no colours; see https://no-color.org
+pub fn no_color() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_themes_parse() {
+        for name in BUILTIN {
+            let theme = load(name).unwrap();
+            assert_eq!(theme.name, *name);
+        }
+        assert_ne!(load("light").unwrap().text, Theme::dark().text);
+        assert!(load("no such theme").is_err());
+    }
+
+    #[test]
+    fn test_parse_custom_theme() {
+        let theme = Theme::parse(
+            "# Mine\nbase = \"solarized\"\naccent = \"light green\"\nhighlight = { bg = \"#073642\", bold = true }\n",
+            "goblin",
+        )
+        .unwrap();
+        let solarized = load("solarized").unwrap();
+        assert_eq!(theme.name, "goblin");
+        assert_eq!(theme.accent, Style::default().fg(Color::LightGreen));
+        assert_eq!(theme.highlight, Style::default().bg(Color::Rgb(0x07, 0x36, 0x42)).add_modifier(Modifier::BOLD));
+        assert_eq!(theme.error, solarized.error);
+
+        let error = |text: &str| Theme::parse(text, "goblin").unwrap_err();
+        assert_eq!(error("accent = \"red\"\nborders = \"red\"\n"), "line 2: unknown style borders");
+        assert_eq!(error("base = \"goblin\"\n"), "line 1: base should be one of dark, light, high-contrast, solarized");
+        assert_eq!(
+            error("\ncursor = { fg = \"red\", blink = true }\n"),
+            "line 2: cursor: unknown key blink"
+        );
+        assert!(error("accent = \"nope\"").contains("nope is not a colour name"));
+
+        // The config file's colours go over the theme's
+        let colors = ColorConfig { accent: Some(Color::Blue), ..ColorConfig::default() };
+        let theme = theme.with_colors(&colors);
+        assert_eq!(theme.accent, Style::default().fg(Color::Blue));
+        assert_eq!(theme.border, solarized.border);
+    }
+}
@@ -0,0 +1,537 @@
+// This file implements the optional vi-style editing of the input line.
+//
+// Insert mode types like the normal input; Esc switches to normal mode,
+// where keys are motions (w b e 0 $ f t F T h l), operators taking a motion
+// (d c y, doubled for the whole line) and the usual commands (i a I A x D C
+// p P u Ctrl-R). Counts multiply, and `.` replays the last change from the
+// keys that made it.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{line_editor::LineEditor, text};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ViMode {
+    Normal,
+    #[default]
+    Insert,
+}
+
+impl ViMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            ViMode::Normal => "NORMAL",
+            ViMode::Insert => "INSERT",
+        }
+    }
+}
+
+/// Whether vi used a key or the regular input handling should see it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViOutcome {
+    Handled,
+    PassThrough,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    WordStart,
+    WordBack,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    // f, t, F or T and the character to look for
+    Find(char, char),
+}
+
+impl Motion {
+    fn from_key(c: char) -> Option<Self> {
+        Some(match c {
+            'h' => Motion::Left,
+            'l' | ' ' => Motion::Right,
+            'w' => Motion::WordStart,
+            'b' => Motion::WordBack,
+            'e' => Motion::WordEnd,
+            '0' => Motion::LineStart,
+            '$' => Motion::LineEnd,
+            _ => return None,
+        })
+    }
+
+    /// Operators include the grapheme these motions land on
+    fn inclusive(self) -> bool {
+        matches!(self, Motion::WordEnd | Motion::Find('f' | 't', _))
+    }
+
+    /// Where the motion takes the cursor, or None if it cannot move
+    fn target(self, text: &str, cursor: usize, count: usize) -> Option<usize> {
+        let line_start = text::line_start(text, cursor);
+        let line_end = text::line_end(text, cursor);
+        let mut offset = cursor;
+        for _ in 0..count {
+            offset = match self {
+                Motion::Left if offset > line_start => text::previous_boundary(text, offset),
+                Motion::Right if offset < line_end => text::next_boundary(text, offset),
+                Motion::Left | Motion::Right => break,
+                Motion::WordStart => word_start_forward(text, offset),
+                Motion::WordBack => word_start_back(text, offset),
+                Motion::WordEnd => word_end_forward(text, offset),
+                Motion::LineStart => line_start,
+                Motion::LineEnd => line_end,
+                Motion::Find(kind, target) => find_on_line(text, offset, kind, target)?,
+            };
+        }
+        Some(offset)
+    }
+}
+
+// Grapheme classes for word motions: blanks, word characters, punctuation
+fn class(grapheme: &str) -> u8 {
+    match grapheme.chars().next() {
+        Some(c) if c.is_whitespace() => 0,
+        Some(c) if c.is_alphanumeric() || c == '_' => 1,
+        _ => 2,
+    }
+}
+
+/// Start of the next word (`w`)
+fn word_start_forward(text: &str, offset: usize) -> usize {
+    let mut graphemes = text[offset..].grapheme_indices(true).peekable();
+    let start_class = graphemes.peek().map(|(_, g)| class(g));
+    for (index, grapheme) in graphemes {
+        let current = class(grapheme);
+        if current != 0 && Some(current) != start_class {
+            return offset + index;
+        }
+        if current == 0 {
+            // Past the first word; the next non-blank starts a new one
+            return text[offset + index..]
+                .grapheme_indices(true)
+                .find(|(_, g)| class(g) != 0)
+                .map_or(text.len(), |(i, _)| offset + index + i);
+        }
+    }
+    text.len()
+}
+
+/// Start of this or the previous word (`b`)
+fn word_start_back(text: &str, offset: usize) -> usize {
+    let mut start = offset;
+    let mut word_class = None;
+    for (index, grapheme) in text[..offset].grapheme_indices(true).rev() {
+        let current = class(grapheme);
+        match word_class {
+            None if current == 0 => {}
+            None => word_class = Some(current),
+            Some(word) if word != current => break,
+            Some(_) => {}
+        }
+        start = index;
+    }
+    if word_class.is_none() { 0 } else { start }
+}
+
+/// Last grapheme of this or the next word (`e`)
+fn word_end_forward(text: &str, offset: usize) -> usize {
+    let mut end = offset;
+    let mut word_class = None;
+    // Always move at least one grapheme, so `e` on a word end goes to the next
+    for (index, grapheme) in text[offset..].grapheme_indices(true).skip(1) {
+        let current = class(grapheme);
+        match word_class {
+            None if current == 0 => continue,
+            None => word_class = Some(current),
+            Some(word) if word != current => break,
+            Some(_) => {}
+        }
+        end = offset + index;
+    }
+    end
+}
+
+/// The `count`th match of `f`, `t`, `F` or `T` on the cursor's line
+fn find_on_line(text: &str, offset: usize, kind: char, target: char) -> Option<usize> {
+    let line_start = text::line_start(text, offset);
+    let line_end = text::line_end(text, offset);
+    let is_target = |grapheme: &str| grapheme.starts_with(target);
+    match kind {
+        'f' | 't' => {
+            // `t` from just before a match would not move, so look past it
+            let from = text::next_boundary(text, offset).min(line_end);
+            let skip = if kind == 't' { text::next_boundary(text, from).min(line_end) } else { from };
+            let (index, _) = text[skip..line_end].grapheme_indices(true).find(|(_, g)| is_target(g))?;
+            let found = skip + index;
+            Some(if kind == 't' { text::previous_boundary(text, found) } else { found })
+        }
+        _ => {
+            let before = if kind == 'T' { text::previous_boundary(text, offset).max(line_start) } else { offset };
+            let (index, _) = text[line_start..before].grapheme_indices(true).rev().find(|(_, g)| is_target(g))?;
+            let found = line_start + index;
+            Some(if kind == 'T' { text::next_boundary(text, found) } else { found })
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ViState {
+    pub mode: ViMode,
+    count: Option<usize>,
+    // A d, c or y waiting for its motion, with the count typed before it
+    operator: Option<(char, usize)>,
+    // f, t, F or T waiting for its character
+    find: Option<char>,
+    // Keys of the command being typed, kept for `.` if it changes the text
+    keys: Vec<KeyEvent>,
+    last_change: Vec<KeyEvent>,
+    // A change typed in insert mode is only complete at Esc
+    recording_insert: bool,
+    replaying: bool,
+}
+
+impl ViState {
+    /// Back to insert mode for a new line, forgetting any pending command
+    pub fn reset(&mut self) {
+        let last_change = std::mem::take(&mut self.last_change);
+        *self = Self { last_change, ..Self::default() };
+    }
+
+    pub fn handle_key(&mut self, editor: &mut LineEditor, key: &KeyEvent) -> ViOutcome {
+        match self.mode {
+            ViMode::Insert => self.handle_insert_key(editor, key),
+            ViMode::Normal => self.handle_normal_key(editor, key),
+        }
+    }
+
+    fn handle_insert_key(&mut self, editor: &mut LineEditor, key: &KeyEvent) -> ViOutcome {
+        let plain = !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Esc => {
+                self.mode = ViMode::Normal;
+                // The cursor steps back onto the last inserted grapheme
+                if editor.cursor() > text::line_start(editor.text(), editor.cursor()) {
+                    editor.move_left();
+                }
+                if self.recording_insert {
+                    self.keys.push(*key);
+                    self.finish_change();
+                    self.recording_insert = false;
+                }
+                return ViOutcome::Handled;
+            }
+            KeyCode::Char(c) if plain => editor.insert_char(c),
+            KeyCode::Backspace => editor.backspace(),
+            KeyCode::Delete => editor.delete(),
+            _ => return ViOutcome::PassThrough,
+        }
+        if self.recording_insert {
+            self.keys.push(*key);
+        }
+        ViOutcome::Handled
+    }
+
+    fn handle_normal_key(&mut self, editor: &mut LineEditor, key: &KeyEvent) -> ViOutcome {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let c = match key.code {
+            KeyCode::Char('r') if ctrl => {
+                editor.redo();
+                return ViOutcome::Handled;
+            }
+            KeyCode::Esc => {
+                self.cancel();
+                return ViOutcome::Handled;
+            }
+            KeyCode::Char(c) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => c,
+            KeyCode::Left => 'h',
+            KeyCode::Right => 'l',
+            KeyCode::Home => '0',
+            KeyCode::End => '$',
+            KeyCode::Backspace => 'h',
+            // Enter, Up/Down, Tab and the Ctrl shortcuts keep working
+            _ => return ViOutcome::PassThrough,
+        };
+        self.keys.push(*key);
+
+        if let Some(kind) = self.find.take() {
+            self.apply_motion(editor, Motion::Find(kind, c));
+            return ViOutcome::Handled;
+        }
+        // Digits build a count; a leading 0 is the line start motion
+        if let Some(digit) = c.to_digit(10)
+            && (digit != 0 || self.count.is_some())
+        {
+            self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit as usize));
+            return ViOutcome::Handled;
+        }
+        if let Some(motion) = Motion::from_key(c) {
+            self.apply_motion(editor, motion);
+            return ViOutcome::Handled;
+        }
+
+        let given = self.count.take();
+        let count = given.unwrap_or(1);
+        match c {
+            'f' | 't' | 'F' | 'T' => {
+                self.count = Some(count);
+                self.find = Some(c);
+                return ViOutcome::Handled;
+            }
+            'd' | 'c' | 'y' => match self.operator.take() {
+                // Doubled, the operator works on the whole line
+                Some((operator, _)) if operator == c => self.apply_to_line(editor, c),
+                Some(_) => self.cancel(),
+                None => {
+                    self.operator = Some((c, count));
+                    return ViOutcome::Handled;
+                }
+            },
+            _ if self.operator.is_some() => self.cancel(),
+            '.' => {
+                self.keys.clear();
+                self.repeat(editor, given);
+                return ViOutcome::Handled;
+            }
+            'i' => self.start_insert(),
+            'a' => {
+                if editor.cursor() < text::line_end(editor.text(), editor.cursor()) {
+                    editor.move_right();
+                }
+                self.start_insert();
+            }
+            'I' => {
+                editor.move_line_start();
+                self.start_insert();
+            }
+            'A' => {
+                editor.move_line_end();
+                self.start_insert();
+            }
+            'x' => {
+                let end = Motion::Right.target(editor.text(), editor.cursor(), count).unwrap_or(editor.cursor());
+                editor.cut(editor.cursor()..end);
+                self.finish_change();
+            }
+            'D' | 'C' => {
+                editor.cut(editor.cursor()..text::line_end(editor.text(), editor.cursor()));
+                if c == 'C' {
+                    self.start_insert();
+                } else {
+                    self.finish_change();
+                }
+            }
+            'p' | 'P' => {
+                let offset = if c == 'p' { text::next_boundary(editor.text(), editor.cursor()) } else { editor.cursor() };
+                for _ in 0..count {
+                    editor.paste_at(offset);
+                }
+                self.finish_change();
+            }
+            'u' => {
+                for _ in 0..count {
+                    editor.undo();
+                }
+                self.keys.clear();
+            }
+            _ => self.cancel(),
+        }
+        self.clamp_cursor(editor);
+        ViOutcome::Handled
+    }
+
+    /// Moves by `motion`, or applies the pending operator over it
+    fn apply_motion(&mut self, editor: &mut LineEditor, mut motion: Motion) {
+        let count = self.count.take().unwrap_or(1);
+        let Some((operator, operator_count)) = self.operator.take() else {
+            if let Some(target) = motion.target(editor.text(), editor.cursor(), count) {
+                editor.set_cursor(target);
+            }
+            self.keys.clear();
+            self.clamp_cursor(editor);
+            return;
+        };
+
+        let cursor = editor.cursor();
+        let mut steps = count * operator_count;
+        // Like vim, `cw` on a word changes to its end, not up to the next word
+        let mut graphemes = editor.text()[cursor..].graphemes(true).map(class);
+        let (here, next) = (graphemes.next(), graphemes.next());
+        if operator == 'c' && motion == Motion::WordStart && here.is_some_and(|here| here != 0) {
+            motion = Motion::WordEnd;
+            // On a word's last grapheme, `e` would jump to the next word
+            if here != next {
+                steps -= 1;
+            }
+        }
+        let Some(target) = motion.target(editor.text(), cursor, steps) else {
+            return self.cancel();
+        };
+        let (start, end) = if target < cursor { (target, cursor) } else { (cursor, target) };
+        let end = if motion.inclusive() { text::next_boundary(editor.text(), end) } else { end };
+        self.operate(editor, operator, start..end);
+    }
+
+    fn apply_to_line(&mut self, editor: &mut LineEditor, operator: char) {
+        let cursor = editor.cursor();
+        let start = text::line_start(editor.text(), cursor);
+        let end = text::line_end(editor.text(), cursor);
+        // Deleting a whole line of several takes its newline too
+        let range = match operator {
+            'd' if end < editor.text().len() => start..end + 1,
+            'd' if start > 0 => start - 1..end,
+            _ => start..end,
+        };
+        self.operate(editor, operator, range);
+    }
+
+    fn operate(&mut self, editor: &mut LineEditor, operator: char, range: std::ops::Range<usize>) {
+        match operator {
+            'y' => {
+                editor.copy(range.clone());
+                editor.set_cursor(range.start);
+                self.keys.clear();
+            }
+            'c' => {
+                editor.cut(range);
+                self.start_insert();
+                return;
+            }
+            _ => {
+                editor.cut(range);
+                self.finish_change();
+            }
+        }
+        self.clamp_cursor(editor);
+    }
+
+    fn start_insert(&mut self) {
+        self.mode = ViMode::Insert;
+        self.recording_insert = true;
+    }
+
+    /// Keeps the keys of a finished change for `.`
+    fn finish_change(&mut self) {
+        if !self.replaying {
+            self.last_change = std::mem::take(&mut self.keys);
+        }
+        self.keys.clear();
+    }
+
+    fn cancel(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.find = None;
+        self.keys.clear();
+    }
+
+    /// Replays the last change. A count replaces the one the change was
+    /// made with, as in vim: `3x` then `2.` deletes two.
+    fn repeat(&mut self, editor: &mut LineEditor, count: Option<usize>) {
+        let keys = match count {
+            Some(count) => with_count(&self.last_change, count),
+            None => self.last_change.clone(),
+        };
+        self.replaying = true;
+        for key in &keys {
+            self.handle_key(editor, key);
+        }
+        self.replaying = false;
+        self.keys.clear();
+    }
+
+    /// In normal mode the cursor rests on a grapheme, never past the end
+    fn clamp_cursor(&self, editor: &mut LineEditor) {
+        let cursor = editor.cursor();
+        if self.mode == ViMode::Normal
+            && cursor == text::line_end(editor.text(), cursor)
+            && cursor > text::line_start(editor.text(), cursor)
+        {
+            editor.set_cursor(text::previous_boundary(editor.text(), cursor));
+        }
+    }
+}
+
+/// The keys of a change with its counts, before the command and after an
+/// operator, replaced by `count`
+fn with_count(keys: &[KeyEvent], count: usize) -> Vec<KeyEvent> {
+    // The length of the count `keys` start with; a count can't start with 0
+    let count_len = |keys: &[KeyEvent]| {
+        let digit = |key: &KeyEvent| matches!(key.code, KeyCode::Char(c) if c.is_ascii_digit());
+        match keys.first() {
+            Some(key) if digit(key) && key.code != KeyCode::Char('0') => keys.iter().take_while(|key| digit(key)).count(),
+            _ => 0,
+        }
+    };
+    let rest = &keys[count_len(keys)..];
+    let mut replaced: Vec<KeyEvent> = count.to_string().chars().map(|c| KeyEvent::from(KeyCode::Char(c))).collect();
+    match rest.split_first() {
+        Some((operator, motion)) if matches!(operator.code, KeyCode::Char('d' | 'c' | 'y')) => {
+            replaced.push(*operator);
+            replaced.extend_from_slice(&motion[count_len(motion)..]);
+        }
+        _ => replaced.extend_from_slice(rest),
+    }
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feeds keys to a vi editor holding `text`, starting in normal mode at
+    // `cursor`. Esc is written as `|`.
+    fn run(text: &str, cursor: usize, keys: &str) -> (String, usize, ViMode) {
+        let mut editor = LineEditor::default();
+        editor.set_text(text.to_string());
+        editor.set_cursor(cursor);
+        let mut vi = ViState { mode: ViMode::Normal, ..ViState::default() };
+        for c in keys.chars() {
+            let code = if c == '|' { KeyCode::Esc } else { KeyCode::Char(c) };
+            vi.handle_key(&mut editor, &KeyEvent::from(code));
+        }
+        (editor.text().to_string(), editor.cursor(), vi.mode)
+    }
+
+    #[test]
+    fn test_motions_and_counts() {
+        let text = "a red-eyed goblin, eating";
+        assert_eq!(run(text, 0, "w").1, 2);
+        assert_eq!(run(text, 0, "3w").1, 6);
+        assert_eq!(run(text, 0, "2e").1, 5);
+        assert_eq!(run(text, 11, "b").1, 6);
+        assert_eq!(run(text, 0, "$").1, text.len() - 1);
+        assert_eq!(run(text, 9, "0").1, 0);
+        assert_eq!(run(text, 0, "fe").1, 3);
+        assert_eq!(run(text, 0, "2fe").1, 6);
+        assert_eq!(run(text, 0, "t,").1, 16);
+        assert_eq!(run(text, 24, "Fo").1, 12);
+        // A count given to . replaces the change's own
+        assert_eq!(run("abcdefgh", 0, "3x2.").0, "fgh");
+        assert_eq!(run("a b c d e f g h", 0, "2d2w1.").0, "f g h");
+        assert_eq!(run(text, 0, "d2w2.").0, "goblin, eating");
+    }
+
+    #[test]
+    fn test_operators() {
+        let text = "a red-eyed goblin";
+        assert_eq!(run(text, 2, "dw").0, "a -eyed goblin");
+        assert_eq!(run(text, 2, "d2w").0, "a eyed goblin");
+        assert_eq!(run(text, 2, "dt ").0, "a  goblin");
+        assert_eq!(run(text, 0, "d$").0, "");
+        assert_eq!(run(text, 5, "dd").0, "");
+        assert_eq!(run(text, 2, "cwold|"), (String::from("a old-eyed goblin"), 4, ViMode::Normal));
+        assert_eq!(run(text, 11, "ywP").0, "a red-eyed goblingoblin");
+        assert_eq!(run(text, 2, "x$p").0, "a ed-eyed goblinr");
+        assert_eq!(run(text, 11, "C|").0, "a red-eyed ");
+        assert_eq!(run("two\nlines", 5, "dd").0, "two");
+    }
+
+    #[test]
+    fn test_dot_repeats_last_change() {
+        assert_eq!(run("a b c d", 0, "dw..").0, "d");
+        assert_eq!(run("a b c d", 0, "dw2.").0, "d");
+        assert_eq!(run("pie pie pie", 0, "cwtart|w.").0, "tart tart pie");
+        assert_eq!(run("abc", 0, "x.u").0, "bc");
+    }
+}
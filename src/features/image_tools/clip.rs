@@ -0,0 +1,280 @@
+// This file counts prompt tokens the way the CLIP text encoder sees them,
+// so it is obvious when a prompt runs past the 75 tokens of one chunk.
+//
+// The tokenizer is CLIP's byte-level BPE. Its merges come from the
+// `bpe_simple_vocab_16e6.txt.gz` file that ships with CLIP (gzipped or
+// not), and the vocabulary is built from them the same way CLIP does.
+// Chunking follows Automatic1111: prompt syntax is not tokenized, `BREAK`
+// pads the current chunk out, and padding counts as used tokens.
+
+use std::{collections::HashMap, fmt, fs, io::{self, Read}, ops::Range, path::{Path, PathBuf}};
+
+use flate2::read::GzDecoder;
+
+use crate::core::{
+    config::Config,
+    paths,
+    prompt::{self, PromptTokenKind},
+};
+
+/// The file the merges are read from, in the data directory
+pub const VOCAB_FILE: &str = "bpe_simple_vocab_16e6.txt.gz";
+
+/// Where the merges are read from: the `clip_vocabulary` option, or
+/// `VOCAB_FILE` in the data directory
+pub fn vocabulary_path(config: &Config) -> Option<PathBuf> {
+    match config.general.clip_vocabulary.as_str() {
+        "" => paths::data_dir().map(|dir| dir.join(VOCAB_FILE)),
+        path => Some(paths::expand_tilde(path)),
+    }
+}
+
+/// Tokens in one chunk, not counting its start and end tokens
+pub const CHUNK_LENGTH: usize = 75;
+
+// CLIP only uses the merges that fit its 49408-token vocabulary
+const MERGE_COUNT: usize = 49152 - 256 - 2;
+
+const END_OF_WORD: &str = "</w>";
+
+#[derive(Debug, Clone)]
+pub struct ClipTokenizer {
+    encoder: HashMap<String, u32>,
+    ranks: HashMap<(String, String), usize>,
+    byte_encoder: [char; 256],
+}
+
+/// How much of the CLIP context a prompt takes up
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClipCount {
+    pub tokens: usize,
+    pub chunks: usize,
+    /// The words that start each chunk after the first
+    pub boundaries: Vec<Range<usize>>,
+}
+
+impl fmt::Display for ClipCount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = if self.chunks == 1 { "" } else { "s" };
+        write!(f, "tokens: {}/{} ({} chunk{})", self.tokens, self.chunks * CHUNK_LENGTH, self.chunks, plural)
+    }
+}
+
+impl ClipTokenizer {
+    /// Reads the merges from `path`, gunzipping them if needed
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let text = if bytes.starts_with(&[0x1f, 0x8b]) {
+            let mut text = String::new();
+            GzDecoder::new(bytes.as_slice()).read_to_string(&mut text)?;
+            text
+        } else {
+            String::from_utf8(bytes).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?
+        };
+        Ok(Self::from_merges(&text))
+    }
+
+    /// Builds the tokenizer from the text of a merges file, whose first
+    /// line is a version header
+    pub fn from_merges(text: &str) -> Self {
+        let byte_encoder = bytes_to_unicode();
+        let merges: Vec<(String, String)> = text
+            .lines()
+            .skip(1)
+            .take(MERGE_COUNT)
+            .filter_map(|line| line.split_once(' '))
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+            .collect();
+
+        // Same order as CLIP: bytes, bytes ending a word, merges, specials
+        let mut vocab: Vec<String> = byte_order().map(|byte| byte_encoder[byte as usize].to_string()).collect();
+        let word_ends: Vec<String> = vocab.iter().map(|symbol| format!("{}{}", symbol, END_OF_WORD)).collect();
+        vocab.extend(word_ends);
+        vocab.extend(merges.iter().map(|(a, b)| format!("{}{}", a, b)));
+        vocab.extend([String::from("<|startoftext|>"), String::from("<|endoftext|>")]);
+
+        let encoder = vocab.into_iter().enumerate().map(|(id, symbol)| (symbol, id as u32)).collect();
+        let ranks = merges.into_iter().enumerate().map(|(rank, pair)| (pair, rank)).collect();
+        Self { encoder, ranks, byte_encoder }
+    }
+
+    /// Token ids for plain text, without start and end tokens
+    pub fn encode(&self, text: &str) -> Vec<u32> {
+        words(text, 0)
+            .into_iter()
+            .flat_map(|word| self.bpe(&text[word]))
+            .filter_map(|symbol| self.encoder.get(&symbol).copied())
+            .collect()
+    }
+
+    /// Counts the tokens of a prompt and where its chunks start
+    pub fn count(&self, prompt: &str) -> ClipCount {
+        let mut count = ClipCount::default();
+        // Tokens in the chunk being filled
+        let mut current = 0;
+        let finish_chunk = |count: &mut ClipCount, current: &mut usize| {
+            count.tokens += CHUNK_LENGTH;
+            count.chunks += 1;
+            *current = 0;
+        };
+
+        for token in prompt::tokenize(prompt) {
+            match token.kind {
+                PromptTokenKind::Break => finish_chunk(&mut count, &mut current),
+                PromptTokenKind::Text => {
+                    for word in words(&prompt[token.span.clone()], token.span.start) {
+                        let tokens = self.bpe(&prompt[word.clone()]).len();
+                        for _ in 0..tokens {
+                            if current == CHUNK_LENGTH {
+                                finish_chunk(&mut count, &mut current);
+                            }
+                            if current == 0 && count.chunks > 0 && count.boundaries.last() != Some(&word) {
+                                count.boundaries.push(word.clone());
+                            }
+                            current += 1;
+                        }
+                    }
+                }
+                // Weights, brackets and extra network tags are not sent to CLIP
+                _ => {}
+            }
+        }
+        if current > 0 || count.chunks == 0 {
+            count.tokens += current;
+            count.chunks += 1;
+        }
+        count
+    }
+
+    /// Splits one word into BPE symbols
+    fn bpe(&self, word: &str) -> Vec<String> {
+        let lower = word.to_lowercase();
+        let mut symbols: Vec<String> = lower
+            .bytes()
+            .map(|byte| self.byte_encoder[byte as usize].to_string())
+            .collect();
+        if let Some(last) = symbols.last_mut() {
+            last.push_str(END_OF_WORD);
+        }
+
+        // Keep merging the pair that was learned first
+        loop {
+            let best = symbols
+                .windows(2)
+                .filter_map(|pair| self.ranks.get(&(pair[0].clone(), pair[1].clone())))
+                .min();
+            let Some(&rank) = best else {
+                break;
+            };
+            let mut merged = Vec::with_capacity(symbols.len());
+            let mut i = 0;
+            while i < symbols.len() {
+                if i + 1 < symbols.len() && self.ranks.get(&(symbols[i].clone(), symbols[i + 1].clone())) == Some(&rank) {
+                    merged.push(format!("{}{}", symbols[i], symbols[i + 1]));
+                    i += 2;
+                } else {
+                    merged.push(symbols[i].clone());
+                    i += 1;
+                }
+            }
+            symbols = merged;
+        }
+        symbols
+    }
+}
+
+/// Splits text the way CLIP's pre-tokenizer does: contractions, runs of
+/// letters, single digits and runs of other symbols. Ranges are offset by
+/// `base`; escaping backslashes are skipped.
+fn words(text: &str, base: usize) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() || (c == '\\' && chars.peek().is_some()) {
+            continue;
+        }
+        let rest = &text[start..];
+        let contraction = ["'s", "'t", "'re", "'ve", "'m", "'ll", "'d"]
+            .into_iter()
+            .find(|contraction| rest.get(..contraction.len()).is_some_and(|s| s.eq_ignore_ascii_case(contraction)));
+        let end = match contraction {
+            Some(contraction) => start + contraction.len(),
+            None if c.is_numeric() => start + c.len_utf8(),
+            None => {
+                let same_run = |next: char| {
+                    if c.is_alphabetic() {
+                        next.is_alphabetic()
+                    } else {
+                        !next.is_whitespace() && !next.is_alphabetic() && !next.is_numeric() && next != '\\'
+                    }
+                };
+                rest.char_indices().find(|&(_, next)| !same_run(next)).map_or(text.len(), |(i, _)| start + i.max(c.len_utf8()))
+            }
+        };
+        while chars.next_if(|&(i, _)| i < end).is_some() {}
+        words.push(base + start..base + end);
+    }
+    words
+}
+
+// Bytes in the order CLIP lists them: the printable ones first
+fn byte_order() -> impl Iterator<Item = u8> {
+    let printable = |byte: &u8| matches!(byte, b'!'..=b'~' | 0xa1..=0xac | 0xae..=0xff);
+    (0..=255).filter(printable).chain((0..=255).filter(move |byte| !printable(byte)))
+}
+
+/// CLIP's reversible map from bytes to printable characters: printable
+/// bytes stand for themselves, the rest are shifted past 255
+fn bytes_to_unicode() -> [char; 256] {
+    let mut table = ['\0'; 256];
+    let mut shifted = 0;
+    for byte in byte_order() {
+        let code = if matches!(byte, b'!'..=b'~' | 0xa1..=0xac | 0xae..=0xff) {
+            byte as u32
+        } else {
+            shifted += 1;
+            255 + shifted
+        };
+        table[byte as usize] = char::from_u32(code).unwrap_or('\0');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenizer() -> ClipTokenizer {
+        ClipTokenizer::from_merges("#version: 0.2\ng o\ngo b\nl i\nli n</w>\ngob lin</w>\ni e</w>")
+    }
+
+    #[test]
+    fn test_bpe_and_words() {
+        let tokenizer = tokenizer();
+        assert_eq!(tokenizer.bpe("Goblin"), ["goblin</w>"]);
+        assert_eq!(tokenizer.bpe("pie"), ["p", "ie</w>"]);
+        assert_eq!(
+            words("a goblin's pie, 42!", 0).into_iter().map(|word| &"a goblin's pie, 42!"[word]).collect::<Vec<_>>(),
+            ["a", "goblin", "'s", "pie", ",", "4", "2", "!"]
+        );
+        // Single bytes come first in the vocabulary, merges after both byte sets
+        assert_eq!(tokenizer.encode("!"), [256]);
+        assert_eq!(tokenizer.encode("goblin"), [512 + 4]);
+    }
+
+    #[test]
+    fn test_count_chunks() {
+        let tokenizer = tokenizer();
+        assert_eq!(tokenizer.count("").to_string(), "tokens: 0/75 (1 chunk)");
+        assert_eq!(tokenizer.count("(goblin:1.2) <lora:troll:1> pie").to_string(), "tokens: 3/75 (1 chunk)");
+
+        let prompt = "a ".repeat(160);
+        let count = tokenizer.count(&prompt);
+        assert_eq!(count.to_string(), "tokens: 160/225 (3 chunks)");
+        assert_eq!(count.boundaries, [150..151, 300..301]);
+
+        let count = tokenizer.count("goblin BREAK pie BREAK");
+        assert_eq!(count.to_string(), "tokens: 150/150 (2 chunks)");
+        assert_eq!(count.boundaries.first(), Some(&(13..16)));
+    }
+}
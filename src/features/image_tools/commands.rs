@@ -0,0 +1,147 @@
+// This file registers the Image Tools slash commands.
+// They do the same things as the submenu entries, for people who would
+// rather type `/open ~/outputs/goblin.png` than walk the file browser.
+
+use super::{
+    feature::{state_mut, NAVIGATION_ID},
+    handlers::{convert_image, edit_parameters, open_image, pin_image, strip_image, toggle_diff},
+    metadata::MetadataSource,
+};
+use crate::core::{
+    app_state::AppState,
+    command::{ArgSpec, Command, CommandArgs, CommandRegistry},
+    paths::expand_tilde,
+};
+
+pub fn register_commands(registry: &mut CommandRegistry) {
+    registry.register(Command {
+        name: "open",
+        aliases: &["o"],
+        args: vec![ArgSpec::optional("path", "Image to open; without it, the file browser opens").path()],
+        help: "Open an image in Image Tools",
+        handler: open,
+    });
+    registry.register(Command {
+        name: "close",
+        aliases: &[],
+        args: vec![],
+        help: "Close the current image",
+        handler: |app_state, _| {
+            let Some(state) = state_mut(app_state) else {
+                return String::new();
+            };
+            state.close_image();
+            String::from("Image closed")
+        },
+    });
+    registry.register(Command {
+        name: "pin",
+        aliases: &[],
+        args: vec![],
+        help: "Pin the current image to compare the next one against",
+        handler: |app_state, _| state_mut(app_state).map(pin_image).unwrap_or_default(),
+    });
+    registry.register(Command {
+        name: "diff",
+        aliases: &[],
+        args: vec![],
+        help: "Toggle the comparison of the pinned and current images",
+        handler: |app_state, _| {
+            app_state.show_feature(NAVIGATION_ID);
+            state_mut(app_state).and_then(toggle_diff).unwrap_or_default()
+        },
+    });
+    registry.register(Command {
+        name: "edit",
+        aliases: &[],
+        args: vec![],
+        help: "Edit the parameters stored in the current image",
+        handler: |app_state, _| state_mut(app_state).and_then(edit_parameters).unwrap_or_default(),
+    });
+    registry.register(Command {
+        name: "strip",
+        aliases: &[],
+        args: vec![],
+        help: "Remove all metadata from the current image (a backup is kept)",
+        handler: |app_state, _| state_mut(app_state).map(strip_image).unwrap_or_default(),
+    });
+    registry.register(Command {
+        name: "convert",
+        aliases: &[],
+        args: vec![ArgSpec::required("format", "a1111 or comfyui").values(&["a1111", "comfyui"])],
+        help: "Rewrite the current image's metadata in another tool's format",
+        handler: convert,
+    });
+    registry.register(Command {
+        name: "library",
+        aliases: &["lib"],
+        args: vec![],
+        help: "Show the indexed image library",
+        handler: |app_state, _| {
+            app_state.show_feature(NAVIGATION_ID);
+            if let Some(state) = state_mut(app_state) {
+                state.open_library();
+            }
+            String::new()
+        },
+    });
+    registry.register(Command {
+        name: "scan",
+        aliases: &[],
+        args: vec![ArgSpec::optional("folder", "Folder to add; without it, every known folder is rescanned").folder()],
+        help: "Scan a folder into the image library",
+        handler: scan,
+    });
+}
+
+fn open(app_state: &mut AppState, args: &CommandArgs) -> String {
+    app_state.show_feature(NAVIGATION_ID);
+    let path = args.get("path").map(expand_tilde);
+    let Some(state) = state_mut(app_state) else {
+        return String::new();
+    };
+    match path {
+        Some(path) => open_image(state, &path),
+        None => {
+            state.open_file_browser();
+            String::new()
+        }
+    }
+}
+
+fn convert(app_state: &mut AppState, args: &CommandArgs) -> String {
+    let target = match args.get("format").map(str::to_lowercase).as_deref() {
+        Some("a1111" | "automatic1111") => MetadataSource::Automatic1111,
+        Some("comfyui" | "comfy") => MetadataSource::ComfyUi,
+        _ => return String::from("Unknown format; expected a1111 or comfyui"),
+    };
+    state_mut(app_state).map(|state| convert_image(state, target)).unwrap_or_default()
+}
+
+fn scan(app_state: &mut AppState, args: &CommandArgs) -> String {
+    app_state.show_feature(NAVIGATION_ID);
+    let Some(state) = state_mut(app_state) else {
+        return String::new();
+    };
+    state.open_library();
+    // Opening an empty library asks for a folder; a folder given here makes that moot
+    let folder = args.get("folder").map(expand_tilde);
+    if folder.is_some() {
+        state.close_file_browser();
+    }
+    let Some(library) = state.library.as_mut() else {
+        return String::new();
+    };
+    match folder {
+        Some(folder) if !folder.is_dir() => format!("Not a folder: {}", folder.display()),
+        Some(folder) => {
+            let message = format!("Scanning {}", folder.display());
+            library.start_scan(vec![folder]);
+            message
+        }
+        None => {
+            library.start_scan(Vec::new());
+            library.status.clone()
+        }
+    }
+}
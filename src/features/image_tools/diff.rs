@@ -0,0 +1,230 @@
+// This file compares the generation metadata of two images.
+//
+// Prompts are compared token by token, where a token is one comma-separated
+// chunk of the prompt ("a goblin", "(eating pie:1.2)"), since that's the unit
+// people actually add, remove and reweight between renders. Settings and
+// LoRAs are compared field by field.
+
+use super::metadata::{GenerationMetadata, LoraRef};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Same,
+    // Only in the pinned (left) image
+    Removed,
+    // Only in the current (right) image
+    Added,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenDiff {
+    pub kind: DiffKind,
+    pub token: String,
+}
+
+/// One setting on both sides; None where the image doesn't record it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingChange {
+    pub label: &'static str,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+impl SettingChange {
+    pub fn is_changed(&self) -> bool {
+        self.left != self.right
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoraChange {
+    Same(LoraRef),
+    Removed(LoraRef),
+    Added(LoraRef),
+    // Same LoRA, different strength
+    Changed { left: LoraRef, right: LoraRef },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataDiff {
+    pub prompt: Vec<TokenDiff>,
+    pub negative_prompt: Vec<TokenDiff>,
+    pub settings: Vec<SettingChange>,
+    pub loras: Vec<LoraChange>,
+}
+
+impl MetadataDiff {
+    /// True if the two images were generated with identical metadata
+    pub fn is_identical(&self) -> bool {
+        self.prompt.iter().chain(&self.negative_prompt).all(|token| token.kind == DiffKind::Same)
+            && self.settings.iter().all(|setting| !setting.is_changed())
+            && self.loras.iter().all(|lora| matches!(lora, LoraChange::Same(_)))
+    }
+}
+
+pub fn diff_metadata(left: &GenerationMetadata, right: &GenerationMetadata) -> MetadataDiff {
+    MetadataDiff {
+        prompt: diff_tokens(&prompt_tokens(&left.prompt), &prompt_tokens(&right.prompt)),
+        negative_prompt: diff_tokens(
+            &prompt_tokens(&left.negative_prompt),
+            &prompt_tokens(&right.negative_prompt),
+        ),
+        settings: diff_settings(left, right),
+        loras: diff_loras(&left.loras, &right.loras),
+    }
+}
+
+/// Splits a prompt into its comma- or newline-separated chunks
+pub fn prompt_tokens(prompt: &str) -> Vec<String> {
+    prompt
+        .split([',', '\n'])
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Longest-common-subsequence diff; prompts are short enough for the O(n*m) table
+fn diff_tokens(left: &[String], right: &[String]) -> Vec<TokenDiff> {
+    let (n, m) = (left.len(), right.len());
+    let mut table = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            table[i][j] = if left[i] == right[j] {
+                table[i + 1][j + 1] + 1
+            } else {
+                table[i + 1][j].max(table[i][j + 1])
+            };
+        }
+    }
+
+    let token = |kind, token: &String| TokenDiff { kind, token: token.clone() };
+    let mut diff = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if left[i] == right[j] {
+            diff.push(token(DiffKind::Same, &left[i]));
+            i += 1;
+            j += 1;
+        } else if table[i + 1][j] >= table[i][j + 1] {
+            diff.push(token(DiffKind::Removed, &left[i]));
+            i += 1;
+        } else {
+            diff.push(token(DiffKind::Added, &right[j]));
+            j += 1;
+        }
+    }
+    diff.extend(left[i..].iter().map(|t| token(DiffKind::Removed, t)));
+    diff.extend(right[j..].iter().map(|t| token(DiffKind::Added, t)));
+    diff
+}
+
+fn diff_settings(left: &GenerationMetadata, right: &GenerationMetadata) -> Vec<SettingChange> {
+    let settings = |g: &GenerationMetadata| {
+        [
+            ("Model", g.model.clone()),
+            ("Model hash", g.model_hash.clone()),
+            ("Seed", g.seed.map(|seed| seed.to_string())),
+            ("Steps", g.steps.map(|steps| steps.to_string())),
+            ("Sampler", g.sampler.clone()),
+            ("Scheduler", g.scheduler.clone()),
+            ("CFG scale", g.cfg_scale.map(|cfg| cfg.to_string())),
+            ("Size", g.size.map(|(w, h)| format!("{}x{}", w, h))),
+        ]
+    };
+    settings(left)
+        .into_iter()
+        .zip(settings(right))
+        .map(|((label, left), (_, right))| SettingChange { label, left, right })
+        .filter(|setting| setting.left.is_some() || setting.right.is_some())
+        .collect()
+}
+
+fn diff_loras(left: &[LoraRef], right: &[LoraRef]) -> Vec<LoraChange> {
+    let mut changes = Vec::new();
+    for lora in left {
+        let change = match right.iter().find(|other| other.name == lora.name) {
+            Some(other) if other == lora => LoraChange::Same(lora.clone()),
+            Some(other) => LoraChange::Changed {
+                left: lora.clone(),
+                right: other.clone(),
+            },
+            None => LoraChange::Removed(lora.clone()),
+        };
+        changes.push(change);
+    }
+    for lora in right {
+        if !left.iter().any(|other| other.name == lora.name) {
+            changes.push(LoraChange::Added(lora.clone()));
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lora(name: &str, strength: f32) -> LoraRef {
+        LoraRef {
+            name: name.to_string(),
+            strength_model: strength,
+            strength_clip: None,
+        }
+    }
+
+    #[test]
+    fn test_prompt_token_diff() {
+        let left = prompt_tokens("a goblin, eating pie, in a cave");
+        let right = prompt_tokens("a goblin, eating cake,\nin a cave, night");
+        let diff = diff_tokens(&left, &right);
+        let kinds: Vec<(DiffKind, &str)> = diff.iter().map(|diff| (diff.kind, diff.token.as_str())).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (DiffKind::Same, "a goblin"),
+                (DiffKind::Removed, "eating pie"),
+                (DiffKind::Added, "eating cake"),
+                (DiffKind::Same, "in a cave"),
+                (DiffKind::Added, "night"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_settings_and_lora_changes() {
+        let left = GenerationMetadata {
+            seed: Some(1),
+            steps: Some(30),
+            model: Some("base".to_string()),
+            loras: vec![lora("pie", 0.6), lora("cave", 1.0)],
+            ..GenerationMetadata::default()
+        };
+        let right = GenerationMetadata {
+            seed: Some(2),
+            steps: Some(30),
+            model: Some("base".to_string()),
+            loras: vec![lora("pie", 0.8), lora("night", 1.0)],
+            ..GenerationMetadata::default()
+        };
+
+        let diff = diff_metadata(&left, &right);
+        let changed: Vec<&str> = diff
+            .settings
+            .iter()
+            .filter(|setting| setting.is_changed())
+            .map(|setting| setting.label)
+            .collect();
+        assert_eq!(changed, vec!["Seed"]);
+        assert_eq!(
+            diff.loras,
+            vec![
+                LoraChange::Changed { left: lora("pie", 0.6), right: lora("pie", 0.8) },
+                LoraChange::Removed(lora("cave", 1.0)),
+                LoraChange::Added(lora("night", 1.0)),
+            ]
+        );
+        assert!(!diff.is_identical());
+        assert!(diff_metadata(&left, &left).is_identical());
+    }
+}
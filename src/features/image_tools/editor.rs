@@ -0,0 +1,230 @@
+#![allow(clippy::module_name_repetitions)]
+
+// This file implements the modal editor for an image's parameters text.
+// It is a small multi-line text area: type to insert, arrows to move,
+// Ctrl-S to write the text into the image file, Esc to throw it away.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Position, Rect},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use crate::core::theme::Theme;
+
+/// What the caller should do after a key press in the editor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditorEvent {
+    None,
+    Save(String),
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParametersEditor {
+    pub text: String,
+    // Byte offset into `text`, always on a char boundary
+    pub cursor: usize,
+}
+
+impl ParametersEditor {
+    pub fn new(text: String) -> Self {
+        let cursor = text.len();
+        Self { text, cursor }
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(c) = self.text[..self.cursor].chars().next_back() {
+            self.cursor -= c.len_utf8();
+            self.text.remove(self.cursor);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.cursor < self.text.len() {
+            self.text.remove(self.cursor);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        if let Some(c) = self.text[..self.cursor].chars().next_back() {
+            self.cursor -= c.len_utf8();
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if let Some(c) = self.text[self.cursor..].chars().next() {
+            self.cursor += c.len_utf8();
+        }
+    }
+
+    pub fn move_line_start(&mut self) {
+        self.cursor = self.line_start(self.cursor);
+    }
+
+    pub fn move_line_end(&mut self) {
+        self.cursor = self.text[self.cursor..]
+            .find('\n')
+            .map_or(self.text.len(), |i| self.cursor + i);
+    }
+
+    /// Moves to the previous line, keeping the column where possible
+    pub fn move_up(&mut self) {
+        let start = self.line_start(self.cursor);
+        if start == 0 {
+            return;
+        }
+        let column = self.text[start..self.cursor].chars().count();
+        let previous = self.line_start(start - 1);
+        self.cursor = self.offset_in_line(previous, column);
+    }
+
+    /// Moves to the next line, keeping the column where possible
+    pub fn move_down(&mut self) {
+        let start = self.line_start(self.cursor);
+        let column = self.text[start..self.cursor].chars().count();
+        if let Some(newline) = self.text[self.cursor..].find('\n') {
+            self.cursor = self.offset_in_line(self.cursor + newline + 1, column);
+        }
+    }
+
+    fn line_start(&self, offset: usize) -> usize {
+        self.text[..offset].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// Byte offset of `column` chars into the line starting at `start`, clamped to its end
+    fn offset_in_line(&self, start: usize, column: usize) -> usize {
+        self.text[start..]
+            .char_indices()
+            .take_while(|(_, c)| *c != '\n')
+            .nth(column)
+            .map_or_else(
+                || self.text[start..].find('\n').map_or(self.text.len(), |i| start + i),
+                |(i, _)| start + i,
+            )
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent) -> EditorEvent {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('s') if ctrl => return EditorEvent::Save(self.text.clone()),
+            KeyCode::Esc => return EditorEvent::Cancelled,
+            KeyCode::Char(c) if !ctrl => self.insert(c),
+            KeyCode::Enter => self.insert('\n'),
+            KeyCode::Backspace => self.backspace(),
+            KeyCode::Delete => self.delete(),
+            KeyCode::Left => self.move_left(),
+            KeyCode::Right => self.move_right(),
+            KeyCode::Up => self.move_up(),
+            KeyCode::Down => self.move_down(),
+            KeyCode::Home => self.move_line_start(),
+            KeyCode::End => self.move_line_end(),
+            _ => {}
+        }
+        EditorEvent::None
+    }
+
+    /// Splits the text into rows of at most `width` chars, returning the rows
+    /// and the row/column the cursor lands on
+    fn wrap(&self, width: usize) -> (Vec<String>, (usize, usize)) {
+        let width = width.max(1);
+        let mut rows = Vec::new();
+        let mut cursor = (0, 0);
+        let mut offset = 0;
+
+        for line in self.text.split('\n') {
+            let chars: Vec<(usize, char)> = line.char_indices().collect();
+            let row_count = chars.len().div_ceil(width).max(1);
+            for row in 0..row_count {
+                let slice = &chars[(row * width).min(chars.len())..((row + 1) * width).min(chars.len())];
+                let row_start = offset + slice.first().map_or(line.len(), |(i, _)| *i);
+                let row_end = offset + slice.last().map_or(line.len(), |(i, c)| i + c.len_utf8());
+                // The cursor sits on this row if it falls inside it, or at the
+                // end of the line's last row
+                if self.cursor >= row_start && (self.cursor < row_end || (row + 1 == row_count && self.cursor == row_end)) {
+                    cursor = (rows.len(), self.text[row_start..self.cursor].chars().count());
+                }
+                rows.push(slice.iter().map(|(_, c)| c).collect());
+            }
+            offset += line.len() + 1;
+        }
+        (rows, cursor)
+    }
+}
+
+pub fn render_parameters_editor(f: &mut Frame, area: Rect, editor: &ParametersEditor, theme: &Theme) {
+    let block = Block::default()
+        .title("Edit parameters")
+        .borders(Borders::ALL)
+        .border_style(theme.panel);
+    let inner = block.inner(area);
+
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(1),    // Text
+            Constraint::Length(1), // Key hints
+        ])
+        .split(inner);
+
+    let (rows, (cursor_row, cursor_column)) = editor.wrap(chunks[0].width as usize);
+    // Scroll just enough to keep the cursor in view
+    let height = chunks[0].height as usize;
+    let scroll = (cursor_row + 1).saturating_sub(height);
+    let lines: Vec<Line> = rows.into_iter().skip(scroll).take(height).map(Line::from).collect();
+    f.render_widget(Paragraph::new(lines), chunks[0]);
+
+    let hints = Line::from(vec![
+        Span::styled("Ctrl-S", theme.accent),
+        Span::raw(" save to file   "),
+        Span::styled("Esc", theme.accent),
+        Span::raw(" cancel"),
+    ]);
+    f.render_widget(Paragraph::new(hints), chunks[1]);
+
+    f.set_cursor_position(Position::new(
+        chunks[0].x + cursor_column as u16,
+        chunks[0].y + (cursor_row - scroll) as u16,
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vertical_moves_keep_column() {
+        let mut editor = ParametersEditor::new(String::from("a goblin\npie\nin a cave"));
+        editor.move_up();
+        assert_eq!(editor.cursor, "a goblin\npie".len());
+        editor.move_up();
+        assert_eq!(editor.cursor, "a g".len());
+        editor.move_down();
+        editor.move_down();
+        assert_eq!(editor.cursor, "a goblin\npie\nin ".len());
+
+        editor.backspace();
+        editor.insert('\n');
+        assert_eq!(editor.text, "a goblin\npie\nin\na cave");
+    }
+
+    #[test]
+    fn test_wrap_places_cursor() {
+        let mut editor = ParametersEditor::new(String::from("abcdef\ngh"));
+        assert_eq!(editor.wrap(4), (vec!["abcd".into(), "ef".into(), "gh".into()], (2, 2)));
+        editor.cursor = 4;
+        assert_eq!(editor.wrap(4).1, (1, 0));
+        editor.cursor = 6;
+        assert_eq!(editor.wrap(4).1, (1, 2));
+    }
+}
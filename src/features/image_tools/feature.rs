@@ -0,0 +1,144 @@
+// This file plugs Image Tools into the shell as a `Feature`.
+// The state is the feature: the registry owns it, and the commands and
+// navigation actions reach it through `state` and `state_mut`.
+
+use crossterm::event::KeyEvent;
+use ratatui::{layout::Rect, Frame};
+
+use super::{
+    clip::{self, ClipTokenizer},
+    commands,
+    editor::render_parameters_editor,
+    handlers::{self, handle_editor_key, handle_file_browser_key, handle_preview_action},
+    state::ImageToolsState,
+    views::render_image_tools_view,
+};
+use crate::{
+    core::{
+        app_state::AppState,
+        command::CommandRegistry,
+        config::Config,
+        keymap::{Action, Context},
+        navigation::NavigationItem,
+        paths,
+    },
+    features::{Feature, InputAnnotation},
+    ui::{file_browser::render_file_browser, graphics::GraphicsOutput, utils::centered_rect},
+};
+
+/// Id of the Image Tools entry in the navigation menu
+pub const NAVIGATION_ID: &str = "image_tools";
+
+/// The Image Tools state in `app_state`
+pub fn state(app_state: &AppState) -> Option<&ImageToolsState> {
+    app_state.features.get()
+}
+
+pub fn state_mut(app_state: &mut AppState) -> Option<&mut ImageToolsState> {
+    app_state.features.get_mut()
+}
+
+impl Feature for ImageToolsState {
+    fn id(&self) -> &str {
+        NAVIGATION_ID
+    }
+
+    fn navigation_item(&self) -> NavigationItem {
+        handlers::navigation_item()
+    }
+
+    fn register_commands(&mut self, registry: &mut CommandRegistry) {
+        commands::register_commands(registry);
+    }
+
+    fn render(&self, f: &mut Frame, area: Rect, app_state: &AppState) {
+        render_image_tools_view(f, area, self, app_state);
+    }
+
+    fn key_context(&self) -> Option<Context> {
+        self.is_library_shown().then_some(Context::Library)
+    }
+
+    fn handle_action(&mut self, action: Action) -> bool {
+        handle_preview_action(self, action)
+    }
+
+    fn is_modal(&self) -> bool {
+        self.is_browsing() || self.is_editing()
+    }
+
+    fn handle_modal_key(&mut self, key: &KeyEvent) -> Option<String> {
+        if self.is_browsing() {
+            handle_file_browser_key(self, key)
+        } else {
+            handle_editor_key(self, key)
+        }
+    }
+
+    fn graphics(&self) -> Option<GraphicsOutput> {
+        self.graphics_output.borrow().clone()
+    }
+
+    fn render_overlay(&self, f: &mut Frame, app_state: &AppState) {
+        if let Some(browser) = &self.file_browser {
+            render_file_browser(f, centered_rect(70, 70, f.area()), browser, &app_state.theme);
+        }
+        if let Some(editor) = &self.editor {
+            render_parameters_editor(f, centered_rect(80, 60, f.area()), editor, &app_state.theme);
+        }
+    }
+
+    fn poll(&mut self) -> Vec<String> {
+        let mut messages = std::mem::take(&mut self.messages);
+        messages.extend(self.poll_library_scan());
+        messages
+    }
+
+    // The library, while shown, is searched from the input line
+    fn input_changed(&mut self, text: &str) {
+        if self.is_library_shown() {
+            self.search_library(text);
+        }
+    }
+
+    // Prompts show their CLIP token count, and where each chunk starts.
+    // The count is kept until the text changes, as every draw asks for it.
+    fn input_annotation(&self, text: &str) -> Option<InputAnnotation> {
+        let clip = self.clip.as_ref()?;
+        let mut cached = self.clip_count.borrow_mut();
+        let count = match &*cached {
+            Some((counted, count)) if counted == text => count,
+            _ => &cached.insert((text.to_string(), clip.count(text))).1,
+        };
+        Some(InputAnnotation { label: count.to_string(), marks: count.boundaries.clone() })
+    }
+
+    // The search stays applied after the input clears; an empty line resets it
+    fn submit_input(&mut self, line: &str) {
+        if self.is_library_shown() {
+            self.search_library(line);
+        }
+    }
+
+    fn config_changed(&mut self, config: &Config) {
+        let vocabulary = clip::vocabulary_path(config);
+        if vocabulary == self.clip_vocabulary {
+            return;
+        }
+        self.clip = None;
+        self.clip_count.replace(None);
+        if let Some(path) = &vocabulary {
+            match ClipTokenizer::load(path) {
+                Ok(clip) => self.clip = Some(clip),
+                // No file just means no token counts; a broken one is worth saying
+                Err(error) if path.exists() => self.messages.push(format!(
+                    "Could not read the CLIP vocabulary {}: {}",
+                    paths::display_path(path),
+                    error
+                )),
+                Err(_) => {}
+            }
+        }
+        self.clip_vocabulary = vocabulary;
+    }
+}
@@ -5,7 +5,23 @@
 // between the UI and the state. Think of it as the controller
 // in an MVC pattern.
 
-use super::state::ImageToolsState;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crossterm::event::KeyEvent;
+
+use super::{
+    editor::EditorEvent,
+    feature::{state, state_mut, NAVIGATION_ID},
+    metadata::MetadataSource,
+    state::ImageToolsState,
+};
+use crate::{
+    core::{app_state::AppState, keymap::Action, navigation::NavigationItem},
+    ui::file_browser::{BrowserPurpose, FileBrowserEvent},
+};
 
 #[allow(dead_code)] // Can be removed once `state` is actively used
 pub struct ImageToolsHandler {
@@ -24,4 +40,226 @@ impl ImageToolsHandler {
     pub fn get_welcome_message(&self) -> &str {
         &self.state.welcome_message
     }
-}
\ No newline at end of file
+}
+/// Carries out actions while the Image Tools view has focus.
+/// Returns true if the action was used.
+pub fn handle_preview_action(state: &mut ImageToolsState, action: Action) -> bool {
+    if state.is_library_shown() {
+        return handle_library_action(state, action);
+    }
+
+    let Some(graph) = state
+        .current_image
+        .as_ref()
+        .and_then(|image| image.metadata.comfy_graph.as_ref())
+    else {
+        return false;
+    };
+    let Some(selected_id) = graph.nodes.get(state.node_tree.selected).map(|node| node.id.clone()) else {
+        return false;
+    };
+    let node_count = graph.nodes.len();
+
+    match action {
+        Action::NavDown => state.node_tree.select_next(node_count),
+        Action::NavUp => state.node_tree.select_previous(),
+        Action::Activate | Action::Toggle => state.node_tree.toggle(&selected_id),
+        Action::StepForward if !state.node_tree.is_expanded(&selected_id) => {
+            state.node_tree.toggle(&selected_id);
+        }
+        Action::StepBack if state.node_tree.is_expanded(&selected_id) => {
+            state.node_tree.toggle(&selected_id);
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Drives the library table: move, sort, rescan, add folders, open an image
+fn handle_library_action(state: &mut ImageToolsState, action: Action) -> bool {
+    let Some(library) = state.library.as_mut() else {
+        return false;
+    };
+    match action {
+        Action::NavDown => library.select_next(),
+        Action::NavUp => library.select_previous(),
+        Action::Sort => library.cycle_sort(),
+        Action::ReverseSort => library.reverse_sort(),
+        Action::Rescan => library.start_scan(Vec::new()),
+        Action::AddFolder => state.open_folder_browser(),
+        Action::Back => state.show_library = false,
+        Action::Activate => {
+            if let Err(err) = state.open_library_selection() {
+                state.last_error = Some(format!("Could not open the image: {}", err));
+                state.show_library = false;
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// The Image Tools entry of the navigation menu and its submenu
+pub fn navigation_item() -> NavigationItem {
+    NavigationItem::new(NAVIGATION_ID, "Image Tools")
+        .preview(welcome_message)
+        .children(vec![
+            NavigationItem::new("image_tools.home", "Home")
+                .preview(welcome_message)
+                .action(|app_state| {
+                    app_state.exit_submenu();
+                    None
+                }),
+            NavigationItem::new("image_tools.open", "Open")
+                .preview_text("Press Enter to browse for an image file to analyze...")
+                .action(|app_state| {
+                    state_mut(app_state)?.open_file_browser();
+                    None
+                }),
+            NavigationItem::new("image_tools.close", "Close")
+                .preview_text("Press Enter to close the current image...")
+                .action(|app_state| {
+                    state_mut(app_state)?.close_image();
+                    None
+                }),
+            NavigationItem::new("image_tools.pin", "Pin")
+                .preview_text("Press Enter to pin the current image for comparison...")
+                .action(|app_state| state_mut(app_state).map(pin_image)),
+            NavigationItem::new("image_tools.diff", "Diff")
+                .preview_text("Press Enter to compare the pinned image with the current one...")
+                .action(|app_state| state_mut(app_state).and_then(toggle_diff)),
+            NavigationItem::new("image_tools.edit", "Edit")
+                .preview_text("Press Enter to edit the parameters stored in the current image...")
+                .action(|app_state| state_mut(app_state).and_then(edit_parameters)),
+            NavigationItem::new("image_tools.strip", "Strip")
+                .preview_text("Press Enter to remove all metadata from the current image...")
+                .action(|app_state| state_mut(app_state).map(strip_image)),
+            NavigationItem::new("image_tools.to_a1111", "To A1111")
+                .preview_text("Press Enter to rewrite the metadata as A1111 parameters...")
+                .action(|app_state| state_mut(app_state).map(|state| convert_image(state, MetadataSource::Automatic1111))),
+            NavigationItem::new("image_tools.to_comfyui", "To ComfyUI")
+                .preview_text("Press Enter to rewrite the metadata as a ComfyUI prompt...")
+                .action(|app_state| state_mut(app_state).map(|state| convert_image(state, MetadataSource::ComfyUi))),
+            NavigationItem::new("image_tools.library", "Library")
+                .preview_text("Press Enter to browse and search the indexed image folders...")
+                .action(|app_state| {
+                    state_mut(app_state)?.open_library();
+                    None
+                }),
+        ])
+}
+
+fn welcome_message(app_state: &AppState) -> String {
+    state(app_state).map(|state| state.welcome_message.clone()).unwrap_or_default()
+}
+
+/// Loads an image, recording a failure in the view as well as returning it
+pub fn open_image(state: &mut ImageToolsState, path: &Path) -> String {
+    match state.load_image(path) {
+        Ok(()) => format!("Opened {}", path.display()),
+        Err(err) => {
+            let message = format!("Could not open {}: {}", path.display(), err);
+            state.last_error = Some(message.clone());
+            message
+        }
+    }
+}
+
+pub fn pin_image(state: &mut ImageToolsState) -> String {
+    match state.pin_current_image() {
+        Some(image) => format!("Pinned {}", image.path.display()),
+        None => String::from("Open an image before pinning it"),
+    }
+}
+
+/// Turns the comparison view on or off. Returns a message if it can't be shown.
+pub fn toggle_diff(state: &mut ImageToolsState) -> Option<String> {
+    if state.show_diff {
+        state.show_diff = false;
+    } else if state.can_diff() {
+        state.show_diff = true;
+    } else {
+        return Some(String::from(
+            "Pin an image and open a second one, both with generation metadata, to compare them",
+        ));
+    }
+    None
+}
+
+/// Opens the parameters editor. Returns a message if there is nothing to edit.
+pub fn edit_parameters(state: &mut ImageToolsState) -> Option<String> {
+    (!state.open_editor()).then(|| String::from("Open an image before editing its parameters"))
+}
+
+pub fn strip_image(state: &mut ImageToolsState) -> String {
+    let result = state.strip_current_image();
+    report_write(state, "Stripped metadata from", result)
+}
+
+pub fn convert_image(state: &mut ImageToolsState, target: MetadataSource) -> String {
+    let result = state.convert_current_image(target);
+    let action = match target {
+        MetadataSource::ComfyUi => "Converted to a ComfyUI prompt:",
+        MetadataSource::Automatic1111 | MetadataSource::SwarmUi => "Converted to A1111 parameters:",
+    };
+    report_write(state, action, result)
+}
+
+/// Feeds a key to the parameters editor, saving into the image on Ctrl-S.
+/// Returns a message for the output log once the file was written.
+pub fn handle_editor_key(state: &mut ImageToolsState, key: &KeyEvent) -> Option<String> {
+    let editor = state.editor.as_mut()?;
+    match editor.handle_key(key) {
+        EditorEvent::None => None,
+        EditorEvent::Cancelled => {
+            state.close_editor();
+            None
+        }
+        EditorEvent::Save(text) => {
+            state.close_editor();
+            let result = state.save_parameters(text);
+            Some(report_write(state, "Saved parameters to", result))
+        }
+    }
+}
+
+/// Describes the outcome of rewriting the current image, recording failures
+/// in the view as well as the log
+fn report_write(state: &mut ImageToolsState, action: &str, result: io::Result<PathBuf>) -> String {
+    let name = state
+        .current_image
+        .as_ref()
+        .map(|image| image.path.display().to_string())
+        .unwrap_or_default();
+    match result {
+        Ok(backup) => format!("{} {} (original kept at {})", action, name, backup.display()),
+        Err(err) => {
+            let message = format!("Could not write {}: {}", name, err);
+            state.last_error = Some(message.clone());
+            message
+        }
+    }
+}
+
+/// Feeds a key to the open file browser, loading the image once one is chosen.
+/// Returns a message for the output log when something was opened.
+pub fn handle_file_browser_key(state: &mut ImageToolsState, key: &KeyEvent) -> Option<String> {
+    let browser = state.file_browser.as_mut()?;
+    match browser.handle_key(key) {
+        FileBrowserEvent::None => None,
+        FileBrowserEvent::Cancelled => {
+            state.close_file_browser();
+            None
+        }
+        FileBrowserEvent::Chosen(path) if browser.purpose == BrowserPurpose::ChooseFolder => {
+            state.close_file_browser();
+            let library = state.library.as_mut()?;
+            library.start_scan(vec![path.clone()]);
+            Some(format!("Scanning {}", path.display()))
+        }
+        FileBrowserEvent::Chosen(path) => {
+            state.close_file_browser();
+            Some(open_image(state, &path))
+        }
+    }
+}
@@ -0,0 +1,550 @@
+#![allow(clippy::module_name_repetitions)]
+
+// This file keeps a searchable index of the images under one or more folders.
+//
+// Scanning walks each folder recursively and reads the metadata of every
+// supported image (no pixel decoding, so it stays fast). Files whose size and
+// modification time match the previous scan are not read again, which keeps
+// rescans of large output folders cheap. The index is stored as one JSON file
+// in the data directory and loaded when the library is opened.
+//
+// Scans run on a background thread and report progress over a channel that
+// the UI loop polls between frames.
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::UNIX_EPOCH,
+};
+
+use serde_json::{Value, json};
+
+use super::metadata;
+use crate::ui::file_browser::is_image_path;
+
+/// Bumped whenever the stored entry layout changes; older indexes are discarded
+const INDEX_VERSION: u64 = 1;
+
+/// How often (in files) a running scan reports its progress
+const PROGRESS_INTERVAL: usize = 100;
+
+/// What the index remembers about one image
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    // Modification time in seconds since the Unix epoch
+    pub modified: u64,
+    pub width: u32,
+    pub height: u32,
+    pub model: Option<String>,
+    pub seed: Option<u64>,
+    pub sampler: Option<String>,
+    pub prompt: String,
+    pub negative_prompt: String,
+}
+
+impl IndexEntry {
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// True if every whitespace-separated term of the (lowercased) query appears
+    /// in the prompts, the model or the file name
+    fn matches(&self, terms: &[String]) -> bool {
+        let haystack = format!(
+            "{}\n{}\n{}\n{}",
+            self.prompt,
+            self.negative_prompt,
+            self.model.as_deref().unwrap_or_default(),
+            self.file_name()
+        )
+        .to_lowercase();
+        terms.iter().all(|term| haystack.contains(term.as_str()))
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "path": self.path.to_string_lossy(),
+            "size": self.size,
+            "modified": self.modified,
+            "width": self.width,
+            "height": self.height,
+            "model": self.model,
+            "seed": self.seed,
+            "sampler": self.sampler,
+            "prompt": self.prompt,
+            "negative_prompt": self.negative_prompt,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let string = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+        let number = |key: &str| value.get(key).and_then(Value::as_u64);
+        Some(Self {
+            path: PathBuf::from(string("path")?),
+            size: number("size")?,
+            modified: number("modified")?,
+            width: number("width").unwrap_or_default() as u32,
+            height: number("height").unwrap_or_default() as u32,
+            model: string("model"),
+            seed: number("seed"),
+            sampler: string("sampler"),
+            prompt: string("prompt").unwrap_or_default(),
+            negative_prompt: string("negative_prompt").unwrap_or_default(),
+        })
+    }
+}
+
+/// Totals from one scan, for the status line and output log
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanReport {
+    pub read: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub failed: usize,
+}
+
+impl ScanReport {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} read, {} unchanged, {} removed, {} unreadable",
+            self.read, self.unchanged, self.removed, self.failed
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImageIndex {
+    // Folders that have been scanned, rescanned together
+    pub roots: Vec<PathBuf>,
+    pub entries: Vec<IndexEntry>,
+}
+
+impl ImageIndex {
+    /// Loads the index, treating a missing or outdated file as empty
+    pub fn load(file: &Path) -> io::Result<Self> {
+        let text = match fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+        let root: Value = serde_json::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if root.get("version").and_then(Value::as_u64) != Some(INDEX_VERSION) {
+            return Ok(Self::default());
+        }
+
+        let roots = root
+            .get("roots")
+            .and_then(Value::as_array)
+            .map(|roots| roots.iter().filter_map(Value::as_str).map(PathBuf::from).collect())
+            .unwrap_or_default();
+        let entries = root
+            .get("entries")
+            .and_then(Value::as_array)
+            .map(|entries| entries.iter().filter_map(IndexEntry::from_json).collect())
+            .unwrap_or_default();
+        Ok(Self { roots, entries })
+    }
+
+    pub fn save(&self, file: &Path) -> io::Result<()> {
+        let root = json!({
+            "version": INDEX_VERSION,
+            "roots": self.roots.iter().map(|root| root.to_string_lossy()).collect::<Vec<_>>(),
+            "entries": self.entries.iter().map(IndexEntry::to_json).collect::<Vec<_>>(),
+        });
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write then rename, so a crash mid-save leaves the old index intact
+        let temp = file.with_extension("tmp");
+        fs::write(&temp, root.to_string())?;
+        fs::rename(&temp, file)
+    }
+}
+
+/// Rescans `roots`, reusing entries from `previous` whose size and mtime are
+/// unchanged. Entries under other roots are carried over as they are.
+/// `progress` is called with the number of images seen so far.
+pub fn scan(previous: ImageIndex, roots: &[PathBuf], mut progress: impl FnMut(usize)) -> (ImageIndex, ScanReport) {
+    let mut report = ScanReport::default();
+    let mut known: HashMap<PathBuf, IndexEntry> = HashMap::new();
+    let mut index = ImageIndex {
+        roots: previous.roots,
+        entries: Vec::new(),
+    };
+    for entry in previous.entries {
+        if roots.iter().any(|root| entry.path.starts_with(root)) {
+            known.insert(entry.path.clone(), entry);
+        } else {
+            index.entries.push(entry);
+        }
+    }
+    for root in roots {
+        if !index.roots.contains(root) {
+            index.roots.push(root.clone());
+        }
+    }
+
+    let mut files = Vec::new();
+    for root in roots {
+        collect_images(root, &mut files);
+    }
+    // Nested roots would otherwise list the same file twice
+    files.sort();
+    files.dedup();
+    for (count, path) in files.into_iter().enumerate() {
+        if count % PROGRESS_INTERVAL == 0 {
+            progress(count);
+        }
+        let Ok(stat) = fs::metadata(&path) else {
+            report.failed += 1;
+            continue;
+        };
+        let size = stat.len();
+        let modified = stat
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |age| age.as_secs());
+
+        match known.remove(&path) {
+            Some(entry) if entry.size == size && entry.modified == modified => {
+                report.unchanged += 1;
+                index.entries.push(entry);
+            }
+            _ => match read_entry(&path, size, modified) {
+                Ok(entry) => {
+                    report.read += 1;
+                    index.entries.push(entry);
+                }
+                Err(_) => report.failed += 1,
+            },
+        }
+    }
+    // Whatever is left was deleted since the last scan
+    report.removed = known.len();
+
+    (index, report)
+}
+
+fn read_entry(path: &Path, size: u64, modified: u64) -> io::Result<IndexEntry> {
+    let metadata = metadata::read_metadata(path)?;
+    let generation = metadata.generation.unwrap_or_default();
+    Ok(IndexEntry {
+        path: path.to_path_buf(),
+        size,
+        modified,
+        width: metadata.width,
+        height: metadata.height,
+        model: generation.model,
+        seed: generation.seed,
+        sampler: generation.sampler,
+        prompt: generation.prompt,
+        negative_prompt: generation.negative_prompt,
+    })
+}
+
+/// Recursively gathers image files, skipping hidden files and folders.
+/// Symlinked folders are not followed, so link loops can't trap the scan.
+fn collect_images(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else { continue };
+        let path = entry.path();
+        if file_type.is_dir() {
+            collect_images(&path, files);
+        } else if is_image_path(&path) {
+            files.push(path);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortColumn {
+    Name,
+    Model,
+    Seed,
+    Sampler,
+    Size,
+    Date,
+}
+
+impl SortColumn {
+    pub const ALL: [SortColumn; 6] = [
+        SortColumn::Name,
+        SortColumn::Model,
+        SortColumn::Seed,
+        SortColumn::Sampler,
+        SortColumn::Size,
+        SortColumn::Date,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortColumn::Name => "File",
+            SortColumn::Model => "Model",
+            SortColumn::Seed => "Seed",
+            SortColumn::Sampler => "Sampler",
+            SortColumn::Size => "Size",
+            SortColumn::Date => "Date",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|column| *column == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn compare(self, a: &IndexEntry, b: &IndexEntry) -> Ordering {
+        match self {
+            SortColumn::Name => a.file_name().to_lowercase().cmp(&b.file_name().to_lowercase()),
+            SortColumn::Model => a.model.cmp(&b.model),
+            SortColumn::Seed => a.seed.cmp(&b.seed),
+            SortColumn::Sampler => a.sampler.cmp(&b.sampler),
+            SortColumn::Size => {
+                // Widened, as the sizes come from the index file
+                let area = |entry: &IndexEntry| u64::from(entry.width) * u64::from(entry.height);
+                area(a).cmp(&area(b))
+            }
+            SortColumn::Date => a.modified.cmp(&b.modified),
+        }
+    }
+}
+
+enum ScanMessage {
+    Progress(usize),
+    Done(ImageIndex, ScanReport),
+}
+
+/// The library view: the index plus how it is currently searched and sorted
+pub struct LibraryState {
+    pub index: ImageIndex,
+    pub query: String,
+    pub sort: SortColumn,
+    pub descending: bool,
+    // Positions in `index.entries` that match the query, in display order
+    pub visible: Vec<usize>,
+    pub selected: usize,
+    pub status: String,
+    // Where the index is saved; None keeps it in memory only
+    index_file: Option<PathBuf>,
+    scan: Option<Receiver<ScanMessage>>,
+}
+
+impl LibraryState {
+    pub fn new(index_file: Option<PathBuf>) -> Self {
+        let (index, status) = match index_file.as_deref().map(ImageIndex::load) {
+            Some(Err(err)) => (ImageIndex::default(), format!("Could not read the index: {}", err)),
+            Some(Ok(index)) => {
+                let status = format!("{} images indexed", index.entries.len());
+                (index, status)
+            }
+            None => (ImageIndex::default(), String::new()),
+        };
+        let mut library = Self {
+            index,
+            query: String::new(),
+            sort: SortColumn::Date,
+            descending: true,
+            visible: Vec::new(),
+            selected: 0,
+            status,
+            index_file,
+            scan: None,
+        };
+        library.refresh();
+        library
+    }
+
+    pub fn is_scanning(&self) -> bool {
+        self.scan.is_some()
+    }
+
+    /// Starts a background scan of `roots`, or of every known root if empty
+    pub fn start_scan(&mut self, roots: Vec<PathBuf>) {
+        if self.is_scanning() {
+            return;
+        }
+        let roots = if roots.is_empty() { self.index.roots.clone() } else { roots };
+        if roots.is_empty() {
+            self.status = String::from("No folders to scan yet");
+            return;
+        }
+
+        let previous = self.index.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let progress_sender = sender.clone();
+            let (index, report) = scan(previous, &roots, |count| {
+                let _ = progress_sender.send(ScanMessage::Progress(count));
+            });
+            let _ = sender.send(ScanMessage::Done(index, report));
+        });
+        self.scan = Some(receiver);
+        self.status = String::from("Scanning...");
+    }
+
+    /// Collects scan progress. Returns a message once a scan has finished.
+    pub fn poll_scan(&mut self) -> Option<String> {
+        let receiver = self.scan.as_ref()?;
+        loop {
+            match receiver.try_recv() {
+                Ok(ScanMessage::Progress(count)) => self.status = format!("Scanning... {} images", count),
+                Ok(ScanMessage::Done(index, report)) => {
+                    self.scan = None;
+                    self.index = index;
+                    self.refresh();
+                    let mut message = format!("Scan finished: {}", report.summary());
+                    if let Some(file) = &self.index_file
+                        && let Err(err) = self.index.save(file)
+                    {
+                        message = format!("{} (index not saved: {})", message, err);
+                    }
+                    self.status = format!("{} images indexed, {}", self.index.entries.len(), report.summary());
+                    return Some(message);
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    self.scan = None;
+                    self.status = String::from("The scan stopped unexpectedly");
+                    return Some(self.status.clone());
+                }
+            }
+        }
+    }
+
+    pub fn search(&mut self, query: &str) {
+        self.query = query.trim().to_string();
+        self.selected = 0;
+        self.refresh();
+    }
+
+    /// Moves the sort to the next column
+    pub fn cycle_sort(&mut self) {
+        self.sort = self.sort.next();
+        self.refresh();
+    }
+
+    pub fn reverse_sort(&mut self) {
+        self.descending = !self.descending;
+        self.refresh();
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.visible.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn selected_entry(&self) -> Option<&IndexEntry> {
+        self.visible.get(self.selected).map(|&index| &self.index.entries[index])
+    }
+
+    /// Recomputes the filtered, sorted list of visible entries
+    fn refresh(&mut self) {
+        let terms: Vec<String> = self.query.to_lowercase().split_whitespace().map(str::to_string).collect();
+        let entries = &self.index.entries;
+        self.visible = (0..entries.len()).filter(|&index| entries[index].matches(&terms)).collect();
+
+        let (sort, descending) = (self.sort, self.descending);
+        self.visible.sort_by(|&a, &b| {
+            let ordering = sort.compare(&entries[a], &entries[b]);
+            if descending { ordering.reverse() } else { ordering }
+        });
+        self.selected = self.selected.min(self.visible.len().saturating_sub(1));
+    }
+}
+
+/// Formats a Unix timestamp as `YYYY-MM-DD HH:MM` (UTC)
+pub fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86_400) as i64;
+    let minutes_of_day = (seconds % 86_400) / 60;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes_of_day / 60,
+        minutes_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png_with_parameters(text: &str) -> Vec<u8> {
+        let mut bytes = io::Cursor::new(Vec::new());
+        image::RgbImage::new(3, 2).write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+        let payload = metadata::writer::MetadataPayload {
+            parameters: Some(text.to_string()),
+            ..Default::default()
+        };
+        metadata::writer::embed_metadata(&bytes.into_inner(), &payload).unwrap()
+    }
+
+    #[test]
+    fn test_incremental_scan_and_search() {
+        let dir = std::env::temp_dir().join(format!("pipemind-library-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("night")).unwrap();
+        fs::create_dir_all(dir.join(".thumbs")).unwrap();
+        fs::write(dir.join("goblin.png"), png_with_parameters("a goblin eating pie\nSteps: 20, Seed: 1, Model: base")).unwrap();
+        fs::write(dir.join("night/cave.png"), png_with_parameters("a dark cave\nSteps: 20, Seed: 2, Model: base")).unwrap();
+        fs::write(dir.join(".thumbs/skip.png"), png_with_parameters("hidden")).unwrap();
+        fs::write(dir.join("broken.png"), b"not really a png").unwrap();
+
+        let roots = vec![dir.clone()];
+        let (index, report) = scan(ImageIndex::default(), &roots, |_| {});
+        assert_eq!(report, ScanReport { read: 2, unchanged: 0, removed: 0, failed: 1 });
+        assert_eq!(index.entries.len(), 2);
+
+        fs::remove_file(dir.join("night/cave.png")).unwrap();
+        let (index, report) = scan(index, &roots, |_| {});
+        assert_eq!(report, ScanReport { read: 0, unchanged: 1, removed: 1, failed: 1 });
+
+        let file = dir.join("index.json");
+        index.save(&file).unwrap();
+        let mut library = LibraryState::new(Some(file));
+        assert_eq!(library.index, index);
+        library.search("GOBLIN pie");
+        assert_eq!(library.selected_entry().map(|entry| entry.seed), Some(Some(1)));
+        library.search("goblin cave");
+        assert!(library.selected_entry().is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+        assert_eq!(format_timestamp(1_709_210_096), "2024-02-29 12:34");
+    }
+}
@@ -0,0 +1,281 @@
+// This file parses the "parameters" text written by Automatic1111 and the
+// many UIs that copy its format (Forge, SD.Next, Fooocus in A1111 mode...).
+//
+// The format is loosely defined: the positive prompt, an optional
+// "Negative prompt:" section, and a final line of comma-separated
+// `Key: value` pairs where values containing commas are double-quoted.
+
+use super::{GenerationMetadata, LoraRef};
+
+const NEGATIVE_PROMPT_PREFIX: &str = "Negative prompt:";
+
+/// The last line only counts as the settings line if it holds at least this
+/// many `Key: value` pairs, otherwise it is just part of the prompt.
+const MIN_SETTINGS_PAIRS: usize = 3;
+
+pub fn parse_parameters(text: &str) -> GenerationMetadata {
+    let mut metadata = GenerationMetadata::default();
+    let mut lines: Vec<&str> = text.trim().lines().collect();
+
+    // Peel off the settings line if the last line looks like one
+    if let Some(last) = lines.last() {
+        let pairs = parse_settings_line(last);
+        if pairs.len() >= MIN_SETTINGS_PAIRS {
+            lines.pop();
+            for (key, value) in pairs {
+                apply_setting(&mut metadata, key, value);
+            }
+        }
+    }
+
+    let mut prompt = Vec::new();
+    let mut negative = Vec::new();
+    let mut in_negative = false;
+    for line in lines {
+        if let Some(rest) = line.strip_prefix(NEGATIVE_PROMPT_PREFIX) {
+            in_negative = true;
+            negative.push(rest.trim_start());
+        } else if in_negative {
+            negative.push(line);
+        } else {
+            prompt.push(line);
+        }
+    }
+
+    metadata.prompt = prompt.join("\n").trim().to_string();
+    metadata.negative_prompt = negative.join("\n").trim().to_string();
+    metadata.loras = prompt_loras(&metadata.prompt);
+    metadata
+}
+
+/// Collects the `<lora:name:weight>` (and LyCORIS `<lyco:...>`) tags in a prompt
+pub fn prompt_loras(prompt: &str) -> Vec<LoraRef> {
+    let mut loras = Vec::new();
+    let mut rest = prompt;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>').map(|i| start + i) else {
+            break;
+        };
+        let mut parts = rest[start + 1..end].split(':');
+        if let (Some("lora" | "lyco"), Some(name)) = (parts.next(), parts.next()) {
+            let strength_model = parts.next().and_then(|w| w.trim().parse().ok()).unwrap_or(1.0);
+            let strength_clip = parts.next().and_then(|w| w.trim().parse().ok());
+            loras.push(LoraRef {
+                name: name.trim().to_string(),
+                strength_model,
+                strength_clip,
+            });
+        }
+        rest = &rest[end + 1..];
+    }
+    loras
+}
+
+/// Writes metadata back out in the A1111 format, the inverse of `parse_parameters`.
+/// LoRAs not already tagged in the prompt are appended as `<lora:...>` tags.
+pub fn format_parameters(metadata: &GenerationMetadata) -> String {
+    let mut prompt = metadata.prompt.clone();
+    let tagged = prompt_loras(&prompt);
+    for lora in &metadata.loras {
+        if tagged.iter().any(|other| other.name == lora.name) {
+            continue;
+        }
+        if !prompt.is_empty() {
+            prompt.push(' ');
+        }
+        prompt.push_str(&format!("<lora:{}:{}>", lora.name, lora.strength_model));
+    }
+
+    let mut text = prompt;
+    if !metadata.negative_prompt.is_empty() {
+        text.push_str(&format!("\n{} {}", NEGATIVE_PROMPT_PREFIX, metadata.negative_prompt));
+    }
+
+    let mut settings = Vec::new();
+    let known = [
+        ("Steps", metadata.steps.map(|steps| steps.to_string())),
+        ("Sampler", metadata.sampler.clone()),
+        ("Schedule type", metadata.scheduler.clone()),
+        ("CFG scale", metadata.cfg_scale.map(|cfg| cfg.to_string())),
+        ("Seed", metadata.seed.map(|seed| seed.to_string())),
+        ("Size", metadata.size.map(|(w, h)| format!("{}x{}", w, h))),
+        ("Model hash", metadata.model_hash.clone()),
+        ("Model", metadata.model.clone()),
+    ];
+    for (key, value) in known {
+        if let Some(value) = value {
+            settings.push(format!("{}: {}", key, quote_value(&value)));
+        }
+    }
+    for (key, value) in &metadata.extra {
+        settings.push(format!("{}: {}", key, quote_value(value)));
+    }
+    if !settings.is_empty() {
+        text.push('\n');
+        text.push_str(&settings.join(", "));
+    }
+    text
+}
+
+/// Quotes values that would otherwise be split apart when read back
+fn quote_value(value: &str) -> String {
+    if value.contains([',', ':', '"']) {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Splits a settings line into `(key, value)` pairs, unquoting quoted values
+pub fn parse_settings_line(line: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut chars = line.char_indices().peekable();
+
+    loop {
+        // Skip separators between pairs
+        while matches!(chars.peek(), Some((_, c)) if c.is_whitespace() || *c == ',') {
+            chars.next();
+        }
+        let Some(&(key_start, first)) = chars.peek() else {
+            break;
+        };
+        if !first.is_alphanumeric() {
+            break;
+        }
+
+        let mut key_end = None;
+        for (i, c) in chars.by_ref() {
+            if c == ':' {
+                key_end = Some(i);
+                break;
+            }
+            if c == ',' {
+                break;
+            }
+        }
+        let Some(key_end) = key_end else {
+            // A fragment without a colon means this is not a settings line
+            return Vec::new();
+        };
+        let key = line[key_start..key_end].trim().to_string();
+
+        while matches!(chars.peek(), Some((_, c)) if *c == ' ') {
+            chars.next();
+        }
+
+        let value = if matches!(chars.peek(), Some((_, '"'))) {
+            chars.next();
+            let mut value = String::new();
+            while let Some((_, c)) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some((_, escaped)) = chars.next() {
+                            value.push(escaped);
+                        }
+                    }
+                    '"' => break,
+                    _ => value.push(c),
+                }
+            }
+            value
+        } else {
+            let mut value = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if c == ',' {
+                    break;
+                }
+                value.push(c);
+                chars.next();
+            }
+            value.trim().to_string()
+        };
+
+        pairs.push((key, value));
+    }
+
+    pairs
+}
+
+fn apply_setting(metadata: &mut GenerationMetadata, key: String, value: String) {
+    match key.as_str() {
+        "Steps" => metadata.steps = value.parse().ok(),
+        "Sampler" => metadata.sampler = Some(value),
+        "Schedule type" => metadata.scheduler = Some(value),
+        "CFG scale" => metadata.cfg_scale = value.parse().ok(),
+        "Seed" => metadata.seed = value.parse().ok(),
+        "Size" => metadata.size = parse_size(&value),
+        "Model hash" => metadata.model_hash = Some(value),
+        "Model" => metadata.model = Some(value),
+        _ => metadata.extra.push((key, value)),
+    }
+}
+
+fn parse_size(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.split_once('x')?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_full_parameters() {
+        let text = "a goblin, (eating pie:1.2)\nin a cave <lora:cave_light:0.7>\n\
+            Negative prompt: blurry, lowres\n\
+            Steps: 30, Sampler: DPM++ 2M, Schedule type: Karras, CFG scale: 6.5, Seed: 1234567890, \
+            Size: 832x1216, Model hash: 31e35c80fc, Model: sd_xl_base_1.0, \
+            Lora hashes: \"goblin: abc123, pie: def456\", Version: v1.10.1";
+
+        let metadata = parse_parameters(text);
+        assert_eq!(metadata.prompt, "a goblin, (eating pie:1.2)\nin a cave <lora:cave_light:0.7>");
+        assert_eq!(
+            metadata.loras,
+            vec![LoraRef { name: "cave_light".to_string(), strength_model: 0.7, strength_clip: None }]
+        );
+        assert_eq!(metadata.negative_prompt, "blurry, lowres");
+        assert_eq!(metadata.steps, Some(30));
+        assert_eq!(metadata.sampler.as_deref(), Some("DPM++ 2M"));
+        assert_eq!(metadata.scheduler.as_deref(), Some("Karras"));
+        assert_eq!(metadata.cfg_scale, Some(6.5));
+        assert_eq!(metadata.seed, Some(1234567890));
+        assert_eq!(metadata.size, Some((832, 1216)));
+        assert_eq!(metadata.model_hash.as_deref(), Some("31e35c80fc"));
+        assert_eq!(metadata.model.as_deref(), Some("sd_xl_base_1.0"));
+        assert_eq!(
+            metadata.extra,
+            vec![
+                ("Lora hashes".to_string(), "goblin: abc123, pie: def456".to_string()),
+                ("Version".to_string(), "v1.10.1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_round_trips() {
+        let mut metadata = parse_parameters(
+            "a goblin <lora:pie:0.5>\nNegative prompt: blurry\n\
+            Steps: 20, Sampler: Euler a, CFG scale: 7, Seed: 5, Lora hashes: \"pie: abc, cave: def\"",
+        );
+        metadata.loras.push(LoraRef { name: "cave".to_string(), strength_model: 0.8, strength_clip: None });
+
+        let text = format_parameters(&metadata);
+        assert_eq!(
+            text,
+            "a goblin <lora:pie:0.5> <lora:cave:0.8>\nNegative prompt: blurry\n\
+            Steps: 20, Sampler: Euler a, CFG scale: 7, Seed: 5, Lora hashes: \"pie: abc, cave: def\""
+        );
+        let reparsed = parse_parameters(&text);
+        assert_eq!(reparsed.loras, metadata.loras);
+        assert_eq!(reparsed.extra, metadata.extra);
+        assert_eq!(format_parameters(&reparsed), text);
+    }
+
+    #[test]
+    fn test_prompt_only_keeps_last_line() {
+        let metadata = parse_parameters("a goblin\nsitting: on a chair");
+        assert_eq!(metadata.prompt, "a goblin\nsitting: on a chair");
+        assert!(metadata.steps.is_none());
+        assert!(metadata.extra.is_empty());
+    }
+}
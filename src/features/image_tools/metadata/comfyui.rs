@@ -0,0 +1,541 @@
+// This file decodes the node graphs that ComfyUI embeds in its images.
+//
+// ComfyUI writes two JSON documents: `prompt`, the API-format graph that was
+// actually executed, and `workflow`, the editor layout. Either one is turned
+// into a `ComfyGraph`, which we then walk backwards from the sampler to find
+// the prompt text, seed, model, LoRAs and latent size that produced the image.
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+use super::{GenerationMetadata, LoraRef, MetadataSource};
+
+/// Guards against cycles and absurdly deep graphs when following links
+const MAX_WALK_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputValue {
+    Link { node: String, slot: u64 },
+    Value(Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComfyNode {
+    pub id: String,
+    pub class_type: String,
+    pub title: Option<String>,
+    pub inputs: Vec<(String, InputValue)>,
+}
+
+impl ComfyNode {
+    pub fn input(&self, name: &str) -> Option<&InputValue> {
+        self.inputs
+            .iter()
+            .find(|(input_name, _)| input_name == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn link(&self, name: &str) -> Option<&str> {
+        match self.input(name) {
+            Some(InputValue::Link { node, .. }) => Some(node),
+            _ => None,
+        }
+    }
+
+    /// Name shown in listings: the user's title if they set one, else the node type
+    pub fn display_name(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.class_type)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComfyGraph {
+    pub nodes: Vec<ComfyNode>,
+}
+
+impl ComfyGraph {
+    /// Decodes the API-format `prompt` chunk: `{"<id>": {"class_type", "inputs"}}`
+    pub fn from_prompt(json: &str) -> Option<Self> {
+        let root: Value = serde_json::from_str(json).ok()?;
+        let object = root.as_object()?;
+
+        let mut nodes = Vec::new();
+        for (id, node) in object {
+            let Some(class_type) = node.get("class_type").and_then(Value::as_str) else {
+                continue;
+            };
+            let inputs = node
+                .get("inputs")
+                .and_then(Value::as_object)
+                .map(|inputs| {
+                    inputs
+                        .iter()
+                        .map(|(name, value)| (name.clone(), prompt_input(value)))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let title = node
+                .pointer("/_meta/title")
+                .and_then(Value::as_str)
+                .filter(|title| *title != class_type)
+                .map(str::to_string);
+
+            nodes.push(ComfyNode {
+                id: id.clone(),
+                class_type: class_type.to_string(),
+                title,
+                inputs,
+            });
+        }
+
+        Some(Self::sorted(nodes))
+    }
+
+    /// Decodes the editor-format `workflow` chunk. Widget values are stored
+    /// positionally there, so names are only recovered for well-known nodes.
+    pub fn from_workflow(json: &str) -> Option<Self> {
+        let root: Value = serde_json::from_str(json).ok()?;
+
+        // link id -> (origin node, origin slot)
+        let mut links = HashMap::new();
+        for link in root.get("links").and_then(Value::as_array).into_iter().flatten() {
+            let parsed = match link {
+                Value::Array(parts) if parts.len() >= 3 => {
+                    Some((parts[0].as_u64(), json_id(&parts[1]), parts[2].as_u64()))
+                }
+                Value::Object(_) => Some((
+                    link.get("id").and_then(Value::as_u64),
+                    link.get("origin_id").and_then(json_id),
+                    link.get("origin_slot").and_then(Value::as_u64),
+                )),
+                _ => None,
+            };
+            if let Some((Some(id), Some(origin), Some(slot))) = parsed {
+                links.insert(id, (origin, slot));
+            }
+        }
+
+        let mut nodes = Vec::new();
+        for node in root.get("nodes").and_then(Value::as_array).into_iter().flatten() {
+            let (Some(id), Some(class_type)) = (
+                node.get("id").and_then(json_id),
+                node.get("type").and_then(Value::as_str),
+            ) else {
+                continue;
+            };
+            // Mode 2 is muted and mode 4 is bypassed; neither took part in the render
+            if matches!(node.get("mode").and_then(Value::as_u64), Some(2 | 4)) {
+                continue;
+            }
+
+            let mut inputs = Vec::new();
+            for input in node.get("inputs").and_then(Value::as_array).into_iter().flatten() {
+                let name = input.get("name").and_then(Value::as_str);
+                let link = input.get("link").and_then(Value::as_u64).and_then(|id| links.get(&id));
+                if let (Some(name), Some((origin, slot))) = (name, link) {
+                    inputs.push((
+                        name.to_string(),
+                        InputValue::Link { node: origin.clone(), slot: *slot },
+                    ));
+                }
+            }
+
+            let widget_names = known_widgets(class_type);
+            let widgets = node.get("widgets_values").and_then(Value::as_array);
+            for (index, value) in widgets.into_iter().flatten().enumerate() {
+                let name = widget_names
+                    .get(index)
+                    .map(|name| name.to_string())
+                    .unwrap_or_else(|| format!("widget_{}", index));
+                // A widget converted to an input is fed by its link instead
+                if inputs.iter().any(|(existing, _)| *existing == name) {
+                    continue;
+                }
+                inputs.push((name, InputValue::Value(value.clone())));
+            }
+
+            nodes.push(ComfyNode {
+                id,
+                class_type: class_type.to_string(),
+                title: node.get("title").and_then(Value::as_str).map(str::to_string),
+                inputs,
+            });
+        }
+
+        Some(Self::sorted(nodes))
+    }
+
+    fn sorted(mut nodes: Vec<ComfyNode>) -> Self {
+        // Numeric ids sort numerically, anything else (e.g. "12:3" group ids) after them
+        nodes.sort_by(|a, b| {
+            let key = |node: &ComfyNode| (node.id.parse::<u64>().unwrap_or(u64::MAX), node.id.clone());
+            key(a).cmp(&key(b))
+        });
+        Self { nodes }
+    }
+
+    /// Copies user-assigned node titles from the editor graph, which the
+    /// API-format graph only carries in newer ComfyUI versions
+    pub fn merge_titles(&mut self, workflow: &ComfyGraph) {
+        for node in &mut self.nodes {
+            if node.title.is_none()
+                && let Some(title) = workflow.node(&node.id).and_then(|other| other.title.clone())
+            {
+                node.title = Some(title);
+            }
+        }
+    }
+
+    pub fn node(&self, id: &str) -> Option<&ComfyNode> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    /// Picks the sampler that produced the saved image: the first one reachable
+    /// backwards from an output node, or failing that the first sampler in the graph.
+    pub fn final_sampler(&self) -> Option<&ComfyNode> {
+        let outputs = self.nodes.iter().filter(|node| is_output(&node.class_type));
+        for output in outputs {
+            let mut queue = vec![output.id.as_str()];
+            let mut seen = HashSet::new();
+            while let Some(id) = queue.pop() {
+                if !seen.insert(id) {
+                    continue;
+                }
+                let Some(node) = self.node(id) else { continue };
+                if is_sampler(&node.class_type) {
+                    return Some(node);
+                }
+                for (_, input) in &node.inputs {
+                    if let InputValue::Link { node, .. } = input {
+                        queue.insert(0, node.as_str());
+                    }
+                }
+            }
+        }
+        self.nodes.iter().find(|node| is_sampler(&node.class_type))
+    }
+
+    /// Walks the graph from the final sampler and fills in the generation settings
+    pub fn extract(&self) -> Option<GenerationMetadata> {
+        let sampler = self.final_sampler()?;
+        let mut metadata = GenerationMetadata {
+            source: MetadataSource::ComfyUi,
+            ..GenerationMetadata::default()
+        };
+
+        // SamplerCustomAdvanced keeps model, conditioning and cfg on a separate guider node
+        let guider = sampler.link("guider").and_then(|id| self.node(id));
+        let conditioning_owner = guider.unwrap_or(sampler);
+
+        metadata.seed = self
+            .scalar(sampler, "seed")
+            .or_else(|| self.scalar(sampler, "noise_seed"))
+            .or_else(|| self.linked_scalar(sampler, "noise", "noise_seed"))
+            .and_then(|value| as_u64(&value));
+
+        metadata.steps = self
+            .scalar(sampler, "steps")
+            .or_else(|| self.linked_scalar(sampler, "sigmas", "steps"))
+            .and_then(|value| as_u64(&value))
+            .map(|steps| steps as u32);
+
+        metadata.cfg_scale = self
+            .scalar(conditioning_owner, "cfg")
+            .or_else(|| self.scalar(sampler, "cfg"))
+            .and_then(|value| value.as_f64())
+            .map(|cfg| cfg as f32);
+
+        metadata.sampler = self
+            .scalar(sampler, "sampler_name")
+            .or_else(|| self.linked_scalar(sampler, "sampler", "sampler_name"))
+            .and_then(|value| value.as_str().map(str::to_string));
+
+        metadata.scheduler = self
+            .scalar(sampler, "scheduler")
+            .or_else(|| self.linked_scalar(sampler, "sigmas", "scheduler"))
+            .and_then(|value| value.as_str().map(str::to_string));
+
+        let positive = conditioning_owner
+            .link("positive")
+            .or_else(|| conditioning_owner.link("conditioning"));
+        if let Some(id) = positive {
+            metadata.prompt = self.conditioning_text(id);
+        }
+        if let Some(id) = conditioning_owner.link("negative") {
+            metadata.negative_prompt = self.conditioning_text(id);
+        }
+
+        if let Some(id) = conditioning_owner.link("model").or_else(|| sampler.link("model")) {
+            self.walk_model(id, &mut metadata);
+        }
+
+        if let Some(id) = sampler.link("latent_image") {
+            metadata.size = self.latent_size(id);
+        }
+
+        if let Some(denoise) = self.scalar(sampler, "denoise").and_then(|value| value.as_f64())
+            && denoise < 1.0
+        {
+            metadata.extra.push(("Denoise".to_string(), denoise.to_string()));
+        }
+
+        Some(metadata)
+    }
+
+    /// Resolves an input to a literal value, following a link to a primitive
+    /// or seed node if the widget was converted to an input
+    fn scalar(&self, node: &ComfyNode, name: &str) -> Option<Value> {
+        let mut current = node;
+        let mut input_name = name;
+        for _ in 0..MAX_WALK_DEPTH {
+            match current.input(input_name) {
+                Some(InputValue::Value(value)) => return Some(value.clone()),
+                Some(InputValue::Link { node, .. }) => {
+                    current = self.node(node)?;
+                    if current.input(input_name).is_none() {
+                        // Primitive nodes expose their value under their own name
+                        input_name = current
+                            .inputs
+                            .iter()
+                            .find(|(_, value)| matches!(value, InputValue::Value(v) if !v.is_array()))
+                            .map(|(name, _)| name.as_str())?;
+                    }
+                }
+                None => return None,
+            }
+        }
+        None
+    }
+
+    fn linked_scalar(&self, node: &ComfyNode, link: &str, name: &str) -> Option<Value> {
+        let target = self.node(node.link(link)?)?;
+        self.scalar(target, name)
+    }
+
+    /// Collects the text of every encoder feeding a conditioning input,
+    /// passing through combine, area and ControlNet style nodes
+    fn conditioning_text(&self, id: &str) -> String {
+        let mut texts = Vec::new();
+        let mut stack = vec![(id.to_string(), 0)];
+        let mut seen = HashSet::new();
+
+        while let Some((id, depth)) = stack.pop() {
+            if depth > MAX_WALK_DEPTH || !seen.insert(id.clone()) {
+                continue;
+            }
+            let Some(node) = self.node(&id) else { continue };
+
+            if node.class_type.contains("TextEncode") {
+                for name in ["text", "text_g", "text_l", "clip_l", "t5xxl", "prompt"] {
+                    if let Some(text) = self.scalar(node, name).and_then(|v| v.as_str().map(str::to_string))
+                        && !text.trim().is_empty()
+                        && !texts.contains(&text)
+                    {
+                        texts.push(text);
+                    }
+                }
+                continue;
+            }
+
+            // Push in reverse so conditioning_1 is visited before conditioning_2
+            for (name, input) in node.inputs.iter().rev() {
+                if let InputValue::Link { node, .. } = input
+                    && (name.starts_with("conditioning") || name == "positive" || name == "negative")
+                {
+                    stack.push((node.clone(), depth + 1));
+                }
+            }
+        }
+
+        texts.join("\n")
+    }
+
+    /// Follows the model input back to its loader, picking up LoRAs on the way
+    fn walk_model(&self, id: &str, metadata: &mut GenerationMetadata) {
+        let mut current = Some(id);
+        for _ in 0..MAX_WALK_DEPTH {
+            let Some(node) = current.and_then(|id| self.node(id)) else {
+                break;
+            };
+
+            if let Some(name) = self.scalar(node, "lora_name").and_then(|v| v.as_str().map(str::to_string)) {
+                let strength = |input| self.scalar(node, input).and_then(|v| v.as_f64()).map(|s| s as f32);
+                metadata.loras.push(LoraRef {
+                    name: file_stem(&name),
+                    strength_model: strength("strength_model").unwrap_or(1.0),
+                    strength_clip: strength("strength_clip"),
+                });
+            }
+
+            let checkpoint = self
+                .scalar(node, "ckpt_name")
+                .or_else(|| self.scalar(node, "unet_name"))
+                .and_then(|v| v.as_str().map(str::to_string));
+            if let Some(checkpoint) = checkpoint {
+                metadata.model = Some(file_stem(&checkpoint));
+                break;
+            }
+
+            current = node.link("model");
+        }
+        // Loaders are visited from the sampler outwards; list them in application order
+        metadata.loras.reverse();
+    }
+
+    fn latent_size(&self, id: &str) -> Option<(u32, u32)> {
+        let mut current = Some(id);
+        for _ in 0..MAX_WALK_DEPTH {
+            let node = self.node(current?)?;
+            let width = self.scalar(node, "width").and_then(|v| as_u64(&v));
+            let height = self.scalar(node, "height").and_then(|v| as_u64(&v));
+            if let (Some(width), Some(height)) = (width, height) {
+                return Some((width as u32, height as u32));
+            }
+            current = ["samples", "latent_image", "latent", "pixels"]
+                .iter()
+                .find_map(|name| node.link(name));
+        }
+        None
+    }
+}
+
+fn prompt_input(value: &Value) -> InputValue {
+    // Links are encoded as a two element array: [source node id, output slot]
+    if let Value::Array(parts) = value
+        && parts.len() == 2
+        && let (Some(node), Some(slot)) = (json_id(&parts[0]), parts[1].as_u64())
+    {
+        return InputValue::Link { node, slot };
+    }
+    InputValue::Value(value.clone())
+}
+
+fn json_id(value: &Value) -> Option<String> {
+    match value {
+        Value::String(id) => Some(id.clone()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+fn as_u64(value: &Value) -> Option<u64> {
+    value
+        .as_u64()
+        .or_else(|| value.as_f64().filter(|v| *v >= 0.0).map(|v| v as u64))
+        .or_else(|| value.as_str().and_then(|v| v.trim().parse().ok()))
+}
+
+fn file_stem(path: &str) -> String {
+    let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+pub fn is_sampler(class_type: &str) -> bool {
+    class_type.contains("KSampler") || class_type.starts_with("SamplerCustom")
+}
+
+fn is_output(class_type: &str) -> bool {
+    class_type.contains("SaveImage") || class_type == "PreviewImage" || class_type.starts_with("Image Save")
+}
+
+/// Positional widget names for the core nodes we need to walk through
+fn known_widgets(class_type: &str) -> &'static [&'static str] {
+    match class_type {
+        "KSampler" => &["seed", "control_after_generate", "steps", "cfg", "sampler_name", "scheduler", "denoise"],
+        "KSamplerAdvanced" => &[
+            "add_noise", "noise_seed", "control_after_generate", "steps", "cfg", "sampler_name",
+            "scheduler", "start_at_step", "end_at_step", "return_with_leftover_noise",
+        ],
+        "SamplerCustom" => &["add_noise", "noise_seed", "control_after_generate", "cfg"],
+        "RandomNoise" => &["noise_seed", "control_after_generate"],
+        "KSamplerSelect" => &["sampler_name"],
+        "BasicScheduler" => &["scheduler", "steps", "denoise"],
+        "CFGGuider" => &["cfg"],
+        "CLIPTextEncode" => &["text"],
+        "CheckpointLoaderSimple" => &["ckpt_name"],
+        "CheckpointLoader" => &["config_name", "ckpt_name"],
+        "UNETLoader" => &["unet_name", "weight_dtype"],
+        "LoraLoader" => &["lora_name", "strength_model", "strength_clip"],
+        "LoraLoaderModelOnly" => &["lora_name", "strength_model"],
+        "EmptyLatentImage" | "EmptySD3LatentImage" => &["width", "height", "batch_size"],
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROMPT: &str = r#"{
+        "3": {"class_type": "KSampler", "inputs": {
+            "seed": 156680208700286, "steps": 20, "cfg": 8, "sampler_name": "euler",
+            "scheduler": "normal", "denoise": 1,
+            "model": ["10", 0], "positive": ["6", 0], "negative": ["7", 0], "latent_image": ["5", 0]}},
+        "4": {"class_type": "CheckpointLoaderSimple", "inputs": {"ckpt_name": "sdxl/juggernaut.safetensors"}},
+        "5": {"class_type": "EmptyLatentImage", "inputs": {"width": 832, "height": 1216, "batch_size": 1}},
+        "6": {"class_type": "CLIPTextEncode", "inputs": {"text": "a goblin eating pie", "clip": ["10", 1]}},
+        "7": {"class_type": "CLIPTextEncode", "inputs": {"text": "blurry", "clip": ["10", 1]}},
+        "8": {"class_type": "VAEDecode", "inputs": {"samples": ["3", 0], "vae": ["4", 2]}},
+        "9": {"class_type": "SaveImage", "inputs": {"filename_prefix": "ComfyUI", "images": ["8", 0]}},
+        "10": {"class_type": "LoraLoader", "inputs": {
+            "lora_name": "goblin_style.safetensors", "strength_model": 0.8, "strength_clip": 1,
+            "model": ["4", 0], "clip": ["4", 1]}}
+    }"#;
+
+    #[test]
+    fn test_extracts_from_prompt_graph() {
+        let graph = ComfyGraph::from_prompt(PROMPT).unwrap();
+        assert_eq!(graph.nodes.len(), 8);
+        assert_eq!(graph.nodes[0].id, "3");
+
+        let metadata = graph.extract().unwrap();
+        assert_eq!(metadata.source, MetadataSource::ComfyUi);
+        assert_eq!(metadata.prompt, "a goblin eating pie");
+        assert_eq!(metadata.negative_prompt, "blurry");
+        assert_eq!(metadata.seed, Some(156680208700286));
+        assert_eq!(metadata.steps, Some(20));
+        assert_eq!(metadata.cfg_scale, Some(8.0));
+        assert_eq!(metadata.sampler.as_deref(), Some("euler"));
+        assert_eq!(metadata.scheduler.as_deref(), Some("normal"));
+        assert_eq!(metadata.model.as_deref(), Some("juggernaut"));
+        assert_eq!(metadata.size, Some((832, 1216)));
+        assert_eq!(
+            metadata.loras,
+            vec![LoraRef { name: "goblin_style".to_string(), strength_model: 0.8, strength_clip: Some(1.0) }]
+        );
+    }
+
+    #[test]
+    fn test_extracts_from_workflow_graph() {
+        let workflow = r#"{
+            "nodes": [
+                {"id": 3, "type": "KSampler", "mode": 0,
+                 "inputs": [{"name": "model", "link": 1}, {"name": "positive", "link": 2},
+                            {"name": "negative", "link": 3}, {"name": "latent_image", "link": 4}],
+                 "widgets_values": [42, "fixed", 25, 5.5, "dpmpp_2m", "karras", 1]},
+                {"id": 4, "type": "CheckpointLoaderSimple", "widgets_values": ["model.ckpt"]},
+                {"id": 5, "type": "EmptyLatentImage", "widgets_values": [1024, 1024, 1]},
+                {"id": 6, "type": "CLIPTextEncode", "title": "Positive", "widgets_values": ["a pie"]},
+                {"id": 7, "type": "CLIPTextEncode", "widgets_values": ["ugly"]}
+            ],
+            "links": [[1, 4, 0, 3, 0, "MODEL"], [2, 6, 0, 3, 1, "CONDITIONING"],
+                      [3, 7, 0, 3, 2, "CONDITIONING"], [4, 5, 0, 3, 3, "LATENT"]]
+        }"#;
+
+        let graph = ComfyGraph::from_workflow(workflow).unwrap();
+        assert_eq!(graph.node("6").unwrap().display_name(), "Positive");
+
+        let metadata = graph.extract().unwrap();
+        assert_eq!(metadata.prompt, "a pie");
+        assert_eq!(metadata.negative_prompt, "ugly");
+        assert_eq!(metadata.seed, Some(42));
+        assert_eq!(metadata.steps, Some(25));
+        assert_eq!(metadata.sampler.as_deref(), Some("dpmpp_2m"));
+        assert_eq!(metadata.model.as_deref(), Some("model"));
+        assert_eq!(metadata.size, Some((1024, 1024)));
+    }
+}
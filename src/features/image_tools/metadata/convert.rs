@@ -0,0 +1,206 @@
+// This file converts generation metadata between the A1111 and ComfyUI styles.
+//
+// The two disagree on naming ("DPM++ 2M" + "Karras" versus "dpmpp_2m" +
+// "karras") and on shape: A1111 writes one block of text, ComfyUI a node
+// graph. Going to ComfyUI we build the stock text-to-image graph, which is
+// all a parameters block can describe; anything fancier is lost going the
+// other way, so conversions are best effort by nature.
+
+use serde_json::{Map, Value, json};
+
+use super::{GenerationMetadata, MetadataSource, a1111};
+
+/// A1111 sampler labels and their ComfyUI `sampler_name`
+const SAMPLERS: [(&str, &str); 17] = [
+    ("Euler", "euler"),
+    ("Euler a", "euler_ancestral"),
+    ("Heun", "heun"),
+    ("DPM2", "dpm_2"),
+    ("DPM2 a", "dpm_2_ancestral"),
+    ("LMS", "lms"),
+    ("DPM fast", "dpm_fast"),
+    ("DPM adaptive", "dpm_adaptive"),
+    ("DPM++ 2S a", "dpmpp_2s_ancestral"),
+    ("DPM++ SDE", "dpmpp_sde"),
+    ("DPM++ 2M", "dpmpp_2m"),
+    ("DPM++ 2M SDE", "dpmpp_2m_sde"),
+    ("DPM++ 3M SDE", "dpmpp_3m_sde"),
+    ("DDIM", "ddim"),
+    ("DDPM", "ddpm"),
+    ("UniPC", "uni_pc"),
+    ("LCM", "lcm"),
+];
+
+/// A1111 "Schedule type" labels and their ComfyUI `scheduler`
+const SCHEDULERS: [(&str, &str); 7] = [
+    ("Automatic", "normal"),
+    ("Normal", "normal"),
+    ("Karras", "karras"),
+    ("Exponential", "exponential"),
+    ("SGM Uniform", "sgm_uniform"),
+    ("Simple", "simple"),
+    ("Beta", "beta"),
+];
+
+/// Extension assumed for checkpoints and LoRAs, since both formats drop it
+const MODEL_EXTENSION: &str = ".safetensors";
+
+/// Renames the sampler and scheduler to A1111's labels
+pub fn to_a1111(metadata: &GenerationMetadata) -> GenerationMetadata {
+    let mut converted = metadata.clone();
+    converted.source = MetadataSource::Automatic1111;
+    converted.sampler = metadata.sampler.as_deref().map(|name| lookup(&SAMPLERS, name, false));
+    converted.scheduler = metadata.scheduler.as_deref().map(|name| lookup(&SCHEDULERS, name, false));
+    converted
+}
+
+/// Renames the sampler and scheduler to ComfyUI's names, splitting the
+/// scheduler off old-style A1111 samplers such as "DPM++ 2M Karras"
+pub fn to_comfy(metadata: &GenerationMetadata) -> GenerationMetadata {
+    let mut converted = metadata.clone();
+    converted.source = MetadataSource::ComfyUi;
+
+    let mut sampler = metadata.sampler.clone();
+    let mut scheduler = metadata.scheduler.clone();
+    if scheduler.is_none()
+        && let Some(name) = &sampler
+        && let Some((base, suffix)) = name.rsplit_once(' ')
+        && SCHEDULERS.iter().any(|(label, _)| *label == suffix)
+    {
+        scheduler = Some(suffix.to_string());
+        sampler = Some(base.to_string());
+    }
+    converted.sampler = sampler.as_deref().map(|name| lookup(&SAMPLERS, name, true));
+    converted.scheduler = scheduler.as_deref().map(|name| lookup(&SCHEDULERS, name, true));
+    converted
+}
+
+/// The metadata as an A1111 parameters block
+pub fn to_parameters_text(metadata: &GenerationMetadata) -> String {
+    a1111::format_parameters(&to_a1111(metadata))
+}
+
+/// Builds the API-format `prompt` graph that ComfyUI would have executed to
+/// produce this image: checkpoint, LoRA chain, two text encoders, an empty
+/// latent, the sampler, VAE decode and a save node
+pub fn to_comfy_prompt(metadata: &GenerationMetadata) -> String {
+    let metadata = to_comfy(metadata);
+    let mut graph = Map::new();
+
+    let checkpoint = metadata.model.clone().unwrap_or_else(|| String::from("model"));
+    graph.insert(
+        "4".to_string(),
+        node("CheckpointLoaderSimple", json!({ "ckpt_name": with_extension(&checkpoint) })),
+    );
+
+    // Each LoRA loader takes the model and CLIP from the one before it
+    let (mut model, mut clip) = (json!(["4", 0]), json!(["4", 1]));
+    for (index, lora) in metadata.loras.iter().enumerate() {
+        let id = (10 + index).to_string();
+        graph.insert(
+            id.clone(),
+            node(
+                "LoraLoader",
+                json!({
+                    "lora_name": with_extension(&lora.name),
+                    "strength_model": lora.strength_model,
+                    "strength_clip": lora.strength_clip.unwrap_or(lora.strength_model),
+                    "model": model,
+                    "clip": clip,
+                }),
+            ),
+        );
+        model = json!([id, 0]);
+        clip = json!([id, 1]);
+    }
+
+    let (width, height) = metadata.size.unwrap_or((512, 512));
+    graph.insert(
+        "5".to_string(),
+        node("EmptyLatentImage", json!({ "width": width, "height": height, "batch_size": 1 })),
+    );
+    graph.insert(
+        "6".to_string(),
+        node("CLIPTextEncode", json!({ "text": metadata.prompt, "clip": clip })),
+    );
+    graph.insert(
+        "7".to_string(),
+        node("CLIPTextEncode", json!({ "text": metadata.negative_prompt, "clip": clip })),
+    );
+    graph.insert(
+        "3".to_string(),
+        node(
+            "KSampler",
+            json!({
+                "seed": metadata.seed.unwrap_or(0),
+                "steps": metadata.steps.unwrap_or(20),
+                "cfg": metadata.cfg_scale.unwrap_or(7.0),
+                "sampler_name": metadata.sampler.as_deref().unwrap_or("euler"),
+                "scheduler": metadata.scheduler.as_deref().unwrap_or("normal"),
+                "denoise": 1.0,
+                "model": model,
+                "positive": ["6", 0],
+                "negative": ["7", 0],
+                "latent_image": ["5", 0],
+            }),
+        ),
+    );
+    graph.insert("8".to_string(), node("VAEDecode", json!({ "samples": ["3", 0], "vae": ["4", 2] })));
+    graph.insert(
+        "9".to_string(),
+        node("SaveImage", json!({ "filename_prefix": "pipemind", "images": ["8", 0] })),
+    );
+
+    Value::Object(graph).to_string()
+}
+
+fn node(class_type: &str, inputs: Value) -> Value {
+    json!({ "class_type": class_type, "inputs": inputs })
+}
+
+fn with_extension(name: &str) -> String {
+    if name.contains('.') { name.to_string() } else { format!("{}{}", name, MODEL_EXTENSION) }
+}
+
+/// Maps a name through one of the tables, leaving unknown names untouched
+fn lookup(table: &[(&str, &str)], name: &str, to_comfy: bool) -> String {
+    table
+        .iter()
+        .find(|(a1111, comfy)| if to_comfy { *a1111 == name } else { *comfy == name })
+        .map(|(a1111, comfy)| if to_comfy { *comfy } else { *a1111 })
+        .unwrap_or(name)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::image_tools::metadata::{LoraRef, comfyui::ComfyGraph};
+
+    #[test]
+    fn test_a1111_to_comfy_prompt_round_trip() {
+        let metadata = a1111::parse_parameters(
+            "a goblin\nNegative prompt: blurry\n\
+            Steps: 25, Sampler: DPM++ 2M Karras, CFG scale: 6.5, Seed: 42, Size: 832x1216, Model: juggernaut",
+        );
+        let mut metadata = metadata;
+        metadata.loras.push(LoraRef { name: "pie".to_string(), strength_model: 0.8, strength_clip: None });
+
+        let graph = ComfyGraph::from_prompt(&to_comfy_prompt(&metadata)).unwrap();
+        let extracted = graph.extract().unwrap();
+        assert_eq!(extracted.prompt, "a goblin");
+        assert_eq!(extracted.negative_prompt, "blurry");
+        assert_eq!(extracted.sampler.as_deref(), Some("dpmpp_2m"));
+        assert_eq!(extracted.scheduler.as_deref(), Some("karras"));
+        assert_eq!(extracted.seed, Some(42));
+        assert_eq!(extracted.steps, Some(25));
+        assert_eq!(extracted.cfg_scale, Some(6.5));
+        assert_eq!(extracted.size, Some((832, 1216)));
+        assert_eq!(extracted.model.as_deref(), Some("juggernaut"));
+        assert_eq!(extracted.loras[0].name, "pie");
+
+        let text = to_parameters_text(&extracted);
+        assert!(text.contains("Sampler: DPM++ 2M, Schedule type: Karras"), "{}", text);
+        assert!(text.starts_with("a goblin <lora:pie:0.8>\n"), "{}", text);
+    }
+}
@@ -0,0 +1,251 @@
+// This file reads the handful of EXIF text tags that image generators use.
+//
+// EXIF is a TIFF structure: a byte-order header followed by IFDs (tables of
+// tagged values). We only walk IFD0 and the Exif sub-IFD, and only keep the
+// tags that can carry prompts or tool information.
+
+const TAG_IMAGE_DESCRIPTION: u16 = 0x010e;
+const TAG_MAKE: u16 = 0x010f;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_SOFTWARE: u16 = 0x0131;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_USER_COMMENT: u16 = 0x9286;
+const TAG_XP_COMMENT: u16 = 0x9c9c;
+
+const TYPE_BYTE: u16 = 1;
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+const TYPE_UNDEFINED: u16 = 7;
+
+/// Upper bound on entries per IFD, so a corrupt count cannot stall us
+const MAX_IFD_ENTRIES: usize = 512;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExifField {
+    pub tag: u16,
+    pub name: &'static str,
+    pub value: String,
+}
+
+#[derive(Clone, Copy)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    order: ByteOrder,
+}
+
+impl Tiff<'_> {
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(match self.order {
+            ByteOrder::Little => u16::from_le_bytes(bytes),
+            ByteOrder::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(match self.order {
+            ByteOrder::Little => u32::from_le_bytes(bytes),
+            ByteOrder::Big => u32::from_be_bytes(bytes),
+        })
+    }
+}
+
+/// Parses an EXIF payload. Accepts the data with or without the
+/// `Exif\0\0` prefix that JPEG APP1 segments (and some WebP files) carry.
+pub fn read_exif(data: &[u8]) -> Vec<ExifField> {
+    let data = data.strip_prefix(b"Exif\0\0").unwrap_or(data);
+    let order = match data.get(0..4) {
+        Some(b"II*\0") => ByteOrder::Little,
+        Some(b"MM\0*") => ByteOrder::Big,
+        _ => return Vec::new(),
+    };
+    let tiff = Tiff { data, order };
+
+    let mut fields = Vec::new();
+    let Some(ifd0) = tiff.u32_at(4) else {
+        return fields;
+    };
+    let exif_ifd = read_ifd(&tiff, ifd0 as usize, &mut fields);
+    if let Some(offset) = exif_ifd {
+        read_ifd(&tiff, offset as usize, &mut fields);
+    }
+    fields
+}
+
+/// Collects the text tags of one IFD, returning the Exif sub-IFD offset if present
+fn read_ifd(tiff: &Tiff, offset: usize, fields: &mut Vec<ExifField>) -> Option<u32> {
+    let count = (tiff.u16_at(offset)? as usize).min(MAX_IFD_ENTRIES);
+    let mut exif_ifd = None;
+
+    for index in 0..count {
+        let entry = offset + 2 + index * 12;
+        let (Some(tag), Some(kind), Some(length)) =
+            (tiff.u16_at(entry), tiff.u16_at(entry + 2), tiff.u32_at(entry + 4))
+        else {
+            break;
+        };
+        let length = length as usize;
+
+        if tag == TAG_EXIF_IFD && kind == TYPE_LONG {
+            exif_ifd = tiff.u32_at(entry + 8);
+            continue;
+        }
+
+        let Some(name) = tag_name(tag) else { continue };
+        if !matches!(kind, TYPE_ASCII | TYPE_UNDEFINED | TYPE_BYTE) {
+            continue;
+        }
+
+        // Values of four bytes or less are stored inline in the entry
+        let value_offset = if length <= 4 {
+            entry + 8
+        } else {
+            let Some(offset) = tiff.u32_at(entry + 8) else { continue };
+            offset as usize
+        };
+        let Some(raw) = tiff.data.get(value_offset..value_offset.saturating_add(length)) else {
+            continue;
+        };
+
+        let value = match tag {
+            TAG_USER_COMMENT => decode_user_comment(raw),
+            TAG_XP_COMMENT => decode_utf16(raw, false),
+            _ => String::from_utf8_lossy(raw).trim_end_matches('\0').to_string(),
+        };
+        if !value.trim().is_empty() {
+            fields.push(ExifField { tag, name, value });
+        }
+    }
+
+    exif_ifd
+}
+
+fn tag_name(tag: u16) -> Option<&'static str> {
+    Some(match tag {
+        TAG_IMAGE_DESCRIPTION => "ImageDescription",
+        TAG_MAKE => "Make",
+        TAG_MODEL => "Model",
+        TAG_SOFTWARE => "Software",
+        TAG_USER_COMMENT => "UserComment",
+        TAG_XP_COMMENT => "XPComment",
+        _ => return None,
+    })
+}
+
+/// Decodes a UserComment value, which starts with an eight byte encoding marker
+pub fn decode_user_comment(raw: &[u8]) -> String {
+    if raw.len() < 8 {
+        return String::from_utf8_lossy(raw).trim_end_matches('\0').to_string();
+    }
+    let (prefix, body) = raw.split_at(8);
+    match prefix {
+        b"UNICODE\0" => decode_utf16(body, guess_big_endian(body)),
+        // JIS is rare enough that a lossy read is the best effort worth making
+        b"ASCII\0\0\0" | b"JIS\0\0\0\0\0" | [0, 0, 0, 0, 0, 0, 0, 0] => {
+            String::from_utf8_lossy(body).trim_end_matches('\0').to_string()
+        }
+        _ => String::from_utf8_lossy(raw).trim_end_matches('\0').to_string(),
+    }
+}
+
+/// Writers disagree on the byte order of UNICODE comments (piexif uses big
+/// endian regardless of the TIFF header), so honour a BOM and otherwise look
+/// at which half of each code unit is zero: prompts are mostly ASCII.
+fn guess_big_endian(body: &[u8]) -> bool {
+    match body.get(0..2) {
+        Some([0xfe, 0xff]) => return true,
+        Some([0xff, 0xfe]) => return false,
+        _ => {}
+    }
+    let (even_zeros, odd_zeros) = body
+        .chunks_exact(2)
+        .fold((0, 0), |(even, odd), pair| {
+            (even + (pair[0] == 0) as usize, odd + (pair[1] == 0) as usize)
+        });
+    even_zeros > odd_zeros
+}
+
+fn decode_utf16(raw: &[u8], big_endian: bool) -> String {
+    let units: Vec<u16> = raw
+        .chunks_exact(2)
+        .map(|pair| {
+            if big_endian {
+                u16::from_be_bytes([pair[0], pair[1]])
+            } else {
+                u16::from_le_bytes([pair[0], pair[1]])
+            }
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+        .trim_start_matches('\u{feff}')
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| if big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() })
+            .collect()
+    }
+
+    #[test]
+    fn test_decodes_user_comment_encodings() {
+        let mut big = b"UNICODE\0".to_vec();
+        big.extend(utf16("a goblin, Steps: 20", true));
+        assert_eq!(decode_user_comment(&big), "a goblin, Steps: 20");
+
+        let mut little = b"UNICODE\0".to_vec();
+        little.extend(utf16("pie \u{1f967}", false));
+        assert_eq!(decode_user_comment(&little), "pie \u{1f967}");
+
+        assert_eq!(decode_user_comment(b"ASCII\0\0\0plain\0"), "plain");
+    }
+
+    #[test]
+    fn test_reads_user_comment_from_exif_ifd() {
+        let mut comment = b"UNICODE\0".to_vec();
+        comment.extend(utf16("hello", true));
+
+        // Little endian TIFF: IFD0 holds Software and a pointer to the Exif IFD
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        tiff.extend(2u16.to_le_bytes());
+        tiff.extend(TAG_SOFTWARE.to_le_bytes());
+        tiff.extend(TYPE_ASCII.to_le_bytes());
+        tiff.extend(3u32.to_le_bytes());
+        tiff.extend(b"sd\0\0");
+        tiff.extend(TAG_EXIF_IFD.to_le_bytes());
+        tiff.extend(TYPE_LONG.to_le_bytes());
+        tiff.extend(1u32.to_le_bytes());
+        tiff.extend(38u32.to_le_bytes());
+        tiff.extend(0u32.to_le_bytes());
+        // Exif IFD at offset 38 with a single UserComment entry
+        tiff.extend(1u16.to_le_bytes());
+        tiff.extend(TAG_USER_COMMENT.to_le_bytes());
+        tiff.extend(TYPE_UNDEFINED.to_le_bytes());
+        tiff.extend((comment.len() as u32).to_le_bytes());
+        tiff.extend(56u32.to_le_bytes());
+        tiff.extend(0u32.to_le_bytes());
+        tiff.extend(&comment);
+
+        let mut data = b"Exif\0\0".to_vec();
+        data.extend(tiff);
+
+        let fields = read_exif(&data);
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].name, "Software");
+        assert_eq!(fields[0].value, "sd");
+        assert_eq!(fields[1].name, "UserComment");
+        assert_eq!(fields[1].value, "hello");
+    }
+}
@@ -0,0 +1,78 @@
+// This file walks the marker segments of a JPEG file and hands back the
+// EXIF, XMP and comment payloads that generation settings hide in.
+
+use std::io;
+
+const XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+#[derive(Debug, Clone, Default)]
+pub struct JpegInfo {
+    pub width: u32,
+    pub height: u32,
+    pub exif: Option<Vec<u8>>,
+    pub xmp: Option<String>,
+    pub comments: Vec<String>,
+}
+
+pub fn is_jpeg(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[0xff, 0xd8, 0xff])
+}
+
+/// Reads segments up to the start of the entropy-coded image data
+pub fn read_jpeg_info(bytes: &[u8]) -> io::Result<JpegInfo> {
+    if !is_jpeg(bytes) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a JPEG file"));
+    }
+
+    let mut info = JpegInfo::default();
+    let mut offset = 2;
+
+    while offset + 4 <= bytes.len() {
+        if bytes[offset] != 0xff {
+            break;
+        }
+        let marker = bytes[offset + 1];
+        // Fill bytes and standalone markers carry no length
+        if marker == 0xff {
+            offset += 1;
+            continue;
+        }
+        if matches!(marker, 0x01 | 0xd0..=0xd7) {
+            offset += 2;
+            continue;
+        }
+        // Start of scan: everything after this is pixel data
+        if marker == 0xda || marker == 0xd9 {
+            break;
+        }
+
+        let length = u16::from_be_bytes([bytes[offset + 2], bytes[offset + 3]]) as usize;
+        let data_start = offset + 4;
+        let data_end = offset + 2 + length;
+        if length < 2 || data_end > bytes.len() {
+            break;
+        }
+        let data = &bytes[data_start..data_end];
+
+        match marker {
+            // APP1 holds either EXIF or XMP, told apart by their signatures
+            0xe1 if data.starts_with(b"Exif\0\0") && info.exif.is_none() => {
+                info.exif = Some(data.to_vec());
+            }
+            0xe1 if data.starts_with(XMP_SIGNATURE) && info.xmp.is_none() => {
+                info.xmp = Some(String::from_utf8_lossy(&data[XMP_SIGNATURE.len()..]).into_owned());
+            }
+            0xfe => info.comments.push(String::from_utf8_lossy(data).into_owned()),
+            // Start of frame markers, excluding DHT (c4), JPG (c8) and DAC (cc)
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) && data.len() >= 5 => {
+                info.height = u16::from_be_bytes([data[1], data[2]]) as u32;
+                info.width = u16::from_be_bytes([data[3], data[4]]) as u32;
+            }
+            _ => {}
+        }
+
+        offset = data_end;
+    }
+
+    Ok(info)
+}
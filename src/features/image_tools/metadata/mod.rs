@@ -0,0 +1,319 @@
+#![allow(clippy::module_name_repetitions)]
+
+// This module digs generation metadata out of image files.
+// Each submodule knows one container or one writer's format; this file ties
+// them together into the `GenerationMetadata` model that the view renders.
+//
+// Reading happens in two steps: the container readers (PNG, JPEG, WebP) turn
+// the file into a flat list of `TextEntry`s, then `interpret` decides which
+// entry holds the settings and which writer's format it is in.
+
+pub mod a1111;
+pub mod comfyui;
+pub mod convert;
+pub mod exif;
+pub mod jpeg;
+pub mod png;
+pub mod swarmui;
+pub mod webp;
+pub mod writer;
+pub mod xmp;
+
+use std::{fs, io, path::Path};
+
+use comfyui::ComfyGraph;
+
+/// Which tool's format the generation settings were recovered from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MetadataSource {
+    #[default]
+    Automatic1111,
+    ComfyUi,
+    SwarmUi,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoraRef {
+    pub name: String,
+    pub strength_model: f32,
+    pub strength_clip: Option<f32>,
+}
+
+/// Generation settings recovered from an image, independent of the tool that wrote them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenerationMetadata {
+    pub source: MetadataSource,
+    pub prompt: String,
+    pub negative_prompt: String,
+    pub steps: Option<u32>,
+    pub sampler: Option<String>,
+    pub scheduler: Option<String>,
+    pub cfg_scale: Option<f32>,
+    pub seed: Option<u64>,
+    pub size: Option<(u32, u32)>,
+    pub model_hash: Option<String>,
+    pub model: Option<String>,
+    pub loras: Vec<LoraRef>,
+    // Any remaining settings, in the order they were written
+    pub extra: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Jpeg,
+    WebP,
+}
+
+/// Where in the file a piece of text was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextLocation {
+    PngChunk,
+    Exif,
+    Xmp,
+    JpegComment,
+}
+
+impl TextLocation {
+    pub fn label(self) -> &'static str {
+        match self {
+            TextLocation::PngChunk => "PNG",
+            TextLocation::Exif => "EXIF",
+            TextLocation::Xmp => "XMP",
+            TextLocation::JpegComment => "Comment",
+        }
+    }
+}
+
+/// A raw piece of text found in the file, before any interpretation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEntry {
+    pub location: TextLocation,
+    pub key: String,
+    pub text: String,
+}
+
+/// Everything we managed to read out of an image file
+#[derive(Debug, Clone, Default)]
+pub struct ImageMetadata {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    pub text_entries: Vec<TextEntry>,
+    pub generation: Option<GenerationMetadata>,
+    // The decoded ComfyUI node graph, when the image came out of ComfyUI
+    pub comfy_graph: Option<ComfyGraph>,
+}
+
+pub fn read_metadata(path: &Path) -> io::Result<ImageMetadata> {
+    let bytes = fs::read(path)?;
+    read_metadata_from_bytes(&bytes)
+}
+
+pub fn read_metadata_from_bytes(bytes: &[u8]) -> io::Result<ImageMetadata> {
+    let (format, width, height, text_entries) = if png::is_png(bytes) {
+        let info = png::read_png_info(bytes)?;
+        let entries = info
+            .text_chunks
+            .into_iter()
+            .map(|chunk| TextEntry {
+                location: TextLocation::PngChunk,
+                key: chunk.keyword,
+                text: chunk.text,
+            })
+            .collect();
+        (ImageFormat::Png, info.width, info.height, entries)
+    } else if jpeg::is_jpeg(bytes) {
+        let info = jpeg::read_jpeg_info(bytes)?;
+        let mut entries = container_entries(info.exif.as_deref(), info.xmp.as_deref());
+        entries.extend(info.comments.into_iter().map(|comment| TextEntry {
+            location: TextLocation::JpegComment,
+            key: String::from("Comment"),
+            text: comment,
+        }));
+        (ImageFormat::Jpeg, info.width, info.height, entries)
+    } else if webp::is_webp(bytes) {
+        let info = webp::read_webp_info(bytes)?;
+        let entries = container_entries(info.exif.as_deref(), info.xmp.as_deref());
+        (ImageFormat::WebP, info.width, info.height, entries)
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unsupported image format (expected PNG, JPEG or WebP)",
+        ));
+    };
+
+    Ok(interpret(format, width, height, text_entries))
+}
+
+/// Flattens EXIF fields and XMP properties into text entries
+fn container_entries(exif: Option<&[u8]>, xmp: Option<&str>) -> Vec<TextEntry> {
+    let mut entries: Vec<TextEntry> = exif
+        .map(exif::read_exif)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|field| TextEntry {
+            location: TextLocation::Exif,
+            key: field.name.to_string(),
+            text: field.value,
+        })
+        .collect();
+    entries.extend(xmp.map(xmp::read_xmp).unwrap_or_default().into_iter().map(|(key, text)| TextEntry {
+        location: TextLocation::Xmp,
+        key,
+        text,
+    }));
+    entries
+}
+
+/// Decides which entries hold generation settings and parses them
+fn interpret(format: ImageFormat, width: u32, height: u32, text_entries: Vec<TextEntry>) -> ImageMetadata {
+    let comfy_graph = comfy_graph(&text_entries);
+
+    let mut generation = text_entries
+        .iter()
+        .find_map(parse_settings_entry)
+        .or_else(|| comfy_graph.as_ref().and_then(ComfyGraph::extract));
+    if let Some(generation) = &mut generation
+        && generation.size.is_none()
+        && width > 0
+    {
+        generation.size = Some((width, height));
+    }
+
+    ImageMetadata {
+        format,
+        width,
+        height,
+        text_entries,
+        generation,
+        comfy_graph,
+    }
+}
+
+/// Tries to read one entry as an A1111 or SwarmUI settings block
+fn parse_settings_entry(entry: &TextEntry) -> Option<GenerationMetadata> {
+    let is_candidate = matches!(
+        (entry.location, entry.key.as_str()),
+        (TextLocation::PngChunk, "parameters")
+            | (TextLocation::Exif, "UserComment" | "ImageDescription" | "XPComment")
+            | (TextLocation::Xmp, "parameters" | "UserComment" | "description")
+            | (TextLocation::JpegComment, _)
+    );
+    if !is_candidate {
+        return None;
+    }
+    if let Some(generation) = swarmui::parse_swarm_parameters(&entry.text) {
+        return Some(generation);
+    }
+
+    let generation = a1111::parse_parameters(&entry.text);
+    // The PNG "parameters" chunk is unambiguous; elsewhere a free-form comment
+    // only counts once it has a recognisable settings line
+    let has_settings = generation.steps.is_some() || generation.seed.is_some() || generation.sampler.is_some();
+    let explicit = entry.location == TextLocation::PngChunk;
+    (explicit || has_settings).then_some(generation)
+}
+
+/// Finds ComfyUI's graphs: PNG chunks named `prompt`/`workflow`, or the
+/// `prompt:{...}`/`workflow:{...}` strings its WebP and JPEG savers put in EXIF
+fn comfy_graph(entries: &[TextEntry]) -> Option<ComfyGraph> {
+    let find = |name: &str| {
+        entries.iter().find_map(|entry| match entry.location {
+            TextLocation::PngChunk | TextLocation::Xmp if entry.key == name => Some(entry.text.as_str()),
+            TextLocation::Exif => {
+                let (prefix, json) = entry.text.split_once(':')?;
+                prefix.eq_ignore_ascii_case(name).then_some(json)
+            }
+            _ => None,
+        })
+    };
+
+    let workflow = find("workflow").and_then(ComfyGraph::from_workflow);
+    match (find("prompt").and_then(ComfyGraph::from_prompt), workflow) {
+        (Some(mut prompt), Some(workflow)) => {
+            prompt.merge_titles(&workflow);
+            Some(prompt)
+        }
+        (prompt, workflow) => prompt.or(workflow),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jpeg_with_exif_comment(comment: &str) -> Vec<u8> {
+        let mut user_comment = b"UNICODE\0".to_vec();
+        user_comment.extend(comment.encode_utf16().flat_map(u16::to_be_bytes));
+
+        // Big endian TIFF with IFD0 pointing straight at an Exif IFD
+        let mut tiff = b"MM\0*".to_vec();
+        tiff.extend(8u32.to_be_bytes());
+        tiff.extend(1u16.to_be_bytes());
+        tiff.extend([0x87, 0x69, 0, 4]);
+        tiff.extend(1u32.to_be_bytes());
+        tiff.extend(26u32.to_be_bytes());
+        tiff.extend(0u32.to_be_bytes());
+        tiff.extend(1u16.to_be_bytes());
+        tiff.extend([0x92, 0x86, 0, 7]);
+        tiff.extend((user_comment.len() as u32).to_be_bytes());
+        tiff.extend(44u32.to_be_bytes());
+        tiff.extend(0u32.to_be_bytes());
+        tiff.extend(user_comment);
+
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend(tiff);
+
+        let mut bytes = vec![0xff, 0xd8, 0xff, 0xe1];
+        bytes.extend(((app1.len() + 2) as u16).to_be_bytes());
+        bytes.extend(app1);
+        // Baseline SOF0: precision, height, width, components
+        bytes.extend([0xff, 0xc0, 0, 8, 8, 0x03, 0x00, 0x02, 0x00, 0]);
+        bytes.extend([0xff, 0xda, 0, 2, 0xff, 0xd9]);
+        bytes
+    }
+
+    #[test]
+    fn test_reads_a1111_settings_from_jpeg_user_comment() {
+        let bytes = jpeg_with_exif_comment("a goblin\nSteps: 20, Sampler: Euler a, Seed: 5");
+        let metadata = read_metadata_from_bytes(&bytes).unwrap();
+
+        assert_eq!(metadata.format, ImageFormat::Jpeg);
+        assert_eq!((metadata.width, metadata.height), (512, 768));
+        let generation = metadata.generation.unwrap();
+        assert_eq!(generation.prompt, "a goblin");
+        assert_eq!(generation.seed, Some(5));
+        assert_eq!(generation.size, Some((512, 768)));
+    }
+
+    #[test]
+    fn test_reads_xmp_from_webp() {
+        let xmp = br#"<x:xmpmeta><rdf:Description sd:parameters="pie&#10;Steps: 4, Sampler: LCM, Seed: 9"/></x:xmpmeta>"#;
+        let mut vp8x = vec![0u8; 10];
+        vp8x[4..7].copy_from_slice(&[0xff, 0x03, 0]);
+        vp8x[7..10].copy_from_slice(&[0xff, 0x01, 0]);
+
+        let mut body = b"WEBP".to_vec();
+        body.extend(b"VP8X");
+        body.extend(10u32.to_le_bytes());
+        body.extend(vp8x);
+        body.extend(b"XMP ");
+        body.extend((xmp.len() as u32).to_le_bytes());
+        body.extend(xmp);
+        if xmp.len() % 2 == 1 {
+            body.push(0);
+        }
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend((body.len() as u32).to_le_bytes());
+        bytes.extend(body);
+
+        let metadata = read_metadata_from_bytes(&bytes).unwrap();
+        assert_eq!(metadata.format, ImageFormat::WebP);
+        assert_eq!((metadata.width, metadata.height), (1024, 512));
+        let generation = metadata.generation.unwrap();
+        assert_eq!(generation.prompt, "pie");
+        assert_eq!(generation.sampler.as_deref(), Some("LCM"));
+    }
+}
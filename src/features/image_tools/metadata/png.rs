@@ -0,0 +1,218 @@
+// This file reads the textual chunks (tEXt, zTXt and iTXt) out of a PNG stream.
+// Diffusion front-ends stash their generation settings in these chunks, so
+// this is the first stop when prying open an image.
+
+use std::io::{self, Read};
+
+use flate2::read::ZlibDecoder;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextChunkKind {
+    Text,
+    CompressedText,
+    InternationalText,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    pub kind: TextChunkKind,
+    pub keyword: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PngInfo {
+    pub width: u32,
+    pub height: u32,
+    pub text_chunks: Vec<TextChunk>,
+}
+
+impl PngInfo {
+    /// Returns the text of the first chunk with the given keyword
+    pub fn text(&self, keyword: &str) -> Option<&str> {
+        self.text_chunks
+            .iter()
+            .find(|chunk| chunk.keyword == keyword)
+            .map(|chunk| chunk.text.as_str())
+    }
+}
+
+pub fn is_png(bytes: &[u8]) -> bool {
+    bytes.starts_with(&PNG_SIGNATURE)
+}
+
+/// Walks every chunk in a PNG file and collects the image size and text chunks.
+/// Unknown chunks are skipped; a truncated stream ends the walk early rather
+/// than failing, since the metadata usually sits before the pixel data.
+pub fn read_png_info(bytes: &[u8]) -> io::Result<PngInfo> {
+    if !is_png(bytes) {
+        return Err(invalid_data("not a PNG file"));
+    }
+
+    let mut info = PngInfo::default();
+    let mut offset = PNG_SIGNATURE.len();
+
+    while offset + 8 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        let chunk_type = &bytes[offset + 4..offset + 8];
+        let data_start = offset + 8;
+        let data_end = data_start.saturating_add(length);
+        if data_end > bytes.len() {
+            break;
+        }
+        let data = &bytes[data_start..data_end];
+
+        match chunk_type {
+            b"IHDR" if data.len() >= 8 => {
+                info.width = u32::from_be_bytes(data[0..4].try_into().unwrap());
+                info.height = u32::from_be_bytes(data[4..8].try_into().unwrap());
+            }
+            b"tEXt" => info.text_chunks.extend(parse_text(data)),
+            b"zTXt" => info.text_chunks.extend(parse_compressed_text(data)),
+            b"iTXt" => info.text_chunks.extend(parse_international_text(data)),
+            b"IEND" => break,
+            _ => {}
+        }
+
+        // Skip the data and the trailing CRC
+        offset = data_end + 4;
+    }
+
+    Ok(info)
+}
+
+fn parse_text(data: &[u8]) -> Option<TextChunk> {
+    let (keyword, rest) = split_null(data)?;
+    Some(TextChunk {
+        kind: TextChunkKind::Text,
+        keyword: decode_latin1(keyword),
+        text: decode_latin1(rest),
+    })
+}
+
+fn parse_compressed_text(data: &[u8]) -> Option<TextChunk> {
+    let (keyword, rest) = split_null(data)?;
+    // First byte is the compression method, zlib (0) is the only one defined
+    let (&method, compressed) = rest.split_first()?;
+    if method != 0 {
+        return None;
+    }
+    let text = inflate(compressed).ok()?;
+    Some(TextChunk {
+        kind: TextChunkKind::CompressedText,
+        keyword: decode_latin1(keyword),
+        text: decode_latin1(&text),
+    })
+}
+
+fn parse_international_text(data: &[u8]) -> Option<TextChunk> {
+    let (keyword, rest) = split_null(data)?;
+    if rest.len() < 2 {
+        return None;
+    }
+    let compressed = rest[0] == 1;
+    let method = rest[1];
+    // Language tag and translated keyword are not interesting to us
+    let (_language, rest) = split_null(&rest[2..])?;
+    let (_translated, text) = split_null(rest)?;
+
+    let text = if compressed {
+        if method != 0 {
+            return None;
+        }
+        inflate(text).ok()?
+    } else {
+        text.to_vec()
+    };
+
+    Some(TextChunk {
+        kind: TextChunkKind::InternationalText,
+        keyword: decode_latin1(keyword),
+        text: String::from_utf8_lossy(&text).into_owned(),
+    })
+}
+
+fn split_null(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let pos = data.iter().position(|&b| b == 0)?;
+    Some((&data[..pos], &data[pos + 1..]))
+}
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+
+    fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut out = (data.len() as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(data);
+        // CRC is not verified by the reader
+        out.extend_from_slice(&[0, 0, 0, 0]);
+        out
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn png_with(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&512u32.to_be_bytes());
+        ihdr.extend_from_slice(&768u32.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        bytes.extend(chunk(b"IHDR", &ihdr));
+        for c in chunks {
+            bytes.extend_from_slice(c);
+        }
+        bytes.extend(chunk(b"IEND", &[]));
+        bytes
+    }
+
+    #[test]
+    fn test_reads_all_text_chunk_kinds() {
+        let mut ztxt = b"comment\0\0".to_vec();
+        ztxt.extend(deflate(b"squashed"));
+
+        let mut itxt = b"prompt\0\x01\0en\0\0".to_vec();
+        itxt.extend(deflate("caf\u{e9} \u{1f967}".as_bytes()));
+
+        let bytes = png_with(&[
+            chunk(b"tEXt", b"parameters\0a goblin"),
+            chunk(b"zTXt", &ztxt),
+            chunk(b"iTXt", &itxt),
+        ]);
+
+        let info = read_png_info(&bytes).unwrap();
+        assert_eq!((info.width, info.height), (512, 768));
+        assert_eq!(info.text("parameters"), Some("a goblin"));
+        assert_eq!(info.text("comment"), Some("squashed"));
+        assert_eq!(info.text("prompt"), Some("caf\u{e9} \u{1f967}"));
+        assert_eq!(info.text_chunks[2].kind, TextChunkKind::InternationalText);
+    }
+
+    #[test]
+    fn test_rejects_non_png() {
+        assert!(read_png_info(b"GIF89a").is_err());
+    }
+}
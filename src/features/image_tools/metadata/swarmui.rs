@@ -0,0 +1,92 @@
+// This file parses SwarmUI's metadata, a JSON document stored where other
+// tools put the A1111 parameter line: `{"sui_image_params": {...}}`.
+
+use serde_json::Value;
+
+use super::{GenerationMetadata, LoraRef, MetadataSource};
+
+/// Parameters we map onto dedicated fields; everything else lands in `extra`
+const KNOWN_KEYS: [&str; 13] = [
+    "prompt", "negativeprompt", "model", "seed", "steps", "cfgscale", "width", "height",
+    "sampler", "scheduler", "loras", "loraweights", "modelhash",
+];
+
+pub fn parse_swarm_parameters(text: &str) -> Option<GenerationMetadata> {
+    let text = text.trim();
+    if !text.starts_with('{') {
+        return None;
+    }
+    let root: Value = serde_json::from_str(text).ok()?;
+    let params = root.get("sui_image_params")?.as_object()?;
+
+    let string = |key: &str| params.get(key).and_then(Value::as_str).map(str::to_string);
+    let number = |key: &str| params.get(key).and_then(Value::as_f64);
+
+    let mut metadata = GenerationMetadata {
+        source: MetadataSource::SwarmUi,
+        prompt: string("prompt").unwrap_or_default(),
+        negative_prompt: string("negativeprompt").unwrap_or_default(),
+        steps: number("steps").map(|steps| steps as u32),
+        sampler: string("sampler"),
+        scheduler: string("scheduler"),
+        cfg_scale: number("cfgscale").map(|cfg| cfg as f32),
+        seed: params.get("seed").and_then(Value::as_u64),
+        model_hash: string("modelhash"),
+        model: string("model"),
+        ..GenerationMetadata::default()
+    };
+
+    if let (Some(width), Some(height)) = (number("width"), number("height")) {
+        metadata.size = Some((width as u32, height as u32));
+    }
+
+    let names = params.get("loras").and_then(Value::as_array);
+    let weights = params.get("loraweights").and_then(Value::as_array);
+    for (index, name) in names.into_iter().flatten().enumerate() {
+        let Some(name) = name.as_str() else { continue };
+        let weight = weights
+            .and_then(|weights| weights.get(index))
+            .and_then(|weight| weight.as_f64().or_else(|| weight.as_str()?.parse().ok()))
+            .unwrap_or(1.0);
+        metadata.loras.push(LoraRef {
+            name: name.to_string(),
+            strength_model: weight as f32,
+            strength_clip: None,
+        });
+    }
+
+    for (key, value) in params {
+        if KNOWN_KEYS.contains(&key.as_str()) {
+            continue;
+        }
+        let value = match value {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        metadata.extra.push((key.clone(), value));
+    }
+
+    Some(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_swarm_json() {
+        let text = r#"{"sui_image_params": {"prompt": "a goblin", "negativeprompt": "blurry",
+            "model": "flux1-dev", "seed": 77, "steps": 28, "cfgscale": 1, "width": 1024, "height": 768,
+            "loras": ["pie"], "loraweights": ["0.6"], "swarm_version": "0.9.5"}}"#;
+
+        let metadata = parse_swarm_parameters(text).unwrap();
+        assert_eq!(metadata.source, MetadataSource::SwarmUi);
+        assert_eq!(metadata.prompt, "a goblin");
+        assert_eq!(metadata.seed, Some(77));
+        assert_eq!(metadata.size, Some((1024, 768)));
+        assert_eq!(metadata.loras[0].name, "pie");
+        assert_eq!(metadata.loras[0].strength_model, 0.6);
+        assert_eq!(metadata.extra, vec![("swarm_version".to_string(), "0.9.5".to_string())]);
+        assert!(parse_swarm_parameters("Steps: 20").is_none());
+    }
+}
@@ -0,0 +1,66 @@
+// This file walks the RIFF chunks of a WebP file, picking out the canvas
+// size and the EXIF and XMP chunks.
+
+use std::io;
+
+#[derive(Debug, Clone, Default)]
+pub struct WebpInfo {
+    pub width: u32,
+    pub height: u32,
+    pub exif: Option<Vec<u8>>,
+    pub xmp: Option<String>,
+}
+
+pub fn is_webp(bytes: &[u8]) -> bool {
+    bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP"
+}
+
+pub fn read_webp_info(bytes: &[u8]) -> io::Result<WebpInfo> {
+    if !is_webp(bytes) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a WebP file"));
+    }
+
+    let mut info = WebpInfo::default();
+    let mut offset = 12;
+
+    while offset + 8 <= bytes.len() {
+        let fourcc = &bytes[offset..offset + 4];
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let data_start = offset + 8;
+        let data_end = data_start.saturating_add(size);
+        if data_end > bytes.len() {
+            break;
+        }
+        let data = &bytes[data_start..data_end];
+
+        match fourcc {
+            b"VP8X" if data.len() >= 10 => {
+                info.width = u24_le(&data[4..7]) + 1;
+                info.height = u24_le(&data[7..10]) + 1;
+            }
+            // Simple (lossy) format: dimensions follow the frame tag and start code
+            b"VP8 " if data.len() >= 10 && info.width == 0 => {
+                info.width = (u16::from_le_bytes([data[6], data[7]]) & 0x3fff) as u32;
+                info.height = (u16::from_le_bytes([data[8], data[9]]) & 0x3fff) as u32;
+            }
+            // Lossless format: 14-bit width and height after the 0x2f signature
+            b"VP8L" if data.len() >= 5 && info.width == 0 => {
+                let bits = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+                info.width = (bits & 0x3fff) + 1;
+                info.height = ((bits >> 14) & 0x3fff) + 1;
+            }
+            b"EXIF" => info.exif = Some(data.to_vec()),
+            b"XMP " => info.xmp = Some(String::from_utf8_lossy(data).into_owned()),
+            _ => {}
+        }
+
+        // Chunks are padded to an even length
+        offset = data_end + (size & 1);
+    }
+
+    Ok(info)
+}
+
+fn u24_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0])
+}
@@ -0,0 +1,465 @@
+// This file writes generation metadata back into image files, or strips it.
+//
+// Every operation rebuilds the container around the untouched pixel data:
+// PNG text chunks, the JPEG EXIF/XMP/comment segments and the WebP EXIF/XMP
+// chunks are dropped, then the new payload is written where each format's
+// generators put it (a `parameters`/`prompt`/`workflow` chunk for PNG, EXIF
+// UserComment, Make and Model for JPEG and WebP, the way A1111 and ComfyUI do).
+//
+// Files are replaced atomically, and the first version of a file we touch is
+// kept next to it with a `.bak` suffix.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use flate2::Crc;
+
+use super::{TextEntry, TextLocation, jpeg, png, webp};
+
+const PNG_SIGNATURE_LEN: usize = 8;
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+const TAG_MAKE: u16 = 0x010f;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_USER_COMMENT: u16 = 0x9286;
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+const TYPE_UNDEFINED: u16 = 7;
+
+/// VP8X flag bits for the metadata chunks and alpha
+const VP8X_ALPHA: u8 = 0x10;
+const VP8X_EXIF: u8 = 0x08;
+const VP8X_XMP: u8 = 0x04;
+
+/// Generation text to embed in a file. Empty means "strip everything".
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataPayload {
+    // An A1111-style parameters block
+    pub parameters: Option<String>,
+    // ComfyUI's API-format graph and editor workflow, as JSON
+    pub comfy_prompt: Option<String>,
+    pub comfy_workflow: Option<String>,
+}
+
+impl MetadataPayload {
+    /// Picks out the ComfyUI graphs already present in a file, so an edit of
+    /// the parameters does not throw away the workflow
+    pub fn comfy_from(entries: &[TextEntry]) -> Self {
+        let find = |name: &str| {
+            entries.iter().find_map(|entry| match entry.location {
+                TextLocation::PngChunk | TextLocation::Xmp if entry.key == name => Some(entry.text.clone()),
+                TextLocation::Exif => {
+                    let (prefix, json) = entry.text.split_once(':')?;
+                    prefix.eq_ignore_ascii_case(name).then(|| json.to_string())
+                }
+                _ => None,
+            })
+        };
+        Self {
+            parameters: None,
+            comfy_prompt: find("prompt"),
+            comfy_workflow: find("workflow"),
+        }
+    }
+}
+
+/// Removes all text chunks, EXIF, XMP and comments from an image
+pub fn strip_metadata(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    embed_metadata(bytes, &MetadataPayload::default())
+}
+
+/// Replaces any existing metadata in an image with the payload
+pub fn embed_metadata(bytes: &[u8], payload: &MetadataPayload) -> io::Result<Vec<u8>> {
+    if png::is_png(bytes) {
+        rewrite_png(bytes, payload)
+    } else if jpeg::is_jpeg(bytes) {
+        rewrite_jpeg(bytes, payload)
+    } else if webp::is_webp(bytes) {
+        rewrite_webp(bytes, payload)
+    } else {
+        Err(invalid_data("unsupported image format (expected PNG, JPEG or WebP)"))
+    }
+}
+
+/// Where the original of `path` is kept: `goblin.png` becomes `goblin.png.bak`
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// Replaces a file's contents atomically: the new bytes go to a temporary
+/// file in the same directory which is then renamed over the original.
+/// The original is copied to its backup path first, unless a backup already
+/// exists, so repeated edits never lose the very first version.
+/// Returns the backup path.
+pub fn save_with_backup(path: &Path, bytes: &[u8]) -> io::Result<PathBuf> {
+    let backup = backup_path(path);
+    if !backup.exists() {
+        fs::copy(path, &backup)?;
+    }
+
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(".tmp");
+    let temp = path.with_file_name(temp_name);
+
+    let result = (|| {
+        fs::write(&temp, bytes)?;
+        fs::set_permissions(&temp, fs::metadata(path)?.permissions())?;
+        fs::File::open(&temp)?.sync_all()?;
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result.map(|()| backup)
+}
+
+fn rewrite_png(bytes: &[u8], payload: &MetadataPayload) -> io::Result<Vec<u8>> {
+    let mut out = bytes[..PNG_SIGNATURE_LEN].to_vec();
+    let mut offset = PNG_SIGNATURE_LEN;
+
+    while offset + 8 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        let chunk_type = &bytes[offset + 4..offset + 8];
+        let chunk_end = offset + 12 + length;
+        if chunk_end > bytes.len() {
+            return Err(invalid_data("truncated PNG chunk"));
+        }
+
+        if !matches!(chunk_type, b"tEXt" | b"zTXt" | b"iTXt" | b"eXIf") {
+            out.extend_from_slice(&bytes[offset..chunk_end]);
+        }
+        // Text chunks go straight after the header, ahead of the pixel data
+        if chunk_type == b"IHDR" {
+            let entries = [
+                ("parameters", &payload.parameters),
+                ("prompt", &payload.comfy_prompt),
+                ("workflow", &payload.comfy_workflow),
+            ];
+            for (keyword, text) in entries {
+                if let Some(text) = text {
+                    write_png_text(&mut out, keyword, text);
+                }
+            }
+        }
+        if chunk_type == b"IEND" {
+            break;
+        }
+        offset = chunk_end;
+    }
+
+    Ok(out)
+}
+
+/// Writes a tEXt chunk, or an uncompressed iTXt chunk if the text isn't Latin-1
+fn write_png_text(out: &mut Vec<u8>, keyword: &str, text: &str) {
+    let mut data = keyword.as_bytes().to_vec();
+    data.push(0);
+    if text.chars().all(|c| (c as u32) < 0x100) {
+        data.extend(text.chars().map(|c| c as u8));
+        write_png_chunk(out, b"tEXt", &data);
+    } else {
+        // Compression flag, method, then empty language tag and translated keyword
+        data.extend([0, 0, 0, 0]);
+        data.extend(text.as_bytes());
+        write_png_chunk(out, b"iTXt", &data);
+    }
+}
+
+fn write_png_chunk(out: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    out.extend(chunk_type);
+    out.extend(data);
+    let mut crc = Crc::new();
+    crc.update(chunk_type);
+    crc.update(data);
+    out.extend(crc.sum().to_be_bytes());
+}
+
+fn rewrite_jpeg(bytes: &[u8], payload: &MetadataPayload) -> io::Result<Vec<u8>> {
+    let exif = build_exif(payload);
+    let app1 = match &exif {
+        Some(tiff) => {
+            let length = 2 + EXIF_HEADER.len() + tiff.len();
+            let length = u16::try_from(length)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "metadata too large for a JPEG EXIF segment"))?;
+            let mut segment = vec![0xff, 0xe1];
+            segment.extend(length.to_be_bytes());
+            segment.extend(EXIF_HEADER);
+            segment.extend(tiff);
+            Some(segment)
+        }
+        None => None,
+    };
+
+    let mut out = bytes[..2].to_vec();
+    let mut app1 = app1;
+    let mut offset = 2;
+
+    while offset + 4 <= bytes.len() {
+        if bytes[offset] != 0xff {
+            return Err(invalid_data("malformed JPEG segment"));
+        }
+        let marker = bytes[offset + 1];
+        if marker == 0xff {
+            offset += 1;
+            continue;
+        }
+        // The new EXIF goes after the JFIF header, ahead of everything else
+        if marker != 0xe0
+            && let Some(segment) = app1.take()
+        {
+            out.extend(segment);
+        }
+        // Start of scan: copy the image data and everything after it verbatim
+        if marker == 0xda || marker == 0xd9 {
+            out.extend_from_slice(&bytes[offset..]);
+            return Ok(out);
+        }
+        if matches!(marker, 0x01 | 0xd0..=0xd7) {
+            out.extend_from_slice(&bytes[offset..offset + 2]);
+            offset += 2;
+            continue;
+        }
+
+        let length = u16::from_be_bytes([bytes[offset + 2], bytes[offset + 3]]) as usize;
+        let segment_end = offset + 2 + length;
+        if length < 2 || segment_end > bytes.len() {
+            return Err(invalid_data("truncated JPEG segment"));
+        }
+        let data = &bytes[offset + 4..segment_end];
+        let is_metadata = (marker == 0xe1 && (data.starts_with(EXIF_HEADER) || data.starts_with(XMP_SIGNATURE)))
+            || marker == 0xfe;
+        if !is_metadata {
+            out.extend_from_slice(&bytes[offset..segment_end]);
+        }
+        offset = segment_end;
+    }
+
+    Err(invalid_data("JPEG has no image data"))
+}
+
+fn rewrite_webp(bytes: &[u8], payload: &MetadataPayload) -> io::Result<Vec<u8>> {
+    let info = webp::read_webp_info(bytes)?;
+    let mut chunks: Vec<([u8; 4], Vec<u8>)> = Vec::new();
+    let mut offset = 12;
+
+    while offset + 8 <= bytes.len() {
+        let fourcc: [u8; 4] = bytes[offset..offset + 4].try_into().unwrap();
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let data_end = offset + 8 + size;
+        if data_end > bytes.len() {
+            return Err(invalid_data("truncated WebP chunk"));
+        }
+        if !matches!(&fourcc, b"EXIF" | b"XMP ") {
+            chunks.push((fourcc, bytes[offset + 8..data_end].to_vec()));
+        }
+        offset = data_end + (size & 1);
+    }
+
+    let exif = build_exif(payload);
+    let has_vp8x = chunks.iter().any(|(fourcc, _)| fourcc == b"VP8X");
+    if exif.is_some() && !has_vp8x {
+        // Metadata chunks are only allowed in the extended format
+        let mut vp8x = vec![0u8; 10];
+        let lossless_alpha = chunks.iter().any(|(fourcc, data)| {
+            fourcc == b"VP8L" && data.len() >= 5 && data[4] & 0x10 != 0
+        });
+        if lossless_alpha {
+            vp8x[0] |= VP8X_ALPHA;
+        }
+        vp8x[4..7].copy_from_slice(&info.width.saturating_sub(1).to_le_bytes()[..3]);
+        vp8x[7..10].copy_from_slice(&info.height.saturating_sub(1).to_le_bytes()[..3]);
+        chunks.insert(0, (*b"VP8X", vp8x));
+    }
+    if let Some((_, vp8x)) = chunks.iter_mut().find(|(fourcc, _)| fourcc == b"VP8X")
+        && let Some(flags) = vp8x.first_mut()
+    {
+        *flags &= !(VP8X_EXIF | VP8X_XMP);
+        if exif.is_some() {
+            *flags |= VP8X_EXIF;
+        }
+    }
+    if let Some(tiff) = exif {
+        chunks.push((*b"EXIF", tiff));
+    }
+
+    let mut body = b"WEBP".to_vec();
+    for (fourcc, data) in chunks {
+        body.extend(fourcc);
+        body.extend((data.len() as u32).to_le_bytes());
+        body.extend(&data);
+        if data.len() % 2 == 1 {
+            body.push(0);
+        }
+    }
+    let mut out = b"RIFF".to_vec();
+    out.extend((body.len() as u32).to_le_bytes());
+    out.extend(body);
+    Ok(out)
+}
+
+struct IfdEntry {
+    tag: u16,
+    kind: u16,
+    data: Vec<u8>,
+}
+
+/// Builds a little endian TIFF block holding the payload: ComfyUI's graphs
+/// as `prompt:`/`workflow:` prefixed Make and Model strings, the parameters
+/// as a UNICODE UserComment in the Exif IFD. None if there is nothing to write.
+fn build_exif(payload: &MetadataPayload) -> Option<Vec<u8>> {
+    let ascii = |prefix: &str, text: &str| {
+        let mut data = format!("{}:{}", prefix, text).into_bytes();
+        data.push(0);
+        data
+    };
+    let mut ifd0 = Vec::new();
+    if let Some(prompt) = &payload.comfy_prompt {
+        ifd0.push(IfdEntry { tag: TAG_MAKE, kind: TYPE_ASCII, data: ascii("prompt", prompt) });
+    }
+    if let Some(workflow) = &payload.comfy_workflow {
+        ifd0.push(IfdEntry { tag: TAG_MODEL, kind: TYPE_ASCII, data: ascii("workflow", workflow) });
+    }
+    let mut exif_ifd = Vec::new();
+    if let Some(parameters) = &payload.parameters {
+        let mut data = b"UNICODE\0".to_vec();
+        data.extend(parameters.encode_utf16().flat_map(u16::to_le_bytes));
+        exif_ifd.push(IfdEntry { tag: TAG_USER_COMMENT, kind: TYPE_UNDEFINED, data });
+    }
+    if ifd0.is_empty() && exif_ifd.is_empty() {
+        return None;
+    }
+
+    let ifd_size = |count: usize| 2 + 12 * count + 4;
+    let ifd0_count = ifd0.len() + usize::from(!exif_ifd.is_empty());
+    let exif_ifd_offset = 8 + ifd_size(ifd0_count);
+    if !exif_ifd.is_empty() {
+        ifd0.push(IfdEntry { tag: TAG_EXIF_IFD, kind: TYPE_LONG, data: (exif_ifd_offset as u32).to_le_bytes().to_vec() });
+    }
+    let exif_ifd_size = if exif_ifd.is_empty() { 0 } else { ifd_size(exif_ifd.len()) };
+
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend(8u32.to_le_bytes());
+    let mut values = Vec::new();
+    let values_base = exif_ifd_offset + exif_ifd_size;
+    write_ifd(&mut tiff, &mut ifd0, &mut values, values_base);
+    if !exif_ifd.is_empty() {
+        write_ifd(&mut tiff, &mut exif_ifd, &mut values, values_base);
+    }
+    tiff.extend(values);
+    Some(tiff)
+}
+
+/// Appends an IFD, putting values longer than four bytes in the shared value area
+fn write_ifd(out: &mut Vec<u8>, entries: &mut [IfdEntry], values: &mut Vec<u8>, values_base: usize) {
+    entries.sort_by_key(|entry| entry.tag);
+    out.extend((entries.len() as u16).to_le_bytes());
+    for entry in entries.iter() {
+        // LONG values count units of four bytes, ASCII and UNDEFINED count bytes
+        let count = if entry.kind == TYPE_LONG { entry.data.len() / 4 } else { entry.data.len() };
+        out.extend(entry.tag.to_le_bytes());
+        out.extend(entry.kind.to_le_bytes());
+        out.extend((count as u32).to_le_bytes());
+        if entry.data.len() <= 4 {
+            let mut inline = entry.data.clone();
+            inline.resize(4, 0);
+            out.extend(inline);
+        } else {
+            out.extend(((values_base + values.len()) as u32).to_le_bytes());
+            values.extend(&entry.data);
+            // Value offsets must be word aligned
+            if values.len() % 2 == 1 {
+                values.push(0);
+            }
+        }
+    }
+    // No further IFDs
+    out.extend(0u32.to_le_bytes());
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::image_tools::metadata::{ImageFormat, read_metadata_from_bytes};
+
+    fn payload() -> MetadataPayload {
+        MetadataPayload {
+            parameters: Some("a goblin, ünïcode\nSteps: 20, Sampler: Euler a, Seed: 5".to_string()),
+            comfy_prompt: None,
+            comfy_workflow: None,
+        }
+    }
+
+    fn encode(format: image::ImageFormat) -> Vec<u8> {
+        let mut bytes = io::Cursor::new(Vec::new());
+        image::RgbImage::new(4, 2).write_to(&mut bytes, format).unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn test_embeds_and_strips_every_format() {
+        for (format, expected) in [
+            (image::ImageFormat::Png, ImageFormat::Png),
+            (image::ImageFormat::Jpeg, ImageFormat::Jpeg),
+            (image::ImageFormat::WebP, ImageFormat::WebP),
+        ] {
+            let original = encode(format);
+            let written = embed_metadata(&original, &payload()).unwrap();
+
+            let metadata = read_metadata_from_bytes(&written).unwrap();
+            assert_eq!(metadata.format, expected);
+            assert_eq!((metadata.width, metadata.height), (4, 2));
+            let generation = metadata.generation.unwrap();
+            assert_eq!(generation.prompt, "a goblin, ünïcode");
+            assert_eq!(generation.seed, Some(5));
+            // The pixels must survive untouched
+            assert!(image::load_from_memory(&written).is_ok(), "{:?}", format);
+
+            let stripped = strip_metadata(&written).unwrap();
+            let metadata = read_metadata_from_bytes(&stripped).unwrap();
+            assert!(metadata.text_entries.is_empty(), "{:?}", format);
+            assert!(image::load_from_memory(&stripped).is_ok(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_comfy_graph_survives_exif() {
+        let prompt = r#"{"3": {"class_type": "KSampler", "inputs": {"seed": 7, "steps": 4}}}"#;
+        let payload = MetadataPayload {
+            parameters: None,
+            comfy_prompt: Some(prompt.to_string()),
+            comfy_workflow: None,
+        };
+        let written = embed_metadata(&encode(image::ImageFormat::WebP), &payload).unwrap();
+        let metadata = read_metadata_from_bytes(&written).unwrap();
+        assert_eq!(metadata.generation.unwrap().seed, Some(7));
+        assert_eq!(MetadataPayload::comfy_from(&metadata.text_entries), payload);
+    }
+
+    #[test]
+    fn test_save_keeps_first_backup() {
+        let dir = std::env::temp_dir().join(format!("pipemind_writer_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("goblin.png");
+        fs::write(&path, b"original").unwrap();
+
+        let backup = save_with_backup(&path, b"first edit").unwrap();
+        save_with_backup(&path, b"second edit").unwrap();
+        assert_eq!(backup, dir.join("goblin.png.bak"));
+        assert_eq!(fs::read(&backup).unwrap(), b"original");
+        assert_eq!(fs::read(&path).unwrap(), b"second edit");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
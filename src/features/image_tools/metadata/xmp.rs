@@ -0,0 +1,149 @@
+// This file pulls a few text properties out of an XMP packet.
+//
+// XMP is RDF/XML, but all we need is the text of a handful of properties,
+// written either as elements (`<exif:UserComment>...</exif:UserComment>`) or
+// as attributes (`sd:parameters="..."`). A small tag scanner is plenty for
+// that and keeps us clear of a full XML parser.
+
+/// Properties that generators are known to put prompts or settings in
+const INTERESTING_PROPERTIES: [&str; 5] = ["parameters", "UserComment", "description", "prompt", "workflow"];
+
+/// Returns `(property, text)` pairs for the interesting properties in the packet
+pub fn read_xmp(packet: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    for property in INTERESTING_PROPERTIES {
+        let value = element_text(packet, property).or_else(|| attribute_value(packet, property));
+        if let Some(value) = value
+            && !value.trim().is_empty()
+        {
+            fields.push((property.to_string(), value));
+        }
+    }
+    fields
+}
+
+/// Finds `<prefix:name ...>text</prefix:name>` and returns the text with nested
+/// tags (such as `rdf:Alt`/`rdf:li` wrappers) removed
+fn element_text(packet: &str, local_name: &str) -> Option<String> {
+    let mut search = packet;
+    while let Some(start) = search.find('<') {
+        let rest = &search[start + 1..];
+        let name_end = rest.find(|c: char| c.is_whitespace() || c == '>' || c == '/')?;
+        let tag = &rest[..name_end];
+
+        if local_of(tag) == local_name && !tag.starts_with('/') {
+            let open_end = rest.find('>')?;
+            // Self-closing elements carry their value in attributes instead
+            if rest[..open_end].ends_with('/') {
+                search = &rest[open_end..];
+                continue;
+            }
+            let body = &rest[open_end + 1..];
+            let close = format!("</{}>", tag);
+            let body_end = body.find(&close)?;
+            return Some(unescape(strip_tags(&body[..body_end]).trim()));
+        }
+        search = rest;
+    }
+    None
+}
+
+/// Finds `prefix:name="value"` inside any tag
+fn attribute_value(packet: &str, local_name: &str) -> Option<String> {
+    let needle = format!(":{}=", local_name);
+    let mut search = packet;
+    while let Some(pos) = search.find(&needle) {
+        // Make sure the match is a whole attribute name, not the tail of a longer one
+        let before = &search[..pos];
+        let prefix_start = before
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let boundary = before[..prefix_start].chars().next_back();
+        let after = &search[pos + needle.len()..];
+
+        if matches!(boundary, Some(c) if c.is_whitespace())
+            && let Some(quote) = after.chars().next().filter(|c| *c == '"' || *c == '\'')
+            && let Some(end) = after[1..].find(quote)
+        {
+            return Some(unescape(&after[1..1 + end]));
+        }
+        search = after;
+    }
+    None
+}
+
+fn local_of(tag: &str) -> &str {
+    tag.rsplit(':').next().unwrap_or(tag)
+}
+
+fn strip_tags(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        let entity_end = rest[amp..].find(';').map(|i| amp + i);
+        let decoded = entity_end.and_then(|end| {
+            let entity = &rest[amp + 1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32),
+                _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+                _ => None,
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[amp + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_elements_and_attributes() {
+        let packet = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+            <rdf:RDF><rdf:Description rdf:about="" sd:parameters="a &amp; b&#10;Steps: 20">
+                <exif:UserComment><rdf:Alt><rdf:li xml:lang="x-default">goblin &lt;lora:pie:1&gt;</rdf:li></rdf:Alt></exif:UserComment>
+            </rdf:Description></rdf:RDF></x:xmpmeta>"#;
+
+        let fields = read_xmp(packet);
+        assert_eq!(
+            fields,
+            vec![
+                ("parameters".to_string(), "a & b\nSteps: 20".to_string()),
+                ("UserComment".to_string(), "goblin <lora:pie:1>".to_string()),
+            ]
+        );
+    }
+}
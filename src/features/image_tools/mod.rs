@@ -1,7 +1,17 @@
 
+mod commands;
+pub mod clip;
+pub mod diff;
+pub mod editor;
+mod feature;
 mod handlers;
+pub mod library;
+pub mod metadata;
+pub mod thumbnail;
 pub mod views;
 mod state;
 
-pub use state::ImageToolsState;
+pub use feature::{state, state_mut, NAVIGATION_ID};
+pub use metadata::GenerationMetadata;
+pub use state::{ImageToolsState, LoadedImage, NodeTreeState};
 pub use views::render_image_tools_view;
\ No newline at end of file
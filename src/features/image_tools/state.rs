@@ -5,11 +5,108 @@
 // of the Image Tools functionality, such as welcome messages, image metadata,
 // and any other state that needs to be tracked for this feature.
 
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use image::RgbImage;
+
+use super::{
+    clip::{ClipCount, ClipTokenizer},
+    editor::ParametersEditor,
+    library::LibraryState,
+    metadata::{
+        self, convert,
+        writer::{self, MetadataPayload},
+        ImageMetadata, MetadataSource, TextEntry,
+    },
+    thumbnail::{self, TerminalGraphics},
+};
+use crate::{
+    core::paths,
+    ui::{file_browser::FileBrowser, graphics::GraphicsOutput},
+};
+
+/// File name of the library index inside the data directory
+const LIBRARY_INDEX_FILE: &str = "image_index.json";
+
+/// An image that has been opened and pried apart
+#[derive(Debug, Clone)]
+pub struct LoadedImage {
+    pub path: PathBuf,
+    pub metadata: ImageMetadata,
+    // Downscaled pixels for the preview; None if the image couldn't be decoded
+    pub thumbnail: Option<RgbImage>,
+}
+
+/// Selection and fold state of the ComfyUI node tree
+#[derive(Debug, Clone, Default)]
+pub struct NodeTreeState {
+    // Index into the graph's node list
+    pub selected: usize,
+    // Ids of nodes whose inputs are unfolded
+    pub expanded: HashSet<String>,
+}
+
+impl NodeTreeState {
+    pub fn select_next(&mut self, node_count: usize) {
+        if self.selected + 1 < node_count {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn toggle(&mut self, id: &str) {
+        if !self.expanded.remove(id) {
+            self.expanded.insert(id.to_string());
+        }
+    }
+
+    pub fn is_expanded(&self, id: &str) -> bool {
+        self.expanded.contains(id)
+    }
+}
 
 #[derive(Default)]
 pub struct ImageToolsState {
     // Stores the welcome message shown when entering Image Tools mode
     pub welcome_message: String,
+    // The image currently being inspected, if any
+    pub current_image: Option<LoadedImage>,
+    // An earlier image kept aside to compare the current one against
+    pub pinned_image: Option<LoadedImage>,
+    // Show the pinned/current comparison instead of the single image view
+    pub show_diff: bool,
+    // Fold state for the ComfyUI node list of the current image
+    pub node_tree: NodeTreeState,
+    // The file picker, while the user is choosing an image
+    pub file_browser: Option<FileBrowser>,
+    // The parameters editor, while the user is rewriting the current image's text
+    pub editor: Option<ParametersEditor>,
+    // The folder index, loaded the first time the library is opened
+    pub library: Option<LibraryState>,
+    // Show the library table instead of the single image view
+    pub show_library: bool,
+    // Why the last attempt to open an image failed
+    pub last_error: Option<String>,
+    // How thumbnails are drawn on this terminal
+    pub graphics: TerminalGraphics,
+    // Bitmap produced by the last draw, written to the terminal after the frame
+    pub graphics_output: RefCell<Option<GraphicsOutput>>,
+    // Counts prompt tokens; None until CLIP's vocabulary is installed
+    pub clip: Option<ClipTokenizer>,
+    // Where `clip` was loaded from, to reload it when the option changes
+    pub clip_vocabulary: Option<PathBuf>,
+    // The count of the text last typed in the input, so a draw doesn't redo it
+    pub clip_count: RefCell<Option<(String, ClipCount)>>,
+    // For the output log on the next poll
+    pub messages: Vec<String>,
 }
 
 impl ImageToolsState {
@@ -27,6 +124,218 @@ impl ImageToolsState {
                 Perfect for when you mutter _\"how the gob did I make this?\"_\n\n\
                 📷 Chuck in an image to begin the poking."
             ),
+            current_image: None,
+            pinned_image: None,
+            show_diff: false,
+            node_tree: NodeTreeState::default(),
+            file_browser: None,
+            editor: None,
+            library: None,
+            show_library: false,
+            last_error: None,
+            graphics: TerminalGraphics::detect(),
+            graphics_output: RefCell::new(None),
+            clip: None,
+            clip_vocabulary: None,
+            clip_count: RefCell::new(None),
+            messages: Vec::new(),
+        }
+    }
+
+    pub fn is_browsing(&self) -> bool {
+        self.file_browser.is_some()
+    }
+
+    /// Opens the file picker in the most recently used directory, falling
+    /// back to the directory of the current image or the working directory
+    pub fn open_file_browser(&mut self) {
+        self.file_browser = Some(self.new_file_browser());
+    }
+
+    /// Opens the file picker to choose a folder for the library to scan
+    pub fn open_folder_browser(&mut self) {
+        self.file_browser = Some(self.new_file_browser().choose_folder());
+    }
+
+    fn new_file_browser(&self) -> FileBrowser {
+        let recent_file = paths::data_dir().map(|dir| dir.join("recent_dirs"));
+        let mut browser = FileBrowser::new(&std::env::current_dir().unwrap_or_default(), recent_file);
+
+        let start = browser
+            .recent_dirs
+            .first()
+            .cloned()
+            .or_else(|| self.current_image.as_ref().and_then(|image| image.path.parent().map(Path::to_path_buf)));
+        if let Some(dir) = start.filter(|dir| dir.is_dir()) {
+            browser.change_dir(&dir);
+        }
+        browser
+    }
+
+    pub fn close_file_browser(&mut self) {
+        self.file_browser = None;
+    }
+
+    /// Shows the library, loading the index on first use. With nothing
+    /// indexed yet, goes straight to choosing a folder to scan.
+    pub fn open_library(&mut self) {
+        let library = self
+            .library
+            .get_or_insert_with(|| LibraryState::new(paths::data_dir().map(|dir| dir.join(LIBRARY_INDEX_FILE))));
+        let empty = library.index.roots.is_empty();
+        self.show_library = true;
+        if empty {
+            self.open_folder_browser();
         }
     }
+
+    pub fn is_library_shown(&self) -> bool {
+        self.show_library && self.library.is_some()
+    }
+
+    /// Filters the library by free text; an empty query shows everything
+    pub fn search_library(&mut self, query: &str) {
+        if let Some(library) = &mut self.library {
+            library.search(query);
+        }
+    }
+
+    /// Collects progress from a running library scan. Returns a message once it finishes.
+    pub fn poll_library_scan(&mut self) -> Option<String> {
+        self.library.as_mut()?.poll_scan()
+    }
+
+    /// Opens the image selected in the library table
+    pub fn open_library_selection(&mut self) -> io::Result<()> {
+        let Some(path) = self
+            .library
+            .as_ref()
+            .and_then(|library| library.selected_entry())
+            .map(|entry| entry.path.clone())
+        else {
+            return Ok(());
+        };
+        self.load_image(&path)?;
+        self.show_library = false;
+        Ok(())
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.editor.is_some()
+    }
+
+    /// Opens the editor on the current image's parameters, written out A1111
+    /// style whatever tool they came from. Returns false if no image is open.
+    pub fn open_editor(&mut self) -> bool {
+        let Some(image) = &self.current_image else {
+            return false;
+        };
+        let text = image
+            .metadata
+            .generation
+            .as_ref()
+            .map(convert::to_parameters_text)
+            .unwrap_or_default();
+        self.editor = Some(ParametersEditor::new(text));
+        true
+    }
+
+    pub fn close_editor(&mut self) {
+        self.editor = None;
+    }
+
+    /// Writes edited parameters into the current image, keeping any ComfyUI
+    /// graph it carries. Returns the backup path.
+    pub fn save_parameters(&mut self, text: String) -> io::Result<PathBuf> {
+        let entries = self.current_entries()?;
+        let payload = MetadataPayload {
+            parameters: Some(text),
+            ..MetadataPayload::comfy_from(entries)
+        };
+        self.rewrite_current_image(&payload)
+    }
+
+    /// Removes all metadata from the current image. Returns the backup path.
+    pub fn strip_current_image(&mut self) -> io::Result<PathBuf> {
+        self.rewrite_current_image(&MetadataPayload::default())
+    }
+
+    /// Rewrites the current image's metadata in the other tool's style:
+    /// a parameters block for A1111, an API prompt graph for ComfyUI
+    pub fn convert_current_image(&mut self, target: MetadataSource) -> io::Result<PathBuf> {
+        let generation = self
+            .current_image
+            .as_ref()
+            .and_then(|image| image.metadata.generation.as_ref())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "the image has no generation metadata"))?;
+        let payload = match target {
+            MetadataSource::ComfyUi => MetadataPayload {
+                comfy_prompt: Some(convert::to_comfy_prompt(generation)),
+                ..MetadataPayload::default()
+            },
+            MetadataSource::Automatic1111 | MetadataSource::SwarmUi => MetadataPayload {
+                parameters: Some(convert::to_parameters_text(generation)),
+                ..MetadataPayload::default()
+            },
+        };
+        self.rewrite_current_image(&payload)
+    }
+
+    fn current_entries(&self) -> io::Result<&[TextEntry]> {
+        self.current_image
+            .as_ref()
+            .map(|image| image.metadata.text_entries.as_slice())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no image is open"))
+    }
+
+    /// Embeds the payload in the current image's file and reloads it
+    fn rewrite_current_image(&mut self, payload: &MetadataPayload) -> io::Result<PathBuf> {
+        let path = self
+            .current_image
+            .as_ref()
+            .map(|image| image.path.clone())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no image is open"))?;
+        let bytes = writer::embed_metadata(&fs::read(&path)?, payload)?;
+        let backup = writer::save_with_backup(&path, &bytes)?;
+        self.load_image(&path)?;
+        Ok(backup)
+    }
+
+    pub fn load_image(&mut self, path: &Path) -> io::Result<()> {
+        let bytes = fs::read(path)?;
+        let metadata = metadata::read_metadata_from_bytes(&bytes)?;
+        // Metadata is the point; a picture we can't decode just goes without a thumbnail
+        let thumbnail = image::load_from_memory(&bytes)
+            .ok()
+            .map(|decoded| thumbnail::prepare_thumbnail(&decoded));
+        self.current_image = Some(LoadedImage {
+            path: path.to_path_buf(),
+            metadata,
+            thumbnail,
+        });
+        self.node_tree = NodeTreeState::default();
+        self.last_error = None;
+        Ok(())
+    }
+
+    pub fn close_image(&mut self) {
+        self.current_image = None;
+        self.node_tree = NodeTreeState::default();
+        self.show_diff = false;
+        self.last_error = None;
+    }
+
+    /// Keeps the current image aside so the next one can be compared to it
+    pub fn pin_current_image(&mut self) -> Option<&LoadedImage> {
+        self.pinned_image = Some(self.current_image.clone()?);
+        self.pinned_image.as_ref()
+    }
+
+    /// True if there are two images with generation metadata to compare
+    pub fn can_diff(&self) -> bool {
+        let has_generation = |image: &Option<LoadedImage>| {
+            image.as_ref().is_some_and(|image| image.metadata.generation.is_some())
+        };
+        has_generation(&self.pinned_image) && has_generation(&self.current_image)
+    }
 }
\ No newline at end of file
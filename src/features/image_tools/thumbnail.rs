@@ -0,0 +1,433 @@
+#![allow(clippy::module_name_repetitions)]
+
+// This file draws a small picture of the open image inside the terminal.
+//
+// The default renderer packs two pixels into each cell with the upper half
+// block character (foreground = top pixel, background = bottom pixel), in
+// truecolor or the 256 colour palette. Terminals that speak the Kitty
+// graphics protocol or Sixel get a real bitmap instead. A bitmap can't live in
+// the cell grid, so the widget marks its cells as skipped (ratatui's diffing
+// then leaves the picture alone) and hands the escape sequence back through
+// its state for the terminal loop to write after the frame is drawn.
+
+use std::env;
+
+use image::{imageops::FilterType, RgbImage};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Position, Rect},
+    style::Color,
+    widgets::StatefulWidget,
+};
+
+use crate::ui::graphics::{GraphicsOutput, KITTY_CLEAR};
+
+/// Cell size assumed when the terminal doesn't report its pixel dimensions
+const DEFAULT_CELL_SIZE: (u16, u16) = (8, 16);
+
+/// Images are downscaled to at most this many pixels per side when loaded
+pub const MAX_THUMBNAIL_SIDE: u32 = 512;
+
+/// Kitty limits each escape sequence payload to 4096 bytes of base64
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsProtocol {
+    HalfBlocks,
+    Kitty,
+    Sixel,
+}
+
+/// What the terminal can display, worked out once at startup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalGraphics {
+    pub protocol: GraphicsProtocol,
+    pub color_depth: ColorDepth,
+    pub cell_size: (u16, u16),
+}
+
+impl Default for TerminalGraphics {
+    fn default() -> Self {
+        Self {
+            protocol: GraphicsProtocol::HalfBlocks,
+            color_depth: ColorDepth::TrueColor,
+            cell_size: DEFAULT_CELL_SIZE,
+        }
+    }
+}
+
+impl TerminalGraphics {
+    /// Detects support from the environment the terminal advertises.
+    /// `PIPEMIND_GRAPHICS` (halfblocks, kitty or sixel) overrides the guess.
+    pub fn detect() -> Self {
+        let var = |name: &str| env::var(name).unwrap_or_default();
+        let term = var("TERM");
+        let term_program = var("TERM_PROGRAM");
+
+        let color_depth = match var("COLORTERM").as_str() {
+            "truecolor" | "24bit" => ColorDepth::TrueColor,
+            _ if term.contains("kitty") || term.contains("direct") => ColorDepth::TrueColor,
+            _ => ColorDepth::Ansi256,
+        };
+
+        let protocol = match var("PIPEMIND_GRAPHICS").to_lowercase().as_str() {
+            "kitty" => GraphicsProtocol::Kitty,
+            "sixel" => GraphicsProtocol::Sixel,
+            "halfblocks" => GraphicsProtocol::HalfBlocks,
+            _ if term == "xterm-kitty" || env::var_os("KITTY_WINDOW_ID").is_some() || term_program == "ghostty" => {
+                GraphicsProtocol::Kitty
+            }
+            _ if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") || term_program == "WezTerm" => {
+                GraphicsProtocol::Sixel
+            }
+            _ => GraphicsProtocol::HalfBlocks,
+        };
+
+        // Pixel-based protocols need the real cell size to pick a bitmap size
+        let cell_size = crossterm::terminal::window_size()
+            .ok()
+            .filter(|size| size.width > 0 && size.columns > 0 && size.rows > 0)
+            .map(|size| (size.width / size.columns, size.height / size.rows))
+            .unwrap_or(DEFAULT_CELL_SIZE);
+
+        Self {
+            protocol,
+            color_depth,
+            cell_size,
+        }
+    }
+}
+
+/// Downscales a decoded image to the size kept around for thumbnails
+pub fn prepare_thumbnail(image: &image::DynamicImage) -> RgbImage {
+    image.thumbnail(MAX_THUMBNAIL_SIDE, MAX_THUMBNAIL_SIDE).to_rgb8()
+}
+
+/// A ratatui widget drawing an image into its area, centred and aspect-correct
+pub struct Thumbnail<'a> {
+    image: &'a RgbImage,
+    graphics: TerminalGraphics,
+}
+
+impl<'a> Thumbnail<'a> {
+    pub fn new(image: &'a RgbImage) -> Self {
+        Self {
+            image,
+            graphics: TerminalGraphics::default(),
+        }
+    }
+
+    pub fn graphics(mut self, graphics: TerminalGraphics) -> Self {
+        self.graphics = graphics;
+        self
+    }
+}
+
+impl StatefulWidget for Thumbnail<'_> {
+    // Set to the bitmap to emit when a pixel protocol is in use
+    type State = Option<GraphicsOutput>;
+
+    fn render(self, area: Rect, buf: &mut Buffer, output: &mut Self::State) {
+        *output = None;
+        if area.is_empty() || self.image.width() == 0 || self.image.height() == 0 {
+            return;
+        }
+        match self.graphics.protocol {
+            GraphicsProtocol::HalfBlocks => self.render_half_blocks(area, buf),
+            GraphicsProtocol::Kitty | GraphicsProtocol::Sixel => {
+                *output = Some(self.render_bitmap(area, buf));
+            }
+        }
+    }
+}
+
+impl Thumbnail<'_> {
+    fn render_half_blocks(&self, area: Rect, buf: &mut Buffer) {
+        // Each cell holds two vertically stacked, roughly square pixels
+        let (width, height) = fit(
+            self.image.dimensions(),
+            (area.width as u32, area.height as u32 * 2),
+        );
+        let scaled = image::imageops::resize(self.image, width, height, FilterType::Triangle);
+
+        let cols = width as u16;
+        let rows = height.div_ceil(2) as u16;
+        let left = area.x + (area.width - cols) / 2;
+        let top = area.y + (area.height - rows) / 2;
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let x = col as u32;
+                let upper = scaled.get_pixel(x, row as u32 * 2);
+                let lower = scaled.get_pixel_checked(x, row as u32 * 2 + 1);
+
+                let cell = &mut buf[(left + col, top + row)];
+                cell.set_symbol("▀");
+                cell.set_fg(self.color(upper.0));
+                cell.set_bg(lower.map(|pixel| self.color(pixel.0)).unwrap_or(Color::Reset));
+            }
+        }
+    }
+
+    fn render_bitmap(&self, area: Rect, buf: &mut Buffer) -> GraphicsOutput {
+        let (cell_width, cell_height) = self.graphics.cell_size;
+        let (width, height) = fit(
+            self.image.dimensions(),
+            (area.width as u32 * cell_width as u32, area.height as u32 * cell_height as u32),
+        );
+        let scaled = image::imageops::resize(self.image, width, height, FilterType::Triangle);
+
+        let cols = (width.div_ceil(cell_width as u32) as u16).min(area.width);
+        let rows = (height.div_ceil(cell_height as u32) as u16).min(area.height);
+        let left = area.x + (area.width - cols) / 2;
+        let top = area.y + (area.height - rows) / 2;
+
+        let sequence = match self.graphics.protocol {
+            GraphicsProtocol::Kitty => kitty_sequence(&scaled, cols, rows),
+            _ => sixel_sequence(&scaled),
+        };
+
+        for y in top..top + rows {
+            for x in left..left + cols {
+                buf[(x, y)].set_skip(true);
+            }
+        }
+        GraphicsOutput {
+            position: Position::new(left, top),
+            sequence,
+        }
+    }
+
+    fn color(&self, [r, g, b]: [u8; 3]) -> Color {
+        match self.graphics.color_depth {
+            ColorDepth::TrueColor => Color::Rgb(r, g, b),
+            ColorDepth::Ansi256 => Color::Indexed(ansi256(r, g, b)),
+        }
+    }
+}
+
+/// Largest size with the image's aspect ratio that fits in `bounds`
+fn fit((width, height): (u32, u32), (max_width, max_height): (u32, u32)) -> (u32, u32) {
+    let scale = f64::min(max_width as f64 / width as f64, max_height as f64 / height as f64);
+    let fitted_width = ((width as f64 * scale).floor() as u32).clamp(1, max_width.max(1));
+    let fitted_height = ((height as f64 * scale).floor() as u32).clamp(1, max_height.max(1));
+    (fitted_width, fitted_height)
+}
+
+/// Nearest entry in the xterm 256 colour palette: the 6x6x6 cube or the grey ramp
+pub fn ansi256(r: u8, g: u8, b: u8) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let nearest_level = |value: u8| {
+        (0..6)
+            .min_by_key(|&i| (LEVELS[i] as i32 - value as i32).abs())
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (nearest_level(r), nearest_level(g), nearest_level(b));
+    let cube = (LEVELS[ri], LEVELS[gi], LEVELS[bi]);
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let grey_index = (average.saturating_sub(8) / 10).min(23) as u8;
+    let grey = 8 + grey_index * 10;
+
+    let distance = |(cr, cg, cb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(cr, r) + d(cg, g) + d(cb, b)
+    };
+    if distance((grey, grey, grey)) < distance(cube) {
+        232 + grey_index
+    } else {
+        16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8
+    }
+}
+
+/// Transmits raw RGB pixels and places them over `cols` x `rows` cells
+fn kitty_sequence(image: &RgbImage, cols: u16, rows: u16) -> String {
+    let payload = base64(image.as_raw());
+    let chunks: Vec<&str> = payload
+        .as_bytes()
+        .chunks(KITTY_CHUNK_SIZE)
+        .map(|chunk| std::str::from_utf8(chunk).unwrap_or_default())
+        .collect();
+
+    // Drop earlier placements first so a changed image doesn't stack on the old one
+    let mut sequence = String::from(KITTY_CLEAR);
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = if index + 1 < chunks.len() { 1 } else { 0 };
+        if index == 0 {
+            sequence.push_str(&format!(
+                "\x1b_Ga=T,f=24,s={},v={},c={},r={},C=1,q=2,m={};{}\x1b\\",
+                image.width(),
+                image.height(),
+                cols,
+                rows,
+                more,
+                chunk
+            ));
+        } else {
+            sequence.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+        }
+    }
+    sequence
+}
+
+/// Encodes the image as Sixel using the 6x6x6 colour cube as palette
+fn sixel_sequence(image: &RgbImage) -> String {
+    let (width, height) = image.dimensions();
+    let level = |value: u8| (value as u32 * 5 + 127) / 255;
+    let palette_index = |[r, g, b]: [u8; 3]| (level(r) * 36 + level(g) * 6 + level(b)) as usize;
+
+    let mut sequence = format!("\x1bPq\"1;1;{};{}", width, height);
+    for index in 0..216 {
+        let percent = |component: usize| component * 100 / 5;
+        sequence.push_str(&format!(
+            "#{};2;{};{};{}",
+            index,
+            percent(index / 36),
+            percent(index / 6 % 6),
+            percent(index % 6)
+        ));
+    }
+
+    for band in (0..height).step_by(6) {
+        // Which colours appear in this six pixel tall band, and where
+        let mut rows_by_color: Vec<Option<Vec<u8>>> = vec![None; 216];
+        for y in band..(band + 6).min(height) {
+            for x in 0..width {
+                let color = palette_index(image.get_pixel(x, y).0);
+                let bits = rows_by_color[color].get_or_insert_with(|| vec![0; width as usize]);
+                bits[x as usize] |= 1 << (y - band);
+            }
+        }
+
+        for (color, bits) in rows_by_color.iter().enumerate() {
+            let Some(bits) = bits else { continue };
+            sequence.push_str(&format!("#{}", color));
+            push_sixel_run_lengths(&mut sequence, bits);
+            // Return to the start of the band for the next colour
+            sequence.push('$');
+        }
+        sequence.push('-');
+    }
+
+    sequence.push_str("\x1b\\");
+    sequence
+}
+
+fn push_sixel_run_lengths(sequence: &mut String, bits: &[u8]) {
+    let mut index = 0;
+    while index < bits.len() {
+        let value = bits[index];
+        let run = bits[index..].iter().take_while(|&&other| other == value).count();
+        let symbol = (0x3f + value) as char;
+        if run > 3 {
+            sequence.push_str(&format!("!{}{}", run, symbol));
+        } else {
+            sequence.extend(std::iter::repeat_n(symbol, run));
+        }
+        index += run;
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let triple = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(triple >> (18 - i * 6) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+    use ratatui::{backend::TestBackend, Terminal};
+
+    /// A 2x4 image: red on top, blue at the bottom
+    fn two_tone() -> RgbImage {
+        RgbImage::from_fn(2, 4, |_, y| if y < 2 { Rgb([255, 0, 0]) } else { Rgb([0, 0, 255]) })
+    }
+
+    fn draw(image: &RgbImage, graphics: TerminalGraphics, width: u16, height: u16) -> Buffer {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal
+            .draw(|f| f.render_stateful_widget(Thumbnail::new(image).graphics(graphics), f.area(), &mut None))
+            .unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    #[test]
+    fn test_half_blocks_in_truecolor() {
+        let buffer = draw(&two_tone(), TerminalGraphics::default(), 2, 2);
+
+        let top = &buffer[(0, 0)];
+        assert_eq!(top.symbol(), "▀");
+        assert_eq!(top.fg, Color::Rgb(255, 0, 0));
+        assert_eq!(top.bg, Color::Rgb(255, 0, 0));
+        let bottom = &buffer[(1, 1)];
+        assert_eq!(bottom.fg, Color::Rgb(0, 0, 255));
+        assert_eq!(bottom.bg, Color::Rgb(0, 0, 255));
+    }
+
+    #[test]
+    fn test_half_blocks_fall_back_to_256_colours() {
+        let graphics = TerminalGraphics {
+            color_depth: ColorDepth::Ansi256,
+            ..TerminalGraphics::default()
+        };
+        // Wider area than needed: the image is centred horizontally
+        let buffer = draw(&two_tone(), graphics, 6, 2);
+
+        assert_eq!(buffer[(0, 0)].symbol(), " ");
+        assert_eq!(buffer[(2, 0)].fg, Color::Indexed(196));
+        assert_eq!(buffer[(2, 1)].bg, Color::Indexed(21));
+        assert_eq!(ansi256(128, 128, 128), 244);
+    }
+
+    #[test]
+    fn test_kitty_skips_cells_and_returns_sequence() {
+        let graphics = TerminalGraphics {
+            protocol: GraphicsProtocol::Kitty,
+            cell_size: (1, 2),
+            ..TerminalGraphics::default()
+        };
+        let area = Rect::new(0, 0, 4, 2);
+        let mut buffer = Buffer::empty(area);
+        let mut output = None;
+        Thumbnail::new(&two_tone()).graphics(graphics).render(area, &mut buffer, &mut output);
+
+        let output = output.unwrap();
+        assert_eq!(output.position, Position::new(1, 0));
+        assert!(output.sequence.contains("\x1b_Ga=T,f=24,s=2,v=4,c=2,r=2"));
+        assert!(!buffer[(0, 0)].skip);
+        assert!(buffer[(1, 0)].skip);
+        assert!(buffer[(2, 1)].skip);
+        assert!(!buffer[(3, 1)].skip);
+    }
+
+    #[test]
+    fn test_sixel_encoding() {
+        let sequence = sixel_sequence(&two_tone());
+        assert!(sequence.starts_with("\x1bPq\"1;1;2;4"));
+        // Red fills the top two rows of the band, blue the next two
+        assert!(sequence.contains("#180BB$"));
+        assert!(sequence.contains("#5KK$"));
+        assert!(sequence.ends_with("-\x1b\\"));
+        assert_eq!(base64(b"pie"), "cGll");
+        assert_eq!(base64(b"pi"), "cGk=");
+    }
+}
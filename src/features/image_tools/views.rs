@@ -1,26 +1,508 @@
+use std::path::Path;
+
 use ratatui::{
-    layout::Rect,
-    style::{Color, Style},
-    widgets::{Block, Borders, Paragraph, Wrap},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Cell, List, ListItem, ListState, Paragraph, Row, Table, TableState, Wrap},
     Frame,
 };
 
-use crate::core::app_state::AppState;
+use crate::{
+    core::{
+        app_state::{AppState, FocusArea},
+        keymap::Action,
+        paths,
+        theme::Theme,
+    },
+    ui::utils::prompt_lines,
+};
+use super::{
+    diff::{self, DiffKind, LoraChange, MetadataDiff, TokenDiff},
+    library::{self, LibraryState, SortColumn},
+    metadata::{
+        comfyui::{ComfyGraph, InputValue},
+        GenerationMetadata, LoraRef, MetadataSource,
+    },
+    state::{ImageToolsState, LoadedImage, NodeTreeState},
+    clip::{self, ClipTokenizer},
+    thumbnail::Thumbnail,
+};
+
+pub fn render_image_tools_view(f: &mut Frame, area: Rect, state: &ImageToolsState, app_state: &AppState) {
+    // Only a thumbnail drawn in this frame goes on screen
+    state.graphics_output.replace(None);
+    if state.show_library
+        && let Some(library) = &state.library
+    {
+        render_library_view(f, area, library, app_state.has_focus(FocusArea::Preview), app_state);
+        return;
+    }
+    if state.show_diff
+        && !app_state.is_command_mode
+        && let (Some(pinned), Some(current)) = (&state.pinned_image, &state.current_image)
+        && let (Some(left), Some(right)) = (&pinned.metadata.generation, &current.metadata.generation)
+    {
+        render_diff_view(f, area, (pinned, left), (current, right), &app_state.theme);
+        return;
+    }
+
+    let theme = &app_state.theme;
+    let image = state.current_image.as_ref();
+
+    let mut text = match (app_state.is_command_mode, image) {
+        // While typing this is the command echo, after a failed command the error
+        (true, _) => Text::from(app_state.preview_content.clone()),
+        (false, Some(image)) => {
+            let mut lines = image_lines(image, state.clip.as_ref(), theme);
+            // Say why the prompts have no token counts
+            if state.clip.is_none() && image.metadata.generation.is_some() {
+                lines.push(Line::from(""));
+                lines.push(Line::from(Span::styled(clip_hint(state.clip_vocabulary.as_deref()), theme.muted)));
+            }
+            Text::from(lines)
+        }
+        (false, None) => Text::from(state.welcome_message.clone()),
+    };
+    if let Some(error) = &state.last_error {
+        text.lines.insert(0, Line::from(""));
+        text.lines.insert(0, Line::from(Span::styled(error.clone(), theme.error)));
+    }
 
-pub fn render_image_tools_view(f: &mut Frame, area: Rect, app_state: &AppState) {
-    let text = match app_state.is_command_mode {
-        true => format!("Command Mode: {}", app_state.input_buffer),
-        false => app_state.image_tools_state.welcome_message.clone(),
+    let title = match image.and_then(|image| image.path.file_name()) {
+        Some(name) => format!("Image Tools - {}", name.to_string_lossy()),
+        None => String::from("Image Tools"),
     };
 
     let block = Block::default()
-        .title("Image Tools")
+        .title(title)
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::White));
+        .border_style(theme.panel);
 
     let paragraph = Paragraph::new(text)
         .wrap(Wrap { trim: true })
         .block(block);
 
-    f.render_widget(paragraph, area);
-}
\ No newline at end of file
+    let graph = image.and_then(|image| image.metadata.comfy_graph.as_ref());
+    let (main_area, tree_area) = match graph {
+        Some(_) if !app_state.is_command_mode => {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Min(5),         // Metadata
+                    Constraint::Percentage(45), // Node tree
+                ])
+                .split(area);
+            (chunks[0], Some(chunks[1]))
+        }
+        _ => (area, None),
+    };
+
+    let thumbnail = image.and_then(|image| image.thumbnail.as_ref());
+    match thumbnail {
+        Some(thumbnail) if !app_state.is_command_mode => {
+            let chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([
+                    Constraint::Percentage(40), // Thumbnail
+                    Constraint::Min(10),        // Metadata
+                ])
+                .split(main_area);
+
+            let thumbnail_block = Block::default()
+                .title("Thumbnail")
+                .borders(Borders::ALL)
+                .border_style(theme.panel);
+            let inner = thumbnail_block.inner(chunks[0]);
+            f.render_widget(thumbnail_block, chunks[0]);
+            f.render_stateful_widget(
+                Thumbnail::new(thumbnail).graphics(state.graphics),
+                inner,
+                &mut state.graphics_output.borrow_mut(),
+            );
+            f.render_widget(paragraph, chunks[1]);
+        }
+        _ => f.render_widget(paragraph, main_area),
+    }
+
+    if let (Some(graph), Some(tree_area)) = (graph, tree_area) {
+        let focused = app_state.has_focus(FocusArea::Preview);
+        render_node_tree(f, tree_area, graph, &state.node_tree, focused, theme);
+    }
+}
+
+/// The indexed images as a sortable table, filtered by the search from the input line
+fn render_library_view(f: &mut Frame, area: Rect, library: &LibraryState, focused: bool, app_state: &AppState) {
+    let theme = &app_state.theme;
+    let mut title = format!("Library - {} of {} images", library.visible.len(), library.index.entries.len());
+    if !library.query.is_empty() {
+        title.push_str(&format!(" matching \"{}\"", library.query));
+    }
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(theme.panel);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(1),    // Table
+            Constraint::Length(1), // Status and keys
+        ])
+        .split(inner);
+
+    let header = Row::new(SortColumn::ALL.map(|column| {
+        let label = match (column == library.sort, library.descending) {
+            (true, true) => format!("{} ▼", column.label()),
+            (true, false) => format!("{} ▲", column.label()),
+            (false, _) => column.label().to_string(),
+        };
+        Cell::from(label)
+    }))
+    .style(theme.accent.add_modifier(Modifier::BOLD));
+
+    let rows = library.visible.iter().map(|&index| {
+        let entry = &library.index.entries[index];
+        Row::new([
+            entry.file_name(),
+            entry.model.clone().unwrap_or_default(),
+            entry.seed.map(|seed| seed.to_string()).unwrap_or_default(),
+            entry.sampler.clone().unwrap_or_default(),
+            format!("{}x{}", entry.width, entry.height),
+            library::format_timestamp(entry.modified),
+        ])
+    });
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Percentage(28), // File
+            Constraint::Percentage(22), // Model
+            Constraint::Length(12),     // Seed
+            Constraint::Length(14),     // Sampler
+            Constraint::Length(10),     // Size
+            Constraint::Length(16),     // Date
+        ],
+    )
+    .header(header)
+    .row_highlight_style(theme.highlight);
+
+    let mut table_state = TableState::default();
+    if focused && !library.visible.is_empty() {
+        table_state.select(Some(library.selected));
+    }
+    f.render_stateful_widget(table, chunks[0], &mut table_state);
+
+    let hints = app_state.keymap.hints(&[
+        (&[Action::Activate], "open"),
+        (&[Action::Sort], "sort"),
+        (&[Action::ReverseSort], "reverse"),
+        (&[Action::Rescan], "rescan"),
+        (&[Action::AddFolder], "add folder"),
+        (&[Action::Back], "close"),
+    ]);
+    let status = Line::from(vec![
+        Span::styled(library.status.clone(), theme.success),
+        Span::styled(format!("  {}  (search from the input line)", hints), theme.muted),
+    ]);
+    f.render_widget(Paragraph::new(status), chunks[1]);
+}
+
+/// Shows the pinned image on the left and the current one on the right, with
+/// what only one of them has marked as removed or added
+fn render_diff_view(
+    f: &mut Frame,
+    area: Rect,
+    (pinned, left): (&LoadedImage, &GenerationMetadata),
+    (current, right): (&LoadedImage, &GenerationMetadata),
+    theme: &Theme,
+) {
+    let diff = diff::diff_metadata(left, right);
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+
+    let mut left_lines = diff_side_lines(&diff, DiffSide::Left, theme);
+    if diff.is_identical() {
+        left_lines.insert(0, Line::from(""));
+        left_lines.insert(0, Line::from(Span::styled(
+            "The generation metadata is identical.",
+            theme.accent,
+        )));
+    }
+
+    for (lines, image, label, chunk) in [
+        (left_lines, pinned, "Pinned", chunks[0]),
+        (diff_side_lines(&diff, DiffSide::Right, theme), current, "Current", chunks[1]),
+    ] {
+        let name = image.path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let block = Block::default()
+            .title(format!("{} - {}", label, name))
+            .borders(Borders::ALL)
+            .border_style(theme.panel);
+        f.render_widget(Paragraph::new(lines).wrap(Wrap { trim: true }).block(block), chunk);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DiffSide {
+    Left,
+    Right,
+}
+
+impl DiffSide {
+    /// Style for values that differ on this side
+    fn style(self, theme: &Theme) -> Style {
+        match self {
+            DiffSide::Left => theme.removed,
+            DiffSide::Right => theme.added,
+        }
+    }
+}
+
+fn diff_side_lines(diff: &MetadataDiff, side: DiffSide, theme: &Theme) -> Vec<Line<'static>> {
+    let mut lines = vec![heading("Prompt", theme)];
+    lines.push(token_line(&diff.prompt, side, theme));
+
+    if !diff.negative_prompt.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(heading("Negative prompt", theme));
+        lines.push(token_line(&diff.negative_prompt, side, theme));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(heading("Settings", theme));
+    for setting in &diff.settings {
+        let value = match side {
+            DiffSide::Left => setting.left.clone(),
+            DiffSide::Right => setting.right.clone(),
+        };
+        let value = value.unwrap_or_else(|| String::from("-"));
+        let style = if setting.is_changed() {
+            side.style(theme).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{}: ", setting.label), theme.accent),
+            Span::styled(value, style),
+        ]));
+    }
+
+    if !diff.loras.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(heading("LoRAs", theme));
+    }
+    for change in &diff.loras {
+        let (lora, style) = match (change, side) {
+            (LoraChange::Same(lora), _) => (lora, Style::default()),
+            (LoraChange::Removed(lora), DiffSide::Left) | (LoraChange::Added(lora), DiffSide::Right) => {
+                (lora, side.style(theme))
+            }
+            (LoraChange::Changed { left, .. }, DiffSide::Left) => (left, theme.accent),
+            (LoraChange::Changed { right, .. }, DiffSide::Right) => (right, theme.accent),
+            // Present only on the other side
+            _ => continue,
+        };
+        lines.push(Line::from(Span::styled(lora_label(lora), style)));
+    }
+
+    lines
+}
+
+/// The prompt tokens present on one side, highlighting the ones the other side lacks
+fn token_line(tokens: &[TokenDiff], side: DiffSide, theme: &Theme) -> Line<'static> {
+    let own = match side {
+        DiffSide::Left => DiffKind::Removed,
+        DiffSide::Right => DiffKind::Added,
+    };
+    let mut spans = Vec::new();
+    for token in tokens.iter().filter(|token| token.kind == DiffKind::Same || token.kind == own) {
+        if !spans.is_empty() {
+            spans.push(Span::raw(", "));
+        }
+        let style = if token.kind == own {
+            side.style(theme).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        spans.push(Span::styled(token.token.clone(), style));
+    }
+    Line::from(spans)
+}
+
+fn lora_label(lora: &LoraRef) -> String {
+    let strength = match lora.strength_clip {
+        Some(clip) if clip != lora.strength_model => format!("{} / clip {}", lora.strength_model, clip),
+        _ => lora.strength_model.to_string(),
+    };
+    format!("{} ({})", lora.name, strength)
+}
+
+/// Lists every node in the ComfyUI graph, unfolding the inputs of expanded nodes
+fn render_node_tree(f: &mut Frame, area: Rect, graph: &ComfyGraph, tree: &NodeTreeState, focused: bool, theme: &Theme) {
+    let mut items = Vec::new();
+    let mut selected_row = 0;
+
+    for (index, node) in graph.nodes.iter().enumerate() {
+        let expanded = tree.is_expanded(&node.id);
+        if index == tree.selected {
+            selected_row = items.len();
+        }
+
+        let mut spans = vec![
+            Span::raw(if expanded { "▾ " } else { "▸ " }),
+            Span::styled(format!("#{} ", node.id), theme.muted),
+            Span::styled(node.display_name().to_string(), theme.accent),
+        ];
+        if node.title.is_some() {
+            spans.push(Span::styled(
+                format!(" ({})", node.class_type),
+                theme.muted,
+            ));
+        }
+        items.push(ListItem::new(Line::from(spans)));
+
+        if !expanded {
+            continue;
+        }
+        for (name, input) in &node.inputs {
+            let value = match input {
+                InputValue::Link { node: source, .. } => {
+                    let source_name = graph.node(source).map(|n| n.display_name()).unwrap_or("?");
+                    Span::styled(
+                        format!("← #{} {}", source, source_name),
+                        theme.link,
+                    )
+                }
+                InputValue::Value(value) => Span::raw(format_value(value)),
+            };
+            items.push(ListItem::new(Line::from(vec![
+                Span::raw("    "),
+                Span::styled(format!("{}: ", name), theme.field),
+                value,
+            ])));
+        }
+    }
+
+    let mut list_state = ListState::default();
+    if focused {
+        list_state.select(Some(selected_row));
+    }
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title(format!("Nodes ({})", graph.nodes.len()))
+                .borders(Borders::ALL)
+                .border_style(theme.panel),
+        )
+        .highlight_style(theme.highlight);
+
+    f.render_stateful_widget(list, area, &mut list_state);
+}
+
+fn format_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => {
+            // Keep long prompts to one row; the metadata pane shows them in full
+            let mut lines = text.lines();
+            let first = lines.next().unwrap_or_default().to_string();
+            if lines.next().is_some() { format!("{}…", first) } else { first }
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Where to put CLIP's vocabulary for token counts
+fn clip_hint(path: Option<&Path>) -> String {
+    match path {
+        Some(path) => format!("Token counts need CLIP's {} at {}", clip::VOCAB_FILE, paths::display_path(path)),
+        None => format!("Token counts need CLIP's {}; set general.clip_vocabulary to it", clip::VOCAB_FILE),
+    }
+}
+
+fn image_lines(image: &LoadedImage, clip: Option<&ClipTokenizer>, theme: &Theme) -> Vec<Line<'static>> {
+    match &image.metadata.generation {
+        Some(generation) => generation_lines(generation, clip, theme),
+        None => {
+            let mut lines = vec![
+                Line::from(Span::styled(
+                    "No generation metadata found in this image.",
+                    theme.accent,
+                )),
+                Line::from(""),
+            ];
+            // Still show whatever text the file carries
+            for entry in &image.metadata.text_entries {
+                let label = format!("{} {}", entry.location.label(), entry.key);
+                lines.push(setting_line(&label, entry.text.clone(), theme));
+            }
+            lines
+        }
+    }
+}
+
+fn generation_lines(generation: &GenerationMetadata, clip: Option<&ClipTokenizer>, theme: &Theme) -> Vec<Line<'static>> {
+    // The CLIP token count follows the heading when the vocabulary is installed
+    let prompt_heading = |title: &str, prompt: &str| match clip {
+        Some(clip) => heading(&format!("{} ({})", title, clip.count(prompt)), theme),
+        None => heading(title, theme),
+    };
+    let mut lines = vec![prompt_heading("Prompt", &generation.prompt)];
+    lines.extend(prompt_lines(&generation.prompt, theme).into_iter().map(Line::from));
+
+    if !generation.negative_prompt.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(prompt_heading("Negative prompt", &generation.negative_prompt));
+        lines.extend(prompt_lines(&generation.negative_prompt, theme).into_iter().map(Line::from));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(heading("Settings", theme));
+
+    let source = match generation.source {
+        MetadataSource::Automatic1111 => "Automatic1111",
+        MetadataSource::ComfyUi => "ComfyUI",
+        MetadataSource::SwarmUi => "SwarmUI",
+    };
+    lines.push(setting_line("Source", source.to_string(), theme));
+
+    let settings = [
+        ("Model", generation.model.clone()),
+        ("Model hash", generation.model_hash.clone()),
+        ("Seed", generation.seed.map(|seed| seed.to_string())),
+        ("Steps", generation.steps.map(|steps| steps.to_string())),
+        ("Sampler", generation.sampler.clone()),
+        ("Scheduler", generation.scheduler.clone()),
+        ("CFG scale", generation.cfg_scale.map(|cfg| cfg.to_string())),
+        ("Size", generation.size.map(|(w, h)| format!("{}x{}", w, h))),
+    ];
+    for (label, value) in settings {
+        if let Some(value) = value {
+            lines.push(setting_line(label, value, theme));
+        }
+    }
+    for lora in &generation.loras {
+        lines.push(setting_line("LoRA", lora_label(lora), theme));
+    }
+    for (key, value) in &generation.extra {
+        lines.push(setting_line(key, value.clone(), theme));
+    }
+
+    lines
+}
+
+fn heading(title: &str, theme: &Theme) -> Line<'static> {
+    Line::from(Span::styled(title.to_string(), theme.heading))
+}
+
+fn setting_line(label: &str, value: String, theme: &Theme) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(format!("{}: ", label), theme.accent),
+        Span::raw(value),
+    ])
+}
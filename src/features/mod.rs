@@ -1 +1,254 @@
-pub mod image_tools;
\ No newline at end of file
+// This module holds the tools that plug into the shell.
+//
+// Each tool is a `Feature`: it owns its state and brings its navigation
+// entry, the view it draws, the keys it handles and its slash commands.
+// `register_features` is the only list of them, so adding a tool means
+// writing its module and one line there.
+
+pub mod image_tools;
+pub mod plugins;
+pub mod settings;
+
+use std::{any::Any, ops::Range, path::Path, sync::mpsc::{Receiver, TryRecvError}};
+
+use crossterm::event::KeyEvent;
+use ratatui::{layout::Rect, Frame};
+
+use crate::{
+    core::{
+        app_state::AppState,
+        command::CommandRegistry,
+        config::{self, Config},
+        keymap::{Action, Context},
+        navigation::NavigationItem,
+    },
+    ui::graphics::GraphicsOutput,
+};
+
+/// What a feature shows about a prompt being typed in the input box
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputAnnotation {
+    /// Added to the input box's title
+    pub label: String,
+    /// Bytes of the input to mark
+    pub marks: Vec<Range<usize>>,
+}
+
+pub trait Feature: Any {
+    /// Id of the feature's navigation entry
+    fn id(&self) -> &str;
+
+    /// The navigation entry, with its submenu if it has one
+    fn navigation_item(&self) -> NavigationItem;
+
+    fn register_commands(&mut self, _registry: &mut CommandRegistry) {}
+
+    /// Draws the content area while the user is on the feature's entry or below it
+    fn render(&self, f: &mut Frame, area: Rect, app_state: &AppState);
+
+    /// The keymap context of the view's own keys while the content area has
+    /// focus, looked up before the keys all views share
+    fn key_context(&self) -> Option<Context> {
+        None
+    }
+
+    /// Carries out an action while the content area has focus. Returns true
+    /// if the action was used.
+    fn handle_action(&mut self, _action: Action) -> bool {
+        false
+    }
+
+    /// Whether an overlay is open that takes every key until it closes
+    fn is_modal(&self) -> bool {
+        false
+    }
+
+    /// Feeds a key to the open overlay. Returns a message for the output log.
+    fn handle_modal_key(&mut self, _key: &KeyEvent) -> Option<String> {
+        None
+    }
+
+    /// The bitmap the last `render` drew, for the terminal loop to write
+    /// after the frame
+    fn graphics(&self) -> Option<GraphicsOutput> {
+        None
+    }
+
+    /// Draws the feature's overlays above the whole screen
+    fn render_overlay(&self, _f: &mut Frame, _app_state: &AppState) {}
+
+    /// Runs once per tick for background work. Returns messages for the output log.
+    fn poll(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Sees the input line as it is typed, while the feature is on screen
+    fn input_changed(&mut self, _text: &str) {}
+
+    /// Something to show about `text`, a prompt being typed in the input
+    fn input_annotation(&self, _text: &str) -> Option<InputAnnotation> {
+        None
+    }
+
+    /// Receives a line entered in the input that is not a command, while
+    /// the feature is on screen
+    fn submit_input(&mut self, _line: &str) {}
+
+    /// Sees the configuration at startup and whenever the file is reloaded
+    fn config_changed(&mut self, _config: &Config) {}
+}
+
+/// Features starting in the background, in the order they are to be
+/// registered, or what kept each from starting
+pub type FeatureStarts = Receiver<Result<Box<dyn Feature + Send>, String>>;
+
+/// Owns every feature, in navigation order
+#[derive(Default)]
+pub struct FeatureRegistry {
+    features: Vec<Box<dyn Feature>>,
+    // Features still starting, with the place they take
+    starting: Vec<(usize, FeatureStarts)>,
+}
+
+impl FeatureRegistry {
+    pub fn register(&mut self, feature: impl Feature) {
+        self.features.push(Box::new(feature));
+    }
+
+    /// Saves a place for features that are still starting. They take it as
+    /// `poll_started` finds them ready.
+    pub fn register_later(&mut self, starts: FeatureStarts) {
+        self.starting.push((self.features.len(), starts));
+    }
+
+    /// Registers the features that finished starting since the last call.
+    /// Returns their ids, and what kept others from starting.
+    pub fn poll_started(&mut self) -> (Vec<String>, Vec<String>) {
+        let mut started = Vec::new();
+        let mut errors = Vec::new();
+        let mut finished = Vec::new();
+        for index in 0..self.starting.len() {
+            loop {
+                let (at, starts) = &self.starting[index];
+                let at = *at;
+                match starts.try_recv() {
+                    Ok(Ok(feature)) => {
+                        started.push(feature.id().to_string());
+                        self.features.insert(at, feature);
+                        // Everything placed after it moves along
+                        for (other, (position, _)) in self.starting.iter_mut().enumerate() {
+                            if *position > at || (*position == at && other >= index) {
+                                *position += 1;
+                            }
+                        }
+                    }
+                    Ok(Err(error)) => errors.push(error),
+                    Err(TryRecvError::Empty) => break,
+                    // Every feature has arrived
+                    Err(TryRecvError::Disconnected) => {
+                        finished.push(index);
+                        break;
+                    }
+                }
+            }
+        }
+        for index in finished.into_iter().rev() {
+            self.starting.remove(index);
+        }
+        (started, errors)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Feature> {
+        self.features.iter().map(|feature| feature.as_ref())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut (dyn Feature + 'static)> {
+        self.features.iter_mut().map(|feature| feature.as_mut())
+    }
+
+    pub fn find(&self, id: &str) -> Option<&dyn Feature> {
+        self.iter().find(|feature| feature.id() == id)
+    }
+
+    pub fn find_mut(&mut self, id: &str) -> Option<&mut (dyn Feature + 'static)> {
+        self.iter_mut().find(|feature| feature.id() == id)
+    }
+
+    /// The feature of type `T`, to reach its state
+    pub fn get<T: Feature>(&self) -> Option<&T> {
+        self.iter().find_map(|feature| (feature as &dyn Any).downcast_ref())
+    }
+
+    pub fn get_mut<T: Feature>(&mut self) -> Option<&mut T> {
+        self.iter_mut().find_map(|feature| (feature as &mut dyn Any).downcast_mut())
+    }
+}
+
+/// Every feature the shell starts with, with the plugins installed in
+/// `plugins_dir` to come before Settings once they have started
+pub fn register_features(registry: &mut FeatureRegistry, plugins_dir: Option<&Path>) {
+    registry.register(image_tools::ImageToolsState::new());
+    if let Some(dir) = plugins_dir {
+        registry.register_later(plugins::start_plugins(dir));
+    }
+    registry.register(settings::SettingsState::new(config::config_path()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter {
+        id: &'static str,
+        count: usize,
+    }
+
+    impl Feature for Counter {
+        fn id(&self) -> &str {
+            self.id
+        }
+
+        fn navigation_item(&self) -> NavigationItem {
+            NavigationItem::new(self.id, "Counter")
+        }
+
+        fn render(&self, _f: &mut Frame, _area: Rect, _app_state: &AppState) {}
+
+        fn submit_input(&mut self, _line: &str) {
+            self.count += 1;
+        }
+    }
+
+    #[test]
+    fn test_registry_finds_features_and_their_state() {
+        let mut registry = FeatureRegistry::default();
+        registry.register(Counter { id: "counter", count: 0 });
+        assert!(registry.find("counter").is_some());
+        assert!(registry.find("missing").is_none());
+
+        if let Some(feature) = registry.find_mut("counter") {
+            feature.submit_input("a line");
+        }
+        assert_eq!(registry.get::<Counter>().map(|counter| counter.count), Some(1));
+        assert!(registry.get::<image_tools::ImageToolsState>().is_none());
+    }
+
+    #[test]
+    fn test_features_started_later_keep_their_place() {
+        let (sender, starts) = std::sync::mpsc::channel();
+        let mut registry = FeatureRegistry::default();
+        registry.register(Counter { id: "first", count: 0 });
+        registry.register_later(starts);
+        registry.register(Counter { id: "last", count: 0 });
+
+        sender.send(Ok(Box::new(Counter { id: "plugin", count: 0 }))).unwrap();
+        sender.send(Err(String::from("Plugin broken failed to start"))).unwrap();
+        drop(sender);
+        let (started, errors) = registry.poll_started();
+        assert_eq!(started, ["plugin"]);
+        assert_eq!(errors, ["Plugin broken failed to start"]);
+        let ids: Vec<&str> = registry.iter().map(|feature| feature.id()).collect();
+        assert_eq!(ids, ["first", "plugin", "last"]);
+        assert!(registry.starting.is_empty());
+    }
+}
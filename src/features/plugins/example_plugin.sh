@@ -0,0 +1,37 @@
+#!/bin/sh
+# An example plugin, run by the plugin tests. It reads one JSON-RPC message
+# per line and answers with printf; a real plugin would use a JSON library.
+#
+#   /echo <text>   answers with the text
+#   /hang          never answers
+#   /crash         exits with an error
+#   Enter on Greet pushes a styled preview; input lines come back in the log
+
+field() {
+    printf '%s\n' "$2" | sed -n "s/.*\"$1\":\"\\{0,1\\}\\([^\",}]*\\).*/\\1/p"
+}
+
+while IFS= read -r message; do
+    id=$(field id "$message")
+    case "$message" in
+        *'"method":"initialize"'*)
+            printf '{"jsonrpc":"2.0","id":%s,"result":{"title":"Echo","navigation":[{"id":"greet","name":"Greet","preview":"Enter to be greeted"}],"commands":[{"name":"echo","help":"Answer with the text","args":[{"name":"text","kind":"rest","required":true}]},{"name":"hang","help":"Never answer"},{"name":"crash","help":"Exit with an error"}]}}\n' "$id"
+            ;;
+        *'"method":"command"'*'"name":"echo"'*)
+            printf '{"jsonrpc":"2.0","id":%s,"result":{"text":"%s"}}\n' "$id" "$(field text "$message")"
+            ;;
+        *'"name":"crash"'*)
+            echo "goblin in the works" >&2
+            exit 3
+            ;;
+        *'"method":"activate"'*)
+            printf '{"jsonrpc":"2.0","method":"preview","params":{"lines":[[{"text":"Hello, ","bold":true},{"text":"%s","fg":"green"}]]}}\n' "$(field item "$message")"
+            ;;
+        *'"method":"input"'*)
+            printf '{"jsonrpc":"2.0","method":"log","params":{"message":"heard %s"}}\n' "$(field line "$message")"
+            ;;
+        *'"method":"shutdown"'*)
+            exit 0
+            ;;
+    esac
+done
@@ -0,0 +1,467 @@
+// This file turns a running plugin into a `Feature`.
+//
+// What the plugin declared in its `initialize` answer becomes a navigation
+// entry and slash commands like any built-in tool's. Commands and menu
+// actions are plain functions, so they find their plugin again through the
+// feature registry by the command name or the highlighted item. A command
+// only sends its request; the answer replaces the preview when it arrives.
+
+use std::{any::Any, path::Path, str::FromStr, time::Duration};
+
+use ratatui::{
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Paragraph, Wrap},
+    Frame,
+};
+use serde_json::{json, Map, Value};
+
+use super::rpc::{PluginEvent, PluginProcess};
+use crate::{
+    core::{
+        app_state::AppState,
+        command::{ArgCompletion, ArgKind, ArgSpec, Command, CommandArgs, CommandRegistry},
+        navigation::NavigationItem,
+    },
+    features::Feature,
+};
+
+/// Version of the protocol the host speaks, sent with `initialize`
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// How long a plugin has to answer a command
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+// A slash command a plugin declared
+struct PluginCommand {
+    name: String,
+    help: String,
+    args: Vec<ArgSpec>,
+}
+
+pub struct PluginFeature {
+    name: String,
+    // "plugins.<name>", also the id of its navigation entry
+    id: String,
+    title: String,
+    process: PluginProcess,
+    navigation: NavigationItem,
+    commands: Vec<PluginCommand>,
+    // The commands that made it into the registry
+    registered: Vec<&'static str>,
+    // The last preview the plugin pushed, or the answer to a command
+    content: Option<Vec<Line<'static>>>,
+    // Ids of the command requests still unanswered, with the command's name
+    waiting: Vec<(u64, String)>,
+    // Messages for the log on the next poll
+    messages: Vec<String>,
+    timeout: Duration,
+}
+
+impl PluginFeature {
+    /// Runs the plugin and waits up to `timeout` for its `initialize` answer
+    pub fn start(name: &str, command: &Path, args: &[String], dir: &Path, timeout: Duration) -> Result<Self, String> {
+        let mut process = PluginProcess::spawn(command, args, dir).map_err(|error| error.to_string())?;
+        let request = process.request("initialize", json!({ "protocol": PROTOCOL_VERSION }), timeout)?;
+        let info = process.wait(request)?;
+
+        let id = format!("plugins.{}", name);
+        let title = info.get("title").and_then(Value::as_str).unwrap_or(name).to_string();
+        let children = list(&info, "navigation").iter().filter_map(|item| navigation_item(&id, item)).collect();
+        let navigation = NavigationItem::new(&id, &title)
+            .preview_text(info.get("preview").and_then(Value::as_str).unwrap_or(&title))
+            .action(activate_item)
+            .children(children);
+        let commands = list(&info, "commands").iter().filter_map(plugin_command).collect();
+
+        Ok(Self {
+            name: name.to_string(),
+            id,
+            title,
+            process,
+            navigation,
+            commands,
+            registered: Vec::new(),
+            content: None,
+            waiting: Vec::new(),
+            messages: Vec::new(),
+            timeout: REQUEST_TIMEOUT,
+        })
+    }
+
+    /// Sets how long the plugin has to answer a command
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Whether `/command` runs on this plugin
+    pub fn owns_command(&self, command: &str) -> bool {
+        self.registered.contains(&command)
+    }
+
+    /// Sends one of the plugin's commands. Returns the text to preview until
+    /// the answer arrives.
+    pub fn run_command(&mut self, args: &CommandArgs) -> String {
+        let spec = self.commands.iter().find(|command| command.name == args.command());
+        let values: Map<String, Value> = args
+            .iter()
+            .map(|(name, value)| {
+                let is_switch = spec.is_some_and(|spec| {
+                    spec.args.iter().any(|arg| arg.name == name && arg.kind == ArgKind::Switch)
+                });
+                let value = if is_switch { Value::Bool(true) } else { Value::from(value) };
+                (name.to_string(), value)
+            })
+            .collect();
+
+        let params = json!({ "name": args.command(), "args": values });
+        match self.process.request("command", params, self.timeout) {
+            Ok(id) => {
+                self.waiting.push((id, args.command().to_string()));
+                self.content = None;
+                format!("Waiting for {} to answer /{}…", self.title, args.command())
+            }
+            Err(error) => format!("Plugin {} failed on /{}: {}", self.name, args.command(), error),
+        }
+    }
+
+    // Shows the answer to a command, or logs why there was none
+    fn handle_response(&mut self, id: u64, result: Result<Value, String>) {
+        let Some(index) = self.waiting.iter().position(|(waiting, _)| *waiting == id) else {
+            return;
+        };
+        let (_, command) = self.waiting.remove(index);
+        let text = match result {
+            Ok(result) => match result.get("text").and_then(Value::as_str) {
+                Some(text) => text.to_string(),
+                None => result.as_str().unwrap_or_default().to_string(),
+            },
+            Err(error) => {
+                let message = format!("Plugin {} failed on /{}: {}", self.name, command, error);
+                self.messages.push(message.clone());
+                message
+            }
+        };
+        // The answer replaces whatever the plugin pushed before
+        self.content = Some(text.lines().map(|line| Line::from(line.to_string())).collect());
+    }
+
+    /// Tells the plugin Enter was pressed on one of its items
+    pub fn activate(&mut self, item: &str) -> Option<String> {
+        let item = item.strip_prefix(&self.id).map_or(item, |rest| rest.trim_start_matches('.'));
+        self.notify("activate", json!({ "item": item }))
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Option<String> {
+        if self.process.is_closed() {
+            return Some(format!("Plugin {} is no longer running", self.name));
+        }
+        self.process
+            .notify(method, params)
+            .err()
+            .map(|error| format!("Plugin {} could not be sent {}: {}", self.name, method, error))
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Value) {
+        match method {
+            "preview" => match preview_lines(params) {
+                Some(lines) => self.content = Some(lines),
+                None => self.messages.push(format!("Plugin {} sent a preview without text or lines", self.name)),
+            },
+            "log" => {
+                let message = params.get("message").and_then(Value::as_str).unwrap_or_default();
+                self.messages.push(format!("[{}] {}", self.name, message));
+            }
+            _ => self.messages.push(format!("Plugin {} sent an unknown notification: {}", self.name, method)),
+        }
+    }
+}
+
+impl Feature for PluginFeature {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn navigation_item(&self) -> NavigationItem {
+        self.navigation.clone()
+    }
+
+    // Names taken by the shell or another plugin are skipped and reported
+    fn register_commands(&mut self, registry: &mut CommandRegistry) {
+        for command in &self.commands {
+            if registry.find(&command.name).is_some() {
+                self.messages.push(format!("Plugin {}: /{} is already taken", self.name, command.name));
+                continue;
+            }
+            let name = leak(command.name.clone());
+            registry.register(Command {
+                name,
+                aliases: &[],
+                args: command.args.clone(),
+                help: leak(command.help.clone()),
+                handler: run_plugin_command,
+            });
+            self.registered.push(name);
+        }
+    }
+
+    fn render(&self, f: &mut Frame, area: Rect, app_state: &AppState) {
+        let text = match &self.content {
+            Some(lines) if !app_state.is_command_mode => Text::from(lines.clone()),
+            _ => Text::from(app_state.preview_content.clone()),
+        };
+        let title = if self.process.is_closed() {
+            format!("{} (exited)", self.title)
+        } else {
+            self.title.clone()
+        };
+        let block = Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(app_state.theme.panel);
+        f.render_widget(Paragraph::new(text).wrap(Wrap { trim: false }).block(block), area);
+    }
+
+    fn poll(&mut self) -> Vec<String> {
+        for event in self.process.events() {
+            match event {
+                PluginEvent::Notification { method, params } => self.handle_notification(&method, &params),
+                PluginEvent::Response { id, result } => self.handle_response(id, result),
+                PluginEvent::Invalid(line) => {
+                    self.messages.push(format!("Plugin {} sent something that isn't JSON-RPC: {}", self.name, line));
+                }
+                PluginEvent::Stderr(line) => self.messages.push(format!("[{}] {}", self.name, line)),
+                PluginEvent::Closed => self.messages.push(format!("Plugin {} exited", self.name)),
+            }
+        }
+        std::mem::take(&mut self.messages)
+    }
+
+    fn submit_input(&mut self, line: &str) {
+        if let Some(message) = self.notify("input", json!({ "line": line })) {
+            self.messages.push(message);
+        }
+    }
+}
+
+/// Handler of every plugin command: finds the plugin that declared it
+fn run_plugin_command(app_state: &mut AppState, args: &CommandArgs) -> String {
+    let plugin = app_state
+        .features
+        .iter_mut()
+        .filter_map(|feature| (feature as &mut dyn Any).downcast_mut::<PluginFeature>())
+        .find(|plugin| plugin.owns_command(args.command()));
+    let Some(plugin) = plugin else {
+        return format!("No plugin handles /{}", args.command());
+    };
+    let id = plugin.id.clone();
+    let preview = plugin.run_command(args);
+    // The answer shows in the plugin's view
+    app_state.show_feature(&id);
+    preview
+}
+
+/// Action of every plugin menu item
+fn activate_item(app_state: &mut AppState) -> Option<String> {
+    let item = app_state.navigation.selected()?.id.clone();
+    let feature = app_state.active_feature_mut()?;
+    let plugin = (feature as &mut dyn Any).downcast_mut::<PluginFeature>()?;
+    plugin.activate(&item)
+}
+
+// Commands need 'static names and help. Plugins are loaded once per run,
+// so this is a bounded amount.
+fn leak(text: String) -> &'static str {
+    Box::leak(text.into_boxed_str())
+}
+
+fn list<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value.get(key).and_then(Value::as_array).map_or(&[], Vec::as_slice)
+}
+
+/// `{id, name, preview, children}`, with the id placed under the plugin's
+fn navigation_item(parent: &str, item: &Value) -> Option<NavigationItem> {
+    let id = format!("{}.{}", parent, item.get("id")?.as_str()?);
+    let name = item.get("name").and_then(Value::as_str).unwrap_or(&id).to_string();
+    let mut navigation = NavigationItem::new(&id, &name).action(activate_item);
+    if let Some(preview) = item.get("preview").and_then(Value::as_str) {
+        navigation = navigation.preview_text(preview);
+    }
+    let children = list(item, "children").iter().filter_map(|child| navigation_item(&id, child)).collect();
+    Some(navigation.children(children))
+}
+
+/// `{name, help, args: [{name, help, kind, required}]}`
+fn plugin_command(command: &Value) -> Option<PluginCommand> {
+    let name = command.get("name")?.as_str()?.trim_start_matches('/').to_string();
+    let help = command.get("help").and_then(Value::as_str).unwrap_or_default().to_string();
+    let args = list(command, "args")
+        .iter()
+        .filter_map(|arg| {
+            let kind = match arg.get("kind").and_then(Value::as_str).unwrap_or("positional") {
+                "rest" => ArgKind::Rest,
+                "option" => ArgKind::Option,
+                "switch" => ArgKind::Switch,
+                _ => ArgKind::Positional,
+            };
+            Some(ArgSpec {
+                name: leak(arg.get("name")?.as_str()?.to_string()),
+                help: leak(arg.get("help").and_then(Value::as_str).unwrap_or_default().to_string()),
+                required: arg.get("required").and_then(Value::as_bool).unwrap_or(false) && kind != ArgKind::Switch,
+                kind,
+                completion: ArgCompletion::None,
+            })
+        })
+        .collect();
+    Some(PluginCommand { name, help, args })
+}
+
+/// A pushed preview: `{text}` or `{lines: [[{text, fg, bg, bold, italic, underline}]]}`
+fn preview_lines(params: &Value) -> Option<Vec<Line<'static>>> {
+    if let Some(text) = params.get("text").and_then(Value::as_str) {
+        return Some(text.lines().map(|line| Line::from(line.to_string())).collect());
+    }
+    let lines = params.get("lines")?.as_array()?;
+    Some(
+        lines
+            .iter()
+            .map(|line| Line::from(line.as_array().map_or(&[][..], Vec::as_slice).iter().map(styled_span).collect::<Vec<_>>()))
+            .collect(),
+    )
+}
+
+// A span is an object with a style, or just a string
+fn styled_span(span: &Value) -> Span<'static> {
+    if let Some(text) = span.as_str() {
+        return Span::raw(text.to_string());
+    }
+    let color = |key: &str| span.get(key).and_then(Value::as_str).and_then(|name| Color::from_str(name).ok());
+    let flag = |key: &str| span.get(key).and_then(Value::as_bool).unwrap_or(false);
+
+    let mut style = Style::default();
+    if let Some(fg) = color("fg") {
+        style = style.fg(fg);
+    }
+    if let Some(bg) = color("bg") {
+        style = style.bg(bg);
+    }
+    for (key, modifier) in [("bold", Modifier::BOLD), ("italic", Modifier::ITALIC), ("underline", Modifier::UNDERLINED)] {
+        if flag(key) {
+            style = style.add_modifier(modifier);
+        }
+    }
+    let text = span.get("text").and_then(Value::as_str).unwrap_or_default();
+    Span::styled(text.to_string(), style)
+}
+
+#[cfg(test)]
+pub(super) const EXAMPLE_PLUGIN: &str = include_str!("example_plugin.sh");
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, path::PathBuf, time::Instant};
+
+    use super::*;
+    use crate::core::{command, navigation::Navigation};
+
+    // An app with nothing but the example plugin in it
+    fn example_app(dir: &Path) -> AppState {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("example_plugin.sh"), EXAMPLE_PLUGIN).unwrap();
+        let args = [String::from("example_plugin.sh")];
+        let mut plugin = PluginFeature::start("echo", Path::new("sh"), &args, dir, Duration::from_secs(5))
+            .unwrap()
+            .request_timeout(Duration::from_millis(300));
+
+        let mut app_state = AppState::for_tests();
+        app_state.navigation = Navigation::new(vec![plugin.navigation_item()]);
+        plugin.register_commands(&mut app_state.commands);
+        app_state.features.register(plugin);
+        app_state
+    }
+
+    fn plugin(app_state: &AppState) -> &PluginFeature {
+        app_state.features.get().unwrap()
+    }
+
+    // Runs a command and polls until the plugin's view shows its answer
+    fn answer(app_state: &mut AppState, line: &str) -> String {
+        let waiting = command::execute(app_state, line).unwrap();
+        assert!(waiting.starts_with("Waiting for Echo"), "{}", waiting);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while plugin(app_state).content.is_none() && Instant::now() < deadline {
+            app_state.poll_features();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let content = plugin(app_state).content.as_deref().unwrap_or_default();
+        content.iter().map(Line::to_string).collect::<Vec<_>>().join("\n")
+    }
+
+    // Polls until the log has a line containing `text`; notifications arrive in the background
+    fn wait_for_log(app_state: &mut AppState, text: &str) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            app_state.poll_features();
+            if app_state.output_log.iter().any(|message| message.contains(text)) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn test_example_plugin_speaks_the_protocol() {
+        let dir: PathBuf = std::env::temp_dir().join(format!("pipemind_plugin_echo_{}", std::process::id()));
+        let mut app_state = example_app(&dir);
+        assert_eq!(plugin(&app_state).title(), "Echo");
+        assert!(app_state.navigation.reveal("plugins.echo.greet"));
+        assert_eq!(app_state.active_feature().map(|feature| feature.id()), Some("plugins.echo"));
+
+        assert_eq!(answer(&mut app_state, "/echo hello there"), "hello there");
+        assert!(command::execute(&mut app_state, "/echo").is_err());
+
+        // A styled preview comes back for the activated item
+        app_state.features.get_mut::<PluginFeature>().unwrap().content = None;
+        assert_eq!(app_state.activate_navigation_item(), None);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while plugin(&app_state).content.is_none() && Instant::now() < deadline {
+            app_state.poll_features();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let content = plugin(&app_state).content.clone().unwrap();
+        assert_eq!(content[0].to_string(), "Hello, greet");
+        assert_eq!(content[0].spans[1].style.fg, Some(Color::Green));
+
+        if let Some(feature) = app_state.active_feature_mut() {
+            feature.submit_input("a goblin");
+        }
+        assert!(wait_for_log(&mut app_state, "[echo] heard a goblin"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_hangs_and_crashes_are_contained() {
+        let dir: PathBuf = std::env::temp_dir().join(format!("pipemind_plugin_crash_{}", std::process::id()));
+        let mut app_state = example_app(&dir);
+
+        // The UI carries on while the plugin hangs
+        let sent = Instant::now();
+        assert!(command::execute(&mut app_state, "/hang").unwrap().starts_with("Waiting for Echo to answer /hang"));
+        assert!(sent.elapsed() < Duration::from_millis(300));
+        assert!(wait_for_log(&mut app_state, "Plugin echo failed on /hang: command timed out"));
+        assert_eq!(answer(&mut app_state, "/echo still here"), "still here");
+
+        assert!(answer(&mut app_state, "/crash").contains("exited before answering command"));
+        assert!(wait_for_log(&mut app_state, "Plugin echo exited"));
+        assert!(wait_for_log(&mut app_state, "[echo] goblin in the works"));
+        assert!(command::execute(&mut app_state, "/echo again").unwrap().contains("no longer running"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
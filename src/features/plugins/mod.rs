@@ -0,0 +1,142 @@
+// This module runs third-party tools as plugins, each in its own process.
+//
+// A plugin is declared by a manifest in the `plugins` folder of the data
+// directory, say `plugins/echo.json`:
+//
+//     { "command": "echo.py", "args": ["--quiet"] }
+//
+// The file name is the plugin's name. The command is looked up next to the
+// manifest first and on the PATH after that, and runs in the plugins folder.
+//
+// Host and plugin exchange JSON-RPC 2.0 messages, one per line, over the
+// plugin's stdin and stdout. Its stderr goes to the output log.
+//
+// Host to plugin:
+//   initialize {protocol}   request, answered with
+//                           {title, preview, navigation: [{id, name, preview, children}],
+//                            commands: [{name, help, args: [{name, help, kind, required}]}]}
+//                           where kind is positional, rest, option or switch
+//   command {name, args}    request, answered with {text} for the preview
+//   activate {item}         Enter was pressed on one of its navigation items
+//   input {line}            a line was entered while the plugin is on screen
+//   shutdown                the shell is closing
+//
+// Plugin to host:
+//   preview {text} or {lines: [[{text, fg, bg, bold, italic, underline}]]}
+//                           replaces what its view shows; colours are names or #rrggbb
+//   log {message}           adds a line to the output log
+//
+// Plugins start in the background and join the menu as they answer. A
+// plugin that crashes, hangs or talks nonsense is reported in the log and
+// the rest of the shell carries on.
+
+mod feature;
+pub mod rpc;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use serde_json::Value;
+
+use super::{Feature, FeatureStarts};
+use crate::core::paths;
+
+pub use feature::{PluginFeature, PROTOCOL_VERSION, REQUEST_TIMEOUT};
+
+/// How long a plugin has to answer `initialize`
+pub const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The folder plugin manifests are read from
+pub fn plugins_dir() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("plugins"))
+}
+
+/// Starts the plugin of every manifest in `dir` in the background, each in
+/// its own thread. They arrive in name order, or as what went wrong, for
+/// the output log.
+pub fn start_plugins(dir: &Path) -> FeatureStarts {
+    let (sender, starts) = mpsc::channel();
+    let dir = dir.to_path_buf();
+    thread::spawn(move || {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            return;
+        };
+        let mut manifests: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .collect();
+        manifests.sort();
+
+        let threads: Vec<_> = manifests
+            .into_iter()
+            .filter_map(|manifest| {
+                let name = manifest.file_stem()?.to_string_lossy().into_owned();
+                let dir = dir.clone();
+                let thread = {
+                    let name = name.clone();
+                    thread::spawn(move || start_plugin(&name, &manifest, &dir))
+                };
+                Some((name, thread))
+            })
+            .collect();
+        for (name, thread) in threads {
+            let started = match thread.join() {
+                Ok(Ok(plugin)) => Ok(Box::new(plugin) as Box<dyn Feature + Send>),
+                Ok(Err(error)) => Err(format!("Plugin {} failed to start: {}", name, error)),
+                Err(_) => Err(format!("Plugin {} failed to start", name)),
+            };
+            // The shell has closed
+            if sender.send(started).is_err() {
+                return;
+            }
+        }
+    });
+    starts
+}
+
+fn start_plugin(name: &str, manifest: &Path, dir: &Path) -> Result<PluginFeature, String> {
+    let text = fs::read_to_string(manifest).map_err(|error| error.to_string())?;
+    let manifest: Value = serde_json::from_str(&text).map_err(|error| format!("bad manifest: {}", error))?;
+    let command = manifest
+        .get("command")
+        .and_then(Value::as_str)
+        .ok_or_else(|| String::from("the manifest has no command"))?;
+    let args: Vec<String> = manifest
+        .get("args")
+        .and_then(Value::as_array)
+        .map(|args| args.iter().filter_map(Value::as_str).map(str::to_string).collect())
+        .unwrap_or_default();
+
+    let local = dir.join(command);
+    let command = if local.is_file() { local } else { PathBuf::from(command) };
+    PluginFeature::start(name, &command, &args, dir, START_TIMEOUT)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_plugins_reports_broken_manifests() {
+        let dir = std::env::temp_dir().join(format!("pipemind_plugins_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("example_plugin.sh"), feature::EXAMPLE_PLUGIN).unwrap();
+        // Run through sh so the script needs no execute permission
+        fs::write(dir.join("echo.json"), r#"{"command": "sh", "args": ["example_plugin.sh"]}"#).unwrap();
+        fs::write(dir.join("broken.json"), r#"{"args": []}"#).unwrap();
+        fs::write(dir.join("missing.json"), r#"{"command": "./no_such_plugin"}"#).unwrap();
+
+        let started: Vec<_> = start_plugins(&dir).iter().collect();
+        assert_eq!(started.len(), 3);
+        assert!(started[0].as_ref().is_err_and(|error| error.starts_with("Plugin broken failed to start: the manifest has no command")));
+        assert_eq!(started[1].as_ref().map(|plugin| plugin.id()).ok(), Some("plugins.echo"));
+        assert!(started[2].as_ref().is_err_and(|error| error.starts_with("Plugin missing failed to start")));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,313 @@
+// This file runs one plugin process and speaks JSON-RPC 2.0 with it, one
+// message per line on its stdin and stdout.
+//
+// Two threads read the plugin's stdout and stderr and a third writes to
+// its stdin, so the UI never blocks on it, even when the plugin stops
+// reading. A request returns its id straight away; its response, or its
+// timeout, is handed out by `events` on a later poll with everything else
+// the plugin sent.
+
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+
+/// How long a plugin has to exit after `shutdown` before it is killed
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(200);
+
+/// Something the plugin sent, or that happened to it
+#[derive(Debug, Clone, PartialEq)]
+pub enum PluginEvent {
+    Notification { method: String, params: Value },
+    /// A line that isn't a JSON-RPC message
+    Invalid(String),
+    /// A line written to stderr
+    Stderr(String),
+    /// The answer to a request, or why none came: the plugin's own error
+    /// message, a timeout or an exit, ready for the log
+    Response { id: u64, result: Result<Value, String> },
+    /// Stdout closed: the plugin exited or crashed
+    Closed,
+}
+
+// What the reader threads pass back
+enum Incoming {
+    Event(PluginEvent),
+    Response { id: u64, result: Result<Value, String> },
+}
+
+// A request still waiting for its response
+struct Request {
+    id: u64,
+    method: String,
+    deadline: Instant,
+    timeout: Duration,
+}
+
+pub struct PluginProcess {
+    child: Child,
+    // Messages for the writer thread; None once the plugin has exited
+    outgoing: Option<Sender<Value>>,
+    incoming: Receiver<Incoming>,
+    next_id: u64,
+    requests: Vec<Request>,
+    // Events not handed out yet
+    pending: VecDeque<PluginEvent>,
+    closed: bool,
+}
+
+impl PluginProcess {
+    /// Starts `command` with `args` in the folder `dir`
+    pub fn spawn(command: &Path, args: &[String], dir: &Path) -> io::Result<Self> {
+        let mut child = Command::new(command)
+            .args(args)
+            .current_dir(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let (sender, incoming) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(stdout).lines() {
+                    let Ok(line) = line else { break };
+                    if !line.trim().is_empty() && sender.send(parse_message(&line)).is_err() {
+                        return;
+                    }
+                }
+                let _ = sender.send(Incoming::Event(PluginEvent::Closed));
+            });
+        }
+        if let Some(stderr) = child.stderr.take() {
+            thread::spawn(move || {
+                for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                    if sender.send(Incoming::Event(PluginEvent::Stderr(line))).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+
+        let (sender, messages) = mpsc::channel::<Value>();
+        let outgoing = child.stdin.take().map(|mut stdin| {
+            thread::spawn(move || {
+                for message in messages {
+                    if writeln!(stdin, "{}", message).and_then(|()| stdin.flush()).is_err() {
+                        return;
+                    }
+                }
+            });
+            sender
+        });
+        Ok(Self {
+            child,
+            outgoing,
+            incoming,
+            next_id: 1,
+            requests: Vec::new(),
+            pending: VecDeque::new(),
+            closed: false,
+        })
+    }
+
+    /// Whether the plugin's stdout has closed
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Sends a request without waiting for it. Returns its id; the response
+    /// comes from `events` as a `Response`, failed if none came in `timeout`.
+    pub fn request(&mut self, method: &str, params: Value, timeout: Duration) -> Result<u64, String> {
+        if self.closed {
+            return Err(String::from("it is no longer running"));
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .map_err(|error| format!("could not send {}: {}", method, error))?;
+        let deadline = Instant::now() + timeout;
+        self.requests.push(Request { id, method: method.to_string(), deadline, timeout });
+        Ok(id)
+    }
+
+    /// Blocks until request `id` is answered or times out. For starting the
+    /// plugin, which happens away from the UI thread.
+    pub fn wait(&mut self, id: u64) -> Result<Value, String> {
+        loop {
+            let answered = self
+                .pending
+                .iter()
+                .position(|event| matches!(event, PluginEvent::Response { id: answered, .. } if *answered == id));
+            if let Some(PluginEvent::Response { result, .. }) = answered.and_then(|index| self.pending.remove(index)) {
+                return result;
+            }
+            let Some(deadline) = self.requests.iter().find(|request| request.id == id).map(|request| request.deadline)
+            else {
+                return Err(format!("nothing is waiting for request {}", id));
+            };
+            match self.incoming.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(incoming) => self.receive(incoming),
+                Err(RecvTimeoutError::Timeout) => self.expire(),
+                Err(RecvTimeoutError::Disconnected) => self.queue(PluginEvent::Closed),
+            }
+        }
+    }
+
+    /// Sends a notification, which gets no response
+    pub fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    /// Everything the plugin sent since the last call, without waiting
+    pub fn events(&mut self) -> Vec<PluginEvent> {
+        loop {
+            match self.incoming.try_recv() {
+                Ok(incoming) => self.receive(incoming),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.queue(PluginEvent::Closed);
+                    break;
+                }
+            }
+        }
+        self.expire();
+        self.pending.drain(..).collect()
+    }
+
+    fn receive(&mut self, incoming: Incoming) {
+        match incoming {
+            Incoming::Event(event) => self.queue(event),
+            Incoming::Response { id, result } => {
+                // Late answers to requests that already timed out are dropped
+                if let Some(index) = self.requests.iter().position(|request| request.id == id) {
+                    self.requests.remove(index);
+                    self.pending.push_back(PluginEvent::Response { id, result });
+                }
+            }
+        }
+    }
+
+    // Fails the requests whose time is up
+    fn expire(&mut self) {
+        let now = Instant::now();
+        let (expired, waiting) = std::mem::take(&mut self.requests)
+            .into_iter()
+            .partition(|request| request.deadline <= now);
+        self.requests = waiting;
+        for request in expired {
+            let error = format!("{} timed out after {:.1}s", request.method, request.timeout.as_secs_f32());
+            self.pending.push_back(PluginEvent::Response { id: request.id, result: Err(error) });
+        }
+    }
+
+    fn queue(&mut self, event: PluginEvent) {
+        if event == PluginEvent::Closed {
+            // Both the reader and the channel closing report the same exit
+            if self.closed {
+                return;
+            }
+            self.closed = true;
+            self.outgoing = None;
+            for request in std::mem::take(&mut self.requests) {
+                let error = format!("it exited before answering {}", request.method);
+                self.pending.push_back(PluginEvent::Response { id: request.id, result: Err(error) });
+            }
+        }
+        self.pending.push_back(event);
+    }
+
+    // Hands the message to the writer thread
+    fn send(&mut self, message: Value) -> io::Result<()> {
+        let closed = || io::Error::new(io::ErrorKind::BrokenPipe, "the plugin's input is closed");
+        let outgoing = self.outgoing.as_ref().ok_or_else(closed)?;
+        outgoing.send(message).map_err(|_| closed())
+    }
+}
+
+impl Drop for PluginProcess {
+    // Asks the plugin to exit, then makes sure it does
+    fn drop(&mut self) {
+        if !self.closed {
+            let _ = self.notify("shutdown", Value::Null);
+        }
+        // The writer closes the plugin's stdin once it has sent what is queued
+        self.outgoing = None;
+        let deadline = Instant::now() + SHUTDOWN_GRACE;
+        while Instant::now() < deadline {
+            if !matches!(self.child.try_wait(), Ok(None)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Sorts a line from the plugin into a response or an event
+fn parse_message(line: &str) -> Incoming {
+    let Ok(message) = serde_json::from_str::<Value>(line) else {
+        return Incoming::Event(PluginEvent::Invalid(line.to_string()));
+    };
+    if let Some(method) = message.get("method").and_then(Value::as_str) {
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        return Incoming::Event(PluginEvent::Notification { method: method.to_string(), params });
+    }
+    let Some(id) = message.get("id").and_then(Value::as_u64) else {
+        return Incoming::Event(PluginEvent::Invalid(line.to_string()));
+    };
+    let result = match message.get("error") {
+        Some(error) => Err(error
+            .get("message")
+            .and_then(Value::as_str)
+            .map_or_else(|| error.to_string(), str::to_string)),
+        None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+    };
+    Incoming::Response { id, result }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_message() {
+        assert!(matches!(
+            parse_message(r#"{"jsonrpc":"2.0","id":3,"result":{"text":"hi"}}"#),
+            Incoming::Response { id: 3, result: Ok(_) }
+        ));
+        assert!(matches!(
+            parse_message(r#"{"jsonrpc":"2.0","id":4,"error":{"code":-32601,"message":"no such method"}}"#),
+            Incoming::Response { id: 4, result: Err(message) } if message == "no such method"
+        ));
+        assert!(matches!(
+            parse_message(r#"{"jsonrpc":"2.0","method":"log","params":{"message":"hi"}}"#),
+            Incoming::Event(PluginEvent::Notification { method, .. }) if method == "log"
+        ));
+        assert!(matches!(parse_message("hello"), Incoming::Event(PluginEvent::Invalid(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_a_plugin_that_stops_reading_never_blocks() {
+        let args = [String::from("-c"), String::from("sleep 5")];
+        let mut process = PluginProcess::spawn(Path::new("sh"), &args, &std::env::temp_dir()).unwrap();
+        let sent = Instant::now();
+        // Far more than a pipe holds
+        let line = "x".repeat(64 * 1024);
+        for _ in 0..32 {
+            process.notify("input", json!({ "line": line })).unwrap();
+        }
+        assert!(process.request("command", Value::Null, Duration::from_millis(50)).is_ok());
+        assert!(sent.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,349 @@
+// This module is the Settings screen: every option of `config::OPTIONS`,
+// grouped by section, edited in place and saved back to config.toml.
+//
+// Each kind of option has its own editor. Booleans toggle, enums and
+// colours cycle with the arrow keys, numbers step with them, and anything
+// can be typed in after Enter, checked as it is typed. Saving writes only
+// the options that changed; the config watcher then reloads the file, which
+// is what actually applies them.
+
+mod view;
+
+use std::path::PathBuf;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{layout::Rect, style::Color, Frame};
+
+use crate::{
+    core::{
+        app_state::{AppState, FocusArea},
+        config::{self, Config, ConfigOption, ConfigValue, OptionKind, OPTIONS},
+        keymap::{Action, Context},
+        line_editor::LineEditor,
+        navigation::NavigationItem,
+        paths,
+    },
+    features::Feature,
+};
+
+/// Id of the Settings entry in the navigation menu
+pub const NAVIGATION_ID: &str = "settings";
+
+// What the arrow keys cycle a colour through; "" is the theme's own
+const PALETTE: &[&str] = &[
+    "", "black", "red", "green", "yellow", "blue", "magenta", "cyan", "gray", "dark gray", "light red",
+    "light green", "light yellow", "light blue", "light magenta", "light cyan", "white",
+];
+
+// The value being typed in for the selected option
+struct TextEdit {
+    input: LineEditor,
+    // Why the text isn't a valid value yet
+    error: Option<String>,
+}
+
+pub struct SettingsState {
+    path: Option<PathBuf>,
+    // One per entry of `OPTIONS`, as edited
+    values: Vec<ConfigValue>,
+    // As in the file, to tell which options changed
+    saved: Vec<ConfigValue>,
+    selected: usize,
+    editing: Option<TextEdit>,
+    // The outcome of the last save
+    status: Option<Result<String, String>>,
+    messages: Vec<String>,
+}
+
+impl SettingsState {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            values: Vec::new(),
+            saved: Vec::new(),
+            selected: 0,
+            editing: None,
+            status: None,
+            messages: Vec::new(),
+        }
+    }
+
+    pub fn option(&self) -> &'static ConfigOption {
+        &OPTIONS[self.selected]
+    }
+
+    pub fn value(&self, index: usize) -> Option<&ConfigValue> {
+        self.values.get(index)
+    }
+
+    pub fn is_dirty(&self, index: usize) -> bool {
+        self.values.get(index) != self.saved.get(index)
+    }
+
+    pub fn has_changes(&self) -> bool {
+        self.values != self.saved
+    }
+
+    pub fn select(&mut self, index: usize) {
+        self.selected = index.min(OPTIONS.len() - 1);
+    }
+
+    /// Sets the selected option, marking it changed if it differs from the file
+    fn set(&mut self, value: ConfigValue) {
+        if let Some(slot) = self.values.get_mut(self.selected) {
+            *slot = value;
+        }
+    }
+
+    /// The next or previous value for the arrow keys, where the option has one
+    fn step(&self, forward: bool) -> Option<ConfigValue> {
+        let value = self.values.get(self.selected)?;
+        match (self.option().kind, value) {
+            (OptionKind::Bool, ConfigValue::Bool(value)) => Some(ConfigValue::Bool(!value)),
+            (OptionKind::Number { min, max }, ConfigValue::Number(number)) => {
+                let number = if forward { number + 1 } else { number - 1 };
+                Some(ConfigValue::Number(number.clamp(min, max)))
+            }
+            (OptionKind::Enum(values), ConfigValue::Text(text)) => {
+                cycle(values, |value| value == text, forward).map(|value| ConfigValue::Text(value.to_string()))
+            }
+            (OptionKind::Color, ConfigValue::Text(text)) => {
+                let current = text.parse::<Color>().ok();
+                cycle(PALETTE, |name| name.parse::<Color>().ok() == current, forward)
+                    .map(|name| ConfigValue::Text(name.to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Opens the text editor on the selected option
+    fn start_editing(&mut self) {
+        let mut input = LineEditor::default();
+        input.set_text(self.values.get(self.selected).map(ToString::to_string).unwrap_or_default());
+        self.editing = Some(TextEdit { input, error: None });
+    }
+
+    fn handle_edit_key(&mut self, key: &KeyEvent) {
+        let option = self.option();
+        let Some(edit) = &mut self.editing else {
+            return;
+        };
+        match key.code {
+            KeyCode::Esc => self.editing = None,
+            KeyCode::Enter => match option.parse_text(edit.input.text()) {
+                Ok(value) => {
+                    self.editing = None;
+                    self.set(value);
+                }
+                Err(error) => edit.error = Some(error),
+            },
+            KeyCode::Left => edit.input.move_left(),
+            KeyCode::Right => edit.input.move_right(),
+            KeyCode::Home => edit.input.move_line_start(),
+            KeyCode::End => edit.input.move_line_end(),
+            KeyCode::Backspace => edit.input.backspace(),
+            KeyCode::Delete => edit.input.delete(),
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => edit.input.kill_line_start(),
+            KeyCode::Char(c) => edit.input.insert_char(c),
+            _ => {}
+        }
+        // Checked as it is typed
+        if let Some(edit) = &mut self.editing
+            && !matches!(key.code, KeyCode::Enter)
+        {
+            edit.error = option.parse_text(edit.input.text()).err();
+        }
+    }
+
+    /// Writes the changed options to the config file
+    pub fn save(&mut self) -> String {
+        let Some(path) = self.path.clone() else {
+            return self.report(Err(String::from("There is no config folder to save settings in")));
+        };
+        let changes: Vec<(&ConfigOption, ConfigValue)> = (0..OPTIONS.len())
+            .filter(|&index| self.is_dirty(index))
+            .map(|index| (&OPTIONS[index], self.values[index].clone()))
+            .collect();
+        if changes.is_empty() {
+            return self.report(Ok(String::from("No settings have changed")));
+        }
+        match config::save_changes(&path, &changes) {
+            Ok(()) => {
+                self.saved = self.values.clone();
+                let plural = if changes.len() == 1 { "" } else { "s" };
+                self.report(Ok(format!("Saved {} setting{} to {}", changes.len(), plural, paths::display_path(&path))))
+            }
+            Err(error) => self.report(Err(format!("Could not save {}: {}", paths::display_path(&path), error))),
+        }
+    }
+
+    fn report(&mut self, status: Result<String, String>) -> String {
+        let message = match &status {
+            Ok(message) | Err(message) => message.clone(),
+        };
+        self.status = Some(status);
+        message
+    }
+}
+
+impl Feature for SettingsState {
+    fn id(&self) -> &str {
+        NAVIGATION_ID
+    }
+
+    fn navigation_item(&self) -> NavigationItem {
+        NavigationItem::new(NAVIGATION_ID, "Settings")
+            .preview_text("Change the options in config.toml.")
+            .action(|app_state| {
+                app_state.set_focus(FocusArea::Preview);
+                None
+            })
+    }
+
+    fn render(&self, f: &mut Frame, area: Rect, app_state: &AppState) {
+        view::render_settings(f, area, self, app_state);
+    }
+
+    fn key_context(&self) -> Option<Context> {
+        Some(Context::Settings)
+    }
+
+    fn handle_action(&mut self, action: Action) -> bool {
+        match action {
+            Action::NavUp => self.select(self.selected.saturating_sub(1)),
+            Action::NavDown => self.select(self.selected + 1),
+            Action::StepBack | Action::StepForward | Action::Toggle => {
+                if let Some(value) = self.step(action != Action::StepBack) {
+                    self.set(value);
+                }
+            }
+            Action::Activate => match self.option().kind {
+                OptionKind::Bool | OptionKind::Enum(_) => {
+                    if let Some(value) = self.step(true) {
+                        self.set(value);
+                    }
+                }
+                _ => self.start_editing(),
+            },
+            // Back to what the file says
+            Action::Revert => {
+                if let Some(saved) = self.saved.get(self.selected).cloned() {
+                    self.set(saved);
+                }
+            }
+            // Back to the built-in default
+            Action::Reset => {
+                let value = (self.option().get)(&Config::default());
+                self.set(value);
+            }
+            Action::Save => {
+                let message = self.save();
+                self.messages.push(message);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn is_modal(&self) -> bool {
+        self.editing.is_some()
+    }
+
+    fn handle_modal_key(&mut self, key: &KeyEvent) -> Option<String> {
+        self.handle_edit_key(key);
+        None
+    }
+
+    fn poll(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.messages)
+    }
+
+    // Options that haven't been edited follow the file
+    fn config_changed(&mut self, config: &Config) {
+        let values: Vec<ConfigValue> = OPTIONS.iter().map(|option| (option.get)(config)).collect();
+        if self.values.len() != values.len() {
+            self.values = values.clone();
+        }
+        for (index, value) in values.iter().enumerate() {
+            if !self.is_dirty(index) {
+                self.values[index] = value.clone();
+            }
+        }
+        self.saved = values;
+    }
+}
+
+/// The value after (or before) the one `is_current` picks, wrapping around.
+/// Starts from the first value if none is current.
+fn cycle<'a>(values: &[&'a str], is_current: impl Fn(&str) -> bool, forward: bool) -> Option<&'a str> {
+    let len = values.len();
+    let index = match values.iter().position(|value| is_current(value)) {
+        Some(index) if forward => (index + 1) % len,
+        Some(index) => (index + len - 1) % len,
+        None => 0,
+    };
+    values.get(index).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn select(settings: &mut SettingsState, section: &str, key: &str) {
+        let index = OPTIONS.iter().position(|option| option.section == section && option.key == key).unwrap();
+        settings.select(index);
+    }
+
+    #[test]
+    fn test_edit_validate_and_save() {
+        let path = std::env::temp_dir().join(format!("pipemind_settings_{}.toml", std::process::id()));
+        fs::write(&path, "# Tuned by hand\n[general]\npoll_interval_ms = 50 # fast\n").unwrap();
+        let (config, _) = Config::load(&path);
+        let mut settings = SettingsState::new(Some(path.clone()));
+        settings.config_changed(&config);
+        assert!(!settings.has_changes());
+
+        select(&mut settings, "general", "vi_mode");
+        settings.handle_action(Action::Activate);
+        assert_eq!(settings.value(settings.selected), Some(&ConfigValue::Bool(true)));
+        assert!(settings.is_dirty(settings.selected));
+
+        // A number typed out of range stays in the editor until fixed
+        select(&mut settings, "general", "poll_interval_ms");
+        settings.handle_action(Action::Activate);
+        assert!(settings.is_modal());
+        for code in [KeyCode::Backspace, KeyCode::Backspace, KeyCode::Char('5')] {
+            settings.handle_modal_key(&key(code));
+        }
+        assert!(settings.editing.as_ref().is_some_and(|edit| edit.error.is_some()));
+        settings.handle_modal_key(&key(KeyCode::Enter));
+        assert!(settings.is_modal());
+        settings.handle_modal_key(&key(KeyCode::Char('0')));
+        settings.handle_modal_key(&key(KeyCode::Enter));
+        assert!(!settings.is_modal());
+        assert_eq!(settings.value(settings.selected), Some(&ConfigValue::Number(50)));
+        assert!(!settings.is_dirty(settings.selected));
+
+        select(&mut settings, "layout", "navigation_side");
+        settings.handle_action(Action::StepForward);
+        select(&mut settings, "colors", "accent");
+        settings.handle_action(Action::StepForward);
+        assert_eq!(settings.value(settings.selected), Some(&ConfigValue::Text(String::from("black"))));
+
+        assert_eq!(settings.save(), format!("Saved 3 settings to {}", paths::display_path(&path)));
+        assert!(!settings.has_changes());
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("# Tuned by hand\n[general]\npoll_interval_ms = 50 # fast\nvi_mode = true\n"));
+        let (config, errors) = Config::parse(&text);
+        assert!(errors.is_empty());
+        assert_eq!(config.layout.navigation_side, config::NavigationSide::Right);
+        assert_eq!(config.colors.accent, Some(Color::Black));
+        let _ = fs::remove_file(&path);
+    }
+}
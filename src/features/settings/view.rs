@@ -0,0 +1,121 @@
+// This file draws the Settings screen: one row per option under its
+// section's heading, with the help text or the open editor beneath the
+// selected row and the key hints at the bottom.
+
+use ratatui::{
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use super::SettingsState;
+use crate::{
+    core::{
+        app_state::{AppState, FocusArea},
+        config::{ConfigValue, OptionKind, OPTIONS},
+        keymap::Action,
+    },
+    ui::utils::border_color,
+};
+
+pub fn render_settings(f: &mut Frame, area: Rect, state: &SettingsState, app_state: &AppState) {
+    let theme = &app_state.theme;
+    let focused = app_state.has_focus(FocusArea::Preview);
+    let width = OPTIONS.iter().map(|option| option.key.len()).max().unwrap_or(0);
+
+    let mut lines = Vec::new();
+    let mut selected_line = 0;
+    let mut section = "";
+    for (index, option) in OPTIONS.iter().enumerate() {
+        if option.section != section {
+            if !section.is_empty() {
+                lines.push(Line::from(""));
+            }
+            section = option.section;
+            lines.push(Line::from(Span::styled(format!("[{}]", section), theme.heading)));
+        }
+
+        let selected = index == state.selected;
+        let marker = if state.is_dirty(index) { "* " } else { "  " };
+        let mut spans = vec![
+            Span::styled(marker, theme.accent),
+            Span::raw(format!("{:<width$}  ", option.key, width = width)),
+        ];
+        let value = state.value(index);
+        if let (OptionKind::Color, Some(ConfigValue::Text(name))) = (option.kind, value)
+            && let Ok(color) = name.parse::<Color>()
+        {
+            spans.push(Span::styled("██ ", Style::default().fg(color)));
+        }
+        spans.push(Span::raw(match value {
+            Some(ConfigValue::Text(text)) if text.is_empty() && option.kind == OptionKind::Color => {
+                String::from("(the theme's)")
+            }
+            Some(ConfigValue::Text(text)) if text.is_empty() => String::from("(default)"),
+            Some(value) => value.to_string(),
+            None => String::new(),
+        }));
+        let mut line = Line::from(spans);
+        if selected && focused {
+            line = line.style(theme.highlight);
+        }
+        if selected {
+            selected_line = lines.len();
+        }
+        lines.push(line);
+
+        if !selected {
+            continue;
+        }
+        // The editor, or what the option does
+        match &state.editing {
+            Some(edit) => {
+                let (before, after) = edit.input.text().split_at(edit.input.cursor());
+                lines.push(Line::from(vec![
+                    Span::raw(format!("  {:width$}  ", "", width = width)),
+                    Span::styled(before.to_string(), Style::default().add_modifier(Modifier::UNDERLINED)),
+                    Span::styled("│", theme.cursor),
+                    Span::styled(after.to_string(), Style::default().add_modifier(Modifier::UNDERLINED)),
+                ]));
+                let note = match &edit.error {
+                    Some(error) => Span::styled(error.clone(), theme.error),
+                    None => Span::styled("Enter to keep, Esc to cancel", theme.muted),
+                };
+                lines.push(Line::from(vec![Span::raw(format!("  {:width$}  ", "", width = width)), note]));
+            }
+            None => lines.push(Line::from(vec![
+                Span::raw(format!("  {:width$}  ", "", width = width)),
+                Span::styled(option.help, theme.muted),
+            ])),
+        }
+    }
+
+    lines.push(Line::from(""));
+    match &state.status {
+        Some(Ok(message)) => lines.push(Line::from(message.as_str())),
+        Some(Err(message)) => lines.push(Line::from(Span::styled(message.as_str(), theme.error))),
+        None => {}
+    }
+    let hints = app_state.keymap.hints(&[
+        (&[Action::NavUp, Action::NavDown], "select"),
+        (&[Action::StepBack, Action::StepForward], "change"),
+        (&[Action::Activate], "edit"),
+        (&[Action::Revert], "revert"),
+        (&[Action::Reset], "default"),
+        (&[Action::Save], "save"),
+    ]);
+    lines.push(Line::from(Span::styled(hints, theme.muted)));
+
+    // Keep the selected option and the lines under it in view
+    let height = area.height.saturating_sub(2) as usize;
+    let scroll = (selected_line + 3).saturating_sub(height);
+
+    let title = if state.has_changes() { "Settings *" } else { "Settings" };
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(border_color(app_state, FocusArea::Preview));
+    f.render_widget(Paragraph::new(lines).scroll((scroll as u16, 0)).block(block), area);
+}
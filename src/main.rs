@@ -1,18 +1,19 @@
 
-use std::io;
+use std::io::{self, Write};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyModifiers},
+    cursor,
+    event::{self, Event},
     terminal,
     ExecutableCommand,
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
 
 use pipemind_rust::{
-    core::{
-        app_state::{AppState, FocusArea},
-        input::handle_key_event,
+    core::{app_state::AppState, dispatch},
+    ui::{
+        graphics::{GraphicsOutput, KITTY_CLEAR},
+        ui_framework::draw_ui,
     },
-    ui::ui_framework::draw_ui,
 };
 
 /// Handles the terminal setup and cleanup
@@ -31,136 +32,57 @@ impl TerminalManager {
     }
 
     fn run(&mut self, app_state: &mut AppState) -> io::Result<()> {
+        let mut shown_graphics = None;
         loop {
-            self.terminal.draw(|f| draw_ui(f, app_state))?;
+            app_state.poll_config();
+            app_state.poll_features();
+            let mut graphics = None;
+            self.terminal.draw(|f| graphics = draw_ui(f, app_state))?;
+            self.sync_graphics(app_state, graphics, &mut shown_graphics)?;
 
-            if event::poll(std::time::Duration::from_millis(100))? {
-                if let Event::Key(key) = event::read()? {
-                    if self.handle_key_event(app_state, key) {
-                        break;
-                    }
-                }
+            let poll_interval = std::time::Duration::from_millis(app_state.config.general.poll_interval_ms);
+            if event::poll(poll_interval)?
+                && let Event::Key(key) = event::read()?
+                && dispatch::handle_key(app_state, &key)
+            {
+                break;
             }
         }
         Ok(())
     }
 
-    fn handle_key_event(&self, app_state: &mut AppState, key: event::KeyEvent) -> bool {
-        match key.code {
-            KeyCode::Char('q') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                app_state.show_quit_modal = true;
-                false
-            }
-            KeyCode::Char('y') if app_state.show_quit_modal => true,
-            KeyCode::Char('n') if app_state.show_quit_modal => {
-                app_state.show_quit_modal = false;
-                false
-            }
-            KeyCode::F(n) => {
-                let focus = match n {
-                    1 => Some(FocusArea::Header),
-                    2 => Some(FocusArea::Navigation),
-                    3 => Some(FocusArea::Preview),
-                    4 => Some(FocusArea::Input),
-                    5 => Some(FocusArea::Footer),
-                    _ => None,
-                };
-                if let Some(area) = focus {
-                    app_state.set_focus(area);
-                }
-                false
-            }
-            _ => {
-                if app_state.has_focus(FocusArea::Input) {
-                    handle_key_event(app_state, &key);
-                } else {
-                    self.handle_navigation(app_state, key);
-                }
-                false
-            }
+    /// Writes the Kitty/Sixel bitmap a view drew, which lives outside ratatui's
+    /// cell grid, whenever it appears, moves or changes
+    fn sync_graphics(
+        &mut self,
+        app_state: &AppState,
+        graphics: Option<GraphicsOutput>,
+        shown: &mut Option<GraphicsOutput>,
+    ) -> io::Result<()> {
+        // Overlays are plain text, so bitmaps are hidden while one is open
+        let overlay_open = app_state.show_quit_modal
+            || app_state.has_config_errors()
+            || app_state.features.iter().any(|feature| feature.is_modal());
+        let wanted = graphics.filter(|_| !overlay_open);
+        if wanted == *shown {
+            return Ok(());
         }
-    }
 
-    fn handle_navigation(&self, app_state: &mut AppState, key: event::KeyEvent) {
-        match key.code {
-            KeyCode::Char('h') => {
-                let new_focus = match app_state.focus {
-                    FocusArea::Navigation => FocusArea::Header,
-                    FocusArea::Preview => FocusArea::Navigation,
-                    FocusArea::Footer => FocusArea::Preview,
-                    FocusArea::Header => FocusArea::Footer,
-                    FocusArea::Input => app_state.focus,
-                };
-                app_state.set_focus(new_focus);
-            }
-            KeyCode::Char('l') => {
-                let new_focus = match app_state.focus {
-                    FocusArea::Header => FocusArea::Navigation,
-                    FocusArea::Navigation => FocusArea::Preview,
-                    FocusArea::Preview => FocusArea::Footer,
-                    FocusArea::Footer => FocusArea::Header,
-                    FocusArea::Input => app_state.focus,
-                };
-                app_state.set_focus(new_focus);
-            }
-            KeyCode::Char('j') => {
-                if app_state.has_focus(FocusArea::Navigation) {
-                    let current_count = app_state.get_current_navigation_count();
-                    let current_index = app_state.get_current_selection_index();
-                    let next_index = if current_index + 1 < current_count {
-                        current_index + 1
-                    } else {
-                        current_index
-                    };
-                    app_state.select_navigation_item(next_index);
-                } else {
-                    let new_focus = match app_state.focus {
-                        FocusArea::Header => FocusArea::Navigation,
-                        FocusArea::Navigation => FocusArea::Preview,
-                        FocusArea::Preview => FocusArea::Footer,
-                        FocusArea::Footer => FocusArea::Header,
-                        FocusArea::Input => app_state.focus,
-                    };
-                    app_state.set_focus(new_focus);
-                }
-            }
-            KeyCode::Char('k') => {
-                if app_state.has_focus(FocusArea::Navigation) {
-                    let current_index = app_state.get_current_selection_index();
-                    if current_index > 0 {
-                        app_state.select_navigation_item(current_index - 1);
-                    }
-                } else {
-                    let new_focus = match app_state.focus {
-                        FocusArea::Header => FocusArea::Footer,
-                        FocusArea::Navigation => FocusArea::Header,
-                        FocusArea::Preview => FocusArea::Navigation,
-                        FocusArea::Footer => FocusArea::Preview,
-                        FocusArea::Input => app_state.focus,
-                    };
-                    app_state.set_focus(new_focus);
-                }
-            }
-            KeyCode::Enter => {
-                if app_state.has_focus(FocusArea::Navigation) {
-                    if app_state.is_in_submenu() {
-                        let current_index = app_state.get_current_selection_index();
-                        if current_index == 0 {  // If "Home" is selected in submenu
-                            app_state.exit_submenu();
-                        }
-                        // Add handling for other submenu items here if needed
-                    } else {
-                        app_state.enter_submenu();
-                    }
-                }
-            }
-            KeyCode::Esc => {
-                if app_state.has_focus(FocusArea::Navigation) {
-                    app_state.exit_submenu();
-                }
+        if shown.is_some() {
+            self.terminal.backend_mut().write_all(KITTY_CLEAR.as_bytes())?;
+        }
+        match &wanted {
+            Some(output) => {
+                let backend = self.terminal.backend_mut();
+                backend.execute(cursor::MoveTo(output.position.x, output.position.y))?;
+                backend.write_all(output.sequence.as_bytes())?;
+                backend.flush()?;
             }
-            _ => {}
+            // Repaint the cells the old bitmap covered
+            None => self.terminal.clear()?,
         }
+        *shown = wanted;
+        Ok(())
     }
 }
 
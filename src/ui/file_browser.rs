@@ -0,0 +1,427 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::Modifier,
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+use crate::{
+    core::{
+        paths::{display_path, expand_tilde},
+        theme::Theme,
+    },
+};
+
+/// Extensions shown when the browser is limited to images
+pub const IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "webp"];
+
+/// How many recently used directories are remembered
+const MAX_RECENT_DIRS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrowserEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+/// Whether the browser picks an image or a folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrowserPurpose {
+    OpenImage,
+    ChooseFolder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrowserView {
+    Directory,
+    Recent,
+}
+
+/// What a key press in the browser asks the owner to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileBrowserEvent {
+    None,
+    Chosen(PathBuf),
+    Cancelled,
+}
+
+/// A keyboard-driven file picker, shown as a modal over the main layout
+#[derive(Debug, Clone)]
+pub struct FileBrowser {
+    pub purpose: BrowserPurpose,
+    pub current_dir: PathBuf,
+    pub entries: Vec<BrowserEntry>,
+    pub selected: usize,
+    pub show_hidden: bool,
+    pub view: BrowserView,
+    // Text typed after pressing `/`, when jumping to a path
+    pub path_input: Option<String>,
+    pub recent_dirs: Vec<PathBuf>,
+    pub error: Option<String>,
+    // Where recent directories are persisted; None keeps them in memory only
+    recent_file: Option<PathBuf>,
+}
+
+impl FileBrowser {
+    pub fn new(start_dir: &Path, recent_file: Option<PathBuf>) -> Self {
+        let recent_dirs = recent_file
+            .as_deref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .map(|text| text.lines().filter(|line| !line.is_empty()).map(PathBuf::from).collect())
+            .unwrap_or_default();
+
+        let mut browser = Self {
+            purpose: BrowserPurpose::OpenImage,
+            current_dir: start_dir.to_path_buf(),
+            entries: Vec::new(),
+            selected: 0,
+            show_hidden: false,
+            view: BrowserView::Directory,
+            path_input: None,
+            recent_dirs,
+            error: None,
+            recent_file,
+        };
+        browser.refresh();
+        browser
+    }
+
+    /// Switches the browser to picking folders: `s` chooses the folder being shown
+    pub fn choose_folder(mut self) -> Self {
+        self.purpose = BrowserPurpose::ChooseFolder;
+        self
+    }
+
+    /// Re-reads the current directory, keeping the selection in range
+    pub fn refresh(&mut self) {
+        match list_directory(&self.current_dir, self.show_hidden) {
+            Ok(entries) => {
+                self.entries = entries;
+                self.error = None;
+            }
+            Err(err) => {
+                self.entries.clear();
+                self.error = Some(format!("Cannot read {}: {}", self.current_dir.display(), err));
+            }
+        }
+        self.selected = self.selected.min(self.visible_len().saturating_sub(1));
+    }
+
+    pub fn change_dir(&mut self, dir: &Path) {
+        let previous = std::mem::replace(&mut self.current_dir, dir.to_path_buf());
+        self.selected = 0;
+        self.view = BrowserView::Directory;
+        self.refresh();
+
+        // Coming back up from a child directory, land on the one we left
+        if let Some(index) = self.entries.iter().position(|entry| entry.path == previous) {
+            self.selected = index;
+        }
+    }
+
+    pub fn go_to_parent(&mut self) {
+        if let Some(parent) = self.current_dir.parent().map(Path::to_path_buf) {
+            self.change_dir(&parent);
+        }
+    }
+
+    pub fn toggle_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+        self.refresh();
+    }
+
+    pub fn toggle_recent(&mut self) {
+        self.view = match self.view {
+            BrowserView::Directory => BrowserView::Recent,
+            BrowserView::Recent => BrowserView::Directory,
+        };
+        self.selected = 0;
+    }
+
+    fn visible_len(&self) -> usize {
+        match self.view {
+            BrowserView::Directory => self.entries.len(),
+            BrowserView::Recent => self.recent_dirs.len(),
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.visible_len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Opens the selected directory or chooses the selected file
+    pub fn activate(&mut self) -> FileBrowserEvent {
+        match self.view {
+            BrowserView::Recent => {
+                if let Some(dir) = self.recent_dirs.get(self.selected).cloned() {
+                    self.change_dir(&dir);
+                }
+                FileBrowserEvent::None
+            }
+            BrowserView::Directory => match self.entries.get(self.selected).cloned() {
+                Some(entry) if entry.is_dir => {
+                    self.change_dir(&entry.path);
+                    FileBrowserEvent::None
+                }
+                Some(entry) => {
+                    self.remember_dir(self.current_dir.clone());
+                    FileBrowserEvent::Chosen(entry.path)
+                }
+                None => FileBrowserEvent::None,
+            },
+        }
+    }
+
+    /// Jumps to a typed path: directories are opened, files are chosen directly
+    pub fn jump_to(&mut self, input: &str) -> FileBrowserEvent {
+        let path = expand_tilde(input.trim());
+        let path = if path.is_relative() { self.current_dir.join(path) } else { path };
+
+        if path.is_dir() {
+            self.change_dir(&path);
+            FileBrowserEvent::None
+        } else if path.is_file() {
+            if let Some(parent) = path.parent() {
+                self.remember_dir(parent.to_path_buf());
+            }
+            FileBrowserEvent::Chosen(path)
+        } else {
+            self.error = Some(format!("No such file or directory: {}", path.display()));
+            FileBrowserEvent::None
+        }
+    }
+
+    fn remember_dir(&mut self, dir: PathBuf) {
+        self.recent_dirs.retain(|existing| *existing != dir);
+        self.recent_dirs.insert(0, dir);
+        self.recent_dirs.truncate(MAX_RECENT_DIRS);
+
+        if let Some(file) = &self.recent_file {
+            let contents: Vec<String> = self.recent_dirs.iter().map(|dir| dir.display().to_string()).collect();
+            // Losing the recent list is not worth interrupting the user over
+            if let Some(parent) = file.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            let _ = fs::write(file, contents.join("\n"));
+        }
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent) -> FileBrowserEvent {
+        // Typing a path to jump to
+        if let Some(input) = &mut self.path_input {
+            match key.code {
+                KeyCode::Enter => {
+                    let input = std::mem::take(input);
+                    self.path_input = None;
+                    return self.jump_to(&input);
+                }
+                KeyCode::Esc => self.path_input = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => input.push(c),
+                _ => {}
+            }
+            return FileBrowserEvent::None;
+        }
+
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => self.select_next(),
+            KeyCode::Char('k') | KeyCode::Up => self.select_previous(),
+            KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter => return self.activate(),
+            KeyCode::Char('h') | KeyCode::Left | KeyCode::Backspace => self.go_to_parent(),
+            KeyCode::Char('.') => self.toggle_hidden(),
+            KeyCode::Char('r') => self.toggle_recent(),
+            KeyCode::Char('s') if self.purpose == BrowserPurpose::ChooseFolder => {
+                self.remember_dir(self.current_dir.clone());
+                return FileBrowserEvent::Chosen(self.current_dir.clone());
+            }
+            KeyCode::Char('/') | KeyCode::Char('g') => {
+                self.path_input = Some(format!("{}/", display_path(&self.current_dir).trim_end_matches('/')));
+            }
+            KeyCode::Char('~') => {
+                if let Some(home) = dirs::home_dir() {
+                    self.change_dir(&home);
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('q') => return FileBrowserEvent::Cancelled,
+            _ => {}
+        }
+        FileBrowserEvent::None
+    }
+}
+
+/// Lists a directory: folders first, then images, each sorted case-insensitively
+pub fn list_directory(dir: &Path, show_hidden: bool) -> io::Result<Vec<BrowserEntry>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !show_hidden && name.starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        // Follow symlinks so linked folders can be browsed into
+        let is_dir = path.is_dir();
+        if !is_dir && !is_image_path(&path) {
+            continue;
+        }
+        entries.push(BrowserEntry { name, path, is_dir });
+    }
+
+    entries.sort_by(|a, b| {
+        b.is_dir
+            .cmp(&a.is_dir)
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+    Ok(entries)
+}
+
+pub fn is_image_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+pub fn render_file_browser(f: &mut Frame, area: Rect, browser: &FileBrowser, theme: &Theme) {
+    let action = match browser.purpose {
+        BrowserPurpose::OpenImage => "Open image",
+        BrowserPurpose::ChooseFolder => "Choose folder",
+    };
+    let title = match browser.view {
+        BrowserView::Directory => format!(
+            "{} - {}{}",
+            action,
+            display_path(&browser.current_dir),
+            if browser.show_hidden { " [hidden shown]" } else { "" }
+        ),
+        BrowserView::Recent => format!("{} - Recent directories", action),
+    };
+
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(theme.panel);
+    let inner = block.inner(area);
+
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(1),    // Entries
+            Constraint::Length(1), // Status / path prompt
+        ])
+        .split(inner);
+
+    let items: Vec<ListItem> = match browser.view {
+        BrowserView::Directory => browser
+            .entries
+            .iter()
+            .map(|entry| {
+                if entry.is_dir {
+                    ListItem::new(Span::styled(format!("{}/", entry.name), theme.link))
+                } else {
+                    ListItem::new(entry.name.as_str())
+                }
+            })
+            .collect(),
+        BrowserView::Recent => browser
+            .recent_dirs
+            .iter()
+            .map(|dir| ListItem::new(display_path(dir)))
+            .collect(),
+    };
+
+    let mut list_state = ListState::default();
+    if !items.is_empty() {
+        list_state.select(Some(browser.selected));
+    }
+    let list = List::new(items)
+        .highlight_style(theme.highlight.add_modifier(Modifier::BOLD));
+    f.render_stateful_widget(list, chunks[0], &mut list_state);
+
+    let status = if let Some(input) = &browser.path_input {
+        Line::from(vec![
+            Span::styled("Go to: ", theme.accent),
+            Span::raw(input.as_str()),
+            Span::styled("█", theme.cursor),
+        ])
+    } else if let Some(error) = &browser.error {
+        Line::from(Span::styled(error.as_str(), theme.error))
+    } else {
+        let hints = match browser.purpose {
+            BrowserPurpose::OpenImage => "Enter open  h parent  / go to  . hidden  r recent  ~ home  Esc cancel",
+            BrowserPurpose::ChooseFolder => {
+                "s choose this folder  Enter open  h parent  / go to  . hidden  r recent  Esc cancel"
+            }
+        };
+        Line::from(Span::styled(hints, theme.muted))
+    };
+    f.render_widget(Paragraph::new(status), chunks[1]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pipemind-browser-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("outputs")).unwrap();
+        fs::create_dir_all(dir.join(".cache")).unwrap();
+        for file in ["b.PNG", "a.webp", "notes.txt", ".hidden.png"] {
+            fs::write(dir.join(file), b"").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_lists_directories_then_images() {
+        let dir = scratch_dir("list");
+        let mut browser = FileBrowser::new(&dir, None);
+
+        let names: Vec<&str> = browser.entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["outputs", "a.webp", "b.PNG"]);
+
+        browser.toggle_hidden();
+        let names: Vec<&str> = browser.entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec![".cache", "outputs", ".hidden.png", "a.webp", "b.PNG"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_choosing_a_file_remembers_its_directory() {
+        let dir = scratch_dir("choose");
+        let mut browser = FileBrowser::new(&dir, None);
+
+        browser.select_next();
+        assert_eq!(browser.activate(), FileBrowserEvent::Chosen(dir.join("a.webp")));
+        assert_eq!(browser.recent_dirs, vec![dir.clone()]);
+
+        // Jumping into a directory, then back up, re-selects it
+        browser.jump_to(&dir.join("outputs").display().to_string());
+        assert_eq!(browser.current_dir, dir.join("outputs"));
+        browser.go_to_parent();
+        assert_eq!(browser.entries[browser.selected].name, "outputs");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
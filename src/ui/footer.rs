@@ -6,8 +6,13 @@ use crate::core::app_state::{AppState, FocusArea};
 use crate::ui::utils::create_bordered_paragraph;
 
 pub fn render_footer(f: &mut Frame, area: Rect, app_state: &AppState) {
+    // The keys of a chord typed so far, waiting for the rest
+    let text = match app_state.keymap.pending() {
+        Some(chord) => format!("{} …", chord),
+        None => String::from("footer"),
+    };
     let footer = create_bordered_paragraph(
-        "footer",
+        &text,
         None,
         app_state,
         FocusArea::Footer,
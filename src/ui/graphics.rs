@@ -0,0 +1,17 @@
+// This file holds what the shell needs to show bitmaps that features draw.
+//
+// Kitty and Sixel pictures can't live in ratatui's cell grid, so a view
+// that draws one hands back its escape sequence through
+// `Feature::graphics`, and the terminal loop writes it after the frame.
+
+use ratatui::layout::Position;
+
+/// Removes every Kitty image placement from the screen
+pub const KITTY_CLEAR: &str = "\x1b_Ga=d,d=a,q=2\x1b\\";
+
+/// A bitmap escape sequence and the cell it must be written at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphicsOutput {
+    pub position: Position,
+    pub sequence: String,
+}
@@ -1,65 +1,202 @@
 use ratatui::{
-    layout::Rect,
-    style::{Color, Modifier, Style},
-    text::{Line, Span},
-    widgets::{Block, Borders, Paragraph},
+    layout::{Position, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Clear, Paragraph},
     Frame,
 };
 
-use crate::core::app_state::{AppState, FocusArea};
+use std::ops::Range;
+
+use crate::{
+    core::{
+        app_state::{AppState, FocusArea},
+        prompt, text,
+    },
+    features::InputAnnotation,
+};
+
+/// Byte offset the visible part of the input starts at. Long lines scroll
+/// so the cursor, and the column it is drawn in, stay inside the box.
+fn input_scroll(area: Rect, app_state: &AppState) -> usize {
+    let columns = area.width.saturating_sub(3) as usize;
+    text::scroll_start(app_state.input.text(), app_state.input.cursor(), columns)
+}
+
+// Bytes of the input and how they are drawn
+type InputStyles = Vec<(Range<usize>, Style)>;
+
+/// How each part of the input is drawn. Prompts are highlighted and what
+/// a feature marks in them, such as where a CLIP chunk starts, is reversed;
+/// the part that the last command error points at is underlined.
+fn input_styles(app_state: &AppState, annotation: Option<&InputAnnotation>) -> InputStyles {
+    let text = app_state.input.text();
+    let mut styles: InputStyles = if app_state.is_command_mode {
+        vec![(0..text.len(), Style::default())]
+    } else {
+        prompt::tokenize(text)
+            .into_iter()
+            .map(|token| (token.span, app_state.theme.prompt(token.kind)))
+            .collect()
+    };
+    let mark_style = Style::default().add_modifier(Modifier::REVERSED);
+    for mark in annotation.iter().flat_map(|annotation| &annotation.marks) {
+        styles = overlay(styles, mark, mark_style);
+    }
+    if let Some(error) = &app_state.input_error {
+        let error_style = app_state.theme.error.add_modifier(Modifier::UNDERLINED);
+        styles = overlay(styles, error, error_style);
+    }
+    styles
+}
+
+/// Patches `style` over the parts of `styles` inside `range`
+fn overlay(styles: InputStyles, range: &Range<usize>, style: Style) -> InputStyles {
+    let mut result = Vec::with_capacity(styles.len() + 2);
+    for (part, base) in styles {
+        let start = range.start.clamp(part.start, part.end);
+        let end = range.end.clamp(start, part.end);
+        for (piece, style) in [(part.start..start, base), (start..end, base.patch(style)), (end..part.end, base)] {
+            if !piece.is_empty() {
+                result.push((piece, style));
+            }
+        }
+    }
+    result
+}
+
+/// Splits `range` of the input into spans styled by `styles`
+fn input_spans<'a>(app_state: &'a AppState, styles: &InputStyles, range: Range<usize>) -> Vec<Span<'a>> {
+    let text = app_state.input.text();
+    styles
+        .iter()
+        .map(|(part, style)| (part.start.max(range.start)..part.end.min(range.end), *style))
+        .filter(|(part, _)| !part.is_empty())
+        .map(|(part, style)| Span::styled(&text[part], style))
+        .collect()
+}
+
+/// What a feature has to say about the prompt being typed, such as its CLIP
+/// token count
+fn input_annotation(app_state: &AppState) -> Option<InputAnnotation> {
+    let typing_prompt = !app_state.is_command_mode && app_state.history_search.is_none() && !app_state.input.is_empty();
+    if !typing_prompt {
+        return None;
+    }
+    let text = app_state.input.text();
+    app_state.features.iter().find_map(|feature| feature.input_annotation(text))
+}
+
+/// Rows the input box needs at `width`, borders included. Multi-line input
+/// grows with its text up to `input_max_height`.
+pub fn input_height(app_state: &AppState, width: u16) -> u16 {
+    if !app_state.multiline {
+        return 3;
+    }
+    let rows = text::wrap_rows(app_state.input.text(), width.saturating_sub(2) as usize).len() as u16;
+    (rows + 2).clamp(3, app_state.input_max_height.max(3))
+}
+
+/// The soft-wrapped rows of a multi-line input that fit in `area`, and where
+/// the cursor sits among them
+fn multiline_view<'a>(area: Rect, app_state: &'a AppState, styles: &InputStyles) -> (Vec<Line<'a>>, Position) {
+    let inner = Block::default().borders(Borders::ALL).inner(area);
+    let buffer = app_state.input.text();
+    let rows = text::wrap_rows(buffer, inner.width as usize);
+    let (cursor_row, cursor_column) = text::locate(buffer, &rows, app_state.input.cursor());
+
+    // Scroll just enough to keep the cursor in view
+    let height = inner.height.max(1) as usize;
+    let scroll = (cursor_row + 1).saturating_sub(height);
+    let lines = rows
+        .into_iter()
+        .skip(scroll)
+        .take(height)
+        .map(|row| Line::from(input_spans(app_state, styles, row)))
+        .collect();
+    let cursor = Position::new(
+        inner.x + (cursor_column as u16).min(inner.width.saturating_sub(1)),
+        inner.y + (cursor_row - scroll) as u16,
+    );
+    (lines, cursor)
+}
 
 /// Renders an input field with cursor and styling
 pub fn render_input(f: &mut Frame, area: Rect, app_state: &AppState) {
     let input_focused = app_state.focus == FocusArea::Input;
+    let mut cursor = None;
+    let annotation = input_annotation(app_state);
+    let styles = input_styles(app_state, annotation.as_ref());
 
     // Create text with visible cursor
-    let input_line = if input_focused {
+    let input_text: Text = if let Some(search) = &app_state.history_search {
+        // Shown like readline: the query, then the line it found
+        let failed = search.found.is_none() && !search.query.is_empty();
+        let prompt = format!("({}reverse-i-search)`{}': ", if failed { "failed " } else { "" }, search.query);
+        Line::from(vec![
+            Span::styled(prompt, app_state.theme.accent),
+            Span::raw(app_state.input.text()),
+        ])
+        .into()
+    } else if app_state.multiline {
+        // Wrapped rows use the terminal cursor, which does not push text aside
+        let (lines, position) = multiline_view(area, app_state, &styles);
+        cursor = input_focused.then_some(position);
+        Text::from(lines)
+    } else if input_focused {
         // We'll create a line with: text before cursor + cursor char + text after cursor
-        let (before_cursor, after_cursor) = app_state.input_buffer
-            .split_at(app_state.cursor_position);
+        let cursor = app_state.input.cursor();
+        let end = app_state.input.text().len();
 
         // Create styled spans
-        let mut spans = vec![
-            Span::raw(before_cursor),
-            Span::styled(
-                if after_cursor.is_empty() { "█" } else { "│" },
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::SLOW_BLINK)
-            ),
-        ];
+        let mut spans = input_spans(app_state, &styles, input_scroll(area, app_state)..cursor);
+        spans.push(Span::styled(
+            if cursor == end { "█" } else { "│" },
+            app_state.theme.cursor.add_modifier(Modifier::SLOW_BLINK)
+        ));
 
         // Add text after cursor if any
-        if !after_cursor.is_empty() {
-            spans.push(Span::raw(after_cursor));
-        }
+        spans.extend(input_spans(app_state, &styles, cursor..end));
 
-        Line::from(spans)
+        Line::from(spans).into()
     } else {
         // Just show the input text without cursor when not focused
-        Line::from(Span::raw(&app_state.input_buffer))
+        Line::from(input_spans(app_state, &styles, 0..app_state.input.text().len())).into()
     };
 
     // Create the paragraph widget
     let border_style = if input_focused {
-        Style::default().fg(Color::Green)
+        app_state.theme.input_focused
     } else {
-        Style::default().fg(Color::DarkGray)
+        app_state.theme.border
     };
 
     let text_style = if input_focused {
-        Style::default().fg(Color::White)
+        app_state.theme.text
     } else {
-        Style::default().fg(Color::Gray)
+        app_state.theme.inactive
     };
 
-    let title = if app_state.is_command_mode {
-        "Command"
+    let label = if app_state.history_search.is_some() {
+        "History search"
+    } else {
+        match (app_state.is_command_mode, app_state.multiline) {
+            (true, false) => "Command",
+            (false, false) => "Input",
+            (true, true) => "Command (multi-line, Ctrl-Enter sends)",
+            (false, true) => "Input (multi-line, Ctrl-Enter sends)",
+        }
+    };
+    let mut title = if app_state.vi_mode {
+        format!("{} [{}]", label, app_state.vi.mode.label())
     } else {
-        "Input"
+        label.to_string()
     };
+    if let Some(annotation) = &annotation {
+        title.push_str(&format!(" - {}", annotation.label));
+    }
 
-    let input_paragraph = Paragraph::new(input_line)
+    let input_paragraph = Paragraph::new(input_text)
         .style(text_style)
         .block(
             Block::default()
@@ -69,4 +206,71 @@ pub fn render_input(f: &mut Frame, area: Rect, app_state: &AppState) {
         );
 
     f.render_widget(input_paragraph, area);
+    if let Some(position) = cursor {
+        f.set_cursor_position(position);
+    }
+}
+
+// Most candidates the completion popup shows at once
+const COMPLETION_ROWS: usize = 8;
+
+/// Renders the Tab completion candidates under the input box, or above it
+/// when the screen has no room below
+pub fn render_completion_popup(f: &mut Frame, input_area: Rect, app_state: &AppState) {
+    let Some(popup) = &app_state.completion else {
+        return;
+    };
+    let candidates = &popup.completions.candidates;
+    let screen = f.area();
+
+    let rows = candidates.len().min(COMPLETION_ROWS) as u16;
+    let width = candidates.iter().map(|candidate| text::width(candidate)).max().unwrap_or(0) as u16 + 4;
+    let width = width.clamp(16, screen.width);
+    let height = rows + 2;
+    // Line the popup up with the word being completed
+    let buffer = app_state.input.text();
+    let column = if app_state.multiline {
+        let rows = text::wrap_rows(buffer, input_area.width.saturating_sub(2) as usize);
+        text::locate(buffer, &rows, popup.completions.range.start).1
+    } else {
+        let scroll = input_scroll(input_area, app_state);
+        text::width(&buffer[scroll..popup.completions.range.start.max(scroll)])
+    } as u16;
+    let x = (input_area.x + 1 + column).min(screen.right().saturating_sub(width));
+    let y = if input_area.bottom() + height <= screen.bottom() {
+        input_area.bottom()
+    } else {
+        input_area.y.saturating_sub(height)
+    };
+    let area = Rect::new(x, y, width, height.min(screen.height));
+
+    // Scroll so the selected candidate stays visible
+    let selected = popup.selected.unwrap_or(0);
+    let first = (selected + 1).saturating_sub(rows as usize);
+    let lines: Vec<Line> = candidates
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(rows as usize)
+        .map(|(index, candidate)| {
+            let style = if popup.selected == Some(index) {
+                app_state.theme.selected
+            } else {
+                app_state.theme.text
+            };
+            Line::from(Span::styled(format!(" {} ", candidate), style))
+        })
+        .collect();
+
+    let title = format!("{} matches", candidates.len());
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(app_state.theme.accent),
+        ),
+        area,
+    );
 }
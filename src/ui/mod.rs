@@ -1,7 +1,10 @@
+pub mod file_browser;
 pub mod footer;
+pub mod graphics;
 pub mod header;
 pub mod input;
 pub mod navigation;
 pub mod preview;
+pub mod theme;
 pub mod ui_framework;
 pub mod utils;
\ No newline at end of file
@@ -0,0 +1,36 @@
+// This file draws with the themes of `core::theme`.
+//
+// When `NO_COLOR` is set, `strip_colors` takes every colour back out of the
+// finished frame.
+
+use ratatui::{
+    buffer::Buffer,
+    style::{Color, Modifier},
+};
+
+/// Takes the colours out of a drawn frame for `NO_COLOR`. What had a
+/// background, such as the selected row, is drawn reversed so it still stands out.
+pub fn strip_colors(buffer: &mut Buffer) {
+    for cell in buffer.content.iter_mut() {
+        if cell.bg != Color::Reset {
+            cell.modifier.insert(Modifier::REVERSED);
+        }
+        cell.fg = Color::Reset;
+        cell.bg = Color::Reset;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_colors() {
+        let mut buffer = Buffer::empty(ratatui::layout::Rect::new(0, 0, 2, 1));
+        buffer[(0, 0)].set_fg(Color::Red);
+        buffer[(1, 0)].set_bg(Color::DarkGray);
+        strip_colors(&mut buffer);
+        assert_eq!((buffer[(0, 0)].fg, buffer[(0, 0)].modifier), (Color::Reset, Modifier::empty()));
+        assert_eq!((buffer[(1, 0)].bg, buffer[(1, 0)].modifier), (Color::Reset, Modifier::REVERSED));
+    }
+}
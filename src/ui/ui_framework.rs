@@ -1,34 +1,42 @@
 
 use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout},
-    style::{Color, Style},
+    text::Line,
     widgets::{Clear, Paragraph, Wrap},
     Frame,
 };
 
 use crate::{
-    core::app_state::{AppState, NavigationState},
-    features::image_tools::views::render_image_tools_view,
+    core::{
+        app_state::AppState,
+        config::{NavigationSide, CONFIG_FILE},
+        keymap::KEYS_FILE,
+    },
     ui::{
         header::render_header,
         footer::render_footer,
-        input::render_input,
+        input::{input_height, render_completion_popup, render_input},
         navigation::draw_navigation,
         preview::render_preview,
+        graphics::GraphicsOutput,
+        theme::strip_colors,
         utils::{centered_rect, create_modal_block},
     },
 };
 
-pub fn draw_ui(f: &mut Frame, app_state: &mut AppState) {
+/// Draws a frame. Returns the bitmap the content area drew, which the
+/// caller writes to the terminal after the frame.
+pub fn draw_ui(f: &mut Frame, app_state: &mut AppState) -> Option<GraphicsOutput> {
     let area = f.area();
+    let layout = app_state.config.layout.clone();
 
     // Main layout
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(3),  // Header
+            Constraint::Length(layout.header_height),  // Header
             Constraint::Min(1),     // Body
-            Constraint::Length(3),  // Footer
+            Constraint::Length(layout.footer_height),  // Footer
         ])
         .split(area);
 
@@ -36,40 +44,41 @@ pub fn draw_ui(f: &mut Frame, app_state: &mut AppState) {
     render_header(f, chunks[0], app_state);
 
     // Body layout
+    let navigation = Constraint::Length(layout.navigation_width);
+    let (constraints, navigation_area, content_area) = match layout.navigation_side {
+        NavigationSide::Left => ([navigation, Constraint::Min(1)], 0, 1),
+        NavigationSide::Right => ([Constraint::Min(1), navigation], 1, 0),
+    };
     let body_chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Length(20), // Navigation
-            Constraint::Min(1),     // Content
-        ])
+        .constraints(constraints)
         .split(chunks[1]);
 
     // Render navigation
-    draw_navigation(f, app_state, body_chunks[0]);
+    draw_navigation(f, app_state, body_chunks[navigation_area]);
 
-    // Content layout
+    // Content layout; the input grows while editing several lines
+    let content = body_chunks[content_area];
+    let input_height = input_height(app_state, content.width);
+    app_state.input_width = content.width.saturating_sub(2);
     let content_chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Min(10),    // Preview
-            Constraint::Length(3),  // Input
+            Constraint::Min(layout.preview_min_height),    // Preview
+            Constraint::Length(input_height),  // Input
         ])
-        .split(body_chunks[1]);
+        .split(content);
 
-    // Render main content area based on navigation state
-    match app_state.navigation_state {
-        NavigationState::Main if app_state.selected_navigation_item == 1 => {
-            // Index 1 is "Image Tools" in the main menu
-            render_image_tools_view(f, content_chunks[0], app_state);
-        }
-        NavigationState::Submenu { parent_index: 1 } => {
-            // We're in the Image Tools submenu
-            render_image_tools_view(f, content_chunks[0], app_state);
-        }
-        _ => {
-            // Default preview for other sections
-            render_preview(f, content_chunks[0], app_state);
+    // The navigation item the user is on, or a menu around it, may draw its
+    // own view; otherwise the feature it belongs to draws the content area
+    let mut graphics = None;
+    match (app_state.navigation.view(), app_state.active_feature()) {
+        (Some(view), _) => view(f, content_chunks[0], app_state),
+        (None, Some(feature)) => {
+            feature.render(f, content_chunks[0], app_state);
+            graphics = feature.graphics();
         }
+        (None, None) => render_preview(f, content_chunks[0], app_state),
     }
 
     // Render input with cursor support
@@ -78,16 +87,53 @@ pub fn draw_ui(f: &mut Frame, app_state: &mut AppState) {
     // Render footer
     render_footer(f, chunks[2], app_state);
 
+    // Completion candidates may hang over the footer
+    render_completion_popup(f, content_chunks[1], app_state);
+
+    // Feature overlays such as the file browser go over everything but the quit modal
+    for feature in app_state.features.iter() {
+        feature.render_overlay(f, app_state);
+    }
+
+    // Mistakes in the configuration files, until a key dismisses them
+    if app_state.has_config_errors() {
+        let popup_area = centered_rect(70, 50, f.area());
+        let mut lines: Vec<Line> = Vec::new();
+        for (file, errors) in [(CONFIG_FILE, &app_state.config_errors), (KEYS_FILE, &app_state.key_errors)] {
+            if errors.is_empty() {
+                continue;
+            }
+            if !lines.is_empty() {
+                lines.push(Line::from(""));
+            }
+            lines.push(Line::styled(file, app_state.theme.heading));
+            lines.extend(errors.iter().map(|error| Line::from(error.to_string())));
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from("The other settings were applied. Press Esc to close."));
+        let popup = Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(create_modal_block("Configuration errors", app_state.theme.modal));
+
+        f.render_widget(Clear, popup_area);
+        f.render_widget(popup, popup_area);
+    }
+
     // Render quit modal if active
     if app_state.show_quit_modal {
         let popup_area = centered_rect(40, 20, f.area());
         let popup = Paragraph::new("Quit Pipemind? (y/n)")
-            .style(Style::default().fg(Color::Red))
+            .style(app_state.theme.modal)
             .alignment(Alignment::Center)
             .wrap(Wrap { trim: true })
-            .block(create_modal_block("Confirm Exit"));
+            .block(create_modal_block("Confirm Exit", app_state.theme.modal));
 
         f.render_widget(Clear, popup_area);
         f.render_widget(popup, popup_area);
     }
+
+    if app_state.no_color {
+        strip_colors(f.buffer_mut());
+    }
+    graphics
 }
\ No newline at end of file
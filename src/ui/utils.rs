@@ -1,9 +1,12 @@
 use ratatui::{
     layout::{Layout, Rect, Constraint, Direction},
-    style::{Color, Style},
+    style::Style,
+    text::Span,
     widgets::{Block, Borders, Paragraph},
 };
 use crate::core::app_state::{AppState, FocusArea};
+use crate::core::prompt;
+use crate::core::theme::Theme;
 
 // Existing functions remain the same...
 
@@ -26,9 +29,9 @@ pub fn create_bordered_paragraph<'a>(
 
 pub fn border_color(app_state: &AppState, area: FocusArea) -> Style {
     if app_state.focus == area {
-        Style::default().fg(Color::White)
+        app_state.theme.border_focused
     } else {
-        Style::default().fg(Color::DarkGray)
+        app_state.theme.border
     }
 }
 
@@ -53,9 +56,28 @@ pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
 }
 
 // Add a new function specifically for modal blocks
-pub fn create_modal_block(title: &str) -> Block {
+pub fn create_modal_block(title: &str, style: Style) -> Block<'_> {
     Block::default()
         .borders(Borders::ALL)
-        .style(Style::default().fg(Color::Red))
+        .style(style)
         .title(title)
-}
\ No newline at end of file
+}
+
+/// A prompt as highlighted spans, one list per line
+pub fn prompt_lines(text: &str, theme: &Theme) -> Vec<Vec<Span<'static>>> {
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+    for token in prompt::tokenize(text) {
+        let style = theme.prompt(token.kind);
+        for (index, part) in text[token.span].split('\n').enumerate() {
+            if index > 0 {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !part.is_empty() {
+                line.push(Span::styled(part.to_string(), style));
+            }
+        }
+    }
+    lines.push(line);
+    lines
+}